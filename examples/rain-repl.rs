@@ -0,0 +1,296 @@
+/*!
+An interactive REPL for building, normalizing and inspecting `rain` IR.
+
+Expressions are written in the textual format emitted by the prettyprinter, and are normalized as they are built. Each
+expression entered becomes the current value, which commands inspect unless given an expression of their own.
+*/
+use clap::{App, Arg};
+use rain_ir::graph::dot::DotExporter;
+use rain_ir::lifetime::Live;
+use rain_ir::prettyprinter::parser::{parse, ParseError};
+use rain_ir::prettyprinter::{PrettyPrint, PrettyPrintConfig, PrettyPrinter, VirtualRegister};
+use rain_ir::region::{Region, Regional};
+use rain_ir::typing::Typed;
+use rain_ir::value::{ValId, Value};
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use std::fs;
+
+/// The prompt for a new expression or command
+const PROMPT: &str = "rain> ";
+
+/// The prompt for the continuation of an incomplete expression
+const CONTINUE_PROMPT: &str = "....> ";
+
+/// The help text listing the commands of the REPL
+const HELP: &str = "\
+Enter an expression to build and normalize it, making it the current value. Commands act on the current value, or on
+the expression given after them, if any:
+    :type [EXPR]        Show the type of a value
+    :region [EXPR]      Show the region a value lies in
+    :lifetime [EXPR]    Show the lifetime of a value
+    :deps [EXPR]        Show the direct dependencies of a value
+    :dot [EXPR]         Show the value graph of a value in the DOT language
+    :load FILE          Load a value from a file, making it the current value
+    :save FILE          Save the current value to a file
+    :help               Show this message
+    :quit               Exit the REPL";
+
+/// A REPL session
+#[derive(Debug, Clone, Default)]
+struct Session {
+    /// The value most recently entered or loaded, if any
+    current: Option<ValId>,
+    /// The configuration used to print values
+    config: PrettyPrintConfig,
+}
+
+impl Session {
+    /// Render an object with the session's prettyprinter configuration
+    fn render<V: PrettyPrint>(&self, value: &V) -> String {
+        PrettyPrinter::<VirtualRegister>::with_config(self.config.clone()).render(value)
+    }
+    /// Render a region as its depth and parameter types
+    fn render_region(&self, region: &Region) -> String {
+        if region.is_null() {
+            return "the null region".to_owned();
+        }
+        let params: Vec<_> = region
+            .param_tys()
+            .iter()
+            .map(|ty| self.render(ty))
+            .collect();
+        format!(
+            "depth {}, parameters ({})",
+            region.depth(),
+            params.join(" ")
+        )
+    }
+    /// Describe a parse error in a given input
+    fn describe_error(input: &str, err: &ParseError) -> String {
+        let offset = match err {
+            ParseError::UnexpectedEof => return "error: unexpected end of input".to_owned(),
+            ParseError::Value(err) => return format!("error: {}", err),
+            ParseError::UndefinedRegister(register) => {
+                return format!("error: undefined register %{}", register)
+            }
+            ParseError::UnexpectedToken(offset)
+            | ParseError::InvalidLiteral(offset)
+            | ParseError::InvalidTernary(offset)
+            | ParseError::InvalidForm(offset) => *offset,
+        };
+        let line = input[..offset].matches('\n').count() + 1;
+        let column = offset - input[..offset].rfind('\n').map(|ix| ix + 1).unwrap_or(0) + 1;
+        format!("error at {}:{}: {:?}", line, column, err)
+    }
+    /// Show a value, along with its type and region
+    fn show(&self, value: &ValId) {
+        println!("{}", self.render(value));
+        println!("  : {}", self.render(&value.clone_ty()));
+        println!("  @ {}", self.render_region(&value.clone_region()));
+    }
+    /// Enter a value, making it the current value
+    fn enter(&mut self, value: ValId) {
+        self.show(&value);
+        self.current = Some(value);
+    }
+    /// Get the value a command acts on: either the expression given to it, or the current value
+    fn target(&self, arg: &str) -> Option<ValId> {
+        if arg.is_empty() {
+            if self.current.is_none() {
+                println!("error: no current value");
+            }
+            return self.current.clone();
+        }
+        match parse(arg) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                println!("{}", Self::describe_error(arg, &err));
+                None
+            }
+        }
+    }
+    /// Run a command, given without its leading colon. Return whether to keep running.
+    fn command(&mut self, command: &str) -> bool {
+        let mut parts = command.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("");
+        let arg = parts.next().unwrap_or("").trim();
+        match name {
+            "type" | "t" => {
+                if let Some(value) = self.target(arg) {
+                    println!("{}", self.render(&value.clone_ty()))
+                }
+            }
+            "region" | "r" => {
+                if let Some(value) = self.target(arg) {
+                    println!("{}", self.render_region(&value.clone_region()))
+                }
+            }
+            "lifetime" | "l" => {
+                if let Some(value) = self.target(arg) {
+                    let lifetime = value.clone_lifetime();
+                    println!("region: {}", self.render_region(&value.clone_region()));
+                    if let Some(lender) = lifetime.lender() {
+                        let values: Vec<_> =
+                            lender.values().iter().map(|v| self.render(v)).collect();
+                        println!("lender: {}", values.join(" "));
+                    }
+                    if let Some(transient) = lifetime.transient() {
+                        let values: Vec<_> =
+                            transient.values().iter().map(|v| self.render(v)).collect();
+                        println!("transient: {}", values.join(" "));
+                    }
+                    if let Some(params) = lifetime.params() {
+                        println!("parameters: {}", params.len());
+                    }
+                }
+            }
+            "deps" | "d" => {
+                if let Some(value) = self.target(arg) {
+                    for (ix, dep) in value.deps().iter().enumerate() {
+                        println!("{}: {}", ix, self.render(dep))
+                    }
+                }
+            }
+            "dot" => {
+                if let Some(value) = self.target(arg) {
+                    print!("{}", DotExporter::new().export(&value))
+                }
+            }
+            "load" => self.load(arg),
+            "save" => {
+                if arg.is_empty() {
+                    println!("error: expected a file name")
+                } else if let Some(value) = &self.current {
+                    let text = format!("{}\n", PrettyPrinter::default().render(value));
+                    if let Err(err) = fs::write(arg, text) {
+                        println!("error: could not write {}: {}", arg, err)
+                    }
+                } else {
+                    println!("error: no current value")
+                }
+            }
+            "help" | "h" | "?" => println!("{}", HELP),
+            "quit" | "q" => return false,
+            _ => println!("error: unknown command :{}, try :help", name),
+        }
+        true
+    }
+    /// Load a value from a file, making it the current value
+    fn load(&mut self, file: &str) {
+        if file.is_empty() {
+            println!("error: expected a file name");
+            return;
+        }
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(err) => {
+                println!("error: could not read {}: {}", file, err);
+                return;
+            }
+        };
+        match parse(&text) {
+            Ok(value) => self.enter(value),
+            Err(err) => println!("{}: {}", file, Self::describe_error(&text, &err)),
+        }
+    }
+}
+
+fn main() {
+    let matches = App::new("rain-repl")
+        .about("Build, normalize and inspect rain IR interactively")
+        .arg(
+            Arg::with_name("FILE")
+                .help("A file to load a value from on startup")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .value_name("FILE")
+                .help("A file to load and save the input history from")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .value_name("COLUMNS")
+                .help("The width beyond which printed lines are wrapped")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let mut session = Session::default();
+    if let Some(width) = matches.value_of("width") {
+        match width.parse() {
+            Ok(width) => session.config.max_width = Some(width),
+            Err(_) => {
+                eprintln!("error: invalid width {}", width);
+                std::process::exit(1)
+            }
+        }
+    }
+    if let Some(file) = matches.value_of("FILE") {
+        session.load(file)
+    }
+
+    let history = matches.value_of("history");
+    let mut editor = Editor::<()>::new();
+    if let Some(history) = history {
+        // A missing history file is simply created on exit
+        let _ = editor.load_history(history);
+    }
+
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUE_PROMPT
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                break;
+            }
+        };
+        editor.add_history_entry(line.as_str());
+        if input.is_empty() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if let Some(command) = trimmed.strip_prefix(':') {
+                if session.command(command) {
+                    continue;
+                } else {
+                    break;
+                }
+            }
+        } else if line.trim().is_empty() {
+            // An empty line abandons an incomplete expression
+            input.clear();
+            continue;
+        }
+        input.push_str(&line);
+        input.push('\n');
+        match parse(&input) {
+            Ok(value) => session.enter(value),
+            Err(ParseError::UnexpectedEof) => continue,
+            Err(err) => println!("{}", Session::describe_error(&input, &err)),
+        }
+        input.clear();
+    }
+
+    if let Some(history) = history {
+        if let Err(err) = editor.save_history(history) {
+            eprintln!("error: could not save history to {}: {}", history, err)
+        }
+    }
+}
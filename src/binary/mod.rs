@@ -0,0 +1,269 @@
+/*!
+A compact, versioned binary representation of `rain` value graphs, which can be quickly serialized and deserialized.
+
+A byte stream consists of a header, followed by one record per region and value in the graph, in topological order,
+followed by the indices of the root values serialized. Reading a stream back re-hash-conses every region and value it
+contains through the [`REGION_CACHE`](crate::region::REGION_CACHE) and [`VALUE_CACHE`](crate::value::VALUE_CACHE),
+so that values which are still alive are recovered pointer-identically.
+
+The records of a stream encode the nodes of a [`ValueGraph`](crate::graph::flat::ValueGraph), which is also the
+representation used by `serde` support, so both formats flatten and rebuild values in exactly the same way.
+*/
+use crate::value::{Error, ValId};
+
+mod read;
+mod write;
+pub use read::*;
+pub use write::*;
+
+/// The magic bytes beginning every serialized `rain` graph
+pub const MAGIC: [u8; 4] = *b"RAIN";
+
+/// The current version of the binary format
+pub const FORMAT_VERSION: u64 = 1;
+
+/// The tags of the records making up a serialized `rain` graph
+mod tag {
+    /// The end of the records, followed by the root values
+    pub const END: u8 = 0;
+    /// A region
+    pub const REGION: u8 = 1;
+    /// An S-expression
+    pub const SEXPR: u8 = 2;
+    /// A parameter
+    pub const PARAMETER: u8 = 3;
+    /// A tuple
+    pub const TUPLE: u8 = 4;
+    /// A product type
+    pub const PRODUCT: u8 = 5;
+    /// The kind of mere propositions
+    pub const PROP: u8 = 6;
+    /// The kind of finite types
+    pub const FIN: u8 = 7;
+    /// An n-set
+    pub const SET: u8 = 8;
+    /// The kind of bits types
+    pub const BITS_KIND: u8 = 9;
+    /// A bits type
+    pub const BITS_TY: u8 = 10;
+    /// A bitset value
+    pub const BITS: u8 = 11;
+    /// The type of booleans
+    pub const BOOL_TY: u8 = 12;
+    /// The boolean `false`
+    pub const FALSE: u8 = 13;
+    /// The boolean `true`
+    pub const TRUE: u8 = 14;
+    /// A finite type
+    pub const FINITE: u8 = 15;
+    /// An index into a finite type
+    pub const INDEX: u8 = 16;
+    /// A pi type
+    pub const PI: u8 = 17;
+    /// A lambda function
+    pub const LAMBDA: u8 = 18;
+    /// A ternary operation
+    pub const TERNARY: u8 = 19;
+    /// A phi node
+    pub const PHI: u8 = 20;
+    /// A logical operation
+    pub const LOGICAL: u8 = 21;
+    /// An identity type
+    pub const ID: u8 = 22;
+    /// An instance of the reflexivity axiom
+    pub const REFL: u8 = 23;
+    /// A family of identity types
+    pub const ID_FAMILY: u8 = 24;
+    /// An instance of the path induction axiom
+    pub const PATH_IND: u8 = 25;
+    /// Bitvector addition
+    pub const ADD: u8 = 26;
+    /// Bitvector subtraction
+    pub const SUB: u8 = 27;
+    /// Bitvector modulo
+    pub const MOD: u8 = 28;
+    /// Bitvector multiplication
+    pub const MUL: u8 = 29;
+    /// Bitvector negation
+    pub const NEG: u8 = 30;
+    /// A reference type
+    pub const REF_TY: u8 = 31;
+    /// A borrow or reborrow
+    pub const BORROW: u8 = 32;
+    /// A dereference
+    pub const DEREFERENCE: u8 = 33;
+    /// An allocation type
+    pub const ALLOC_TY: u8 = 34;
+    /// A memory operation
+    pub const MEM_OP: u8 = 35;
+    /// The type of world tokens
+    pub const WORLD: u8 = 36;
+    /// An effectful primitive
+    pub const EFFECT: u8 = 37;
+    /// An array type
+    pub const ARRAY_TY: u8 = 38;
+    /// An array literal
+    pub const ARRAY: u8 = 39;
+    /// An array operation
+    pub const ARRAY_OP: u8 = 40;
+    /// An external function declaration
+    pub const EXTERN: u8 = 41;
+    /// A thunk
+    pub const THUNK: u8 = 42;
+}
+
+/// An error decoding a serialized `rain` graph
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The stream does not begin with the magic bytes
+    BadMagic,
+    /// The stream is of an unsupported version of the binary format
+    UnsupportedVersion(u64),
+    /// The stream ended in the middle of a record
+    UnexpectedEof,
+    /// A record has an invalid tag
+    InvalidTag(u8),
+    /// A record refers to a region or value not yet defined
+    InvalidIndex(u64),
+    /// A record is malformed
+    InvalidRecord,
+    /// The stream continues past its root values
+    TrailingBytes,
+    /// A record describes an invalid value
+    Value(Error),
+}
+
+impl From<Error> for DecodeError {
+    #[inline]
+    fn from(err: Error) -> DecodeError {
+        DecodeError::Value(err)
+    }
+}
+
+/// Serialize a list of root values, along with everything they depend on, into a byte stream
+pub fn to_bytes(roots: &[ValId]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    for root in roots {
+        writer.write(root);
+    }
+    writer.finish()
+}
+
+/// Deserialize the root values of a byte stream
+#[inline]
+pub fn from_bytes(bytes: &[u8]) -> Result<Vec<ValId>, DecodeError> {
+    Reader::new(bytes)?.read()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::phi::Phi;
+    use crate::control::ternary::Ternary;
+    use crate::data::array::{Array, ArrayOp, ArrayTy};
+    use crate::data::reference::Borrow;
+    use crate::function::lambda::Lambda;
+    use crate::primitive::bits::{BinOp, BitsTy};
+    use crate::primitive::logical::{unary_ty, Bool, Not};
+    use crate::region::Region;
+    use crate::typing::Type;
+    use crate::value::{expr::Sexpr, tuple::Tuple, Value};
+    use crate::{tyarr, valarr};
+
+    fn round_trip(roots: &[ValId]) {
+        let bytes = to_bytes(roots);
+        let decoded = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), roots.len());
+        for (decoded, root) in decoded.iter().zip(roots) {
+            assert_eq!(decoded.as_ptr(), root.as_ptr());
+        }
+    }
+
+    #[test]
+    fn functions_round_trip_pointer_identically() {
+        let region = Region::with(tyarr![Bool.into_ty(); 3], Region::NULL).unwrap();
+        let select = region.param(0).unwrap().into_val();
+        let high = region.param(1).unwrap().into_val();
+        let low = region.param(2).unwrap().into_val();
+        let ternary = Ternary::conditional(high, low).unwrap().into_val();
+        let mux_res = Sexpr::try_new(vec![ternary, select]).unwrap().into_val();
+        let mux = Lambda::try_new(mux_res, region).unwrap().into_val();
+        let add = BinOp::Add.into_val();
+        let bits = BitsTy(8).data(0x7f).unwrap().into_val();
+        round_trip(&[mux.clone(), add, bits, mux.ty().clone_val()]);
+
+        // Shared subgraphs are only written once
+        let once = to_bytes(&[mux.clone()]);
+        let twice = to_bytes(&[mux.clone(), mux]);
+        assert_eq!(twice.len(), once.len() + 1);
+    }
+
+    #[test]
+    fn phi_nodes_round_trip_pointer_identically() {
+        let fn_ty = unary_ty().clone_as_ty();
+        let rec = Region::with(tyarr![fn_ty], Region::NULL).unwrap();
+        let f_rec = rec.param(0).unwrap().into_val();
+        let region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+        let b = region.param(0).unwrap().into_val();
+        let not_b = Sexpr::try_new(vec![Not.into_val(), b]).unwrap().into_val();
+        let result = Sexpr::try_new(vec![f_rec, not_b]).unwrap().into_val();
+        let f = Lambda::try_new(result, region).unwrap().into_val();
+        let phi = Phi::try_new(valarr![f], rec).unwrap();
+        let projection = phi.project(0).unwrap();
+        round_trip(&[phi.into_val(), projection]);
+    }
+
+    #[test]
+    fn data_round_trips_pointer_identically() {
+        let tuple = Tuple::try_new(valarr![true.into_val(), Bool.into_val()])
+            .unwrap()
+            .into_val();
+        let anchor = Tuple::const_anchor().into_val();
+        let array = Array::try_new(valarr![true.into_val(), false.into_val()], Bool.into_ty())
+            .unwrap()
+            .into_val();
+        let arr_ty = ArrayTy::new(Bool.into_ty(), 2).into_var();
+        let map = ArrayOp::map(arr_ty, BitsTy(1).into_ty())
+            .unwrap()
+            .into_val();
+        let borrow = Borrow::shared(array.clone()).unwrap().into_val();
+        round_trip(&[tuple, anchor, array, map, borrow.ty().clone_val(), borrow]);
+    }
+
+    #[test]
+    fn malformed_streams_are_rejected() {
+        let bytes = to_bytes(&[true.into_val()]);
+        assert_eq!(from_bytes(&bytes), Ok(vec![true.into_val()]));
+        assert_eq!(from_bytes(b"NIAR\x01\x00\x00"), Err(DecodeError::BadMagic));
+        let mut future = MAGIC.to_vec();
+        future.extend(&[FORMAT_VERSION as u8 + 1, tag::END, 0]);
+        assert_eq!(
+            from_bytes(&future),
+            Err(DecodeError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+        assert_eq!(
+            from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEof)
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(from_bytes(&trailing), Err(DecodeError::TrailingBytes));
+        let mut dangling = MAGIC.to_vec();
+        dangling.extend(&[FORMAT_VERSION as u8, tag::END, 1, 0]);
+        assert_eq!(from_bytes(&dangling), Err(DecodeError::InvalidIndex(0)));
+        // Applications are re-checked, rather than trusted, on reading
+        let mut ill_typed = MAGIC.to_vec();
+        ill_typed.extend(&[
+            FORMAT_VERSION as u8,
+            tag::TRUE,
+            tag::SEXPR,
+            2,
+            0,
+            0,
+            tag::END,
+            1,
+            1,
+        ]);
+        assert!(matches!(from_bytes(&ill_typed), Err(DecodeError::Value(_))));
+    }
+}
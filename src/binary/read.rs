@@ -0,0 +1,312 @@
+/*!
+Deserializing `rain` value graphs
+*/
+use super::{tag, DecodeError, FORMAT_VERSION, MAGIC};
+use crate::control::ternary::TernaryKind;
+use crate::data::array::ArrayOpKind;
+use crate::data::memory::MemOpKind;
+use crate::data::reference::RefKind;
+use crate::function::external::{CallConv, Linkage, LinkageKind};
+use crate::graph::flat::{GraphBuilder, GraphNode, LifetimeNode};
+use crate::primitive::bits::BinOp;
+use crate::value::ValId;
+use std::convert::{TryFrom, TryInto};
+
+/**
+A reader for serialized `rain` graphs.
+
+Every region and value read is re-hash-consed, and checked by the same constructors used to build it in the first
+place, so malformed streams yield an error rather than an invalid graph.
+*/
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    /// The bytes left to read
+    bytes: &'a [u8],
+    /// The nodes read so far
+    nodes: Vec<GraphNode>,
+}
+
+impl<'a> Reader<'a> {
+    /// Create a new reader over a byte stream, checking its header
+    pub fn new(bytes: &'a [u8]) -> Result<Reader<'a>, DecodeError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let mut reader = Reader {
+            bytes: &bytes[MAGIC.len()..],
+            nodes: Vec::new(),
+        };
+        let version = reader.uint()?.try_into().unwrap_or(u64::MAX);
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        Ok(reader)
+    }
+    /// Read the records of the stream, returning its root values
+    pub fn read(mut self) -> Result<Vec<ValId>, DecodeError> {
+        loop {
+            match self.byte()? {
+                tag::END => break,
+                tag => {
+                    let node = self.node(tag)?;
+                    self.nodes.push(node)
+                }
+            }
+        }
+        let roots = self.indices()?;
+        if !self.bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        let builder = GraphBuilder::build(&self.nodes)?;
+        Ok(roots
+            .into_iter()
+            .map(|root| builder.val(root))
+            .collect::<Result<_, _>>()?)
+    }
+    /// Read a byte
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (byte, rest) = self.bytes.split_first().ok_or(DecodeError::UnexpectedEof)?;
+        self.bytes = rest;
+        Ok(*byte)
+    }
+    /// Read a boolean
+    fn flag(&mut self) -> Result<bool, DecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::InvalidRecord),
+        }
+    }
+    /// Read an unsigned integer, encoded as a LEB128 varint
+    fn uint(&mut self) -> Result<u128, DecodeError> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 128 || (shift > 121 && byte >> (128 - shift) != 0) {
+                return Err(DecodeError::InvalidRecord);
+            }
+            result |= ((byte & 0x7f) as u128) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+    /// Read an unsigned integer fitting in a given type
+    fn small<T: TryFrom<u128>>(&mut self) -> Result<T, DecodeError> {
+        self.uint()?
+            .try_into()
+            .map_err(|_| DecodeError::InvalidRecord)
+    }
+    /// Read a reference to a node read previously
+    fn index(&mut self) -> Result<usize, DecodeError> {
+        let ix: u64 = self.small()?;
+        if ix < self.nodes.len() as u64 {
+            Ok(ix as usize)
+        } else {
+            Err(DecodeError::InvalidIndex(ix))
+        }
+    }
+    /// Read a list of references to nodes read previously
+    fn indices(&mut self) -> Result<Vec<usize>, DecodeError> {
+        let len: usize = self.small()?;
+        (0..len).map(|_| self.index()).collect()
+    }
+    /// Read a list of small integers
+    fn smalls(&mut self) -> Result<Vec<usize>, DecodeError> {
+        let len: usize = self.small()?;
+        (0..len).map(|_| self.small()).collect()
+    }
+    /// Read a reference to a region: `0` for the null region, and one plus its index otherwise
+    fn region(&mut self) -> Result<Option<usize>, DecodeError> {
+        let ix: u64 = self.small()?;
+        match ix.checked_sub(1) {
+            None => Ok(None),
+            Some(ix) if ix < self.nodes.len() as u64 => Ok(Some(ix as usize)),
+            Some(ix) => Err(DecodeError::InvalidIndex(ix)),
+        }
+    }
+    /// Read a string
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len: usize = self.small()?;
+        if len > self.bytes.len() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (string, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(string.to_vec()).map_err(|_| DecodeError::InvalidRecord)
+    }
+    /// Read the kind of a reference
+    fn ref_kind(&mut self) -> Result<RefKind, DecodeError> {
+        match self.byte()? {
+            0 => Ok(RefKind::Shared),
+            1 => Ok(RefKind::Unique),
+            _ => Err(DecodeError::InvalidRecord),
+        }
+    }
+    /// Read a lifetime's region and borrows
+    fn lifetime(&mut self) -> Result<LifetimeNode, DecodeError> {
+        let region = self.region()?;
+        let lender = self.indices()?;
+        let transient = self.indices()?;
+        let len: usize = self.small()?;
+        let params = (0..len).map(|_| self.indices()).collect::<Result<_, _>>()?;
+        Ok(LifetimeNode {
+            region,
+            lender,
+            transient,
+            params,
+        })
+    }
+    /// Read a record with a given tag
+    fn node(&mut self, tag: u8) -> Result<GraphNode, DecodeError> {
+        let node = match tag {
+            tag::REGION => GraphNode::Region {
+                parent: self.region()?,
+                params: self.indices()?,
+            },
+            tag::SEXPR => GraphNode::Sexpr(self.indices()?),
+            tag::PARAMETER => GraphNode::Parameter {
+                region: self.index()?,
+                ix: self.small()?,
+            },
+            tag::TUPLE => GraphNode::Tuple {
+                elems: self.indices()?,
+                anchor: self.flag()?,
+            },
+            tag::PRODUCT => GraphNode::Product {
+                elems: self.indices()?,
+                anchor: self.flag()?,
+                flare: self.flag()?,
+            },
+            tag::PROP => GraphNode::Prop,
+            tag::FIN => GraphNode::Fin,
+            tag::SET => GraphNode::Set(self.small()?),
+            tag::BITS_KIND => GraphNode::BitsKind,
+            tag::BITS_TY => GraphNode::BitsTy(self.small()?),
+            tag::BITS => GraphNode::Bits {
+                len: self.small()?,
+                data: self.uint()?,
+            },
+            tag::BOOL_TY => GraphNode::BoolTy,
+            tag::FALSE => GraphNode::Bool(false),
+            tag::TRUE => GraphNode::Bool(true),
+            tag::FINITE => GraphNode::Finite(self.uint()?),
+            tag::INDEX => GraphNode::Index {
+                ty: self.uint()?,
+                ix: self.uint()?,
+            },
+            tag::PI => GraphNode::Pi {
+                region: self.index()?,
+                result: self.index()?,
+                lenders: self.smalls()?,
+                transients: self.smalls()?,
+            },
+            tag::LAMBDA => GraphNode::Lambda {
+                region: self.index()?,
+                result: self.index()?,
+            },
+            tag::TERNARY => GraphNode::Ternary {
+                kind: match self.byte()? {
+                    0 => TernaryKind::Bool,
+                    1 => TernaryKind::Switch,
+                    _ => return Err(DecodeError::InvalidRecord),
+                },
+                high: self.index()?,
+                low: self.index()?,
+            },
+            tag::PHI => GraphNode::Phi {
+                region: self.index()?,
+                values: self.indices()?,
+            },
+            tag::LOGICAL => GraphNode::Logical {
+                arity: self.byte()?,
+                data: self.uint()?,
+            },
+            tag::ID => GraphNode::Id {
+                left: self.index()?,
+                right: self.index()?,
+            },
+            tag::REFL => GraphNode::Refl(self.index()?),
+            tag::ID_FAMILY => GraphNode::IdFamily(self.index()?),
+            tag::PATH_IND => GraphNode::PathInd {
+                base_tys: self.indices()?,
+                target: self.index()?,
+            },
+            tag::ADD => GraphNode::BinOp(BinOp::Add),
+            tag::SUB => GraphNode::BinOp(BinOp::Sub),
+            tag::MOD => GraphNode::BinOp(BinOp::Mod),
+            tag::MUL => GraphNode::BinOp(BinOp::Mul),
+            tag::NEG => GraphNode::Neg,
+            tag::REF_TY => GraphNode::RefTy {
+                referent: self.index()?,
+                lifetime: self.lifetime()?,
+                kind: self.ref_kind()?,
+            },
+            tag::BORROW => GraphNode::Borrow {
+                source: self.index()?,
+                kind: self.ref_kind()?,
+                reborrow: self.flag()?,
+            },
+            tag::DEREFERENCE => GraphNode::Dereference(self.index()?),
+            tag::ALLOC_TY => GraphNode::AllocTy(self.index()?),
+            tag::MEM_OP => GraphNode::MemOp {
+                kind: match self.byte()? {
+                    0 => MemOpKind::Alloc,
+                    1 => MemOpKind::Free,
+                    2 => MemOpKind::Load,
+                    3 => MemOpKind::Store,
+                    _ => return Err(DecodeError::InvalidRecord),
+                },
+                ty: self.index()?,
+            },
+            tag::WORLD => GraphNode::World,
+            tag::EFFECT => GraphNode::Effect {
+                name: self.string()?,
+                param_tys: self.indices()?,
+                result: self.index()?,
+            },
+            tag::ARRAY_TY => GraphNode::ArrayTy {
+                elem: self.index()?,
+                len: self.uint()?,
+            },
+            tag::ARRAY => GraphNode::Array {
+                elems: self.indices()?,
+                elem_ty: self.index()?,
+            },
+            tag::ARRAY_OP => GraphNode::ArrayOp {
+                kind: match self.byte()? {
+                    0 => ArrayOpKind::Get,
+                    1 => ArrayOpKind::Set,
+                    2 => ArrayOpKind::Map,
+                    3 => ArrayOpKind::Fold,
+                    4 => ArrayOpKind::Repeat,
+                    _ => return Err(DecodeError::InvalidRecord),
+                },
+                array_ty: self.index()?,
+                aux_ty: self.index()?,
+            },
+            tag::EXTERN => GraphNode::Extern {
+                name: self.string()?,
+                ty: self.index()?,
+                linkage: Linkage {
+                    kind: match self.byte()? {
+                        0 => LinkageKind::External,
+                        1 => LinkageKind::Weak,
+                        2 => LinkageKind::Internal,
+                        _ => return Err(DecodeError::InvalidRecord),
+                    },
+                    conv: match self.byte()? {
+                        0 => CallConv::Rain,
+                        1 => CallConv::C,
+                        _ => return Err(DecodeError::InvalidRecord),
+                    },
+                },
+            },
+            tag::THUNK => GraphNode::Thunk(self.indices()?),
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        };
+        Ok(node)
+    }
+}
@@ -0,0 +1,317 @@
+/*!
+Serializing `rain` value graphs
+*/
+use super::{tag, FORMAT_VERSION, MAGIC};
+use crate::control::ternary::TernaryKind;
+use crate::data::array::ArrayOpKind;
+use crate::data::memory::MemOpKind;
+use crate::data::reference::RefKind;
+use crate::function::external::{CallConv, LinkageKind};
+use crate::graph::flat::{GraphNode, GraphWriter, LifetimeNode};
+use crate::primitive::bits::BinOp;
+use crate::value::ValId;
+
+/// Append an unsigned integer to a buffer as a LEB128 varint
+fn put_uint(buf: &mut Vec<u8>, mut n: u128) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/**
+A writer for serialized `rain` graphs.
+
+Each root value written is serialized along with every region and value it depends on, each of which is written
+exactly once, after everything it depends on.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Writer {
+    /// The graph of the values written so far
+    graph: GraphWriter,
+    /// The indices of the root values written so far
+    roots: Vec<usize>,
+}
+
+impl Writer {
+    /// Create a new writer
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+    /// Write a root value, returning its index among the roots written
+    pub fn write(&mut self, value: &ValId) -> usize {
+        let ix = self.graph.value(value);
+        self.roots.push(ix);
+        self.roots.len() - 1
+    }
+    /// Finish writing, returning the serialized graph
+    pub fn finish(self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        put_uint(&mut buf, FORMAT_VERSION as u128);
+        for node in self.graph.nodes() {
+            put_node(&mut buf, node);
+        }
+        buf.push(tag::END);
+        put_list(&mut buf, &self.roots);
+        buf
+    }
+}
+
+/// Append a record describing a node of a value graph to a buffer
+fn put_node(buf: &mut Vec<u8>, node: &GraphNode) {
+    match node {
+        GraphNode::Region { parent, params } => {
+            buf.push(tag::REGION);
+            put_region(buf, *parent);
+            put_list(buf, params);
+        }
+        GraphNode::Sexpr(args) => {
+            buf.push(tag::SEXPR);
+            put_list(buf, args);
+        }
+        GraphNode::Parameter { region, ix } => {
+            buf.push(tag::PARAMETER);
+            put_uint(buf, *region as u128);
+            put_uint(buf, *ix as u128);
+        }
+        GraphNode::Tuple { elems, anchor } => {
+            buf.push(tag::TUPLE);
+            put_list(buf, elems);
+            buf.push(*anchor as u8);
+        }
+        GraphNode::Product {
+            elems,
+            anchor,
+            flare,
+        } => {
+            buf.push(tag::PRODUCT);
+            put_list(buf, elems);
+            buf.push(*anchor as u8);
+            buf.push(*flare as u8);
+        }
+        GraphNode::Prop => buf.push(tag::PROP),
+        GraphNode::Fin => buf.push(tag::FIN),
+        GraphNode::Set(level) => {
+            buf.push(tag::SET);
+            put_uint(buf, *level as u128);
+        }
+        GraphNode::BitsKind => buf.push(tag::BITS_KIND),
+        GraphNode::BitsTy(len) => {
+            buf.push(tag::BITS_TY);
+            put_uint(buf, *len as u128);
+        }
+        GraphNode::Bits { len, data } => {
+            buf.push(tag::BITS);
+            put_uint(buf, *len as u128);
+            put_uint(buf, *data);
+        }
+        GraphNode::BoolTy => buf.push(tag::BOOL_TY),
+        GraphNode::Bool(false) => buf.push(tag::FALSE),
+        GraphNode::Bool(true) => buf.push(tag::TRUE),
+        GraphNode::Finite(n) => {
+            buf.push(tag::FINITE);
+            put_uint(buf, *n);
+        }
+        GraphNode::Index { ty, ix } => {
+            buf.push(tag::INDEX);
+            put_uint(buf, *ty);
+            put_uint(buf, *ix);
+        }
+        GraphNode::Pi {
+            region,
+            result,
+            lenders,
+            transients,
+        } => {
+            buf.push(tag::PI);
+            put_uint(buf, *region as u128);
+            put_uint(buf, *result as u128);
+            put_list(buf, lenders);
+            put_list(buf, transients);
+        }
+        GraphNode::Lambda { region, result } => {
+            buf.push(tag::LAMBDA);
+            put_uint(buf, *region as u128);
+            put_uint(buf, *result as u128);
+        }
+        GraphNode::Ternary { kind, high, low } => {
+            buf.push(tag::TERNARY);
+            buf.push(match kind {
+                TernaryKind::Bool => 0,
+                TernaryKind::Switch => 1,
+            });
+            put_uint(buf, *high as u128);
+            put_uint(buf, *low as u128);
+        }
+        GraphNode::Phi { region, values } => {
+            buf.push(tag::PHI);
+            put_uint(buf, *region as u128);
+            put_list(buf, values);
+        }
+        GraphNode::Logical { arity, data } => {
+            buf.push(tag::LOGICAL);
+            buf.push(*arity);
+            put_uint(buf, *data);
+        }
+        GraphNode::Id { left, right } => {
+            buf.push(tag::ID);
+            put_uint(buf, *left as u128);
+            put_uint(buf, *right as u128);
+        }
+        GraphNode::Refl(value) => {
+            buf.push(tag::REFL);
+            put_uint(buf, *value as u128);
+        }
+        GraphNode::IdFamily(kind) => {
+            buf.push(tag::ID_FAMILY);
+            put_uint(buf, *kind as u128);
+        }
+        GraphNode::PathInd { base_tys, target } => {
+            buf.push(tag::PATH_IND);
+            put_list(buf, base_tys);
+            put_uint(buf, *target as u128);
+        }
+        GraphNode::BinOp(BinOp::Add) => buf.push(tag::ADD),
+        GraphNode::BinOp(BinOp::Sub) => buf.push(tag::SUB),
+        GraphNode::BinOp(BinOp::Mod) => buf.push(tag::MOD),
+        GraphNode::BinOp(BinOp::Mul) => buf.push(tag::MUL),
+        GraphNode::Neg => buf.push(tag::NEG),
+        GraphNode::RefTy {
+            referent,
+            lifetime,
+            kind,
+        } => {
+            buf.push(tag::REF_TY);
+            put_uint(buf, *referent as u128);
+            put_lifetime(buf, lifetime);
+            buf.push(ref_kind(*kind));
+        }
+        GraphNode::Borrow {
+            source,
+            kind,
+            reborrow,
+        } => {
+            buf.push(tag::BORROW);
+            put_uint(buf, *source as u128);
+            buf.push(ref_kind(*kind));
+            buf.push(*reborrow as u8);
+        }
+        GraphNode::Dereference(reference) => {
+            buf.push(tag::DEREFERENCE);
+            put_uint(buf, *reference as u128);
+        }
+        GraphNode::AllocTy(ty) => {
+            buf.push(tag::ALLOC_TY);
+            put_uint(buf, *ty as u128);
+        }
+        GraphNode::MemOp { kind, ty } => {
+            buf.push(tag::MEM_OP);
+            buf.push(match kind {
+                MemOpKind::Alloc => 0,
+                MemOpKind::Free => 1,
+                MemOpKind::Load => 2,
+                MemOpKind::Store => 3,
+            });
+            put_uint(buf, *ty as u128);
+        }
+        GraphNode::World => buf.push(tag::WORLD),
+        GraphNode::Effect {
+            name,
+            param_tys,
+            result,
+        } => {
+            buf.push(tag::EFFECT);
+            put_str(buf, name);
+            put_list(buf, param_tys);
+            put_uint(buf, *result as u128);
+        }
+        GraphNode::ArrayTy { elem, len } => {
+            buf.push(tag::ARRAY_TY);
+            put_uint(buf, *elem as u128);
+            put_uint(buf, *len);
+        }
+        GraphNode::Array { elems, elem_ty } => {
+            buf.push(tag::ARRAY);
+            put_list(buf, elems);
+            put_uint(buf, *elem_ty as u128);
+        }
+        GraphNode::ArrayOp {
+            kind,
+            array_ty,
+            aux_ty,
+        } => {
+            buf.push(tag::ARRAY_OP);
+            buf.push(match kind {
+                ArrayOpKind::Get => 0,
+                ArrayOpKind::Set => 1,
+                ArrayOpKind::Map => 2,
+                ArrayOpKind::Fold => 3,
+                ArrayOpKind::Repeat => 4,
+            });
+            put_uint(buf, *array_ty as u128);
+            put_uint(buf, *aux_ty as u128);
+        }
+        GraphNode::Extern { name, ty, linkage } => {
+            buf.push(tag::EXTERN);
+            put_str(buf, name);
+            put_uint(buf, *ty as u128);
+            buf.push(match linkage.kind {
+                LinkageKind::External => 0,
+                LinkageKind::Weak => 1,
+                LinkageKind::Internal => 2,
+            });
+            buf.push(match linkage.conv {
+                CallConv::Rain => 0,
+                CallConv::C => 1,
+            });
+        }
+        GraphNode::Thunk(args) => {
+            buf.push(tag::THUNK);
+            put_list(buf, args);
+        }
+    }
+}
+
+/// Append a reference to a region to a buffer: `0` for the null region, and one plus its index otherwise
+fn put_region(buf: &mut Vec<u8>, region: Option<usize>) {
+    put_uint(buf, region.map(|ix| ix as u128 + 1).unwrap_or(0))
+}
+
+/// Append a lifetime's region and borrows to a buffer
+fn put_lifetime(buf: &mut Vec<u8>, lifetime: &LifetimeNode) {
+    put_region(buf, lifetime.region);
+    put_list(buf, &lifetime.lender);
+    put_list(buf, &lifetime.transient);
+    put_uint(buf, lifetime.params.len() as u128);
+    for param in lifetime.params.iter() {
+        put_list(buf, param);
+    }
+}
+
+/// Append a list of indices to a buffer, prefixed by its length
+fn put_list(buf: &mut Vec<u8>, list: &[usize]) {
+    put_uint(buf, list.len() as u128);
+    for ix in list {
+        put_uint(buf, *ix as u128);
+    }
+}
+
+/// Append a string to a buffer, prefixed by its length
+fn put_str(buf: &mut Vec<u8>, string: &str) {
+    put_uint(buf, string.len() as u128);
+    buf.extend_from_slice(string.as_bytes());
+}
+
+/// Encode the kind of a reference
+#[inline]
+fn ref_kind(kind: RefKind) -> u8 {
+    match kind {
+        RefKind::Shared => 0,
+        RefKind::Unique => 1,
+    }
+}
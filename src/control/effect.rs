@@ -0,0 +1,456 @@
+/*!
+World tokens and effectful primitives
+
+Side effects are ordered by threading a linear world token through every effectful operation, as in the state edges of
+an RVSDG: since each effectful operation consumes a world token and produces a new one, two otherwise identical
+effectful operations are never merged by hash-consing.
+
+Memory operations thread a separate [memory state](crate::data::memory::MEM_TY) instead. To order memory effects with
+respect to other side effects, a world token is explicitly converted into a memory state with
+[`enter_memory`](enter_memory), and back with [`leave_memory`](leave_memory).
+*/
+use super::ternary::Ternary;
+use crate::data::memory::MEM_TY;
+use crate::eval::Apply;
+use crate::function::{external::Extern, lambda::Lambda, pi::Pi};
+use crate::lifetime::Live;
+use crate::region::{Region, Regional};
+use crate::typing::{primitive::PROP, Type, Typed};
+use crate::value::{
+    arr::TyArr,
+    expr::Sexpr,
+    tuple::{Product, Tuple},
+    Error, NormalValue, TypeId, TypeRef, ValId, Value, ValueEnum, VarId,
+};
+use crate::{
+    debug_from_display, enum_convert, pretty_display, quick_pretty, trivial_substitute, tyarr,
+    valarr,
+};
+use lazy_static::lazy_static;
+use std::iter::once;
+
+/// The type of world tokens, which are linear
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct World;
+
+lazy_static! {
+    /// A reference to the type of world tokens
+    pub static ref WORLD_TY: VarId<World> = VarId::direct_new(World);
+    /// The conversion of a world token into a memory state, `#world -> Mem`
+    pub static ref ENTER_MEMORY: VarId<Extern> =
+        token_conversion("rain_enter_memory", WORLD_TY.clone_as_ty(), MEM_TY.clone_as_ty());
+    /// The conversion of a memory state back into a world token, `Mem -> #world`
+    pub static ref LEAVE_MEMORY: VarId<Extern> =
+        token_conversion("rain_leave_memory", MEM_TY.clone_as_ty(), WORLD_TY.clone_as_ty());
+}
+
+/// Declare the conversion of one state token into another
+fn token_conversion(name: &str, from: TypeId, to: TypeId) -> VarId<Extern> {
+    let region = Region::with(tyarr![from], Region::NULL).expect("State token types are closed");
+    let ty = Pi::try_new(to, region)
+        .expect("Token conversions have valid types")
+        .into_var();
+    Extern::c_fn(name, ty)
+        .expect("Token conversions have closed types")
+        .into_var()
+}
+
+debug_from_display!(World);
+quick_pretty!(World, "#world");
+trivial_substitute!(World);
+enum_convert! {
+    impl InjectionRef<ValueEnum> for World {}
+    impl TryFrom<NormalValue> for World { as ValueEnum, }
+    impl TryFromRef<NormalValue> for World { as ValueEnum, }
+}
+
+impl Live for World {}
+
+impl Typed for World {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        PROP.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        true
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Apply for World {}
+
+impl Value for World {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        0
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        panic!("World has no dependencies (asked for dependency #{})", ix)
+    }
+    #[inline]
+    fn dep_owned(&self, ix: usize) -> bool {
+        panic!("World has no dependencies (asked for dependency #{})", ix)
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::World(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+    #[inline]
+    fn into_val(self) -> ValId {
+        WORLD_TY.clone_val()
+    }
+}
+
+impl Type for World {
+    #[inline]
+    fn is_affine(&self) -> bool {
+        true
+    }
+    #[inline]
+    fn is_relevant(&self) -> bool {
+        true
+    }
+}
+
+impl From<World> for NormalValue {
+    #[inline]
+    fn from(world: World) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::World(world))
+    }
+}
+
+/// Check whether a type is the type of an effectful function, i.e. a pi type taking a world token as its first parameter
+#[inline]
+pub fn is_effectful(ty: &ValueEnum) -> bool {
+    match ty {
+        ValueEnum::Pi(pi) => match pi.param_tys().first().map(|ty| ty.as_enum()) {
+            Some(ValueEnum::World(_)) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/**
+An effectful primitive: an external function declaration which threads a world token.
+
+An effectful primitive taking parameters `A, B, ...` and returning `R` is declared as an external function of type
+`#world -> A -> B -> ... -> [#world R]`, and hence always evaluates symbolically, and is bound to an implementation
+like any other external function.
+*/
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Effect {
+    /// The external declaration of this effectful primitive
+    decl: Extern,
+}
+
+impl Effect {
+    /// Declare a new effectful primitive with a given name, parameter types and result type.
+    /// Return an error if the resulting type is not closed.
+    pub fn try_new(name: &str, param_tys: &[TypeId], result: TypeId) -> Result<Effect, Error> {
+        let parent = result.gcrs(param_tys.iter())?.clone_region();
+        let param_tys: TyArr = once(WORLD_TY.clone_as_ty())
+            .chain(param_tys.iter().cloned())
+            .collect();
+        let def_region = Region::with(param_tys, parent)?;
+        let result = Product::try_new(tyarr![WORLD_TY.clone_as_ty(), result])?.into_ty();
+        let ty = Pi::try_new(result, def_region)?.into_var();
+        Ok(Effect {
+            decl: Extern::c_fn(name, ty)?,
+        })
+    }
+    /// Get the name of this effectful primitive
+    #[inline]
+    pub fn name(&self) -> &str {
+        self.decl.name()
+    }
+    /// Get the type of this effectful primitive as a guaranteed pi type
+    #[inline]
+    pub fn get_ty(&self) -> &VarId<Pi> {
+        self.decl.get_ty()
+    }
+    /// Get the external declaration of this effectful primitive
+    #[inline]
+    pub fn decl(&self) -> &Extern {
+        &self.decl
+    }
+}
+
+impl Typed for Effect {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.decl.ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        false
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for Effect {}
+
+impl Apply for Effect {}
+
+impl Value for Effect {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => self.get_ty().as_val(),
+            ix => panic!("Invalid index into an effect's dependencies: {}", ix),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::Effect(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<Effect> for NormalValue {
+    #[inline]
+    fn from(effect: Effect) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::Effect(effect))
+    }
+}
+
+trivial_substitute!(Effect);
+debug_from_display!(Effect);
+pretty_display!(Effect, "#effect {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for Effect {}
+    impl TryFrom<NormalValue> for Effect { as ValueEnum, }
+    impl TryFromRef<NormalValue> for Effect { as ValueEnum, }
+}
+
+/**
+Thread a world token through a lambda function.
+
+Given a function `|x: A, ...| e`, return the effectful function `|w: #world, x: A, ...| [w e]`, which may then be
+composed with other effectful functions.
+*/
+pub fn thread_lambda(f: &Lambda) -> Result<Lambda, Error> {
+    let param_tys: TyArr = once(WORLD_TY.clone_as_ty())
+        .chain(f.def_region().param_tys().iter().cloned())
+        .collect();
+    let region = Region::with(param_tys, f.def_region().parent().clone())?;
+    let mut params = region.params().map(|param| param.into_val());
+    let world = params.next().expect("Threaded region has a world token");
+    let args: Vec<ValId> = params.collect();
+    let result = f.applied(&args)?;
+    let result = Tuple::try_new(valarr![world, result])?.into_val();
+    Lambda::try_new(result, region)
+}
+
+/**
+Thread a world token through a conditional between two effectful branches.
+
+Given a boolean condition, a world token and two effectful functions taking only a world token, apply `high` to the
+world token if `cond` is true and `low` otherwise, yielding the result of the branch taken.
+Return an error if `world` is not a world token or either branch is not effectful.
+*/
+pub fn thread_ternary(cond: ValId, world: ValId, high: ValId, low: ValId) -> Result<ValId, Error> {
+    match world.ty().as_enum() {
+        ValueEnum::World(_) => {}
+        _ => return Err(Error::TypeMismatch),
+    }
+    if !is_effectful(high.ty().as_enum()) || !is_effectful(low.ty().as_enum()) {
+        return Err(Error::TypeMismatch);
+    }
+    let ternary = Ternary::conditional(high, low)?.into_val();
+    Ok(Sexpr::try_new(vec![ternary, cond, world])?.into_val())
+}
+
+/**
+Convert a world token into a memory state, to be threaded through memory operations.
+
+Since the world token is consumed, memory operations threading the resulting memory state are ordered after every side
+effect the world token was threaded through. Return an error if `world` is not a world token.
+*/
+pub fn enter_memory(world: ValId) -> Result<ValId, Error> {
+    match world.ty().as_enum() {
+        ValueEnum::World(_) => {}
+        _ => return Err(Error::TypeMismatch),
+    }
+    Ok(Sexpr::try_new(vec![ENTER_MEMORY.clone_val(), world])?.into_val())
+}
+
+/**
+Convert a memory state back into a world token.
+
+Since the memory state is consumed, side effects threading the resulting world token are ordered after every memory
+operation the memory state was threaded through. Return an error if `memory` is not a memory state.
+*/
+pub fn leave_memory(memory: ValId) -> Result<ValId, Error> {
+    if memory.ty() != MEM_TY.clone_as_ty() {
+        return Err(Error::TypeMismatch);
+    }
+    Ok(Sexpr::try_new(vec![LEAVE_MEMORY.clone_val(), memory])?.into_val())
+}
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use std::fmt::{self, Display, Formatter};
+
+    impl PrettyPrint for Effect {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "(#effect {:?} ", self.name())?;
+            self.get_ty().prettyprint(printer, fmt)?;
+            write!(fmt, ")")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::memory::{AllocTy, MemOp};
+    use crate::function::external::ExternBindings;
+    use crate::primitive::logical::Bool;
+    use crate::value::ErrorKind;
+
+    #[test]
+    fn effectful_calls_are_ordered() {
+        assert!(WORLD_TY.is_linear());
+        let read = Effect::try_new("read", &[], Bool.into_ty())
+            .unwrap()
+            .into_val();
+        assert!(is_effectful(read.ty().as_enum()));
+        let region = Region::binary(WORLD_TY.clone_as_ty());
+        let w0 = region.param(0).unwrap().into_val();
+        let w1 = region.param(1).unwrap().into_val();
+        let r0 = Sexpr::try_new(vec![read.clone(), w0]).unwrap().into_val();
+        let r1 = Sexpr::try_new(vec![read.clone(), w1]).unwrap().into_val();
+        assert_ne!(r0, r1);
+        let expected: TypeId = Product::try_new(tyarr![WORLD_TY.clone_as_ty(), Bool.into_ty()])
+            .unwrap()
+            .into();
+        assert_eq!(r0.ty(), expected);
+        assert_eq!(
+            read.as_enum(),
+            &ValueEnum::from(Effect::try_new("read", &[], Bool.into_ty()).unwrap())
+        );
+    }
+
+    #[test]
+    fn effects_are_external_declarations() {
+        let read = Effect::try_new("read", &[], Bool.into_ty()).unwrap();
+        assert_eq!(read.decl().name(), "read");
+        assert_eq!(read.decl().get_ty(), read.get_ty());
+        let mut bindings = ExternBindings::new();
+        bindings.bind("read", |args| {
+            Ok(Tuple::try_new(valarr![args[0].clone(), true.into_val()])?.into_val())
+        });
+        let region = Region::unary(WORLD_TY.clone_as_ty());
+        let w = region.param(0).unwrap().into_val();
+        assert_eq!(
+            bindings.call(read.decl(), &[w.clone()]),
+            Ok(Tuple::try_new(valarr![w, true.into_val()])
+                .unwrap()
+                .into_val())
+        );
+        let error = bindings.call(read.decl(), &[true.into_val()]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TypeMismatch);
+    }
+
+    #[test]
+    fn threading_through_lambdas() {
+        let id = Lambda::id(Bool.into_ty());
+        let threaded = thread_lambda(&id).unwrap();
+        assert!(is_effectful(threaded.get_ty().as_enum()));
+        let region = Region::unary(WORLD_TY.clone_as_ty());
+        let w = region.param(0).unwrap().into_val();
+        let applied = threaded.applied(&[w.clone(), true.into_val()]).unwrap();
+        assert_eq!(
+            applied,
+            Tuple::try_new(valarr![w, true.into_val()])
+                .unwrap()
+                .into_val()
+        );
+    }
+
+    #[test]
+    fn threading_through_ternaries() {
+        let branch_region = Region::unary(WORLD_TY.clone_as_ty());
+        let bw = branch_region.param(0).unwrap().into_val();
+        let branch = |b: bool| {
+            let result = Tuple::try_new(valarr![bw.clone(), b.into_val()]).unwrap();
+            Lambda::try_new(result.into_val(), branch_region.clone())
+                .unwrap()
+                .into_val()
+        };
+        let (high, low) = (branch(true), branch(false));
+
+        let region =
+            Region::with(tyarr![Bool.into_ty(), WORLD_TY.clone_as_ty()], Region::NULL).unwrap();
+        let cond = region.param(0).unwrap().into_val();
+        let w = region.param(1).unwrap().into_val();
+        let symbolic = thread_ternary(cond.clone(), w.clone(), high.clone(), low.clone()).unwrap();
+        let expected: TypeId = Product::try_new(tyarr![WORLD_TY.clone_as_ty(), Bool.into_ty()])
+            .unwrap()
+            .into();
+        assert_eq!(symbolic.ty(), expected);
+        assert_eq!(
+            thread_ternary(true.into_val(), w.clone(), high.clone(), low.clone()),
+            Ok(Tuple::try_new(valarr![w.clone(), true.into_val()])
+                .unwrap()
+                .into_val())
+        );
+        assert_eq!(
+            thread_ternary(cond.clone(), true.into_val(), high.clone(), low.clone()),
+            Err(Error::TypeMismatch)
+        );
+        assert_eq!(
+            thread_ternary(cond, w, true.into_val(), low),
+            Err(Error::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn converting_between_world_and_memory_tokens() {
+        assert!(is_effectful(ENTER_MEMORY.ty().as_enum()));
+        assert!(!is_effectful(LEAVE_MEMORY.ty().as_enum()));
+        let alloc_ty = AllocTy::try_new(Bool.into_ty()).unwrap().into_ty();
+        let region = Region::with(tyarr![WORLD_TY.clone_as_ty(), alloc_ty], Region::NULL).unwrap();
+        let w = region.param(0).unwrap().into_val();
+        let a = region.param(1).unwrap().into_val();
+        let m = enter_memory(w.clone()).unwrap();
+        assert_eq!(m.ty(), MEM_TY.clone_as_ty());
+        let free = MemOp::free(Bool.into_ty()).unwrap().into_val();
+        let m1 = Sexpr::try_new(vec![free, m.clone(), a]).unwrap().into_val();
+        let w1 = leave_memory(m1).unwrap();
+        assert_eq!(w1.ty(), WORLD_TY.clone_as_ty());
+        assert_ne!(w1, w);
+        assert_eq!(enter_memory(m), Err(Error::TypeMismatch));
+        assert_eq!(leave_memory(w), Err(Error::TypeMismatch));
+    }
+}
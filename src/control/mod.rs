@@ -7,12 +7,14 @@ This module contains the following primitive elements for describing control flo
 - [`switch`](switch) nodes for control flow on finite types
 - [`rec`](rec) nodes for control flow on `n`-ary sum types and primitive recursion
 - [`phi`](phi) nodes for arbitrary recursion, using the types in the `termination` module
+- [`effect`](effect) tokens for ordering side effects, and effectful primitives
 
 The [`termination`](termination) module describes a type system for encapsulating non-termination without introducing inconsistencies, and the
 [`nondeterministic`](nondeterministic) module describes a similar monadic type system for encapsulating non-parametric nondeterminism, as well as
 nondeterministic control flow primitives.
 */
 
+pub mod effect;
 pub mod nondeterministic;
 pub mod phi;
 pub mod rec;
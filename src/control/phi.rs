@@ -3,10 +3,14 @@ Phi nodes, describing general recursion
 */
 
 use crate::eval::{Apply, EvalCtx, Substitute};
-use crate::lifetime::{LifetimeBorrow, Live};
+use crate::function::{lambda::Lambda, pi::Pi};
+use crate::lifetime::{Lifetime, LifetimeBorrow, Live};
+use crate::primitive::finite::Finite;
+use crate::region::{Parametrized, Region};
 use crate::typing::Typed;
 use crate::value::{
     arr::{ValArr, ValSet},
+    expr::Sexpr,
     tuple::Product,
     Error, NormalValue, TypeRef, ValId, Value, ValueEnum, VarId,
 };
@@ -23,11 +27,84 @@ pub struct Phi {
     deps: ValSet,
     /// The type of this phi node as a value
     ty: VarId<Product>,
+    /// The defining region of this phi node, whose parameters stand for the recursively defined objects
+    def_region: Region,
+    /// The (cached) lifetime of this phi node
+    lifetime: Lifetime,
+}
+
+impl Phi {
+    /**
+    Create a new phi node from a nonempty array of values in a defining region, whose parameters stand for the
+    values themselves.
+
+    Return an error if the values do not lie in the defining region, or do not have the types of the corresponding
+    parameters. Function types are compared by signature.
+    */
+    pub fn try_new(values: ValArr, def_region: Region) -> Result<Phi, Error> {
+        if values.is_empty() || values.len() != def_region.len() {
+            return Err(Error::TupleLengthMismatch);
+        }
+        let mut deps = Vec::new();
+        for (ix, (value, param_ty)) in values.iter().zip(def_region.param_tys().iter()).enumerate()
+        {
+            if !Pi::ty_matches(value, param_ty) {
+                return Err(Error::type_mismatch(param_ty.clone(), value.clone_ty())
+                    .with_value(value.clone())
+                    .at_arg(ix));
+            }
+            let param = Parametrized::try_new(value.clone(), def_region.clone())?;
+            deps.extend(param.deps().iter().cloned());
+        }
+        let deps: ValSet = deps.into_iter().collect();
+        let lifetime = Lambda::deps_lifetime(&def_region, &deps)?;
+        let ty = Product::try_new(def_region.param_tys().clone())?.into();
+        Ok(Phi {
+            values,
+            deps,
+            ty,
+            def_region,
+            lifetime,
+        })
+    }
+    /// Get the recursively defined objects of this phi node
+    #[inline]
+    pub fn values(&self) -> &ValArr {
+        &self.values
+    }
+    /// Get the defining region of this phi node
+    #[inline]
+    pub fn def_region(&self) -> &Region {
+        &self.def_region
+    }
+    /// Get the type of this phi node as a guaranteed product type
+    #[inline]
+    pub fn get_ty(&self) -> &VarId<Product> {
+        &self.ty
+    }
+    /// Get the index type of the members of this phi node
+    #[inline]
+    pub fn index_ty(&self) -> Finite {
+        Finite(self.values.len() as u128)
+    }
+    /// Get a symbolic projection of this phi node, standing for its `ix`th member
+    pub fn project(&self, ix: usize) -> Result<ValId, Error> {
+        let ix = self
+            .index_ty()
+            .ix(ix as u128)
+            .map_err(|_| Error::TupleLengthMismatch)?;
+        Sexpr::try_new(vec![self.clone().into_val(), ix.into_val()]).map(Sexpr::into_val)
+    }
+    /// Get symbolic projections of every member of this phi node, in order
+    pub fn projections(&self) -> Result<Vec<ValId>, Error> {
+        (0..self.values.len()).map(|ix| self.project(ix)).collect()
+    }
 }
 
 impl Live for Phi {
+    #[inline]
     fn lifetime(&self) -> LifetimeBorrow {
-        LifetimeBorrow::STATIC
+        self.lifetime.lifetime()
     }
 }
 
@@ -99,15 +176,43 @@ enum_convert! {
 mod prettyprint_impl {
     use super::*;
     use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use crate::tokens::*;
     use std::fmt::{self, Display, Formatter};
 
     impl PrettyPrint for Phi {
         fn prettyprint<I: From<usize> + Display>(
             &self,
-            _printer: &mut PrettyPrinter<I>,
+            printer: &mut PrettyPrinter<I>,
             fmt: &mut Formatter,
         ) -> Result<(), fmt::Error> {
-            write!(fmt, "UNIMPLEMENTED!")
+            write!(fmt, "{}{}", KEYWORD_PHI, PARAM_OPEN)?;
+            let mut first = true;
+            for param in self.def_region.params() {
+                if !first {
+                    write!(fmt, " ")?;
+                }
+                first = false;
+                printer.prettyprint_index(fmt, ValId::<()>::from(param).borrow_val())?;
+            }
+            write!(fmt, "{} ", PARAM_CLOSE)?;
+            // The members of a phi node are bound in its scope, and then listed
+            printer.push_scope();
+            for value in self.values.iter() {
+                printer.prettyprint_valid_and_deps(fmt, value.borrow_val())?;
+            }
+            printer.print_tabs(fmt)?;
+            write!(fmt, "{}", TUPLE_OPEN)?;
+            let mut first = true;
+            for value in self.values.iter() {
+                if !first {
+                    write!(fmt, " ")?;
+                }
+                first = false;
+                value.prettyprint(printer, fmt)?;
+            }
+            write!(fmt, "{}", TUPLE_CLOSE)?;
+            printer.pop_scope(fmt)?;
+            Ok(())
         }
     }
 }
@@ -192,6 +192,7 @@ impl Ternary {
 }
 /// Kinds of ternary node
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TernaryKind {
     /// A boolean branch
     Bool,
@@ -325,12 +326,17 @@ impl From<Ternary> for NormalValue {
     fn from(ternary: Ternary) -> NormalValue {
         if ternary.is_const() {
             // Cast this ternary to a constant lambda
+            //FIXME: stack def region appropriately!
+            let def_region = ternary.ty.def_region().clone();
+            let deps = std::iter::once(ternary.low).collect();
+            let lifetime = Lambda::deps_lifetime(&def_region, &deps)
+                .expect("Constant lambda has a single dependency");
             NormalValue::assert_normal(ValueEnum::Lambda(Lambda {
                 result: ternary.high,
-                //FIXME: stack def region appropriately!
-                def_region: ternary.ty.def_region().clone(),
+                def_region,
                 ty: ternary.ty,
-                deps: std::iter::once(ternary.low).collect(),
+                deps,
+                lifetime,
             }))
         } else {
             NormalValue::assert_normal(ValueEnum::Ternary(ternary))
@@ -342,29 +348,42 @@ impl From<Ternary> for NormalValue {
 mod prettyprint_impl {
     use super::*;
     use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use crate::tokens::*;
     use std::fmt::{self, Display, Formatter};
 
     impl PrettyPrint for Ternary {
         fn prettyprint<I: From<usize> + Display>(
             &self,
-            _printer: &mut PrettyPrinter<I>,
+            printer: &mut PrettyPrinter<I>,
             fmt: &mut Formatter,
         ) -> Result<(), fmt::Error> {
-            write!(fmt, "#gamma")?;
             match self.ternary_kind() {
-                TernaryKind::Bool => write!(
-                    fmt,
-                    "|#bool| {{ #true => {}, #false => {} }}",
-                    self.high(),
-                    self.low()
-                ),
-                TernaryKind::Switch => write!(
-                    fmt,
-                    "|#finite(2)| {{ #ix[2](0) => {}, #ix[2](1) => {} }}",
-                    self.low(),
-                    self.high()
-                ),
+                TernaryKind::Bool => {
+                    write!(
+                        fmt,
+                        "#gamma{}{}{} {{ {} => ",
+                        PARAM_OPEN, KEYWORD_BOOL, PARAM_CLOSE, KEYWORD_TRUE
+                    )?;
+                    self.high().prettyprint(printer, fmt)?;
+                    write!(fmt, ", {} => ", KEYWORD_FALSE)?;
+                    self.low().prettyprint(printer, fmt)?;
+                }
+                TernaryKind::Switch => {
+                    let ty = Finite(2);
+                    write!(
+                        fmt,
+                        "#gamma{}{}{} {{ {} => ",
+                        PARAM_OPEN,
+                        ty,
+                        PARAM_CLOSE,
+                        ty.ix(0).expect("0 < 2")
+                    )?;
+                    self.low().prettyprint(printer, fmt)?;
+                    write!(fmt, ", {} => ", ty.ix(1).expect("1 < 2"))?;
+                    self.high().prettyprint(printer, fmt)?;
+                }
             }
+            write!(fmt, " }}")
         }
     }
 }
@@ -0,0 +1,841 @@
+/*!
+Fixed-size arrays, indexed by finite types
+*/
+use crate::eval::{Application, Apply, EvalCtx, Substitute};
+use crate::function::pi::Pi;
+use crate::lifetime::{Lifetime, LifetimeBorrow, Live};
+use crate::primitive::finite::{Finite, Index};
+use crate::region::{Region, Regional};
+use crate::typing::{Type, Typed};
+use crate::value::{
+    arr::ValArr, Error, NormalValue, TypeId, TypeRef, ValId, Value, ValueEnum, VarId,
+};
+use crate::{debug_from_display, enum_convert, pretty_display, substitute_to_valid, tyarr};
+use std::convert::TryInto;
+use std::ops::Deref;
+
+/// The type of arrays `[T; n]` of `n` values of type `T`, indexed by `#finite(n)`
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct ArrayTy {
+    /// The type of the elements of arrays of this type
+    elem: TypeId,
+    /// The type of indices into arrays of this type
+    index: VarId<Finite>,
+}
+
+impl ArrayTy {
+    /// Create a new array type with a given element type and length
+    #[inline]
+    pub fn new(elem: TypeId, len: u128) -> ArrayTy {
+        ArrayTy {
+            elem,
+            index: Finite(len).into_var(),
+        }
+    }
+    /// Get the type of the elements of arrays of this type
+    #[inline]
+    pub fn elem(&self) -> &TypeId {
+        &self.elem
+    }
+    /// Get the type of indices into arrays of this type
+    #[inline]
+    pub fn index_ty(&self) -> &VarId<Finite> {
+        &self.index
+    }
+    /// Get the length of arrays of this type
+    #[inline]
+    pub fn len(&self) -> u128 {
+        self.index.0
+    }
+    /// Check whether arrays of this type are empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Check whether a value is an index into arrays of this type. Return an error if it is not an index at all.
+    fn check_index(&self, ix: &ValId) -> Result<(), Error> {
+        match ix.ty().as_enum() {
+            ValueEnum::Finite(f) if f.0 == self.len() => Ok(()),
+            ValueEnum::Finite(_) => Err(Error::ArrayLengthMismatch),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+impl Typed for ArrayTy {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.elem.ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        true
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for ArrayTy {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.elem.region().into()
+    }
+}
+
+impl Apply for ArrayTy {}
+
+impl Type for ArrayTy {
+    #[inline]
+    fn is_affine(&self) -> bool {
+        !self.is_empty() && self.elem.is_affine()
+    }
+    #[inline]
+    fn is_relevant(&self) -> bool {
+        !self.is_empty() && self.elem.is_relevant()
+    }
+    #[inline]
+    fn apply_ty_in(&self, args: &[ValId], ctx: &mut Option<EvalCtx>) -> Result<TypeId, Error> {
+        if args.is_empty() {
+            return Ok(self.clone().into_ty());
+        }
+        self.check_index(&args[0])?;
+        self.elem.apply_ty_in(&args[1..], ctx)
+    }
+}
+
+impl Substitute for ArrayTy {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<ArrayTy, Error> {
+        Ok(ArrayTy {
+            elem: self.elem.substitute_ty(ctx)?,
+            index: self.index.clone(),
+        })
+    }
+}
+
+impl Value for ArrayTy {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => self.elem.as_val(),
+            ix => panic!("Invalid index into an array type's dependencies: {}", ix),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::ArrayTy(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<ArrayTy> for NormalValue {
+    #[inline]
+    fn from(ty: ArrayTy) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::ArrayTy(ty))
+    }
+}
+
+substitute_to_valid!(ArrayTy);
+debug_from_display!(ArrayTy);
+pretty_display!(ArrayTy, "[{{...}}; {{...}}]");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for ArrayTy {}
+    impl TryFrom<NormalValue> for ArrayTy { as ValueEnum, }
+    impl TryFromRef<NormalValue> for ArrayTy { as ValueEnum, }
+}
+
+/// An array literal
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Array {
+    /// The elements of this array
+    elems: ValArr,
+    /// The (cached) lifetime of this array
+    lifetime: Lifetime,
+    /// The (cached) type of this array
+    ty: VarId<ArrayTy>,
+}
+
+impl Array {
+    /// Try to create a new array from a list of elements of a given type.
+    /// Return an error if an element does not have this type, or elements have incompatible lifetimes.
+    pub fn try_new(elems: ValArr, elem_ty: TypeId) -> Result<Array, Error> {
+        if let Some((ix, elem)) = elems
+            .iter()
+            .enumerate()
+            .find(|(_, elem)| elem.ty() != elem_ty)
+        {
+            return Err(Error::type_mismatch(elem_ty, elem.clone_ty())
+                .with_value(elem.clone())
+                .at_arg(ix));
+        }
+        let lifetime = Lifetime::from_deps(&Region::NULL, elems.iter().map(|elem| (elem, true)))?;
+        let ty = ArrayTy::new(elem_ty, elems.len() as u128).into_var();
+        Ok(Array {
+            elems,
+            lifetime,
+            ty,
+        })
+    }
+    /// Get the type of this array as a guaranteed array type
+    #[inline]
+    pub fn get_ty(&self) -> &VarId<ArrayTy> {
+        &self.ty
+    }
+    /// Get the element of this array at a given index. Return an error if the index is for a different length.
+    #[inline]
+    pub fn get(&self, ix: &Index) -> Result<&ValId, Error> {
+        if ix.get_ty().0 != self.len() as u128 {
+            return Err(Error::ArrayLengthMismatch);
+        }
+        Ok(&self.elems[ix.ix() as usize])
+    }
+    /// Set the element of this array at a given index, yielding a new array.
+    /// Return an error if the index is for a different length, or `value` is of the wrong type.
+    pub fn set(&self, ix: &Index, value: ValId) -> Result<Array, Error> {
+        self.get(ix)?;
+        let mut elems: Vec<ValId> = self.elems.iter().cloned().collect();
+        elems[ix.ix() as usize] = value;
+        Array::try_new(elems.into(), self.ty.elem().clone())
+    }
+}
+
+impl Deref for Array {
+    type Target = ValArr;
+    #[inline]
+    fn deref(&self) -> &ValArr {
+        &self.elems
+    }
+}
+
+impl Typed for Array {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.ty.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        false
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for Array {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.lifetime.lifetime()
+    }
+}
+
+impl Apply for Array {
+    /**
+    Arrays accept indices of the appropriate finite type as arguments, which is an element access.
+    */
+    fn apply_in<'a>(
+        &self,
+        args: &'a [ValId],
+        ctx: &mut Option<EvalCtx>,
+    ) -> Result<Application<'a>, Error> {
+        if args.is_empty() {
+            return Ok(Application::Symbolic(self.clone_ty()));
+        }
+        self.ty.check_index(&args[0])?;
+        match args[0].as_enum() {
+            ValueEnum::Index(ix) => Ok(Application::Success(&args[1..], self.get(ix)?.clone())),
+            _ => self
+                .ty
+                .elem()
+                .apply_ty_in(&args[1..], ctx)
+                .map(Application::Symbolic),
+        }
+    }
+}
+
+impl Substitute for Array {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<Array, Error> {
+        let elems: ValArr = self
+            .elems
+            .iter()
+            .map(|elem| elem.substitute(ctx))
+            .collect::<Result<_, _>>()?;
+        let elem_ty = self.ty.elem().substitute_ty(ctx)?;
+        Array::try_new(elems, elem_ty)
+    }
+}
+
+impl Value for Array {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        self.len()
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        &self.elems[ix]
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        true
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::Array(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<Array> for NormalValue {
+    #[inline]
+    fn from(array: Array) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::Array(array))
+    }
+}
+
+substitute_to_valid!(Array);
+debug_from_display!(Array);
+pretty_display!(Array, "#array[...]");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for Array {}
+    impl TryFrom<NormalValue> for Array { as ValueEnum, }
+    impl TryFromRef<NormalValue> for Array { as ValueEnum, }
+}
+
+/// The kind of an array operation
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArrayOpKind {
+    /// Get an element of an array: `[T; n] -> #finite(n) -> T`
+    Get,
+    /// Set an element of an array: `[T; n] -> #finite(n) -> T -> [T; n]`
+    Set,
+    /// Map a function over an array: `(T -> U) -> [T; n] -> [U; n]`
+    Map,
+    /// Fold a function over an array, from the first element to the last: `(A -> T -> A) -> A -> [T; n] -> A`
+    Fold,
+    /// Repeat a value to fill an array: `T -> [T; n]`
+    Repeat,
+}
+
+/**
+An operation on arrays of a given type.
+
+Array operations fold when applied to array literals and, for `Get` and `Set`, constant indices.
+
+Repeating a value stays symbolic, so that the size of a repeated array does not grow with its length: getting an
+element of a repeated value folds to that value, and mapping over it folds to the repetition of the mapped value.
+*/
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct ArrayOp {
+    /// The kind of this array operation
+    kind: ArrayOpKind,
+    /// The type of arrays this operation acts on
+    ty: VarId<ArrayTy>,
+    /// The auxiliary type of this operation: the result element type of a map, the accumulator type of a fold, and
+    /// the element type otherwise
+    aux: TypeId,
+    /// The (cached) type of this array operation
+    fn_ty: VarId<Pi>,
+}
+
+impl ArrayOp {
+    /// Create a new array operation acting on arrays of a given type, with a given auxiliary type
+    ///
+    /// Return an error for a repetition which would duplicate an affine value or discard a relevant one.
+    fn try_new(kind: ArrayOpKind, ty: VarId<ArrayTy>, aux: TypeId) -> Result<ArrayOp, Error> {
+        if kind == ArrayOpKind::Repeat {
+            if ty.len() > 1 && ty.elem().is_affine() {
+                return Err(Error::AffineUsed);
+            }
+            if ty.len() == 0 && ty.elem().is_relevant() {
+                return Err(Error::RelevantUnused);
+            }
+        }
+        let parent = ty.gcr(&aux)?.clone_region();
+        let arr_ty = ty.clone_as_ty();
+        let elem = ty.elem().clone();
+        let (param_tys, result) = match kind {
+            ArrayOpKind::Get => (tyarr![arr_ty, ty.index_ty().clone_as_ty()], elem),
+            ArrayOpKind::Set => (
+                tyarr![arr_ty.clone(), ty.index_ty().clone_as_ty(), elem],
+                arr_ty,
+            ),
+            ArrayOpKind::Map => {
+                let f_region = Region::with(tyarr![elem], parent.clone())?;
+                let f_ty = Pi::try_new(aux.clone(), f_region)?.into_ty();
+                let result = ArrayTy::new(aux.clone(), ty.len()).into_ty();
+                (tyarr![f_ty, arr_ty], result)
+            }
+            ArrayOpKind::Fold => {
+                let f_region = Region::with(tyarr![aux.clone(), elem], parent.clone())?;
+                let f_ty = Pi::try_new(aux.clone(), f_region)?.into_ty();
+                (tyarr![f_ty, aux.clone(), arr_ty], aux.clone())
+            }
+            ArrayOpKind::Repeat => (tyarr![elem], arr_ty),
+        };
+        let def_region = Region::with(param_tys, parent)?;
+        let fn_ty = Pi::try_new(result, def_region)?.into_var();
+        Ok(ArrayOp {
+            kind,
+            ty,
+            aux,
+            fn_ty,
+        })
+    }
+    /// Create a new array access operation
+    #[inline]
+    pub fn get(ty: VarId<ArrayTy>) -> Result<ArrayOp, Error> {
+        let elem = ty.elem().clone();
+        Self::try_new(ArrayOpKind::Get, ty, elem)
+    }
+    /// Create a new array update operation
+    #[inline]
+    pub fn set(ty: VarId<ArrayTy>) -> Result<ArrayOp, Error> {
+        let elem = ty.elem().clone();
+        Self::try_new(ArrayOpKind::Set, ty, elem)
+    }
+    /// Create a new array map operation, mapping into arrays with elements of type `target`
+    #[inline]
+    pub fn map(ty: VarId<ArrayTy>, target: TypeId) -> Result<ArrayOp, Error> {
+        Self::try_new(ArrayOpKind::Map, ty, target)
+    }
+    /// Create a new array fold operation, with an accumulator of type `acc`
+    #[inline]
+    pub fn fold(ty: VarId<ArrayTy>, acc: TypeId) -> Result<ArrayOp, Error> {
+        Self::try_new(ArrayOpKind::Fold, ty, acc)
+    }
+    /// Create a new array repetition operation. Return an error if the element type is affine and the array holds more
+    /// than one element, or is relevant and the array is empty.
+    #[inline]
+    pub fn repeat(ty: VarId<ArrayTy>) -> Result<ArrayOp, Error> {
+        let elem = ty.elem().clone();
+        Self::try_new(ArrayOpKind::Repeat, ty, elem)
+    }
+    /// Get the kind of this array operation
+    #[inline]
+    pub fn kind(&self) -> ArrayOpKind {
+        self.kind
+    }
+    /// Get the type of arrays this operation acts on
+    #[inline]
+    pub fn array_ty(&self) -> &VarId<ArrayTy> {
+        &self.ty
+    }
+    /// Get the auxiliary type of this array operation: the result element type of a map, the accumulator type of a
+    /// fold, and the element type otherwise
+    #[inline]
+    pub fn aux_ty(&self) -> &TypeId {
+        &self.aux
+    }
+    /// Get the type of this array operation as a guaranteed pi type
+    #[inline]
+    pub fn get_ty(&self) -> &VarId<Pi> {
+        &self.fn_ty
+    }
+    /// Get the value repeated by an array, if it is a repetition
+    fn repeated(arr: &ValId) -> Option<&ValId> {
+        match arr.as_enum() {
+            ValueEnum::Sexpr(s) if s.len() == 2 => match s[0].as_enum() {
+                ValueEnum::ArrayOp(op) if op.kind == ArrayOpKind::Repeat => Some(&s[1]),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    /// Attempt to fold this operation applied to a full list of arguments, returning `None` if they are not constant
+    fn fold_constant(&self, args: &[ValId]) -> Result<Option<ValId>, Error> {
+        let folded = match self.kind {
+            ArrayOpKind::Get => match (args[0].as_enum(), args[1].as_enum()) {
+                (ValueEnum::Array(arr), ValueEnum::Index(ix)) => Some(arr.get(ix)?.clone()),
+                _ => Self::repeated(&args[0]).cloned(),
+            },
+            ArrayOpKind::Set => match (args[0].as_enum(), args[1].as_enum()) {
+                (ValueEnum::Array(arr), ValueEnum::Index(ix)) => {
+                    Some(arr.set(ix, args[2].clone())?.into_val())
+                }
+                _ => None,
+            },
+            ArrayOpKind::Map => match args[1].as_enum() {
+                ValueEnum::Array(arr) => {
+                    let elems: ValArr = arr
+                        .iter()
+                        .map(|elem| args[0].applied(&[elem.clone()]))
+                        .collect::<Result<_, _>>()?;
+                    Some(Array::try_new(elems, self.aux.clone())?.into_val())
+                }
+                _ => match Self::repeated(&args[1]) {
+                    Some(elem) => {
+                        let mapped = args[0].applied(&[elem.clone()])?;
+                        let ty = ArrayTy::new(self.aux.clone(), self.ty.len()).into_var();
+                        Some(ArrayOp::repeat(ty)?.into_val().applied(&[mapped])?)
+                    }
+                    None => None,
+                },
+            },
+            ArrayOpKind::Fold => match args[2].as_enum() {
+                ValueEnum::Array(arr) => {
+                    let mut acc = args[1].clone();
+                    for elem in arr.iter() {
+                        acc = args[0].applied(&[acc, elem.clone()])?;
+                    }
+                    Some(acc)
+                }
+                _ => None,
+            },
+            ArrayOpKind::Repeat if self.ty.is_empty() => {
+                Some(Array::try_new(ValArr::EMPTY, self.aux.clone())?.into_val())
+            }
+            ArrayOpKind::Repeat => None,
+        };
+        Ok(folded)
+    }
+}
+
+impl Typed for ArrayOp {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.fn_ty.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        false
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for ArrayOp {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.fn_ty.def_region().parent().region().into()
+    }
+}
+
+impl Apply for ArrayOp {
+    fn apply_in<'a>(
+        &self,
+        args: &'a [ValId],
+        ctx: &mut Option<EvalCtx>,
+    ) -> Result<Application<'a>, Error> {
+        // Null evaluation
+        if args.is_empty() {
+            return Ok(Application::Symbolic(self.clone_ty()));
+        }
+        let param_tys = self.fn_ty.param_tys();
+        for (ix, (arg, param_ty)) in args.iter().zip(param_tys.iter()).enumerate() {
+            if !Pi::ty_matches(arg, param_ty) {
+                return Err(Error::type_mismatch(param_ty.clone(), arg.clone_ty())
+                    .with_value(arg.clone())
+                    .at_arg(ix));
+            }
+        }
+        // Partial application
+        let n = param_tys.len();
+        if args.len() < n {
+            let region = Region::with(
+                param_tys[args.len()..].iter().cloned().collect(),
+                self.fn_ty.def_region().parent().clone(),
+            )?;
+            let pi = Pi::try_new(self.fn_ty.result().clone(), region)?;
+            return Ok(Application::Symbolic(pi.into_ty()));
+        }
+        // Full application
+        if let Some(value) = self.fold_constant(&args[..n])? {
+            return Ok(Application::Success(&args[n..], value));
+        }
+        self.fn_ty
+            .result()
+            .apply_ty_in(&args[n..], ctx)
+            .map(Application::Symbolic)
+    }
+}
+
+impl Substitute for ArrayOp {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<ArrayOp, Error> {
+        let ty: VarId<ArrayTy> = self
+            .ty
+            .substitute(ctx)?
+            .try_into()
+            .map_err(|_val| Error::InvalidSubKind)?;
+        let aux = self.aux.substitute_ty(ctx)?;
+        ArrayOp::try_new(self.kind, ty, aux)
+    }
+}
+
+impl Value for ArrayOp {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        2
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => self.ty.as_val(),
+            1 => self.aux.as_val(),
+            ix => panic!(
+                "Invalid index into an array operation's dependencies: {}",
+                ix
+            ),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::ArrayOp(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<ArrayOp> for NormalValue {
+    #[inline]
+    fn from(op: ArrayOp) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::ArrayOp(op))
+    }
+}
+
+substitute_to_valid!(ArrayOp);
+debug_from_display!(ArrayOp);
+pretty_display!(ArrayOp, "#array_op {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for ArrayOp {}
+    impl TryFrom<NormalValue> for ArrayOp { as ValueEnum, }
+    impl TryFromRef<NormalValue> for ArrayOp { as ValueEnum, }
+}
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use std::fmt::{self, Display, Formatter};
+
+    impl PrettyPrint for ArrayTy {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "[")?;
+            self.elem.prettyprint(printer, fmt)?;
+            write!(fmt, "; {}]", self.len())
+        }
+    }
+
+    impl PrettyPrint for Array {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "#array")?;
+            if self.is_empty() {
+                // The element type of an empty array cannot be recovered from its elements
+                write!(fmt, "(")?;
+                self.ty.elem().prettyprint(printer, fmt)?;
+                write!(fmt, ")")?;
+            }
+            write!(fmt, "[")?;
+            let mut first = true;
+            for elem in self.iter() {
+                if !first {
+                    write!(fmt, " ")?;
+                }
+                first = false;
+                elem.prettyprint(printer, fmt)?;
+            }
+            write!(fmt, "]")
+        }
+    }
+
+    impl PrettyPrint for ArrayOp {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            match self.kind {
+                ArrayOpKind::Get => write!(fmt, "(#array_get ")?,
+                ArrayOpKind::Set => write!(fmt, "(#array_set ")?,
+                ArrayOpKind::Map => write!(fmt, "(#array_map ")?,
+                ArrayOpKind::Fold => write!(fmt, "(#array_fold ")?,
+                ArrayOpKind::Repeat => write!(fmt, "(#array_repeat ")?,
+            }
+            self.ty.prettyprint(printer, fmt)?;
+            match self.kind {
+                ArrayOpKind::Map | ArrayOpKind::Fold => {
+                    write!(fmt, " ")?;
+                    self.aux.prettyprint(printer, fmt)?;
+                }
+                _ => {}
+            }
+            write!(fmt, ")")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::lambda::Lambda;
+    use crate::primitive::logical::{Bool, Not, Or};
+    use crate::typing::layout::Layout;
+    use crate::valarr;
+    use crate::value::{expr::Sexpr, tuple::Product, ErrorKind};
+
+    fn bool_array(bits: &[bool]) -> Array {
+        let elems: ValArr = bits.iter().map(|&b| b.into_val()).collect();
+        Array::try_new(elems, Bool.into_ty()).unwrap()
+    }
+
+    #[test]
+    fn array_construction_and_access() {
+        let arr = bool_array(&[true, false, true]);
+        assert_eq!(*arr.get_ty().elem(), Bool.into_ty());
+        assert_eq!(arr.get_ty().len(), 3);
+        assert_eq!(
+            Array::try_new(
+                valarr![true.into_val(), Finite(2).into_val()],
+                Bool.into_ty()
+            ),
+            Err(Error::TypeMismatch)
+        );
+        let one = Finite(3).ix(1).unwrap().into_val();
+        let arr = arr.into_val();
+        assert_eq!(arr.applied(&[one]), Ok(false.into_val()));
+        assert_eq!(
+            arr.applied(&[Finite(4).ix(1).unwrap().into_val()]),
+            Err(Error::ArrayLengthMismatch)
+        );
+        assert_eq!(
+            Layout::of(&ArrayTy::new(Bool.into_ty(), 1024).into_val()),
+            Layout::from_size_align(1024, 1)
+        );
+    }
+
+    #[test]
+    fn constant_get_and_set_fold() {
+        let arr = bool_array(&[true, false]);
+        let ty = arr.get_ty().clone();
+        let get = ArrayOp::get(ty.clone()).unwrap().into_val();
+        let set = ArrayOp::set(ty.clone()).unwrap().into_val();
+        let zero = Finite(2).ix(0).unwrap().into_val();
+        let arr = arr.into_val();
+        assert_eq!(
+            get.applied(&[arr.clone(), zero.clone()]),
+            Ok(true.into_val())
+        );
+        let updated = set
+            .applied(&[arr.clone(), zero.clone(), false.into_val()])
+            .unwrap();
+        assert_eq!(updated, bool_array(&[false, false]).into_val());
+
+        // Symbolic indices
+        let region = Region::unary(ty.index_ty().clone_as_ty());
+        let ix = region.param(0).unwrap().into_val();
+        let symbolic = Sexpr::try_new(vec![get, arr, ix]).unwrap();
+        assert_eq!(symbolic.ty(), Bool.into_ty());
+    }
+
+    #[test]
+    fn constant_map_and_fold() {
+        let arr = bool_array(&[true, false, false]);
+        let ty = arr.get_ty().clone();
+        let arr = arr.into_val();
+        let not = Not.into_val();
+        let map = ArrayOp::map(ty.clone(), Bool.into_ty()).unwrap().into_val();
+        assert_eq!(
+            map.applied(&[not, arr.clone()]),
+            Ok(bool_array(&[false, true, true]).into_val())
+        );
+
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let or = Sexpr::try_new(vec![Or.into_val(), x, y])
+            .unwrap()
+            .into_val();
+        let or = Lambda::try_new(or, region).unwrap().into_val();
+        let fold = ArrayOp::fold(ty, Bool.into_ty()).unwrap().into_val();
+        assert_eq!(
+            fold.applied(&[or.clone(), false.into_val(), arr]),
+            Ok(true.into_val())
+        );
+        let error = fold.applied(&[false.into_val()]).unwrap_err();
+        assert_eq!(error, ErrorKind::TypeMismatch);
+        assert_eq!(error.value(), Some(&false.into_val()));
+        assert_eq!(error.context().unwrap().arg, Some(0));
+        assert_eq!(error.context().unwrap().actual, Some(Bool.into_ty()));
+    }
+
+    #[test]
+    fn repeated_arrays_stay_symbolic() {
+        let ty = ArrayTy::new(Bool.into_ty(), 1 << 20).into_var();
+        let repeat = ArrayOp::repeat(ty.clone()).unwrap().into_val();
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let repeated = repeat.applied(&[x.clone()]).unwrap();
+        assert_eq!(repeated.ty(), ty.clone_as_ty());
+        assert_eq!(repeated.deps().len(), 2);
+
+        let get = ArrayOp::get(ty.clone()).unwrap().into_val();
+        let ix = Finite(1 << 20).ix(12345).unwrap().into_val();
+        assert_eq!(get.applied(&[repeated.clone(), ix]), Ok(x.clone()));
+
+        let map = ArrayOp::map(ty, Bool.into_ty()).unwrap().into_val();
+        let not_x = Sexpr::try_new(vec![Not.into_val(), x]).unwrap().into_val();
+        assert_eq!(
+            map.applied(&[Not.into_val(), repeated]),
+            repeat.applied(&[not_x])
+        );
+
+        let empty_ty = ArrayTy::new(Bool.into_ty(), 0).into_var();
+        let empty = ArrayOp::repeat(empty_ty).unwrap().into_val();
+        assert_eq!(
+            empty.applied(&[true.into_val()]),
+            Ok(bool_array(&[]).into_val())
+        );
+    }
+
+    #[test]
+    fn repetition_respects_substructurality() {
+        let anchor_ty = Product::anchor_ty().into_ty();
+        assert!(anchor_ty.is_affine());
+        let single = ArrayTy::new(anchor_ty.clone(), 1).into_var();
+        assert!(ArrayOp::repeat(single).is_ok());
+        let pair = ArrayTy::new(anchor_ty, 2).into_var();
+        assert_eq!(
+            ArrayOp::repeat(pair).map(Value::into_val),
+            Err(Error::AffineUsed)
+        );
+        let linear_ty = Product::linear_anchor_ty().into_ty();
+        let empty = ArrayTy::new(linear_ty.clone(), 0).into_var();
+        assert_eq!(
+            ArrayOp::repeat(empty).map(Value::into_val),
+            Err(Error::RelevantUnused)
+        );
+        let single = ArrayTy::new(linear_ty, 1).into_var();
+        assert!(ArrayOp::repeat(single).is_ok());
+    }
+}
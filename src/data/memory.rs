@@ -0,0 +1,512 @@
+/*!
+A linear memory model, in which allocations are threaded through memory state tokens
+*/
+use super::reference::RefTy;
+use crate::eval::{Application, Apply, EvalCtx, Substitute};
+use crate::function::pi::Pi;
+use crate::lifetime::{Lifetime, LifetimeBorrow, Live};
+use crate::region::{Region, Regional};
+use crate::typing::{layout::Layout, primitive::FIN, Type, Typed};
+use crate::value::{
+    tuple::Product, Error, NormalValue, TypeId, TypeRef, ValId, Value, ValueEnum, VarId,
+};
+use crate::{debug_from_display, enum_convert, pretty_display, substitute_to_valid, tyarr};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// The type of memory states, a linear anchor
+    pub static ref MEM_TY: VarId<Product> = VarId::direct_new(Product::linear_anchor_ty());
+}
+
+/// The type of allocations holding a value of a given type.
+///
+/// Allocations are linear, and their kind is not a representation: they may only be accessed through memory operations.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct AllocTy {
+    /// The type of the value held by this allocation
+    ty: TypeId,
+    /// The (cached) layout of the value held by this allocation
+    layout: Layout,
+}
+
+impl AllocTy {
+    /// Create a new allocation type. Return an error if `ty` has no layout, or is relevant, since freeing an allocation
+    /// discards the value it holds: in particular, memory states and world tokens may not be stored in memory.
+    pub fn try_new(ty: TypeId) -> Result<AllocTy, Error> {
+        if ty.is_relevant() {
+            return Err(Error::RelevantAlloc);
+        }
+        let layout = Layout::of(ty.as_val()).ok_or(Error::NoLayout)?;
+        Ok(AllocTy { ty, layout })
+    }
+    /// Get the type of the value held by allocations of this type
+    #[inline]
+    pub fn alloc_ty(&self) -> &TypeId {
+        &self.ty
+    }
+    /// Get the layout of the value held by allocations of this type
+    #[inline]
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+}
+
+impl Typed for AllocTy {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        FIN.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        true
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for AllocTy {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.ty.region().into()
+    }
+}
+
+impl Apply for AllocTy {}
+
+impl Substitute for AllocTy {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<AllocTy, Error> {
+        AllocTy::try_new(self.ty.substitute_ty(ctx)?)
+    }
+}
+
+impl Value for AllocTy {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => self.ty.as_val(),
+            ix => panic!(
+                "Invalid index into an allocation type's dependencies: {}",
+                ix
+            ),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::AllocTy(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl Type for AllocTy {
+    #[inline]
+    fn is_affine(&self) -> bool {
+        true
+    }
+    #[inline]
+    fn is_relevant(&self) -> bool {
+        true
+    }
+}
+
+impl From<AllocTy> for NormalValue {
+    #[inline]
+    fn from(ty: AllocTy) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::AllocTy(ty))
+    }
+}
+
+substitute_to_valid!(AllocTy);
+debug_from_display!(AllocTy);
+pretty_display!(AllocTy, "#alloc {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for AllocTy {}
+    impl TryFrom<NormalValue> for AllocTy { as ValueEnum, }
+    impl TryFromRef<NormalValue> for AllocTy { as ValueEnum, }
+}
+
+/// The kind of a memory operation
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemOpKind {
+    /// Allocate memory for a value: `Mem -> [Mem, #alloc T]`
+    Alloc,
+    /// Free an allocation: `Mem -> #alloc T -> Mem`
+    Free,
+    /// Load a copy of a value from an allocation: `Mem -> &(#alloc T) -> [Mem, T]`, where `T` is not affine
+    Load,
+    /// Store a value into an allocation: `Mem -> &mut (#alloc T) -> T -> Mem`
+    Store,
+}
+
+/**
+A memory operation on allocations of a given type.
+
+Every memory operation takes a memory state as its first argument and returns a new memory state, threading memory
+effects in order. Pointers are borrows of allocations, and hence may not be used once their allocation has been freed.
+Reference parameters accept references of any lifetime, and the result of a memory operation does not borrow from
+them.
+*/
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct MemOp {
+    /// The kind of this memory operation
+    kind: MemOpKind,
+    /// The type of value this memory operation acts on
+    ty: TypeId,
+    /// The (cached) type of this memory operation
+    fn_ty: VarId<Pi>,
+}
+
+impl MemOp {
+    /// Create a new memory operation acting on a given type.
+    ///
+    /// Return an error if `ty` has no layout, if `ty` is relevant, as freeing an allocation discards the value it holds,
+    /// or if `kind` is a load and `ty` is affine, as loading copies the value out of an allocation which still holds it.
+    pub fn try_new(kind: MemOpKind, ty: TypeId) -> Result<MemOp, Error> {
+        if kind == MemOpKind::Load && ty.is_affine() {
+            return Err(Error::AffineMove);
+        }
+        let alloc_ty = AllocTy::try_new(ty.clone())?.into_ty();
+        let mem_ty = MEM_TY.clone_as_ty();
+        let (param_tys, result) = match kind {
+            MemOpKind::Alloc => (
+                tyarr![mem_ty.clone()],
+                Product::try_new(tyarr![mem_ty, alloc_ty.clone()])?.into_ty(),
+            ),
+            MemOpKind::Free => (tyarr![mem_ty.clone(), alloc_ty.clone()], mem_ty),
+            MemOpKind::Load => (
+                tyarr![
+                    mem_ty.clone(),
+                    RefTy::shared(alloc_ty.clone(), Lifetime::STATIC)?.into_ty()
+                ],
+                Product::try_new(tyarr![mem_ty, ty.clone()])?.into_ty(),
+            ),
+            MemOpKind::Store => (
+                tyarr![
+                    mem_ty.clone(),
+                    RefTy::unique(alloc_ty.clone(), Lifetime::STATIC)?.into_ty(),
+                    ty.clone()
+                ],
+                mem_ty,
+            ),
+        };
+        let def_region = Region::with(param_tys, alloc_ty.clone_region())?;
+        let fn_ty = Pi::try_new(result, def_region)?.into_var();
+        Ok(MemOp { kind, ty, fn_ty })
+    }
+    /// Create a new allocation operation
+    #[inline]
+    pub fn alloc(ty: TypeId) -> Result<MemOp, Error> {
+        Self::try_new(MemOpKind::Alloc, ty)
+    }
+    /// Create a new free operation
+    #[inline]
+    pub fn free(ty: TypeId) -> Result<MemOp, Error> {
+        Self::try_new(MemOpKind::Free, ty)
+    }
+    /// Create a new load operation
+    #[inline]
+    pub fn load(ty: TypeId) -> Result<MemOp, Error> {
+        Self::try_new(MemOpKind::Load, ty)
+    }
+    /// Create a new store operation
+    #[inline]
+    pub fn store(ty: TypeId) -> Result<MemOp, Error> {
+        Self::try_new(MemOpKind::Store, ty)
+    }
+    /// Get the kind of this memory operation
+    #[inline]
+    pub fn kind(&self) -> MemOpKind {
+        self.kind
+    }
+    /// Get the type of value this memory operation acts on
+    #[inline]
+    pub fn op_ty(&self) -> &TypeId {
+        &self.ty
+    }
+    /// Get the type of this memory operation as a guaranteed pi type
+    #[inline]
+    pub fn get_ty(&self) -> &VarId<Pi> {
+        &self.fn_ty
+    }
+    /// Get the layout of the value this memory operation acts on
+    #[inline]
+    pub fn layout(&self) -> Layout {
+        Layout::of(self.ty.as_val())
+            .expect("Memory operations are only constructed on types with a layout")
+    }
+}
+
+impl Typed for MemOp {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.fn_ty.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        false
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for MemOp {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.ty.region().into()
+    }
+}
+
+impl Apply for MemOp {
+    fn apply_in<'a>(
+        &self,
+        args: &'a [ValId],
+        _ctx: &mut Option<EvalCtx>,
+    ) -> Result<Application<'a>, Error> {
+        // Null evaluation
+        if args.is_empty() {
+            return Ok(Application::Symbolic(self.clone_ty()));
+        }
+        let param_tys = self.fn_ty.param_tys();
+        // Over-evaluation
+        if args.len() > param_tys.len() {
+            return Err(Error::TooManyArgs);
+        }
+        // Type-check arguments, accepting references of any lifetime and of at least the required kind
+        for (arg, param_ty) in args.iter().zip(param_tys.iter()) {
+            let arg_ty = arg.ty();
+            let matches = match (arg_ty.as_enum(), param_ty.as_enum()) {
+                (ValueEnum::RefTy(arg_ty), ValueEnum::RefTy(param_ty)) => {
+                    arg_ty.kind() >= param_ty.kind() && arg_ty.referent() == param_ty.referent()
+                }
+                _ => arg_ty == *param_ty,
+            };
+            if !matches {
+                return Err(Error::TypeMismatch);
+            }
+        }
+        // Full application
+        if args.len() == param_tys.len() {
+            return Ok(Application::Symbolic(self.fn_ty.result().clone()));
+        }
+        // Partial application
+        let region = Region::with(
+            param_tys[args.len()..].iter().cloned().collect(),
+            self.fn_ty.def_region().parent().clone(),
+        )?;
+        Ok(Application::Symbolic(
+            Pi::try_new(self.fn_ty.result().clone(), region)?.into_ty(),
+        ))
+    }
+}
+
+impl Substitute for MemOp {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<MemOp, Error> {
+        MemOp::try_new(self.kind, self.ty.substitute_ty(ctx)?)
+    }
+}
+
+impl Value for MemOp {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => self.ty.as_val(),
+            ix => panic!(
+                "Invalid index into a memory operation's dependencies: {}",
+                ix
+            ),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::MemOp(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<MemOp> for NormalValue {
+    #[inline]
+    fn from(op: MemOp) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::MemOp(op))
+    }
+}
+
+substitute_to_valid!(MemOp);
+debug_from_display!(MemOp);
+pretty_display!(MemOp, "#mem_op {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for MemOp {}
+    impl TryFrom<NormalValue> for MemOp { as ValueEnum, }
+    impl TryFromRef<NormalValue> for MemOp { as ValueEnum, }
+}
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use std::fmt::{self, Display, Formatter};
+
+    impl PrettyPrint for AllocTy {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "(#alloc ")?;
+            self.ty.prettyprint(printer, fmt)?;
+            write!(fmt, ")")
+        }
+    }
+
+    impl PrettyPrint for MemOp {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            match self.kind {
+                MemOpKind::Alloc => write!(fmt, "(#malloc ")?,
+                MemOpKind::Free => write!(fmt, "(#free ")?,
+                MemOpKind::Load => write!(fmt, "(#load ")?,
+                MemOpKind::Store => write!(fmt, "(#store ")?,
+            }
+            self.ty.prettyprint(printer, fmt)?;
+            write!(fmt, ")")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::effect::WORLD_TY;
+    use crate::data::reference::{Borrow, Dereference};
+    use crate::lifetime::LifetimeCtx;
+    use crate::primitive::logical::Bool;
+    use crate::value::expr::Sexpr;
+
+    #[test]
+    fn memory_operation_types() {
+        let mem_ty = MEM_TY.clone_as_ty();
+        assert!(mem_ty.is_linear());
+        let alloc_bool = AllocTy::try_new(Bool.into_ty()).unwrap();
+        assert_eq!(alloc_bool.layout(), Layout::bits(1));
+        assert!(alloc_bool.is_linear());
+        assert_eq!(
+            AllocTy::try_new(mem_ty.clone()).map(Value::into_val),
+            Err(Error::RelevantAlloc)
+        );
+        assert_eq!(
+            AllocTy::try_new(Pi::unary(Bool.into_ty()).into_ty()).map(Value::into_val),
+            Err(Error::NoLayout)
+        );
+        let region = Region::unary(mem_ty.clone());
+        let m = region.param(0).unwrap().into_val();
+        let alloc = MemOp::alloc(Bool.into_ty()).unwrap().into_val();
+        let allocated = Sexpr::try_new(vec![alloc, m.clone()]).unwrap();
+        let expected: TypeId = Product::try_new(tyarr![mem_ty, alloc_bool.into_ty()])
+            .unwrap()
+            .into();
+        assert_eq!(allocated.ty(), expected);
+        let free = MemOp::free(Bool.into_ty()).unwrap().into_val();
+        assert_eq!(
+            Sexpr::try_new(vec![free, true.into_val()]).map(Value::into_val),
+            Err(Error::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn relevant_values_cannot_be_stored() {
+        let world_ty = WORLD_TY.clone_as_ty();
+        assert_eq!(Layout::of(world_ty.as_val()), Some(Layout::ZERO));
+        let mem_ty = MEM_TY.clone_as_ty();
+        let alloc_ty = AllocTy::try_new(Bool.into_ty()).unwrap().into_ty();
+        for ty in [world_ty, mem_ty, alloc_ty].iter() {
+            assert!(ty.is_relevant());
+            for kind in [MemOpKind::Alloc, MemOpKind::Store, MemOpKind::Free].iter() {
+                assert_eq!(
+                    MemOp::try_new(*kind, ty.clone()).map(Value::into_val),
+                    Err(Error::RelevantAlloc)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn allocations_cannot_be_copied_out_of_memory() {
+        let anchor_ty = Product::anchor_ty().into_ty();
+        assert!(MemOp::alloc(anchor_ty.clone()).is_ok());
+        assert!(MemOp::store(anchor_ty.clone()).is_ok());
+        assert_eq!(
+            MemOp::load(anchor_ty).map(Value::into_val),
+            Err(Error::AffineMove)
+        );
+        let alloc_ty = AllocTy::try_new(Bool.into_ty()).unwrap().into_ty();
+        assert_eq!(
+            MemOp::load(alloc_ty.clone()).map(Value::into_val),
+            Err(Error::AffineMove)
+        );
+        assert!(!alloc_ty.ty().is_repr());
+        let region = Region::unary(alloc_ty);
+        let a = region.param(0).unwrap().into_val();
+        let ptr = Borrow::shared(a).unwrap().into_val();
+        assert_eq!(Dereference::eval(ptr), Err(Error::AffineMove));
+    }
+
+    #[test]
+    fn pointers_do_not_outlive_allocations() {
+        let alloc_ty = AllocTy::try_new(Bool.into_ty()).unwrap().into_ty();
+        let region = Region::with(tyarr![MEM_TY.clone_as_ty(), alloc_ty], Region::NULL).unwrap();
+        let m = region.param(0).unwrap().into_val();
+        let a = region.param(1).unwrap().into_val();
+        let store = MemOp::store(Bool.into_ty()).unwrap().into_val();
+        let free = MemOp::free(Bool.into_ty()).unwrap().into_val();
+        let load = MemOp::load(Bool.into_ty()).unwrap().into_val();
+
+        // Store through a unique pointer, then free the allocation
+        let ptr = Borrow::unique(a.clone()).unwrap().into_val();
+        let m1 = Sexpr::try_new(vec![store, m, ptr, true.into_val()])
+            .unwrap()
+            .into_val();
+        assert_eq!(m1.ty(), MEM_TY.clone_as_ty());
+        let m2 = Sexpr::try_new(vec![free, m1, a.clone()])
+            .unwrap()
+            .into_val();
+        let mut ctx = LifetimeCtx::new(region.clone());
+        assert_eq!(ctx.check_borrows(&[&m2]), Ok(()));
+
+        // Loading through a pointer after its allocation has been freed is rejected
+        let ptr = Borrow::shared(a).unwrap().into_val();
+        let loaded = Sexpr::try_new(vec![load, m2, ptr]).unwrap().into_val();
+        let mut ctx = LifetimeCtx::new(region);
+        assert_eq!(
+            ctx.check_borrows(&[&loaded]).map_err(|err| err.error),
+            Err(Error::BorrowUsed)
+        );
+    }
+}
@@ -2,6 +2,10 @@
 `rain` data declarations and compound/inductive types
 */
 
+pub mod array;
+pub mod memory;
+pub mod reference;
+
 /// A record type with named members, supporting row typing
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Struct {}
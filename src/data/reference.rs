@@ -0,0 +1,602 @@
+/*!
+Shared and unique references, along with borrowing and dereferencing
+*/
+use crate::eval::{Apply, EvalCtx, Substitute};
+use crate::lifetime::{Lifetime, LifetimeBorrow, LifetimeData, Live};
+use crate::region::{Region, Regional};
+use crate::typing::{kind::layout::Layout, Type, Typed};
+use crate::value::{Error, NormalValue, TypeId, TypeRef, ValId, Value, ValueEnum, VarId};
+use crate::{debug_from_display, enum_convert, pretty_display, substitute_to_valid};
+
+/// The kind of access a reference grants to its referent
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RefKind {
+    /// A shared reference, which may be freely copied
+    Shared,
+    /// A unique reference, which is affine
+    Unique,
+}
+
+/// A reference type, parametrized by a lifetime and a referent type
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct RefTy {
+    /// The type being referenced
+    referent: TypeId,
+    /// The lifetime of references of this type
+    lifetime: Lifetime,
+    /// The region of this reference type
+    region: Region,
+    /// The kind of this reference type
+    kind: RefKind,
+}
+
+impl RefTy {
+    /// Create a new reference type. Return an error if the referent and lifetime lie in incomparable regions.
+    pub fn try_new(referent: TypeId, lifetime: Lifetime, kind: RefKind) -> Result<RefTy, Error> {
+        let region = referent.gcr(&lifetime)?.clone_region();
+        Ok(RefTy {
+            referent,
+            lifetime,
+            region,
+            kind,
+        })
+    }
+    /// Create a new shared reference type
+    #[inline]
+    pub fn shared(referent: TypeId, lifetime: Lifetime) -> Result<RefTy, Error> {
+        Self::try_new(referent, lifetime, RefKind::Shared)
+    }
+    /// Create a new unique reference type
+    #[inline]
+    pub fn unique(referent: TypeId, lifetime: Lifetime) -> Result<RefTy, Error> {
+        Self::try_new(referent, lifetime, RefKind::Unique)
+    }
+    /// Get the type referenced by this reference type
+    #[inline]
+    pub fn referent(&self) -> &TypeId {
+        &self.referent
+    }
+    /// Get the lifetime of references of this type
+    #[inline]
+    pub fn ref_lifetime(&self) -> &Lifetime {
+        &self.lifetime
+    }
+    /// Get the kind of this reference type
+    #[inline]
+    pub fn kind(&self) -> RefKind {
+        self.kind
+    }
+}
+
+impl Typed for RefTy {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.referent.ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        true
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for RefTy {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.region.region().into()
+    }
+}
+
+impl Apply for RefTy {}
+
+impl Substitute for RefTy {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<RefTy, Error> {
+        let referent = self.referent.substitute_ty(ctx)?;
+        let lifetime = self.lifetime.substitute(ctx)?;
+        RefTy::try_new(referent, lifetime, self.kind)
+    }
+}
+
+impl Value for RefTy {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => self.referent.as_val(),
+            ix => panic!("Invalid index into a reference type's dependencies: {}", ix),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::RefTy(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl Type for RefTy {
+    #[inline]
+    fn is_affine(&self) -> bool {
+        self.kind == RefKind::Unique
+    }
+    #[inline]
+    fn is_relevant(&self) -> bool {
+        false
+    }
+}
+
+impl From<RefTy> for NormalValue {
+    #[inline]
+    fn from(ty: RefTy) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::RefTy(ty))
+    }
+}
+
+substitute_to_valid!(RefTy);
+debug_from_display!(RefTy);
+pretty_display!(RefTy, "#ref {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for RefTy {}
+    impl TryFrom<NormalValue> for RefTy { as ValueEnum, }
+    impl TryFromRef<NormalValue> for RefTy { as ValueEnum, }
+}
+
+/// A borrow of a value, or a reborrow of a reference
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Borrow {
+    /// The value being borrowed, or the reference being reborrowed
+    source: ValId,
+    /// The (cached) type of this borrow
+    ty: VarId<RefTy>,
+    /// The (cached) lifetime of this borrow
+    lifetime: Lifetime,
+    /// Whether this borrow is a reborrow
+    reborrow: bool,
+}
+
+impl Borrow {
+    /// Borrow a value, yielding a reference of a given kind to it
+    pub fn borrow(value: ValId, kind: RefKind) -> Result<Borrow, Error> {
+        let lifetime: Lifetime = LifetimeData::borrow(&value).into();
+        let ty = RefTy::try_new(value.clone_ty(), lifetime.clone(), kind)?.into_var();
+        Ok(Borrow {
+            source: value,
+            ty,
+            lifetime,
+            reborrow: false,
+        })
+    }
+    /// Borrow a value, yielding a shared reference to it
+    #[inline]
+    pub fn shared(value: ValId) -> Result<Borrow, Error> {
+        Self::borrow(value, RefKind::Shared)
+    }
+    /// Borrow a value, yielding a unique reference to it
+    #[inline]
+    pub fn unique(value: ValId) -> Result<Borrow, Error> {
+        Self::borrow(value, RefKind::Unique)
+    }
+    /// Reborrow a reference, yielding a reference of a given kind to the same referent borrowing from the same lender.
+    ///
+    /// Return an error if `reference` is not a reference, or if a unique reborrow of a shared reference is requested.
+    pub fn reborrow(reference: ValId, kind: RefKind) -> Result<Borrow, Error> {
+        let referent = match reference.ty().as_enum() {
+            ValueEnum::RefTy(ty) if ty.kind() >= kind => ty.referent().clone(),
+            ValueEnum::RefTy(_) => return Err(Error::BorrowingMismatch),
+            _ => return Err(Error::TypeMismatch),
+        };
+        let lifetime: Lifetime = LifetimeData::borrow(&reference).into();
+        let ty = RefTy::try_new(referent, lifetime.clone(), kind)?.into_var();
+        Ok(Borrow {
+            source: reference,
+            ty,
+            lifetime,
+            reborrow: true,
+        })
+    }
+    /// Get the value borrowed, or the reference reborrowed
+    #[inline]
+    pub fn source(&self) -> &ValId {
+        &self.source
+    }
+    /// Get the type of this borrow as a guaranteed reference type
+    #[inline]
+    pub fn get_ty(&self) -> &VarId<RefTy> {
+        &self.ty
+    }
+    /// Get the kind of reference produced by this borrow
+    #[inline]
+    pub fn kind(&self) -> RefKind {
+        self.ty.kind()
+    }
+    /// Check whether this borrow is a reborrow
+    #[inline]
+    pub fn is_reborrow(&self) -> bool {
+        self.reborrow
+    }
+}
+
+impl Typed for Borrow {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.ty.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        false
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for Borrow {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.lifetime.lifetime()
+    }
+}
+
+impl Apply for Borrow {}
+
+impl Substitute for Borrow {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<Borrow, Error> {
+        let source = self.source.substitute(ctx)?;
+        if self.reborrow {
+            Borrow::reborrow(source, self.kind())
+        } else {
+            Borrow::borrow(source, self.kind())
+        }
+    }
+}
+
+impl Value for Borrow {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => &self.source,
+            ix => panic!("Invalid index into a borrow's dependencies: {}", ix),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::Borrow(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<Borrow> for NormalValue {
+    #[inline]
+    fn from(borrow: Borrow) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::Borrow(borrow))
+    }
+}
+
+substitute_to_valid!(Borrow);
+debug_from_display!(Borrow);
+pretty_display!(Borrow, "#borrow {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for Borrow {}
+    impl TryFrom<NormalValue> for Borrow { as ValueEnum, }
+    impl TryFromRef<NormalValue> for Borrow { as ValueEnum, }
+}
+
+/// A dereference of a reference to a value of representable type
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Dereference {
+    /// The reference being dereferenced
+    reference: ValId,
+    /// The (cached) type of this dereference
+    ty: TypeId,
+    /// The region of this dereference
+    region: Region,
+}
+
+impl Dereference {
+    /// Dereference a reference.
+    ///
+    /// Return an error if `reference` is not a reference, or its referent type is not representable, i.e. has no
+    /// layout, or is affine, and hence cannot be copied out of the reference.
+    pub fn try_new(reference: ValId) -> Result<Dereference, Error> {
+        let ty = match reference.ty().as_enum() {
+            ValueEnum::RefTy(ty) => ty.referent().clone(),
+            _ => return Err(Error::TypeMismatch),
+        };
+        if Layout::of(ty.as_val()).is_none() {
+            return Err(Error::NonReprDeref);
+        }
+        if ty.is_affine() {
+            return Err(Error::AffineMove);
+        }
+        let region = reference.clone_region();
+        Ok(Dereference {
+            reference,
+            ty,
+            region,
+        })
+    }
+    /// Dereference a reference, folding the dereference of a borrow of a value to that value
+    pub fn eval(reference: ValId) -> Result<ValId, Error> {
+        let deref = Self::try_new(reference)?;
+        match deref.reference.as_enum() {
+            ValueEnum::Borrow(borrow) if borrow.is_reborrow() => {
+                Self::eval(borrow.source().clone())
+            }
+            ValueEnum::Borrow(borrow) => Ok(borrow.source().clone()),
+            _ => Ok(deref.into_val()),
+        }
+    }
+    /// Get the reference being dereferenced
+    #[inline]
+    pub fn reference(&self) -> &ValId {
+        &self.reference
+    }
+}
+
+impl Typed for Dereference {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.ty.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        self.ty.is_kind()
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for Dereference {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.region.region().into()
+    }
+}
+
+impl Apply for Dereference {}
+
+impl Substitute for Dereference {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<Dereference, Error> {
+        let reference = self.reference.substitute(ctx)?;
+        Dereference::try_new(reference)
+    }
+}
+
+impl Value for Dereference {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => &self.reference,
+            ix => panic!("Invalid index into a dereference's dependencies: {}", ix),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::Dereference(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<Dereference> for NormalValue {
+    #[inline]
+    fn from(deref: Dereference) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::Dereference(deref))
+    }
+}
+
+substitute_to_valid!(Dereference);
+debug_from_display!(Dereference);
+pretty_display!(Dereference, "#deref {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for Dereference {}
+    impl TryFrom<NormalValue> for Dereference { as ValueEnum, }
+    impl TryFromRef<NormalValue> for Dereference { as ValueEnum, }
+}
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use std::fmt::{self, Display, Formatter};
+
+    impl PrettyPrint for RefTy {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            match self.kind {
+                RefKind::Shared => write!(fmt, "(#ref ")?,
+                RefKind::Unique => write!(fmt, "(#ref_mut ")?,
+            }
+            self.referent.prettyprint(printer, fmt)?;
+            let groups = [
+                ("#lender", self.lifetime.lender()),
+                ("#transient", self.lifetime.transient()),
+            ];
+            for (keyword, group) in groups.iter() {
+                if let Some(group) = group {
+                    write!(fmt, " {}[", keyword)?;
+                    let mut first = true;
+                    for value in group.values() {
+                        if !first {
+                            write!(fmt, " ")?;
+                        }
+                        first = false;
+                        value.prettyprint(printer, fmt)?;
+                    }
+                    write!(fmt, "]")?;
+                }
+            }
+            write!(fmt, ")")
+        }
+    }
+
+    impl PrettyPrint for Borrow {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            match (self.reborrow, self.kind()) {
+                (false, RefKind::Shared) => write!(fmt, "(#borrow ")?,
+                (false, RefKind::Unique) => write!(fmt, "(#borrow_mut ")?,
+                (true, RefKind::Shared) => write!(fmt, "(#reborrow ")?,
+                (true, RefKind::Unique) => write!(fmt, "(#reborrow_mut ")?,
+            }
+            self.source.prettyprint(printer, fmt)?;
+            write!(fmt, ")")
+        }
+    }
+
+    impl PrettyPrint for Dereference {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "(#deref ")?;
+            self.reference.prettyprint(printer, fmt)?;
+            write!(fmt, ")")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::lambda::Lambda;
+    use crate::function::pi::Pi;
+    use crate::lifetime::{LifetimeCtx, PiLifetime};
+    use crate::primitive::logical::Bool;
+    use crate::value::tuple::{Product, Tuple};
+    use crate::{tyarr, valarr};
+
+    #[test]
+    fn borrow_and_deref_bool() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let shared = Borrow::shared(x.clone()).unwrap();
+        assert_eq!(shared.kind(), RefKind::Shared);
+        assert!(!shared.get_ty().is_affine());
+        assert_eq!(*shared.get_ty().referent(), Bool.into_ty());
+        let lifetime = shared.clone_lifetime();
+        let lenders = lifetime.lender().expect("A borrow has a lender").values();
+        assert_eq!(lenders.len(), 1);
+        assert_eq!(lenders[0].as_addr(), x.as_addr());
+        let shared = shared.into_val();
+        assert_eq!(Dereference::eval(shared.clone()), Ok(x.clone()));
+
+        let reborrow = Borrow::reborrow(shared.clone(), RefKind::Shared).unwrap();
+        let lifetime = reborrow.clone_lifetime();
+        let lenders = lifetime.lender().expect("A reborrow has a lender").values();
+        assert_eq!(lenders.len(), 1);
+        assert_eq!(lenders[0].as_addr(), x.as_addr());
+        assert_eq!(Dereference::eval(reborrow.into_val()), Ok(x.clone()));
+
+        assert_eq!(
+            Borrow::reborrow(shared, RefKind::Unique),
+            Err(Error::BorrowingMismatch)
+        );
+        assert_eq!(
+            Borrow::reborrow(x.clone(), RefKind::Shared),
+            Err(Error::TypeMismatch)
+        );
+        assert_eq!(Dereference::try_new(x), Err(Error::TypeMismatch));
+    }
+
+    #[test]
+    fn unique_borrow_is_affine() {
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let unique = Borrow::unique(x).unwrap();
+        assert!(unique.get_ty().is_affine());
+        let reborrow = Borrow::reborrow(unique.into_val(), RefKind::Shared).unwrap();
+        assert_eq!(reborrow.kind(), RefKind::Shared);
+    }
+
+    #[test]
+    fn borrowing_an_affine_value() {
+        let region = Region::unary(Product::anchor_ty().into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let shared = Borrow::shared(x.clone()).unwrap().into_val();
+        let mut ctx = LifetimeCtx::new(region);
+        assert_eq!(ctx.check_usage(&[&shared]), Ok(()));
+        assert_eq!(ctx.check_borrows(&[&shared]), Ok(()));
+        assert_eq!(
+            Tuple::try_new(valarr![shared, x]).map(Value::into_val),
+            Err(Error::BorrowUsed)
+        );
+    }
+
+    #[test]
+    fn deref_requires_a_representable_referent() {
+        let region = Region::with(
+            tyarr![
+                Product::anchor_ty().into_ty(),
+                Pi::unary(Bool.into_ty()).into_ty()
+            ],
+            Region::NULL,
+        )
+        .unwrap();
+        let anchor = region.param(0).unwrap().into_val();
+        let f = region.param(1).unwrap().into_val();
+        let anchor_ref = Borrow::shared(anchor).unwrap().into_val();
+        assert_eq!(Dereference::eval(anchor_ref), Err(Error::AffineMove));
+        let f_ref = Borrow::shared(f).unwrap().into_val();
+        assert_eq!(Dereference::eval(f_ref), Err(Error::NonReprDeref));
+    }
+
+    #[test]
+    fn borrowing_lambda_application() {
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let shared = Borrow::shared(x).unwrap().into_val();
+        let lambda = Lambda::try_new(shared, region).unwrap();
+        assert_eq!(
+            lambda.get_ty().lifetime_component(),
+            &PiLifetime::borrows_from(0)
+        );
+        let t = true.into_val();
+        let applied = lambda.applied(&[t.clone()]).unwrap();
+        assert_eq!(applied, Borrow::shared(t).unwrap().into_val());
+    }
+}
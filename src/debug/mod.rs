@@ -0,0 +1,284 @@
+/*!
+Debug information for `rain` values and regions, such as source spans and names
+*/
+use crate::region::{Region, Regional};
+use crate::value::{Error, ValAddr, ValId};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+/// A span of source code, given as a range of byte offsets into a file
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Span {
+    /// The name of the file this span lies in
+    pub file: Arc<str>,
+    /// The offset of the start of this span
+    pub start: usize,
+    /// The offset of the end of this span
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a new span in a given file
+    #[inline]
+    pub fn new(file: impl Into<Arc<str>>, start: usize, end: usize) -> Span {
+        Span {
+            file: file.into(),
+            start,
+            end,
+        }
+    }
+    /// Get the length of this span
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+    /// Check whether this span is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}..{}", self.file, self.start, self.end)
+    }
+}
+
+/// The debug information attached to a value or region
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct DebugData {
+    /// The source span this value or region was lowered from, if any
+    pub span: Option<Span>,
+    /// The source-level name of this value or region, if any
+    pub name: Option<String>,
+}
+
+impl DebugData {
+    /// Create debug data consisting of a name
+    #[inline]
+    pub fn named(name: &str) -> DebugData {
+        DebugData {
+            span: None,
+            name: Some(name.to_owned()),
+        }
+    }
+    /// Create debug data consisting of a source span
+    #[inline]
+    pub fn at(span: Span) -> DebugData {
+        DebugData {
+            span: Some(span),
+            name: None,
+        }
+    }
+    /// Attach a source span to this debug data
+    #[inline]
+    pub fn with_span(self, span: Span) -> DebugData {
+        DebugData {
+            span: Some(span),
+            ..self
+        }
+    }
+}
+
+impl Display for DebugData {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match (&self.name, &self.span) {
+            (Some(name), Some(span)) => write!(fmt, "`{}` at {}", name, span),
+            (Some(name), None) => write!(fmt, "`{}`", name),
+            (None, Some(span)) => write!(fmt, "{}", span),
+            (None, None) => write!(fmt, "<unknown>"),
+        }
+    }
+}
+
+/**
+A side table attaching debug information to values and regions.
+
+Values are keyed by address, and hence attaching debug information to a value does not affect hash-consing: two
+values lowered from different source locations which normalize to the same value share a single entry. Entries keep
+the values and regions they describe alive, so an address is never reused while it is in the table.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct DebugInfo {
+    /// The debug information attached to values
+    values: HashMap<ValAddr, (ValId, DebugData), FxBuildHasher>,
+    /// The debug information attached to regions
+    regions: HashMap<Region, DebugData, FxBuildHasher>,
+}
+
+impl DebugInfo {
+    /// Create a new, empty debug information table
+    #[inline]
+    pub fn new() -> DebugInfo {
+        DebugInfo::default()
+    }
+    /// Attach debug information to a value. Return the debug information previously attached to it, if any.
+    #[inline]
+    pub fn insert(&mut self, value: ValId, data: DebugData) -> Option<DebugData> {
+        self.values
+            .insert(value.as_addr(), (value, data))
+            .map(|(_, data)| data)
+    }
+    /// Attach debug information to a region. Return the debug information previously attached to it, if any.
+    #[inline]
+    pub fn insert_region(&mut self, region: Region, data: DebugData) -> Option<DebugData> {
+        self.regions.insert(region, data)
+    }
+    /// Get the debug information attached to a value, if any
+    #[inline]
+    pub fn get(&self, value: &ValId) -> Option<&DebugData> {
+        self.get_addr(value.as_addr())
+    }
+    /// Get the debug information attached to the value at a given address, if any
+    #[inline]
+    pub fn get_addr(&self, addr: ValAddr) -> Option<&DebugData> {
+        self.values.get(&addr).map(|(_, data)| data)
+    }
+    /// Get the debug information attached to a region, if any
+    #[inline]
+    pub fn get_region(&self, region: &Region) -> Option<&DebugData> {
+        self.regions.get(region)
+    }
+    /// Remove the debug information attached to a value, returning it, if any
+    #[inline]
+    pub fn remove(&mut self, value: &ValId) -> Option<DebugData> {
+        self.values.remove(&value.as_addr()).map(|(_, data)| data)
+    }
+    /// Iterate over the values with debug information attached, along with their debug information
+    #[inline]
+    pub fn values(&self) -> impl Iterator<Item = (&ValId, &DebugData)> {
+        self.values.values().map(|(value, data)| (value, data))
+    }
+    /// Iterate over the regions with debug information attached, along with their debug information
+    #[inline]
+    pub fn regions(&self) -> impl Iterator<Item = (&Region, &DebugData)> {
+        self.regions.iter()
+    }
+    /**
+    Carry the debug information attached to a value over to the value it was mapped to, e.g. by substitution.
+
+    Debug information already attached to the new value takes precedence, so a value shared between several source
+    locations keeps the first one it was given. Return whether any debug information was carried over.
+    */
+    pub fn propagate(&mut self, from: &ValId, to: &ValId) -> bool {
+        if from == to || self.values.contains_key(&to.as_addr()) {
+            return false;
+        }
+        if let Some(data) = self.get(from).cloned() {
+            self.insert(to.clone(), data);
+            true
+        } else {
+            false
+        }
+    }
+    /// Carry the debug information attached to a region over to the region it was mapped to, as in `propagate`
+    pub fn propagate_region(&mut self, from: &Region, to: &Region) -> bool {
+        if from == to || self.regions.contains_key(to) {
+            return false;
+        }
+        if let Some(data) = self.get_region(from).cloned() {
+            self.insert_region(to.clone(), data);
+            true
+        } else {
+            false
+        }
+    }
+    /// Get the source span of a value, if known
+    #[inline]
+    pub fn span(&self, value: &ValId) -> Option<&Span> {
+        self.get(value).and_then(|data| data.span.as_ref())
+    }
+    /// Get the source-level name of a value, if known
+    #[inline]
+    pub fn name(&self, value: &ValId) -> Option<&str> {
+        self.get(value).and_then(|data| data.name.as_deref())
+    }
+    /**
+    Describe where a value came from for error reporting, falling back to the nearest region with debug information
+    attached to it, if any.
+    */
+    pub fn locate(&self, value: &ValId) -> Option<String> {
+        if let Some(data) = self.get(value) {
+            return Some(data.to_string());
+        }
+        let mut region = value.region().clone_region();
+        while !region.is_null() {
+            if let Some(data) = self.get_region(&region) {
+                return Some(format!("in region {}", data));
+            }
+            region = region.parent().clone();
+        }
+        None
+    }
+    /// Describe where the value an error arose from came from, if it has one and it is known
+    #[inline]
+    pub fn locate_error(&self, error: &Error) -> Option<String> {
+        error.value().and_then(|value| self.locate(value))
+    }
+    /// Get the number of values and regions with debug information attached
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len() + self.regions.len()
+    }
+    /// Check whether this table is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.regions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::EvalCtx;
+    use crate::primitive::logical::{And, Bool};
+    use crate::typing::Type;
+    use crate::value::{expr::Sexpr, Value};
+
+    #[test]
+    fn debug_info_survives_substitution() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let and = Sexpr::try_new(vec![And.into_val(), x.clone(), y])
+            .unwrap()
+            .into_val();
+        let span = Span::new("and.rn", 4, 11);
+        let mut info = DebugInfo::new();
+        info.insert(
+            and.clone(),
+            DebugData::named("both").with_span(span.clone()),
+        );
+        info.insert(x.clone(), DebugData::named("x"));
+        info.insert_region(region.clone(), DebugData::named("binary"));
+        assert_eq!(info.name(&and), Some("both"));
+        assert_eq!(info.span(&and), Some(&span));
+        assert_eq!(info.len(), 3);
+
+        let mut ctx = EvalCtx::with_debug_info(info);
+        let (new_region, new_and) = ctx.evaluate_in_region(&and, &region).unwrap();
+        assert_ne!(new_region, region);
+        assert_ne!(new_and, and);
+        let info = ctx.take_debug_info().unwrap();
+        assert_eq!(info.name(&new_and), Some("both"));
+        assert_eq!(info.span(&new_and), Some(&span));
+        assert_eq!(
+            info.get_region(&new_region),
+            Some(&DebugData::named("binary"))
+        );
+        assert_eq!(
+            info.name(&new_region.param(0).unwrap().into_val()),
+            Some("x")
+        );
+        assert_eq!(info.locate(&new_and).unwrap(), "`both` at and.rn:4..11");
+        let y = new_region.param(1).unwrap().into_val();
+        assert_eq!(info.locate(&y).unwrap(), "in region `binary`");
+        assert_eq!(info.locate(&true.into_val()), None);
+        let error = Error::TypeMismatch.with_value(new_and);
+        assert_eq!(info.locate_error(&error).unwrap(), "`both` at and.rn:4..11");
+        assert_eq!(info.locate_error(&Error::TypeMismatch), None);
+    }
+}
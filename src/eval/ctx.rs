@@ -3,16 +3,21 @@ A `rain` evaluation context
 */
 
 use super::Error;
-use super::Substitute;
+use super::Fuel;
+use super::{MemoTable, Strategy, Substitute};
+use super::{Trace, TraceEvent};
+use crate::debug::DebugInfo;
 use crate::region::{Region, Regional};
 use crate::typing::{Type, Typed};
-use crate::value::{ValId, Value};
+use crate::value::{arr::ValArr, ValId, Value};
 use fxhash::FxBuildHasher;
 use im_rc::hashmap::Entry;
 use im_rc::{HashMap, Vector};
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
 use std::iter::Iterator;
 use std::ops::Deref;
+use std::rc::Rc;
 use Ordering::*;
 
 /// A `rain` evaluation context
@@ -32,6 +37,22 @@ pub struct EvalCtx {
     /// Anything shallower than this should just be ignored
     /// This must *always* be less than or equal to the depth of the current region
     root_depth: usize,
+    /// The budget of this evaluation context, shared with its parents and fresh contexts created from it.
+    /// `None` if unlimited
+    fuel: Option<Rc<Cell<Fuel>>>,
+    /// The trace of this evaluation context, shared with its parents and fresh contexts created from it.
+    /// `None` if tracing is disabled
+    trace: Option<Rc<RefCell<Trace>>>,
+    /// The debug information carried through this evaluation context, shared with its parents and fresh contexts
+    /// created from it. `None` if debug information is not being preserved
+    debug: Option<Rc<RefCell<DebugInfo>>>,
+    /// The normalization strategy of this evaluation context, shared with fresh contexts created from it
+    strategy: Strategy,
+    /// The memo table shared by this evaluation context, if any
+    memo: Option<MemoTable>,
+    /// The region substitution results in this scope are memoized under, along with the arguments substituted and
+    /// whether they were substituted inline. `None` if results in this scope are not memoized
+    memo_key: Option<(Region, ValArr, bool)>,
 }
 
 impl Default for EvalCtx {
@@ -93,6 +114,225 @@ impl EvalCtx {
             root_depth: 0,
             domain_region: Region::NULL,
             target_region: Region::NULL,
+            fuel: None,
+            trace: None,
+            debug: None,
+            strategy: Strategy::Eager,
+            memo: None,
+            memo_key: None,
+        }
+    }
+    /// Create a new, empty evaluation context which records a trace of its evaluation
+    #[inline]
+    pub fn traced() -> EvalCtx {
+        EvalCtx {
+            trace: Some(Rc::new(RefCell::new(Trace::new()))),
+            ..EvalCtx::new()
+        }
+    }
+    /// Create a new, empty evaluation context which carries debug information over to the results of substitution
+    #[inline]
+    pub fn with_debug_info(info: DebugInfo) -> EvalCtx {
+        EvalCtx {
+            debug: Some(Rc::new(RefCell::new(info))),
+            ..EvalCtx::new()
+        }
+    }
+    /// Create a new, empty evaluation context with a given budget
+    #[inline]
+    pub fn with_fuel(fuel: Fuel) -> EvalCtx {
+        EvalCtx {
+            fuel: Some(Rc::new(Cell::new(fuel))),
+            ..EvalCtx::new()
+        }
+    }
+    /// Create a new, empty evaluation context with a given normalization strategy
+    #[inline]
+    pub fn with_strategy(strategy: Strategy) -> EvalCtx {
+        EvalCtx {
+            strategy,
+            ..EvalCtx::new()
+        }
+    }
+    /**
+    Create a new, empty evaluation context consulting a given memo table.
+
+    Results of evaluation in the outermost scope of a region substitution are memoized in the table, and hence may be
+    reused by other contexts sharing it, including across threads.
+    */
+    #[inline]
+    pub fn with_memo(memo: MemoTable) -> EvalCtx {
+        EvalCtx {
+            memo: Some(memo),
+            ..EvalCtx::new()
+        }
+    }
+    /// Create a new, empty evaluation context drawing on the same budget, recording to the same trace and debug
+    /// information, following the same normalization strategy and consulting the same memo table as this one
+    #[inline]
+    pub fn fresh(&self) -> EvalCtx {
+        EvalCtx {
+            fuel: self.fuel.clone(),
+            trace: self.trace.clone(),
+            debug: self.debug.clone(),
+            strategy: self.strategy,
+            memo: self.memo.clone(),
+            ..EvalCtx::new()
+        }
+    }
+    /// Get a fresh context for the applications made while substituting in this one, if it has a budget, trace,
+    /// debug information, normalization strategy or memo table
+    #[inline]
+    pub(crate) fn app_ctx(&self) -> Option<EvalCtx> {
+        if self.fuel.is_some()
+            || self.trace.is_some()
+            || self.debug.is_some()
+            || self.strategy.is_lazy()
+            || self.memo.is_some()
+        {
+            Some(self.fresh())
+        } else {
+            None
+        }
+    }
+    /// Start recording a trace of this evaluation context, and of every context sharing its budget, if not already
+    #[inline]
+    pub fn enable_trace(&mut self) {
+        if self.trace.is_none() {
+            self.trace = Some(Rc::new(RefCell::new(Trace::new())))
+        }
+    }
+    /// Stop recording a trace of this evaluation context, returning the trace recorded so far, if any
+    #[inline]
+    pub fn disable_trace(&mut self) -> Option<Trace> {
+        self.trace.take().map(|trace| trace.borrow().clone())
+    }
+    /// Get a copy of the trace recorded by this evaluation context, or `None` if tracing is disabled
+    #[inline]
+    pub fn trace(&self) -> Option<Trace> {
+        self.trace.as_ref().map(|trace| trace.borrow().clone())
+    }
+    /// Clear the trace recorded by this evaluation context, if any
+    #[inline]
+    pub fn clear_trace(&mut self) {
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().clear()
+        }
+    }
+    /// Record an event in the trace of this evaluation context. The event is only constructed if tracing is enabled.
+    #[inline]
+    pub fn record<F: FnOnce() -> TraceEvent>(&self, event: F) {
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().push(event())
+        }
+    }
+    /// Start carrying the given debug information over to the results of substitution in this evaluation context
+    #[inline]
+    pub fn set_debug_info(&mut self, info: DebugInfo) {
+        self.debug = Some(Rc::new(RefCell::new(info)))
+    }
+    /// Get a copy of the debug information carried by this evaluation context, if any
+    #[inline]
+    pub fn debug_info(&self) -> Option<DebugInfo> {
+        self.debug.as_ref().map(|debug| debug.borrow().clone())
+    }
+    /// Stop carrying debug information in this evaluation context, returning the debug information gathered, if any
+    #[inline]
+    pub fn take_debug_info(&mut self) -> Option<DebugInfo> {
+        self.debug.take().map(|debug| debug.borrow().clone())
+    }
+    /// Carry the debug information attached to a value over to the value it was mapped to, if debug information is
+    /// being preserved
+    #[inline]
+    pub fn preserve_debug_info(&self, value: &ValId, result: &ValId) {
+        if let Some(debug) = &self.debug {
+            debug.borrow_mut().propagate(value, result);
+        }
+    }
+    /// Carry the debug information attached to a region over to the region it was mapped to, if debug information
+    /// is being preserved
+    #[inline]
+    pub fn preserve_region_debug_info(&self, region: &Region, result: &Region) {
+        if let Some(debug) = &self.debug {
+            debug.borrow_mut().propagate_region(region, result);
+        }
+    }
+    /// Get the normalization strategy of this evaluation context
+    #[inline]
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+    /// Set the normalization strategy of this evaluation context
+    #[inline]
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy
+    }
+    /// Get the memo table consulted by this evaluation context, if any
+    #[inline]
+    pub fn memo(&self) -> Option<&MemoTable> {
+        self.memo.as_ref()
+    }
+    /// Set the memo table consulted by this evaluation context, or `None` to stop memoizing
+    #[inline]
+    pub fn set_memo(&mut self, memo: Option<MemoTable>) {
+        if memo.is_none() {
+            self.memo_key = None
+        }
+        self.memo = memo
+    }
+    /// Memoize the result of evaluating a value in the current scope, if it is memoized
+    #[inline]
+    pub fn memoize(&self, value: &ValId, result: &ValId) {
+        if let (Some(memo), Some((region, args, inline))) = (&self.memo, &self.memo_key) {
+            memo.insert(region, args, *inline, value, result.clone())
+        }
+    }
+    /// Get the current budget of this evaluation context, or `None` if unlimited
+    #[inline]
+    pub fn fuel(&self) -> Option<Fuel> {
+        self.fuel.as_ref().map(|fuel| fuel.get())
+    }
+    /// Set the budget of this evaluation context, and of every context sharing its budget
+    #[inline]
+    pub fn set_fuel(&mut self, fuel: Fuel) {
+        if let Some(shared) = &self.fuel {
+            shared.set(fuel)
+        } else {
+            self.fuel = Some(Rc::new(Cell::new(fuel)))
+        }
+    }
+    /**
+    Add a number of steps to the budget of this evaluation context. Has no effect on an unlimited budget.
+
+    An evaluation which failed with `Error::OutOfFuel` may be resumed by refuelling and re-running it.
+    */
+    #[inline]
+    pub fn refuel(&mut self, steps: u64) {
+        if let Some(shared) = &self.fuel {
+            let mut fuel = shared.get();
+            fuel.refuel(steps);
+            shared.set(fuel)
+        }
+    }
+    /// Consume a step of the budget of this evaluation context, returning an error if it is exhausted
+    #[inline]
+    pub fn consume_fuel(&self) -> Result<(), Error> {
+        if let Some(shared) = &self.fuel {
+            let mut fuel = shared.get();
+            let result = fuel.consume();
+            shared.set(fuel);
+            result
+        } else {
+            Ok(())
+        }
+    }
+    /// Check that a region depth is within the budget of this evaluation context
+    #[inline]
+    pub fn check_depth(&self, depth: usize) -> Result<(), Error> {
+        if let Some(shared) = &self.fuel {
+            shared.get().check_depth(depth)
+        } else {
+            Ok(())
         }
     }
     /// Get whether this evaluation context is empty
@@ -119,14 +359,31 @@ impl EvalCtx {
     #[inline]
     pub fn pop(&mut self) {
         if let Some(parent) = self.parent().cloned() {
-            *self = parent
+            self.restore(parent)
         } else {
             self.clear()
         }
     }
-    /// Clear this evaluation context
+    /// Restore a saved state of this evaluation context, keeping its current budget, trace, debug information,
+    /// strategy and memo table
+    #[inline]
+    fn restore(&mut self, saved: EvalCtx) {
+        let fuel = self.fuel.take();
+        let trace = self.trace.take();
+        let debug = self.debug.take();
+        let strategy = self.strategy;
+        let memo = self.memo.take();
+        *self = saved;
+        self.fuel = fuel;
+        self.trace = trace;
+        self.debug = debug;
+        self.strategy = strategy;
+        self.memo = memo;
+    }
+    /// Clear this evaluation context, keeping its budget, trace, debug information, strategy and memo table
     #[inline]
     pub fn clear(&mut self) {
+        self.memo_key = None;
         self.root_depth = 0;
         self.domain_region = Region::NULL;
         self.target_region = Region::NULL;
@@ -141,11 +398,13 @@ impl EvalCtx {
         rhs: ValId,
         cfg: SubCfg,
     ) -> Result<Option<ValId>, Error> {
+        self.consume_fuel()?;
+
         // Typecheck
         if cfg.check_ty && lhs != rhs {
             let lhs_sub_ty = lhs.ty().substitute_ty(self)?;
             if lhs_sub_ty != rhs.ty() {
-                return Err(Error::TypeMismatch);
+                return Err(Error::type_mismatch(lhs_sub_ty, rhs.clone_ty()).with_value(rhs));
             }
         }
 
@@ -222,6 +481,11 @@ LHS_REGION(depth = {}) <=> DOMAIN_REGION(depth = {}) = {:?}",
             );
         }
 
+        self.record(|| TraceEvent::Substitution {
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+        });
+
         // Evaluation cache insertion
         match self.eval_cache.entry(lhs) {
             Entry::Vacant(v) => {
@@ -282,13 +546,16 @@ LHS_REGION(depth = {}) <=> DOMAIN_REGION(depth = {}) = {:?}",
                             .into(),
                         self.target_region.clone(),
                     )?;
+                    self.preserve_region_debug_info(region, &new_target_region);
                     self.target_region = new_target_region.clone();
                     inline_params.get_or_insert(new_target_region.into_params())
                 }
                 .next()
                 .expect("Too few inline parameters");
-                self.substitute_impl(param.into_val(), inline_param.into_val(), SubCfg::UNCHECKED)
-                    .expect("This should always succeed!");
+                let param = param.into_val();
+                let inline_param = inline_param.into_val();
+                self.preserve_debug_info(&param, &inline_param);
+                self.substitute_impl(param, inline_param, SubCfg::UNCHECKED)?;
             } else {
                 return Err(Error::NoInlineError);
             }
@@ -313,6 +580,7 @@ LHS_REGION(depth = {}) <=> DOMAIN_REGION(depth = {}) = {:?}",
     where
         I: Iterator<Item = ValId>,
     {
+        self.check_depth(region.depth())?;
         match self.domain_region.partial_cmp(region) {
             None => return Err(Error::IncomparableSub),
             Some(Less) => {}
@@ -326,10 +594,23 @@ LHS_REGION(depth = {}) <=> DOMAIN_REGION(depth = {}) = {:?}",
             Some(self.clone())
         };
         self.domain_region = region.clone();
+        // Only results in the outermost scope are memoized, as nested scopes depend on further substitutions.
+        // Lazily evaluated results are not memoized, so as to never hand thunks to eager contexts.
+        let memoized = self.memo.is_some() && old_self.is_none() && !self.strategy().is_lazy();
+        let mut args = Vec::new();
+        let values = values.inspect(|value| {
+            if memoized {
+                args.push(value.clone())
+            }
+        });
         let result = self.substitute_region_body(region, values, inline);
+        self.memo_key = match &result {
+            Ok(None) if memoized => Some((region.clone(), args.into_iter().collect(), inline)),
+            _ => None,
+        };
         if result.is_err() {
             if let Some(old_self) = old_self {
-                *self = old_self
+                self.restore(old_self)
             } else {
                 self.clear()
             }
@@ -346,8 +627,23 @@ LHS_REGION(depth = {}) <=> DOMAIN_REGION(depth = {}) = {:?}",
             return Some(value.clone());
         }
         // Check the cache
-        if let Some(value) = self.eval_cache.get(value) {
-            return Some(value.clone());
+        if let Some(result) = self.eval_cache.get(value) {
+            self.record(|| TraceEvent::CacheHit {
+                value: value.clone(),
+                result: result.clone(),
+            });
+            return Some(result.clone());
+        }
+        // Check the memo table
+        if let (Some(memo), Some((region, args, inline))) = (&self.memo, &self.memo_key) {
+            if let Some(result) = memo.get(region, args, *inline, value) {
+                self.record(|| TraceEvent::CacheHit {
+                    value: value.clone(),
+                    result: result.clone(),
+                });
+                self.preserve_debug_info(value, &result);
+                return Some(result);
+            }
         }
         None
     }
@@ -0,0 +1,188 @@
+/*!
+Budgets on the work an evaluation may perform
+*/
+
+use crate::value::Error;
+
+/**
+A budget on the work an evaluation may perform: a number of evaluation steps, i.e. substitutions and applications,
+and a maximum region depth.
+
+An evaluation which exhausts its budget fails with [`Error::OutOfFuel`](Error::OutOfFuel), and may be resumed by
+refuelling and re-running it.
+
+Cycles are not detected: a non-terminating evaluation is only cut off once it exhausts its budget, so callers which
+cannot risk divergence, e.g. fuzzers or a REPL, should always evaluate under a finite budget.
+*/
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct Fuel {
+    /// The number of steps remaining, or `None` if unlimited
+    remaining: Option<u64>,
+    /// The number of steps consumed so far
+    consumed: u64,
+    /// The maximum region depth, or `None` if unlimited
+    max_depth: Option<usize>,
+}
+
+impl Fuel {
+    /// An unlimited budget
+    pub const UNLIMITED: Fuel = Fuel {
+        remaining: None,
+        consumed: 0,
+        max_depth: None,
+    };
+    /// A budget of a given number of steps, with unlimited region depth
+    #[inline]
+    pub fn steps(steps: u64) -> Fuel {
+        Fuel {
+            remaining: Some(steps),
+            ..Self::UNLIMITED
+        }
+    }
+    /// Limit the region depth of this budget
+    #[inline]
+    pub fn with_max_depth(self, max_depth: usize) -> Fuel {
+        Fuel {
+            max_depth: Some(max_depth),
+            ..self
+        }
+    }
+    /// Get the number of steps remaining, or `None` if unlimited
+    #[inline]
+    pub fn remaining(&self) -> Option<u64> {
+        self.remaining
+    }
+    /// Get the number of steps consumed so far
+    #[inline]
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+    /// Get the maximum region depth, or `None` if unlimited
+    #[inline]
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+    /// Check whether this budget has run out of steps
+    #[inline]
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+    /// Add a number of steps to this budget. Has no effect on an unlimited budget.
+    #[inline]
+    pub fn refuel(&mut self, steps: u64) {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_add(steps)
+        }
+    }
+    /// Consume a step of this budget, returning an error if it is exhausted
+    #[inline]
+    pub fn consume(&mut self) -> Result<(), Error> {
+        match &mut self.remaining {
+            Some(0) => return Err(Error::OutOfFuel),
+            Some(remaining) => *remaining -= 1,
+            None => {}
+        }
+        self.consumed += 1;
+        Ok(())
+    }
+    /// Check that a region depth is within this budget
+    #[inline]
+    pub fn check_depth(&self, depth: usize) -> Result<(), Error> {
+        match self.max_depth {
+            Some(max_depth) if depth > max_depth => Err(Error::RegionTooDeep),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{Application, Apply, EvalCtx};
+    use crate::function::lambda::Lambda;
+    use crate::primitive::logical::{And, Bool};
+    use crate::region::Region;
+    use crate::typing::Type;
+    use crate::value::{expr::Sexpr, Value};
+
+    #[test]
+    fn fuel_consumption_and_refuelling() {
+        let mut fuel = Fuel::steps(2).with_max_depth(3);
+        assert_eq!(fuel.consume(), Ok(()));
+        assert_eq!(fuel.consume(), Ok(()));
+        assert!(fuel.is_exhausted());
+        assert_eq!(fuel.consume(), Err(Error::OutOfFuel));
+        assert_eq!(fuel.consumed(), 2);
+        fuel.refuel(1);
+        assert_eq!(fuel.remaining(), Some(1));
+        assert_eq!(fuel.consume(), Ok(()));
+        assert_eq!(fuel.consumed(), 3);
+        assert_eq!(fuel.check_depth(3), Ok(()));
+        assert_eq!(fuel.check_depth(4), Err(Error::RegionTooDeep));
+        let mut unlimited = Fuel::UNLIMITED;
+        unlimited.refuel(5);
+        assert_eq!(unlimited.remaining(), None);
+        assert_eq!(unlimited.consume(), Ok(()));
+        assert!(!unlimited.is_exhausted());
+    }
+
+    #[test]
+    fn evaluation_context_fuel_can_be_resumed() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let and = Sexpr::try_new(vec![And.into_val(), x, y])
+            .unwrap()
+            .into_val();
+        let and = Lambda::try_new(and, region).unwrap().into_val();
+        let args = [true.into_val(), true.into_val()];
+
+        let mut ctx = Some(EvalCtx::with_fuel(Fuel::steps(1)));
+        assert_eq!(and.curried_in(&args, &mut ctx), Err(Error::OutOfFuel));
+        let ctx_ref = ctx.as_mut().unwrap();
+        assert!(ctx_ref.fuel().unwrap().is_exhausted());
+        ctx_ref.refuel(100);
+        match and.curried_in(&args, &mut ctx) {
+            Ok(Application::Success(rest, value)) => {
+                assert!(rest.is_empty());
+                assert_eq!(value, true.into_val());
+            }
+            app => panic!("Expected a successful application, got {:?}", app),
+        }
+        assert!(ctx.unwrap().fuel().unwrap().consumed() > 1);
+
+        let mut ctx = Some(EvalCtx::with_fuel(Fuel::UNLIMITED.with_max_depth(0)));
+        assert_eq!(and.curried_in(&args, &mut ctx), Err(Error::RegionTooDeep));
+    }
+
+    #[test]
+    fn fuel_runs_out_during_partial_application() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let and = Sexpr::try_new(vec![And.into_val(), x, y])
+            .unwrap()
+            .into_val();
+        let and = Lambda::try_new(and, region).unwrap().into_val();
+        let args = [true.into_val()];
+        let expected = and.applied(&args).unwrap();
+        // Every budget too small to complete the partial application, including one running out exactly at the
+        // substitution of the inlined parameter, fails gracefully, and may be resumed after refuelling
+        let mut steps = 0;
+        loop {
+            let mut ctx = Some(EvalCtx::with_fuel(Fuel::steps(steps)));
+            match and.applied_in(&args, &mut ctx) {
+                Ok(value) => {
+                    assert_eq!(value, expected);
+                    break;
+                }
+                Err(err) => assert_eq!(err, Error::OutOfFuel),
+            }
+            ctx.as_mut().unwrap().refuel(100);
+            assert_eq!(and.applied_in(&args, &mut ctx), Ok(expected.clone()));
+            steps += 1;
+            assert!(steps < 100, "Partial application should terminate");
+        }
+        assert!(steps > 1);
+    }
+}
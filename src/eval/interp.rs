@@ -0,0 +1,564 @@
+/*!
+A reference interpreter for closed `rain` programs, using environments rather than substitution
+*/
+
+use super::{EvalCtx, Fuel};
+use crate::control::{phi::Phi, ternary::Ternary, ternary::TernaryKind};
+use crate::function::{external::ExternBindings, lambda::Lambda};
+use crate::region::{Region, Regional};
+use crate::typing::Typed;
+use crate::value::{expr::Sexpr, tuple::Tuple, Error, ValId, Value, ValueEnum};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+
+/**
+A reference interpreter for closed `rain` programs with call-by-value semantics.
+
+Rather than substituting arguments into a function body and re-normalizing it, the interpreter walks the body in an
+environment binding each parameter to a concrete value, only evaluating the branch of a ternary node which is
+actually taken. Values the interpreter does not understand natively, such as nested lambda functions, are evaluated
+by substitution in an [`EvalCtx`](EvalCtx) built from the current environment.
+
+Each call and each value evaluated consumes a step of the interpreter's budget, if any, which is shared with the
+evaluation contexts it builds. As calls which are not in tail position are evaluated recursively, nested calls are
+limited to a maximum depth, beyond which evaluation fails with [`Error::CallTooDeep`](Error::CallTooDeep) rather than
+overflowing the stack.
+*/
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    /// The implementations of external functions available to this interpreter
+    externs: ExternBindings,
+    /// An empty evaluation context holding the budget of this interpreter
+    ctx: EvalCtx,
+    /// The current depth of nested calls
+    depth: usize,
+    /// The maximum depth of nested calls
+    max_depth: usize,
+}
+
+/// The default maximum depth of nested calls of an interpreter
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 128;
+
+impl Default for Interpreter {
+    #[inline]
+    fn default() -> Interpreter {
+        Interpreter::with_externs(ExternBindings::default())
+    }
+}
+
+/// A frame of an interpreter environment, binding the parameters of a region to concrete values
+#[derive(Debug, Clone)]
+struct Frame {
+    /// The region whose parameters this frame binds
+    region: Region,
+    /// The values of the parameters of this frame's region
+    args: Vec<ValId>,
+    /// The values already computed in this frame
+    cache: HashMap<ValId, ValId, FxBuildHasher>,
+}
+
+/// An interpreter environment: a stack of frames for nested regions, innermost last
+#[derive(Debug, Clone, Default)]
+struct Env {
+    /// The frames of this environment
+    frames: Vec<Frame>,
+    /// An evaluation context equivalent to this environment, built on demand
+    ctx: Option<EvalCtx>,
+}
+
+impl Env {
+    /// Push a frame binding the parameters of a region nested in the innermost region of this environment
+    fn push(&mut self, base: &EvalCtx, region: Region, args: Vec<ValId>) -> Result<(), Error> {
+        base.check_depth(region.depth())?;
+        self.ctx = None;
+        self.frames.push(Frame {
+            region,
+            args,
+            cache: HashMap::default(),
+        });
+        Ok(())
+    }
+    /// Get the index of the frame binding the region of a value, if any
+    fn frame_ix(&self, value: &ValId) -> Option<usize> {
+        let region = value.region();
+        self.frames.iter().rposition(|frame| frame.region == region)
+    }
+    /// Evaluate a value by substitution in an evaluation context equivalent to this environment
+    fn substitute(&mut self, base: &EvalCtx, value: &ValId) -> Result<ValId, Error> {
+        let ctx = if let Some(ctx) = &mut self.ctx {
+            ctx
+        } else {
+            let mut ctx = base.fresh();
+            for frame in self.frames.iter() {
+                ctx.substitute_region(&frame.region, frame.args.iter().cloned(), false)?;
+            }
+            self.ctx.get_or_insert(ctx)
+        };
+        ctx.evaluate(value)
+    }
+}
+
+impl Interpreter {
+    /// Create a new interpreter with no external functions bound
+    #[inline]
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+    /// Create a new interpreter with a set of bindings for external functions
+    #[inline]
+    pub fn with_externs(externs: ExternBindings) -> Interpreter {
+        Interpreter {
+            externs,
+            ctx: EvalCtx::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+    /// Get the external function bindings of this interpreter
+    #[inline]
+    pub fn externs(&self) -> &ExternBindings {
+        &self.externs
+    }
+    /// Mutably get the external function bindings of this interpreter
+    #[inline]
+    pub fn externs_mut(&mut self) -> &mut ExternBindings {
+        &mut self.externs
+    }
+    /// Get the current budget of this interpreter, or `None` if unlimited
+    #[inline]
+    pub fn fuel(&self) -> Option<Fuel> {
+        self.ctx.fuel()
+    }
+    /// Set the budget of this interpreter
+    #[inline]
+    pub fn set_fuel(&mut self, fuel: Fuel) {
+        self.ctx.set_fuel(fuel)
+    }
+    /// Add a number of steps to the budget of this interpreter. Has no effect on an unlimited budget.
+    #[inline]
+    pub fn refuel(&mut self, steps: u64) {
+        self.ctx.refuel(steps)
+    }
+    /// Get the maximum depth of nested calls of this interpreter
+    #[inline]
+    pub fn max_call_depth(&self) -> usize {
+        self.max_depth
+    }
+    /// Set the maximum depth of nested calls of this interpreter
+    #[inline]
+    pub fn set_max_call_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth
+    }
+    /**
+    Run a closed lambda function on a full list of concrete arguments, returning its concrete result.
+
+    Return an error if the lambda function is not closed, or the arguments do not match its parameter types.
+    */
+    pub fn run(&mut self, lambda: &Lambda, args: &[ValId]) -> Result<ValId, Error> {
+        if !lambda.region().is_null() {
+            return Err(Error::NotClosed);
+        }
+        let param_tys = lambda.get_ty().param_tys();
+        if args.len() < param_tys.len() {
+            return Err(Error::TooFewArgs);
+        }
+        if args.len() > param_tys.len() {
+            return Err(Error::TooManyArgs);
+        }
+        for (ix, (arg, param_ty)) in args.iter().zip(param_tys.iter()).enumerate() {
+            if arg.ty() != *param_ty {
+                return Err(Error::type_mismatch(param_ty.clone(), arg.clone_ty())
+                    .with_value(arg.clone())
+                    .at_arg(ix));
+            }
+        }
+        self.enter_lambda(Env::default(), lambda, args.to_vec())
+    }
+    /**
+    Apply a closed value to a list of concrete arguments, returning the concrete result.
+
+    Partial applications of lambda functions and external functions are left symbolic.
+    */
+    pub fn call(&mut self, function: &ValId, args: &[ValId]) -> Result<ValId, Error> {
+        if self.depth >= self.max_depth {
+            return Err(Error::CallTooDeep);
+        }
+        self.depth += 1;
+        let result = self.call_nested(function, args);
+        self.depth -= 1;
+        result
+    }
+    /// Apply a closed value to a list of concrete arguments, one call deeper than the caller
+    fn call_nested(&mut self, function: &ValId, args: &[ValId]) -> Result<ValId, Error> {
+        let mut function = function.clone();
+        let mut args = args.to_vec();
+        while !args.is_empty() {
+            self.ctx.consume_fuel()?;
+            let rest = match function.as_enum() {
+                ValueEnum::Lambda(lambda) => {
+                    if !lambda.region().is_null() {
+                        return Err(Error::NotClosed);
+                    }
+                    let arity = lambda.def_region().len();
+                    if arity > args.len() {
+                        return function.applied(&args);
+                    }
+                    let rest = args.split_off(arity);
+                    function = self.enter_lambda(Env::default(), lambda, args)?;
+                    rest
+                }
+                ValueEnum::Ternary(ternary) => {
+                    let branch = Self::select(ternary, &args[0])?.clone();
+                    function = branch;
+                    args.split_off(1)
+                }
+                ValueEnum::Extern(decl) => {
+                    let arity = decl.arity();
+                    if arity > args.len() {
+                        return function.applied(&args);
+                    }
+                    let rest = args.split_off(arity);
+                    function = self.externs.call(decl, &args)?;
+                    rest
+                }
+                ValueEnum::Effect(effect) => {
+                    let decl = effect.decl();
+                    let arity = decl.arity();
+                    if arity > args.len() {
+                        return function.applied(&args);
+                    }
+                    let rest = args.split_off(arity);
+                    function = self.externs.call(decl, &args)?;
+                    rest
+                }
+                ValueEnum::Sexpr(sexpr) if sexpr.len() > 1 => {
+                    if let Some((phi, ix)) = Self::phi_member(sexpr) {
+                        let mut new_args = Vec::with_capacity(sexpr.len() - 2 + args.len());
+                        new_args.extend(sexpr[2..].iter().cloned());
+                        new_args.extend(args.drain(..));
+                        let (member, rest) = self.enter_phi(phi, ix, new_args)?;
+                        function = member;
+                        rest
+                    } else {
+                        let mut new_args = Vec::with_capacity(sexpr.len() - 1 + args.len());
+                        new_args.extend(sexpr[1..].iter().cloned());
+                        new_args.extend(args.drain(..));
+                        function = sexpr[0].clone();
+                        new_args
+                    }
+                }
+                ValueEnum::Thunk(thunk) => {
+                    let mut new_args = Vec::with_capacity(thunk.len() - 1 + args.len());
+                    new_args.extend(thunk[1..].iter().cloned());
+                    new_args.extend(args.drain(..));
+                    function = thunk[0].clone();
+                    new_args
+                }
+                _ => return function.applied(&args),
+            };
+            args = rest;
+        }
+        // Deferred applications are forced, so as to always yield concrete results
+        if let ValueEnum::Thunk(thunk) = function.as_enum() {
+            return self.call(&thunk[0], &thunk[1..]);
+        }
+        Ok(function)
+    }
+    /// Enter a lambda function with a full list of arguments in a given environment
+    fn enter_lambda(
+        &mut self,
+        mut env: Env,
+        lambda: &Lambda,
+        args: Vec<ValId>,
+    ) -> Result<ValId, Error> {
+        env.push(&self.ctx, lambda.def_region().clone(), args)?;
+        self.eval_in(&mut env, lambda.result())
+    }
+    /**
+    Enter the `ix`th member of a phi node with a list of arguments, binding the phi node's parameters to its members.
+
+    Returns the value of the member, applied to as many arguments as it directly accepts, along with the remaining arguments.
+    */
+    fn enter_phi(
+        &mut self,
+        phi: &Phi,
+        ix: usize,
+        mut args: Vec<ValId>,
+    ) -> Result<(ValId, Vec<ValId>), Error> {
+        let mut env = Env::default();
+        env.push(&self.ctx, phi.def_region().clone(), phi.projections()?)?;
+        let member = &phi.values()[ix];
+        match member.as_enum() {
+            ValueEnum::Lambda(lambda)
+                if lambda.def_region().parent() == phi.def_region()
+                    && lambda.def_region().len() <= args.len() =>
+            {
+                let rest = args.split_off(lambda.def_region().len());
+                Ok((self.enter_lambda(env, lambda, args)?, rest))
+            }
+            _ => Ok((self.eval_in(&mut env, member)?, args)),
+        }
+    }
+    /// Evaluate a value in an environment
+    fn eval_in(&mut self, env: &mut Env, value: &ValId) -> Result<ValId, Error> {
+        let frame = match env.frame_ix(value) {
+            Some(frame) => frame,
+            None if value.region().is_null() => return Ok(value.clone()),
+            None => return env.substitute(&self.ctx, value),
+        };
+        if let Some(result) = env.frames[frame].cache.get(value) {
+            return Ok(result.clone());
+        }
+        self.ctx.consume_fuel()?;
+        let result = match value.as_enum() {
+            ValueEnum::Parameter(param) => env.frames[frame]
+                .args
+                .get(param.ix())
+                .cloned()
+                .ok_or(Error::UndefParam)?,
+            ValueEnum::Sexpr(sexpr) => self.eval_sexpr(env, sexpr)?,
+            ValueEnum::Thunk(thunk) => {
+                let function = self.eval_in(env, &thunk[0])?;
+                let args = thunk[1..]
+                    .iter()
+                    .map(|arg| self.eval_in(env, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(&function, &args)?
+            }
+            ValueEnum::Tuple(tuple) => {
+                let elems = tuple
+                    .iter()
+                    .map(|elem| self.eval_in(env, elem))
+                    .collect::<Result<_, _>>()?;
+                Tuple::try_new(elems)?.into_val()
+            }
+            _ => env.substitute(&self.ctx, value)?,
+        };
+        env.frames[frame]
+            .cache
+            .insert(value.clone(), result.clone());
+        Ok(result)
+    }
+    /// Evaluate an S-expression in an environment, only evaluating the branch of a ternary node which is taken
+    fn eval_sexpr(&mut self, env: &mut Env, sexpr: &Sexpr) -> Result<ValId, Error> {
+        if sexpr.is_empty() {
+            return Ok(sexpr.clone().into_val());
+        }
+        if let ValueEnum::Ternary(ternary) = sexpr[0].as_enum() {
+            if sexpr.len() > 1 {
+                let cond = self.eval_in(env, &sexpr[1])?;
+                let branch = Self::select(ternary, &cond)?;
+                let branch = self.eval_in(env, branch)?;
+                let rest = sexpr[2..]
+                    .iter()
+                    .map(|arg| self.eval_in(env, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return self.call(&branch, &rest);
+            }
+        }
+        let function = self.eval_in(env, &sexpr[0])?;
+        let args = sexpr[1..]
+            .iter()
+            .map(|arg| self.eval_in(env, arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.call(&function, &args)
+    }
+    /// Select the branch of a ternary node taken for a concrete condition
+    fn select<'a>(ternary: &'a Ternary, cond: &ValId) -> Result<&'a ValId, Error> {
+        let high = match (ternary.ternary_kind(), cond.as_enum()) {
+            (TernaryKind::Bool, ValueEnum::Bool(b)) => *b,
+            (TernaryKind::Switch, ValueEnum::Index(ix)) => ix.ix() != 0,
+            (_, ValueEnum::Bool(_)) | (_, ValueEnum::Index(_)) => return Err(Error::TypeMismatch),
+            _ => return Err(Error::NotClosed),
+        };
+        Ok(if high { ternary.high() } else { ternary.low() })
+    }
+    /// Get the phi node and member index projected by an S-expression, if any
+    fn phi_member(sexpr: &Sexpr) -> Option<(&Phi, usize)> {
+        match (sexpr[0].as_enum(), sexpr[1].as_enum()) {
+            (ValueEnum::Phi(phi), ValueEnum::Index(ix)) => Some((phi, ix.ix() as usize)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::external::Extern;
+    use crate::primitive::bits::{BinOp, BitsTy};
+    use crate::primitive::logical::{binary_ty, unary_ty, And, Bool, Not, Or};
+    use crate::typing::Type;
+    use crate::value::TypeId;
+    use crate::{tyarr, valarr};
+
+    #[test]
+    fn boolean_mux_via_ternary() {
+        let region = Region::with(tyarr![Bool.into_ty(); 3], Region::NULL).unwrap();
+        let select = region.param(0).unwrap().into_val();
+        let high = region.param(1).unwrap().into_val();
+        let low = region.param(2).unwrap().into_val();
+        let ternary = Ternary::conditional(high.clone(), low.clone())
+            .unwrap()
+            .into_val();
+        let mux_res = Sexpr::try_new(vec![ternary, select]).unwrap().into_val();
+        let mux = Lambda::try_new(mux_res, region).unwrap();
+        let mut interp = Interpreter::new();
+        for s in [true, false].iter().copied() {
+            for h in [true, false].iter().copied() {
+                for l in [true, false].iter().copied() {
+                    let args = [s.into_val(), h.into_val(), l.into_val()];
+                    assert_eq!(
+                        interp.run(&mux, &args),
+                        Ok(if s { h } else { l }.into_val())
+                    );
+                }
+            }
+        }
+        assert_eq!(interp.run(&mux, &[true.into_val()]), Err(Error::TooFewArgs));
+    }
+
+    #[test]
+    fn logical_tuples_and_bits() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let and = Sexpr::try_new(vec![And.into_val(), x.clone(), y.clone()])
+            .unwrap()
+            .into_val();
+        let or = Sexpr::try_new(vec![Or.into_val(), x, y])
+            .unwrap()
+            .into_val();
+        let pair = Tuple::try_new(valarr![and, or]).unwrap().into_val();
+        let pair = Lambda::try_new(pair, region).unwrap();
+        let mut interp = Interpreter::new();
+        for x in [true, false].iter().copied() {
+            for y in [true, false].iter().copied() {
+                let expected = Tuple::try_new(valarr![(x && y).into_val(), (x || y).into_val()])
+                    .unwrap()
+                    .into_val();
+                assert_eq!(
+                    interp.run(&pair, &[x.into_val(), y.into_val()]),
+                    Ok(expected)
+                );
+            }
+        }
+
+        let bits_ty = BitsTy(8).into_var();
+        let region = Region::unary(bits_ty.clone_as_ty());
+        let x = region.param(0).unwrap().into_val();
+        let double = Sexpr::try_new(vec![
+            BinOp::Add.into_val(),
+            bits_ty.clone_val(),
+            x.clone(),
+            x,
+        ])
+        .unwrap()
+        .into_val();
+        let double = Lambda::try_new(double, region).unwrap();
+        assert_eq!(
+            interp.run(&double, &[bits_ty.data(100).unwrap().into_val()]),
+            Ok(bits_ty.data(200).unwrap().into_val())
+        );
+        assert_eq!(
+            interp.run(&double, &[bits_ty.data(200).unwrap().into_val()]),
+            Ok(bits_ty.data(144).unwrap().into_val())
+        );
+    }
+
+    #[test]
+    fn mutual_recursion_via_phi() {
+        // ping(b) = if b { pong(b) } else { #true }, pong(b) = ping(!b)
+        let fn_ty: TypeId = unary_ty().clone_as_ty();
+        let rec = Region::with(tyarr![fn_ty.clone(), fn_ty], Region::NULL).unwrap();
+        let ping_rec = rec.param(0).unwrap().into_val();
+        let pong_rec = rec.param(1).unwrap().into_val();
+
+        let ping_region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+        let b = ping_region.param(0).unwrap().into_val();
+        let recurse = Sexpr::try_new(vec![pong_rec, b.clone()])
+            .unwrap()
+            .into_val();
+        let ternary = Ternary::conditional(recurse, true.into_val())
+            .unwrap()
+            .into_val();
+        let ping_res = Sexpr::try_new(vec![ternary, b]).unwrap().into_val();
+        let ping = Lambda::try_new(ping_res, ping_region).unwrap().into_val();
+
+        let pong_region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+        let b = pong_region.param(0).unwrap().into_val();
+        let not_b = Sexpr::try_new(vec![Not.into_val(), b]).unwrap().into_val();
+        let pong_res = Sexpr::try_new(vec![ping_rec, not_b]).unwrap().into_val();
+        let pong = Lambda::try_new(pong_res, pong_region).unwrap().into_val();
+
+        let phi = Phi::try_new(valarr![ping, pong], rec).unwrap();
+        let ping = phi.project(0).unwrap();
+        let pong = phi.project(1).unwrap();
+        let mut interp = Interpreter::new();
+        for b in [true, false].iter().copied() {
+            assert_eq!(interp.call(&ping, &[b.into_val()]), Ok(true.into_val()));
+            assert_eq!(interp.call(&pong, &[b.into_val()]), Ok(true.into_val()));
+        }
+        interp.set_fuel(Fuel::steps(3));
+        assert_eq!(
+            interp.call(&ping, &[true.into_val()]),
+            Err(Error::OutOfFuel)
+        );
+        interp.refuel(100);
+        assert_eq!(interp.call(&ping, &[true.into_val()]), Ok(true.into_val()));
+        assert_eq!(interp.max_call_depth(), DEFAULT_MAX_CALL_DEPTH);
+
+        let rec = Region::with(tyarr![binary_ty().clone_as_ty(); 2], Region::NULL).unwrap();
+        assert_eq!(
+            Phi::try_new(valarr![true.into_val(), false.into_val()], rec),
+            Err(Error::TypeMismatch)
+        );
+    }
+
+    #[test]
+    fn unbounded_recursion_is_cut_off() {
+        // ping(b) = pong(b), pong(b) = ping(b)
+        let fn_ty: TypeId = unary_ty().clone_as_ty();
+        let rec = Region::with(tyarr![fn_ty.clone(), fn_ty], Region::NULL).unwrap();
+        let member = |callee: usize| {
+            let region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+            let b = region.param(0).unwrap().into_val();
+            let callee = rec.param(callee).unwrap().into_val();
+            let result = Sexpr::try_new(vec![callee, b]).unwrap().into_val();
+            Lambda::try_new(result, region).unwrap().into_val()
+        };
+        let phi = Phi::try_new(valarr![member(1), member(0)], rec.clone()).unwrap();
+        let ping = phi.project(0).unwrap();
+        let mut interp = Interpreter::new();
+        interp.set_max_call_depth(32);
+        assert_eq!(
+            interp.call(&ping, &[true.into_val()]),
+            Err(Error::CallTooDeep)
+        );
+        // The depth of nested calls is restored after an error
+        let id = Lambda::id(Bool.into_ty()).into_val();
+        assert_eq!(interp.call(&id, &[true.into_val()]), Ok(true.into_val()));
+    }
+
+    #[test]
+    fn calling_bound_externs() {
+        let nand = Extern::c_fn("nand", binary_ty()).unwrap().into_val();
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let not_x = Sexpr::try_new(vec![nand, x.clone(), x]).unwrap().into_val();
+        let not = Lambda::try_new(not_x, region).unwrap();
+        let mut interp = Interpreter::new();
+        assert_eq!(
+            interp.run(&not, &[true.into_val()]),
+            Err(Error::UnboundExtern)
+        );
+        interp.externs_mut().bind("nand", |args| {
+            match (args[0].as_enum(), args[1].as_enum()) {
+                (ValueEnum::Bool(l), ValueEnum::Bool(r)) => Ok((!(*l && *r)).into_val()),
+                _ => Err(Error::TypeMismatch),
+            }
+        });
+        assert_eq!(interp.run(&not, &[true.into_val()]), Ok(false.into_val()));
+        assert_eq!(interp.run(&not, &[false.into_val()]), Ok(true.into_val()));
+    }
+}
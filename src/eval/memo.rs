@@ -0,0 +1,334 @@
+/*!
+Shared, size-bounded memo tables for evaluation, which persist across evaluation contexts and threads
+*/
+
+use crate::region::Region;
+use crate::value::{arr::ValArr, ValId};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The default capacity of a memo table, in entries
+pub const DEFAULT_MEMO_CAPACITY: usize = 1 << 16;
+
+lazy_static! {
+    /// The global memo table
+    static ref GLOBAL_MEMO: MemoTable = MemoTable::new(DEFAULT_MEMO_CAPACITY);
+}
+
+/// The key of a memoized evaluation: a value, evaluated with a list of arguments substituted for a region's parameters
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct MemoKey {
+    /// The region substituted
+    region: Region,
+    /// The arguments substituted for the region's parameters
+    args: ValArr,
+    /// Whether the region was substituted inline
+    inline: bool,
+    /// The value evaluated
+    value: ValId,
+}
+
+/// The data of a memo table
+#[derive(Debug, Clone, Default)]
+struct MemoData {
+    /// The memoized evaluations
+    entries: HashMap<MemoKey, ValId, FxBuildHasher>,
+    /// The keys of the memoized evaluations, oldest first
+    order: VecDeque<MemoKey>,
+    /// The maximum number of entries
+    capacity: usize,
+    /// The number of lookups answered so far
+    hits: u64,
+    /// The number of lookups not answered so far
+    misses: u64,
+}
+
+impl MemoData {
+    /// Evict the oldest entries of this table until it is within its capacity
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            match self.order.pop_front() {
+                Some(key) => {
+                    self.entries.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/**
+A thread-safe, size-bounded memo table of evaluations, keyed by a region, the arguments substituted for its parameters,
+whether they were substituted inline and the value evaluated.
+
+Cloning a memo table yields a handle to the same table, which may be shared by any number of evaluation contexts,
+across threads. When full, the oldest entries are evicted first.
+*/
+#[derive(Debug, Clone)]
+pub struct MemoTable {
+    /// The shared data of this table
+    data: Arc<Mutex<MemoData>>,
+}
+
+impl Default for MemoTable {
+    #[inline]
+    fn default() -> MemoTable {
+        MemoTable::new(DEFAULT_MEMO_CAPACITY)
+    }
+}
+
+impl PartialEq for MemoTable {
+    /// Memo tables are equal if they are handles to the same table
+    #[inline]
+    fn eq(&self, other: &MemoTable) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+}
+
+impl MemoTable {
+    /// Create a new, empty memo table holding at most a given number of entries
+    #[inline]
+    pub fn new(capacity: usize) -> MemoTable {
+        MemoTable {
+            data: Arc::new(Mutex::new(MemoData {
+                capacity,
+                ..MemoData::default()
+            })),
+        }
+    }
+    /// Get a handle to the global memo table
+    #[inline]
+    pub fn global() -> MemoTable {
+        GLOBAL_MEMO.clone()
+    }
+    /// Lock the data of this table
+    #[inline]
+    fn data(&self) -> MutexGuard<MemoData> {
+        // The data of a memo table is always consistent, even if a thread panicked while holding the lock
+        self.data.lock().unwrap_or_else(|err| err.into_inner())
+    }
+    /// Look up the result of evaluating a value with a list of arguments substituted for a region's parameters
+    pub fn get(
+        &self,
+        region: &Region,
+        args: &ValArr,
+        inline: bool,
+        value: &ValId,
+    ) -> Option<ValId> {
+        let key = MemoKey {
+            region: region.clone(),
+            args: args.clone(),
+            inline,
+            value: value.clone(),
+        };
+        let mut data = self.data();
+        let result = data.entries.get(&key).cloned();
+        if result.is_some() {
+            data.hits += 1;
+        } else {
+            data.misses += 1;
+        }
+        result
+    }
+    /// Memoize the result of evaluating a value with a list of arguments substituted for a region's parameters
+    pub fn insert(
+        &self,
+        region: &Region,
+        args: &ValArr,
+        inline: bool,
+        value: &ValId,
+        result: ValId,
+    ) {
+        let key = MemoKey {
+            region: region.clone(),
+            args: args.clone(),
+            inline,
+            value: value.clone(),
+        };
+        let mut data = self.data();
+        if data.capacity == 0 {
+            return;
+        }
+        if data.entries.insert(key.clone(), result).is_none() {
+            data.order.push_back(key);
+            data.evict();
+        }
+    }
+    /// Get the number of entries in this table
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data().entries.len()
+    }
+    /// Check whether this table is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data().entries.is_empty()
+    }
+    /// Get the maximum number of entries in this table
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.data().capacity
+    }
+    /// Set the maximum number of entries in this table, evicting the oldest entries if necessary
+    #[inline]
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut data = self.data();
+        data.capacity = capacity;
+        data.evict()
+    }
+    /// Get the number of lookups this table has answered
+    #[inline]
+    pub fn hits(&self) -> u64 {
+        self.data().hits
+    }
+    /// Get the number of lookups this table has not answered
+    #[inline]
+    pub fn misses(&self) -> u64 {
+        self.data().misses
+    }
+    /// Remove every entry from this table
+    #[inline]
+    pub fn clear(&self) {
+        let mut data = self.data();
+        data.entries.clear();
+        data.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::{DebugData, DebugInfo};
+    use crate::eval::{Application, Apply, EvalCtx};
+    use crate::function::lambda::Lambda;
+    use crate::primitive::logical::{And, Bool, Not};
+    use crate::typing::Type;
+    use crate::valarr;
+    use crate::value::{expr::Sexpr, Value};
+
+    #[test]
+    fn memo_tables_evict_oldest_entries() {
+        let memo = MemoTable::new(2);
+        let region = Region::unary(Bool.into_ty());
+        let args = valarr![true.into_val()];
+        let values = [true.into_val(), false.into_val(), Bool.into_val()];
+        for value in values.iter() {
+            memo.insert(&region, &args, false, value, value.clone());
+        }
+        assert_eq!(memo.len(), 2);
+        assert_eq!(memo.get(&region, &args, false, &values[0]), None);
+        assert_eq!(
+            memo.get(&region, &args, false, &values[2]),
+            Some(values[2].clone())
+        );
+        assert_eq!((memo.hits(), memo.misses()), (1, 1));
+        memo.set_capacity(1);
+        assert_eq!(memo.len(), 1);
+        memo.clear();
+        assert!(memo.is_empty());
+    }
+
+    #[test]
+    fn memo_tables_are_shared_across_contexts_and_threads() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let and = Sexpr::try_new(vec![And.into_val(), x, y])
+            .unwrap()
+            .into_val();
+        let and = Lambda::try_new(and, region).unwrap().into_val();
+        let args = [true.into_val(), false.into_val()];
+        let memo = MemoTable::new(DEFAULT_MEMO_CAPACITY);
+
+        let apply = |memo: &MemoTable| match and
+            .curried_in(&args, &mut Some(EvalCtx::with_memo(memo.clone())))
+        {
+            Ok(Application::Success(rest, value)) => {
+                assert!(rest.is_empty());
+                value
+            }
+            app => panic!("Expected a successful application, got {:?}", app),
+        };
+        assert_eq!(apply(&memo), false.into_val());
+        assert!(!memo.is_empty());
+        assert_eq!(memo.hits(), 0);
+        assert_eq!(apply(&memo), false.into_val());
+        assert!(memo.hits() > 0);
+
+        let shared = memo.clone();
+        let hits = memo.hits();
+        let value = std::thread::spawn(move || {
+            let region = Region::unary(Bool.into_ty());
+            let args = valarr![true.into_val()];
+            let value = region.param(0).unwrap().into_val();
+            shared.insert(&region, &args, false, &value, true.into_val());
+            shared.get(&region, &args, false, &value)
+        })
+        .join()
+        .unwrap();
+        assert_eq!(value, Some(true.into_val()));
+        assert_eq!(memo.hits(), hits + 1);
+    }
+
+    #[test]
+    fn memo_keys_distinguish_inline_substitutions() {
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let not = Sexpr::try_new(vec![Not.into_val(), x]).unwrap().into_val();
+        let args = valarr![true.into_val()];
+        let memo = MemoTable::new(DEFAULT_MEMO_CAPACITY);
+
+        memo.insert(&region, &args, false, &not, false.into_val());
+        assert_eq!(memo.get(&region, &args, true, &not), None);
+        assert_eq!(
+            memo.get(&region, &args, false, &not),
+            Some(false.into_val())
+        );
+        memo.clear();
+
+        let evaluate = |inline| {
+            let mut ctx = EvalCtx::with_memo(memo.clone());
+            ctx.substitute_region(&region, args.iter().cloned(), inline)
+                .unwrap();
+            ctx.evaluate(&not).unwrap()
+        };
+        assert_eq!(evaluate(false), false.into_val());
+        let hits = memo.hits();
+        assert_eq!(evaluate(true), false.into_val());
+        assert_eq!(memo.hits(), hits);
+        assert_eq!(evaluate(true), false.into_val());
+        assert!(memo.hits() > hits);
+    }
+
+    #[test]
+    fn memo_table_hits_preserve_debug_info() {
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let not = Sexpr::try_new(vec![Not.into_val(), x]).unwrap().into_val();
+        let outer = Region::binary(Bool.into_ty());
+        let args = valarr![outer.param(0).unwrap().into_val()];
+        let memo = MemoTable::new(DEFAULT_MEMO_CAPACITY);
+        let mut info = DebugInfo::new();
+        info.insert(not.clone(), DebugData::named("flipped"));
+
+        let evaluate = || {
+            let mut ctx = EvalCtx::with_memo(memo.clone());
+            ctx.set_debug_info(info.clone());
+            ctx.substitute_region(&region, args.iter().cloned(), false)
+                .unwrap();
+            let result = ctx.evaluate(&not).unwrap();
+            (result, ctx.take_debug_info().unwrap())
+        };
+        let (result, first) = evaluate();
+        assert_ne!(result, not);
+        assert_eq!(first.name(&result), Some("flipped"));
+        let hits = memo.hits();
+        let (cached, second) = evaluate();
+        assert_eq!(cached, result);
+        assert!(memo.hits() > hits);
+        assert_eq!(second.name(&cached), Some("flipped"));
+    }
+}
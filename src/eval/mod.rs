@@ -3,10 +3,19 @@
 */
 
 use super::typing::{Type, Typed};
-use crate::region::Regional;
 use crate::value::{expr::Sexpr, Error, TypeId, ValId, Value};
 mod ctx;
+mod fuel;
+mod interp;
+mod memo;
+mod strategy;
+mod trace;
 pub use ctx::EvalCtx;
+pub use fuel::Fuel;
+pub use interp::{Interpreter, DEFAULT_MAX_CALL_DEPTH};
+pub use memo::{MemoTable, DEFAULT_MEMO_CAPACITY};
+pub use strategy::Strategy;
+pub use trace::{Trace, TraceEvent};
 
 /// The result of a *valid* application. An invalid application should return an error!
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -18,31 +27,25 @@ pub enum Application<'a, V = ValId> {
 }
 
 impl<'a> Application<'a> {
-    /// Convert any application into a successful application
+    /// Convert any application into a successful application. Return an error if the arguments of a symbolic
+    /// application have incompatible lifetimes, e.g. if an affine argument is both consumed and borrowed from.
     pub(crate) fn valid_to_success<V: Value + Clone>(
         self,
         value: &V,
         args: &[ValId],
-    ) -> (&'a [ValId], ValId) {
+    ) -> Result<(&'a [ValId], ValId), Error> {
         let ty = match self {
             Application::Symbolic(ty) => ty,
-            Application::Success(rest, val) => return (rest, val),
+            Application::Success(rest, val) => return Ok((rest, val)),
         };
         let mut new_args = Vec::with_capacity(1 + args.len());
         new_args.push(value.clone().into_val());
         new_args.extend_from_slice(args);
-        let region = if let Ok(region) = ty.gcrs(new_args.iter()) {
-            region.clone_region()
-        } else {
-            panic!(
-                "Incomparable regions in successful application:\nTYPE = {}\nARGS = {:#?}",
-                ty, new_args
-            );
-        };
-        (
+        let lifetime = Sexpr::args_lifetime(&ty, &new_args)?;
+        Ok((
             &[],
-            Sexpr::new_unchecked(new_args.into_iter().collect(), region, ty).into_val(),
-        )
+            Sexpr::new_unchecked(new_args.into_iter().collect(), lifetime, ty).into_val(),
+        ))
     }
 }
 
@@ -141,12 +144,18 @@ pub trait Apply: Typed {
         args: &'a [ValId],
         ctx: &mut Option<EvalCtx>,
     ) -> Result<Application<'a>, Error> {
+        if let Some(ctx) = ctx {
+            ctx.consume_fuel()?;
+        }
         let applied = self.apply_in(args, ctx)?;
         let (mut rest, mut value) = match applied {
             Application::Success(rest, value) => (rest, value),
             app => return Ok(app),
         };
         while !rest.is_empty() {
+            if let Some(ctx) = ctx {
+                ctx.consume_fuel()?;
+            }
             let applied = value.apply_in(rest, ctx)?;
             let (new_rest, new_value) = match applied {
                 Application::Success(rest, value) => (rest, value),
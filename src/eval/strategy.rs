@@ -0,0 +1,39 @@
+/*!
+Normalization strategies, controlling when beta reduction is performed
+*/
+
+use super::EvalCtx;
+
+/**
+A normalization strategy, determining whether S-expressions applying a function are beta-reduced as they are
+constructed or deferred into [`Thunk`](crate::value::thunk::Thunk)s to be forced on demand.
+
+The strategy is selected per evaluation context: applications made without one are always beta-reduced eagerly.
+*/
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Strategy {
+    /// Beta-reduce applications as they are constructed
+    Eager,
+    /// Defer beta reduction of applications into thunks, which are reduced at most once when forced
+    Lazy,
+}
+
+impl Default for Strategy {
+    #[inline]
+    fn default() -> Strategy {
+        Strategy::Eager
+    }
+}
+
+impl Strategy {
+    /// Get the normalization strategy of an optional evaluation context, defaulting to eager evaluation
+    #[inline]
+    pub fn of(ctx: &Option<EvalCtx>) -> Strategy {
+        ctx.as_ref().map(EvalCtx::strategy).unwrap_or_default()
+    }
+    /// Check whether this strategy is lazy
+    #[inline]
+    pub fn is_lazy(self) -> bool {
+        self == Strategy::Lazy
+    }
+}
@@ -0,0 +1,240 @@
+/*!
+Structured traces of evaluation, for debugging normalization
+*/
+
+use super::Application;
+use crate::pretty_display;
+use crate::value::{TypeId, ValId};
+
+/// An event recorded during evaluation
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TraceEvent {
+    /// A value was applied to a list of arguments, yielding a value
+    Application {
+        /// The function applied
+        function: ValId,
+        /// The arguments consumed by the application
+        args: Vec<ValId>,
+        /// The result of the application
+        result: ValId,
+    },
+    /// A value was applied to a list of arguments symbolically, yielding the type of the application
+    SymbolicApplication {
+        /// The function applied
+        function: ValId,
+        /// The arguments of the application
+        args: Vec<ValId>,
+        /// The type of the application
+        ty: TypeId,
+    },
+    /// A value was substituted for another
+    Substitution {
+        /// The value substituted for
+        lhs: ValId,
+        /// The value substituted
+        rhs: ValId,
+    },
+    /// An evaluation was answered from the cache of an evaluation context
+    CacheHit {
+        /// The value evaluated
+        value: ValId,
+        /// The cached result
+        result: ValId,
+    },
+}
+
+impl TraceEvent {
+    /// Record the application of a function to a list of arguments
+    pub fn application(function: &ValId, args: &[ValId], application: &Application) -> TraceEvent {
+        match application {
+            Application::Success(rest, result) => TraceEvent::Application {
+                function: function.clone(),
+                args: args[..args.len() - rest.len()].to_vec(),
+                result: result.clone(),
+            },
+            Application::Symbolic(ty) => TraceEvent::SymbolicApplication {
+                function: function.clone(),
+                args: args.to_vec(),
+                ty: ty.clone(),
+            },
+        }
+    }
+}
+
+/// A structured log of the events of an evaluation, in order
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Trace {
+    /// The events of this trace
+    events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    /// Create a new, empty trace
+    #[inline]
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+    /// Record an event in this trace
+    #[inline]
+    pub fn push(&mut self, event: TraceEvent) {
+        self.events.push(event)
+    }
+    /// Get the events of this trace, in order
+    #[inline]
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+    /// Get the number of events in this trace
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+    /// Check whether this trace is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+    /// Clear this trace
+    #[inline]
+    pub fn clear(&mut self) {
+        self.events.clear()
+    }
+}
+
+pretty_display!(TraceEvent, "#event {{...}}");
+pretty_display!(Trace, s, fmt => write!(fmt, "#trace [{} events]", s.len()));
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use std::fmt::{self, Display, Formatter};
+
+    /// Prettyprint a function applied to a list of arguments as an S-expression
+    fn prettyprint_app<I: From<usize> + Display>(
+        printer: &mut PrettyPrinter<I>,
+        fmt: &mut Formatter,
+        function: &ValId,
+        args: &[ValId],
+    ) -> Result<(), fmt::Error> {
+        write!(fmt, "(")?;
+        function.prettyprint(printer, fmt)?;
+        for arg in args {
+            write!(fmt, " ")?;
+            arg.prettyprint(printer, fmt)?;
+        }
+        write!(fmt, ")")
+    }
+
+    impl PrettyPrint for TraceEvent {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            match self {
+                TraceEvent::Application {
+                    function,
+                    args,
+                    result,
+                } => {
+                    write!(fmt, "apply ")?;
+                    prettyprint_app(printer, fmt, function, args)?;
+                    write!(fmt, " => ")?;
+                    result.prettyprint(printer, fmt)
+                }
+                TraceEvent::SymbolicApplication { function, args, ty } => {
+                    write!(fmt, "apply ")?;
+                    prettyprint_app(printer, fmt, function, args)?;
+                    write!(fmt, " : ")?;
+                    ty.prettyprint(printer, fmt)
+                }
+                TraceEvent::Substitution { lhs, rhs } => {
+                    write!(fmt, "subst ")?;
+                    lhs.prettyprint(printer, fmt)?;
+                    write!(fmt, " := ")?;
+                    rhs.prettyprint(printer, fmt)
+                }
+                TraceEvent::CacheHit { value, result } => {
+                    write!(fmt, "cached ")?;
+                    value.prettyprint(printer, fmt)?;
+                    write!(fmt, " => ")?;
+                    result.prettyprint(printer, fmt)
+                }
+            }
+        }
+    }
+
+    impl PrettyPrint for Trace {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            for (ix, event) in self.events.iter().enumerate() {
+                write!(fmt, "{}: ", ix)?;
+                event.prettyprint(printer, fmt)?;
+                writeln!(fmt)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{Apply, EvalCtx};
+    use crate::function::lambda::Lambda;
+    use crate::primitive::logical::{And, Bool};
+    use crate::region::Region;
+    use crate::typing::Type;
+    use crate::value::{expr::Sexpr, Value};
+
+    #[test]
+    fn tracing_lambda_application() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let and = Sexpr::try_new(vec![And.into_val(), x.clone(), y])
+            .unwrap()
+            .into_val();
+        let and = Lambda::try_new(and, region).unwrap().into_val();
+        let args = [true.into_val(), false.into_val()];
+
+        let mut ctx = Some(EvalCtx::new());
+        assert_eq!(ctx.as_ref().unwrap().trace(), None);
+        and.curried_in(&args, &mut ctx).unwrap();
+        assert_eq!(ctx.as_ref().unwrap().trace(), None);
+
+        let mut ctx = Some(EvalCtx::traced());
+        and.curried_in(&args, &mut ctx).unwrap();
+        let trace = ctx.as_ref().unwrap().trace().unwrap();
+        assert!(trace.events().contains(&TraceEvent::Substitution {
+            lhs: x,
+            rhs: true.into_val()
+        }));
+        assert!(trace.events().contains(&TraceEvent::Application {
+            function: And.into_val(),
+            args: args.to_vec(),
+            result: false.into_val(),
+        }));
+        match trace.events().last() {
+            Some(TraceEvent::Application {
+                function, result, ..
+            }) => {
+                assert_eq!(*function, and);
+                assert_eq!(*result, false.into_val());
+            }
+            event => panic!("Expected the outer application last, got {:?}", event),
+        }
+        #[cfg(feature = "prettyprinter")]
+        {
+            let rendered = format!("{}", trace);
+            assert!(rendered.lines().count() >= trace.len());
+            assert!(rendered.contains("subst"));
+        }
+        ctx.as_mut().unwrap().clear_trace();
+        assert_eq!(ctx.unwrap().trace(), Some(Trace::new()));
+    }
+}
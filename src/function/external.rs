@@ -0,0 +1,327 @@
+/*!
+External function declarations, whose bodies live outside of `rain`
+*/
+use super::pi::Pi;
+use crate::eval::Apply;
+use crate::lifetime::Live;
+use crate::region::Regional;
+use crate::typing::{Type, Typed};
+use crate::value::{Error, NormalValue, TypeRef, ValId, Value, ValueEnum, VarId};
+use crate::{debug_from_display, enum_convert, pretty_display, trivial_substitute};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+/// The visibility of an external declaration to the linker
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LinkageKind {
+    /// A symbol defined in another object
+    External,
+    /// A symbol which may be left undefined, or overridden by another definition
+    Weak,
+    /// A symbol defined in this object and not exported
+    Internal,
+}
+
+/// The calling convention of an external declaration
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallConv {
+    /// The native `rain` calling convention
+    Rain,
+    /// The C calling convention of the target platform
+    C,
+}
+
+/// The linkage attributes of an external declaration
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Linkage {
+    /// The visibility of this declaration to the linker
+    pub kind: LinkageKind,
+    /// The calling convention of this declaration
+    pub conv: CallConv,
+}
+
+impl Default for Linkage {
+    /// The default linkage is that of an external C function
+    #[inline]
+    fn default() -> Linkage {
+        Linkage {
+            kind: LinkageKind::External,
+            conv: CallConv::C,
+        }
+    }
+}
+
+/**
+An opaque declaration of an external function, given by a symbol name, a pi type and linkage attributes.
+
+External declarations always evaluate symbolically, and must have closed types, so substitute trivially.
+*/
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Extern {
+    /// The symbol name of this declaration
+    name: Arc<str>,
+    /// The type of this declaration
+    ty: VarId<Pi>,
+    /// The linkage attributes of this declaration
+    linkage: Linkage,
+}
+
+impl Extern {
+    /// Declare a new external function. Return an error if its type is not closed.
+    pub fn try_new(name: &str, ty: VarId<Pi>, linkage: Linkage) -> Result<Extern, Error> {
+        if !ty.region().is_null() {
+            return Err(Error::OpenDeclaration);
+        }
+        Ok(Extern {
+            name: name.into(),
+            ty,
+            linkage,
+        })
+    }
+    /// Declare a new external function with the default linkage. Return an error if its type is not closed.
+    #[inline]
+    pub fn c_fn(name: &str, ty: VarId<Pi>) -> Result<Extern, Error> {
+        Self::try_new(name, ty, Linkage::default())
+    }
+    /// Get the symbol name of this declaration
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Get the type of this declaration as a guaranteed pi type
+    #[inline]
+    pub fn get_ty(&self) -> &VarId<Pi> {
+        &self.ty
+    }
+    /// Get the linkage attributes of this declaration
+    #[inline]
+    pub fn linkage(&self) -> Linkage {
+        self.linkage
+    }
+    /// Get the number of arguments this declaration takes
+    #[inline]
+    pub fn arity(&self) -> usize {
+        self.ty.param_tys().len()
+    }
+}
+
+impl Typed for Extern {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.ty.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        false
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        false
+    }
+}
+
+impl Live for Extern {}
+
+impl Apply for Extern {}
+
+impl Value for Extern {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        1
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        match ix {
+            0 => self.ty.as_val(),
+            ix => panic!(
+                "Invalid index into an external declaration's dependencies: {}",
+                ix
+            ),
+        }
+    }
+    #[inline]
+    fn dep_owned(&self, _ix: usize) -> bool {
+        false
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::Extern(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl From<Extern> for NormalValue {
+    #[inline]
+    fn from(decl: Extern) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::Extern(decl))
+    }
+}
+
+trivial_substitute!(Extern);
+debug_from_display!(Extern);
+pretty_display!(Extern, "#extern {{...}}");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for Extern {}
+    impl TryFrom<NormalValue> for Extern { as ValueEnum, }
+    impl TryFromRef<NormalValue> for Extern { as ValueEnum, }
+}
+
+/// A Rust implementation of an external function, taking a full list of arguments
+pub type ExternFn = Arc<dyn Fn(&[ValId]) -> Result<ValId, Error> + Send + Sync>;
+
+/// A set of bindings of external declarations to Rust implementations, by symbol name
+#[derive(Clone, Default)]
+pub struct ExternBindings {
+    /// The implementations of each bound symbol
+    bindings: HashMap<Arc<str>, ExternFn, FxBuildHasher>,
+}
+
+impl ExternBindings {
+    /// Create a new, empty set of bindings
+    #[inline]
+    pub fn new() -> ExternBindings {
+        ExternBindings::default()
+    }
+    /// Bind a symbol to a Rust implementation, returning the previous implementation, if any
+    pub fn bind<F>(&mut self, name: &str, f: F) -> Option<ExternFn>
+    where
+        F: Fn(&[ValId]) -> Result<ValId, Error> + Send + Sync + 'static,
+    {
+        self.bindings.insert(name.into(), Arc::new(f))
+    }
+    /// Get the implementation bound to a symbol, if any
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&ExternFn> {
+        self.bindings.get(name)
+    }
+    /// Check whether a symbol is bound
+    #[inline]
+    pub fn is_bound(&self, name: &str) -> bool {
+        self.bindings.contains_key(name)
+    }
+    /// Call the implementation bound to an external declaration on a full list of arguments.
+    ///
+    /// Return an error if the declaration is unbound, is given the wrong number of arguments or arguments of the wrong
+    /// type, or if the implementation returns a value of the wrong type.
+    pub fn call(&self, decl: &Extern, args: &[ValId]) -> Result<ValId, Error> {
+        match args.len().cmp(&decl.arity()) {
+            std::cmp::Ordering::Less => return Err(Error::TooFewArgs),
+            std::cmp::Ordering::Greater => return Err(Error::TooManyArgs),
+            std::cmp::Ordering::Equal => {}
+        }
+        for (ix, (arg, param_ty)) in args.iter().zip(decl.ty.param_tys().iter()).enumerate() {
+            if arg.ty() != *param_ty {
+                return Err(Error::type_mismatch(param_ty.clone(), arg.clone_ty())
+                    .with_value(arg.clone())
+                    .at_arg(ix));
+            }
+        }
+        let f = self.get(decl.name()).ok_or(Error::UnboundExtern)?;
+        let result = f(args)?;
+        let result_ty = decl.ty.apply_ty(args)?;
+        if result.ty() != result_ty {
+            return Err(Error::type_mismatch(result_ty, result.clone_ty()).with_value(result));
+        }
+        Ok(result)
+    }
+}
+
+impl Debug for ExternBindings {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
+        fmt.debug_set().entries(self.bindings.keys()).finish()
+    }
+}
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use std::fmt::Display;
+
+    impl PrettyPrint for Extern {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "(#extern {:?} ", self.name)?;
+            self.ty.prettyprint(printer, fmt)?;
+            // Only linkage attributes differing from the default are printed
+            match self.linkage.kind {
+                LinkageKind::External => {}
+                LinkageKind::Weak => write!(fmt, " #weak")?,
+                LinkageKind::Internal => write!(fmt, " #internal")?,
+            }
+            match self.linkage.conv {
+                CallConv::C => {}
+                CallConv::Rain => write!(fmt, " #rain")?,
+            }
+            write!(fmt, ")")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::logical::{binary_ty, Bool};
+    use crate::value::{expr::Sexpr, ErrorKind};
+
+    #[test]
+    fn external_declarations_are_symbolic() {
+        let nand = Extern::c_fn("nand", binary_ty()).unwrap();
+        assert_eq!(nand.arity(), 2);
+        assert_eq!(nand.linkage(), Linkage::default());
+        let nand = nand.into_val();
+        let applied = Sexpr::try_new(vec![nand.clone(), true.into_val(), true.into_val()]).unwrap();
+        assert_eq!(applied.len(), 3);
+        assert_eq!(applied.ty(), Bool.into_ty());
+    }
+
+    #[test]
+    fn binding_external_declarations() {
+        let nand = Extern::c_fn("nand", binary_ty()).unwrap();
+        let mut bindings = ExternBindings::new();
+        assert_eq!(
+            bindings.call(&nand, &[true.into_val(), true.into_val()]),
+            Err(Error::UnboundExtern)
+        );
+        bindings.bind("nand", |args| {
+            match (args[0].as_enum(), args[1].as_enum()) {
+                (ValueEnum::Bool(l), ValueEnum::Bool(r)) => Ok((!(*l && *r)).into_val()),
+                _ => Err(Error::TypeMismatch),
+            }
+        });
+        assert!(bindings.is_bound("nand"));
+        assert_eq!(
+            bindings.call(&nand, &[true.into_val(), true.into_val()]),
+            Ok(false.into_val())
+        );
+        assert_eq!(
+            bindings.call(&nand, &[true.into_val()]),
+            Err(Error::TooFewArgs)
+        );
+        let error = bindings
+            .call(&nand, &[true.into_val(), Bool.into_val()])
+            .unwrap_err();
+        assert_eq!(error, ErrorKind::TypeMismatch);
+        assert_eq!(error.context().unwrap().arg, Some(1));
+        assert_eq!(error.value(), Some(&Bool.into_val()));
+
+        bindings.bind("nand", |_args| Ok(Bool.into_val()));
+        let error = bindings
+            .call(&nand, &[true.into_val(), true.into_val()])
+            .unwrap_err();
+        assert_eq!(error, ErrorKind::TypeMismatch);
+        assert_eq!(error.context().unwrap().expected, Some(Bool.into_ty()));
+        assert_eq!(error.value(), Some(&Bool.into_val()));
+    }
+}
@@ -3,7 +3,7 @@ Lambda functions
 */
 use super::pi::Pi;
 use crate::eval::{Application, Apply, EvalCtx, Substitute};
-use crate::lifetime::{LifetimeBorrow, Live};
+use crate::lifetime::{Lifetime, LifetimeBorrow, Live, PiLifetime};
 use crate::region::{Parameter, Parametrized, Region, Regional};
 use crate::typing::{Type, Typed};
 use crate::value::{
@@ -24,19 +24,35 @@ pub struct Lambda {
     pub(crate) deps: ValSet,
     /// The region of this lambda function
     pub(crate) def_region: Region,
+    /// The (cached) lifetime of this lambda function
+    pub(crate) lifetime: Lifetime,
 }
 
 impl Lambda {
-    /// Create a new lambda function from a parametrized `ValId`
+    /// Create a new lambda function from a parametrized `ValId`.
+    ///
+    /// Panics if the lambda function's dependencies have incompatible lifetimes: use
+    /// [`try_parametrized`](Lambda::try_parametrized) to handle this case.
     pub fn new(result: Parametrized<ValId>) -> Lambda {
+        Self::try_parametrized(result).expect("Lambda dependencies have incompatible lifetimes")
+    }
+    /// Create a new lambda function from a parametrized `ValId`.
+    /// Return an error if the lambda function's dependencies have incompatible lifetimes.
+    pub fn try_parametrized(result: Parametrized<ValId>) -> Result<Lambda, Error> {
         let ty = VarId::from(Pi::ty(&result));
         let (def_region, result, deps) = result.destruct();
-        Lambda {
+        let lifetime = Self::deps_lifetime(&def_region, &deps)?;
+        Ok(Lambda {
             result,
             deps,
             def_region,
             ty,
-        }
+            lifetime,
+        })
+    }
+    /// Compute the lifetime of a lambda function with a given defining region and dependency-set
+    pub(crate) fn deps_lifetime(def_region: &Region, deps: &ValSet) -> Result<Lifetime, Error> {
+        Lifetime::from_deps(def_region.parent(), deps.iter().map(|dep| (dep, true)))
     }
     /// A utility constructor, which creates a new instance of the identity lambda for a given type
     pub fn id(ty: TypeId) -> Lambda {
@@ -53,16 +69,19 @@ impl Lambda {
             .expect("Identity pi type is valid")
             .into();
         let deps = tyset.into_vals();
+        let lifetime = Self::deps_lifetime(&def_region, &deps)
+            .expect("Identity lambda has a single type dependency");
         Lambda {
             result,
             ty,
             deps,
             def_region,
+            lifetime,
         }
     }
     /// Attempt to create a new lambda function from a region and value
     pub fn try_new(value: ValId, region: Region) -> Result<Lambda, Error> {
-        Ok(Self::new(Parametrized::try_new(value, region)?))
+        Self::try_parametrized(Parametrized::try_new(value, region)?)
     }
     /// Get the defining region of this lambda function
     #[inline]
@@ -96,6 +115,11 @@ impl Lambda {
     pub fn depset(&self) -> &ValSet {
         &self.deps
     }
+    /// Get the lifetime component of this lambda function's type, i.e. which parameters its result borrows from
+    #[inline]
+    pub fn lifetime_component(&self) -> &PiLifetime {
+        self.ty.lifetime_component()
+    }
 }
 
 impl Typed for Lambda {
@@ -116,7 +140,7 @@ impl Typed for Lambda {
 impl Live for Lambda {
     #[inline]
     fn lifetime(&self) -> LifetimeBorrow {
-        self.def_region.parent().borrow_region().into()
+        self.lifetime.lifetime()
     }
 }
 
@@ -205,11 +229,13 @@ impl Substitute for Lambda {
             .iter()
             .map(|d| d.substitute(ctx))
             .collect::<Result<_, _>>()?;
+        let lifetime = Self::deps_lifetime(&def_region, &deps)?;
         Ok(Lambda {
             result,
             deps,
             ty,
             def_region,
+            lifetime,
         })
     }
 }
@@ -257,9 +283,60 @@ mod prettyprint_impl {
 mod tests {
     use super::*;
     use crate::primitive::{finite::Finite, logical::*};
+    use crate::tyarr;
     use crate::typing::Type;
     use crate::value::{expr::Sexpr, tuple::Tuple};
 
+    #[test]
+    fn borrowing_lambda_application() {
+        use crate::data::reference::Borrow;
+        let region = Region::unary(Bool.into_ty());
+        let a = region.param(0).unwrap().into_val();
+        let borrow = Borrow::shared(a).unwrap().into_val();
+        let lambda = Lambda::try_new(borrow, region).unwrap();
+        assert_eq!(lambda.lifetime_component(), &PiLifetime::borrows_from(0));
+        let outer = Region::binary(Bool.into_ty());
+        let x = outer.param(0).unwrap().into_val();
+        let applied = Sexpr::try_new(vec![lambda.into_val(), x.clone()])
+            .expect("Valid application")
+            .into_val();
+        let lifetime = applied.clone_lifetime();
+        let lender = lifetime.lender().expect("Result borrows from x").values();
+        assert_eq!(lender.len(), 1);
+        assert_eq!(lender[0].as_addr(), x.as_addr());
+    }
+
+    #[test]
+    fn lambda_lifetime_component() {
+        assert_eq!(
+            Lambda::id(Bool.into()).lifetime_component(),
+            &PiLifetime::default()
+        );
+        let borrowing = Pi::with_lifetime(
+            Parametrized::try_new(Bool.into_ty(), Region::unary(Bool.into_ty())).unwrap(),
+            PiLifetime::borrows_from(0),
+        )
+        .unwrap()
+        .into_ty();
+        // A lambda forwarding a borrow of its second parameter borrows from its second parameter
+        let region = Region::with(tyarr![borrowing.clone(), Bool.into_ty()], Region::NULL).unwrap();
+        let f = region.param(0).unwrap().into_val();
+        let x = region.param(1).unwrap().into_val();
+        let fx = Sexpr::try_new(vec![f, x]).unwrap().into_val();
+        let lambda = Lambda::try_new(fx, region).unwrap();
+        assert_eq!(lambda.lifetime_component(), &PiLifetime::borrows_from(1));
+        let outer = Region::with(tyarr![borrowing, Bool.into_ty()], Region::NULL).unwrap();
+        let g = outer.param(0).unwrap().into_val();
+        let y = outer.param(1).unwrap().into_val();
+        let applied = Sexpr::try_new(vec![lambda.into_val(), g, y.clone()])
+            .expect("Valid application")
+            .into_val();
+        let lifetime = applied.clone_lifetime();
+        let lender = lifetime.lender().expect("Result borrows from y").values();
+        assert_eq!(lender.len(), 1);
+        assert_eq!(lender[0].as_addr(), y.as_addr());
+    }
+
     #[test]
     fn boolean_identity_works_properly() {
         let id = Lambda::id(Bool.into()).into_val();
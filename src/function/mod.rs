@@ -2,5 +2,7 @@
 `rain` functions and associated types
 */
 
+pub mod external;
 pub mod lambda;
 pub mod pi;
+pub mod specialize;
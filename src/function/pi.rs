@@ -2,7 +2,7 @@
 Pi types
 */
 use crate::eval::{Apply, EvalCtx, Substitute};
-use crate::lifetime::{LifetimeBorrow, Live};
+use crate::lifetime::{LifetimeBorrow, Live, PiLifetime};
 use crate::region::{Parameter, Parametrized, Region, Regional};
 use crate::typing::{Type, Typed};
 use crate::value::{
@@ -21,16 +21,29 @@ pub struct Pi {
     result: TypeId,
     /// The direct dependencies of this pi type
     deps: ValSet,
+    /// The lifetime component of this pi type
+    lifetime: PiLifetime,
 }
 
 impl Pi {
-    /// Create a new pi type from a parametrized `TypeId` with a given result lifetime
+    /// Create a new pi type from a parametrized `TypeId` with a trivial result lifetime
     pub fn new(result: Parametrized<TypeId>) -> Result<Pi, Error> {
+        Self::with_lifetime(result, PiLifetime::default())
+    }
+    /// Create a new pi type from a parametrized `TypeId` with a given result lifetime.
+    /// Return an error if the lifetime borrows from a parameter not in the defining region.
+    pub fn with_lifetime(result: Parametrized<TypeId>, lifetime: PiLifetime) -> Result<Pi, Error> {
+        if let Some(ix) = lifetime.max_ix() {
+            if ix >= result.def_region().len() {
+                return Err(Error::InvalidParam);
+            }
+        }
         let (def_region, result, deps) = result.destruct();
         Ok(Pi {
             result,
             deps,
             def_region,
+            lifetime,
         })
     }
     /// Create a new pi type for a unary operator over a type
@@ -41,6 +54,7 @@ impl Pi {
             def_region,
             result: ty,
             deps,
+            lifetime: PiLifetime::default(),
         }
     }
     /// Create a new pi type for a binary operator over a type
@@ -51,6 +65,7 @@ impl Pi {
             def_region,
             result: ty,
             deps,
+            lifetime: PiLifetime::default(),
         }
     }
     /// Create a new pi type for an n-ary operator over a type
@@ -61,11 +76,13 @@ impl Pi {
             def_region,
             result: ty,
             deps,
+            lifetime: PiLifetime::default(),
         }
     }
-    /// Get the type associated with a parametrized `ValId`
+    /// Get the type associated with a parametrized `ValId`, including the parameters its result borrows from
     pub fn ty(param: &Parametrized<ValId>) -> Pi {
-        Self::new(param.ty()).expect("Region conjunction should work!")
+        let lifetime = PiLifetime::from_result(&param.value().clone_lifetime(), param.def_region());
+        Self::with_lifetime(param.ty(), lifetime).expect("Region conjunction should work!")
     }
     /// Attempt to create a new pi type from a region, type, and lifetime
     pub fn try_new(value: TypeId, region: Region) -> Result<Pi, Error> {
@@ -93,12 +110,43 @@ impl Pi {
     pub fn param_tys(&self) -> &TyArr {
         self.def_region().param_tys()
     }
+    /// Get the lifetime component of this pi type, i.e. which parameters its result borrows from
+    #[inline]
+    pub fn lifetime_component(&self) -> &PiLifetime {
+        &self.lifetime
+    }
     /// Get the parameters of this pi type
-    //TODO: parameter lifetimes...
     #[inline]
     pub fn params(&self) -> impl Iterator<Item = Parameter> + ExactSizeIterator {
         self.def_region().clone_region().params()
     }
+    /**
+    Check whether this pi type has the same signature as another, i.e. the same parameter types, result type and
+    lifetime component, with the parents of their defining regions nested in one another.
+
+    Unlike equality, this does not require both types to share a defining region, so that e.g. the type of a lambda
+    function defined in the body of a phi node matches the type declared for it.
+    */
+    pub fn signature_matches(&self, other: &Pi) -> bool {
+        self.param_tys() == other.param_tys()
+            && self.result() == other.result()
+            && self.lifetime == other.lifetime
+            && self
+                .def_region()
+                .parent()
+                .partial_cmp(other.def_region().parent())
+                .is_some()
+    }
+    /// Check whether a value matches an expected type, comparing function types by their signatures
+    pub fn ty_matches(value: &ValId, expected: &TypeId) -> bool {
+        let value_ty = value.ty();
+        match (value_ty.as_enum(), expected.as_enum()) {
+            (ValueEnum::Pi(value_pi), ValueEnum::Pi(expected_pi)) => {
+                value_pi.signature_matches(expected_pi)
+            }
+            _ => value_ty == *expected,
+        }
+    }
 }
 
 impl Typed for Pi {
@@ -203,7 +251,8 @@ impl Type for Pi {
         let rest_args = &args[self.def_region().len().min(args.len())..];
 
         if let Some(def_region) = region {
-            let pi = Pi::try_new(result, def_region)?;
+            let lifetime = self.lifetime.partially_applied(args.len());
+            let pi = Pi::with_lifetime(Parametrized::try_new(result, def_region)?, lifetime)?;
             Ok(pi.into_ty())
         } else {
             result.apply_ty_in(rest_args, ctx_handle)
@@ -221,10 +270,12 @@ impl Substitute for Pi {
             .iter()
             .map(|d| d.substitute(ctx))
             .collect::<Result<_, _>>()?;
+        // The lifetime component refers to parameters by index, which substitution leaves unchanged
         Ok(Pi {
             result,
             deps,
             def_region,
+            lifetime: self.lifetime.clone(),
         })
     }
 }
@@ -257,6 +308,27 @@ mod prettyprint_impl {
             fmt: &mut Formatter,
         ) -> Result<(), fmt::Error> {
             write!(fmt, "#pi")?;
+            let groups = [
+                ("#lender", self.lifetime.lenders()),
+                ("#transient", self.lifetime.transients()),
+            ];
+            for (keyword, ixes) in groups.iter() {
+                if !ixes.is_empty() {
+                    write!(fmt, " {}[", keyword)?;
+                    let mut first = true;
+                    for ix in ixes.iter() {
+                        if !first {
+                            write!(fmt, " ")?;
+                        }
+                        first = false;
+                        write!(fmt, "{}", ix)?;
+                    }
+                    write!(fmt, "]")?;
+                }
+            }
+            if !self.lifetime.is_trivial() {
+                write!(fmt, " ")?;
+            }
             crate::region::prettyprint::prettyprint_parametrized(
                 printer,
                 fmt,
@@ -270,7 +342,10 @@ mod prettyprint_impl {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::primitive::logical::{binary_ty, unary_ty, BOOL_TY};
+    use crate::primitive::finite::Finite;
+    use crate::primitive::logical::{binary_ty, unary_ty, Bool, BOOL_TY};
+    use crate::tyarr;
+    use crate::value::expr::Sexpr;
 
     #[test]
     fn basic_pi_application() {
@@ -287,4 +362,66 @@ mod tests {
         //     (Lifetime::STATIC, unary.clone_ty())
         // );
     }
+
+    #[test]
+    fn borrowing_pi_application() {
+        let def_region = Region::unary(Bool.into_ty());
+        let borrowing = Pi::with_lifetime(
+            Parametrized::try_new(Bool.into_ty(), def_region.clone()).unwrap(),
+            PiLifetime::borrows_from(0),
+        )
+        .unwrap();
+        assert_eq!(borrowing.lifetime_component(), &PiLifetime::borrows_from(0));
+        assert_eq!(
+            Pi::with_lifetime(
+                Parametrized::try_new(Bool.into_ty(), def_region).unwrap(),
+                PiLifetime::borrows_from(1)
+            ),
+            Err(Error::InvalidParam)
+        );
+        let region =
+            Region::with(tyarr![borrowing.into_ty(), Bool.into_ty()], Region::NULL).unwrap();
+        let f = region.param(0).unwrap().into_val();
+        let x = region.param(1).unwrap().into_val();
+        let fx = Sexpr::try_new(vec![f, x.clone()]).unwrap();
+        assert!(fx.dep_owned(0));
+        assert!(!fx.dep_owned(1));
+        let lifetime = fx.clone_lifetime();
+        let lenders = lifetime.lender().expect("Result borrows from x").values();
+        assert_eq!(lenders.len(), 1);
+        assert_eq!(lenders[0].as_addr(), x.as_addr());
+    }
+    #[test]
+    fn signatures_compare_lifetimes() {
+        let unary = Pi::unary(Bool.into_ty());
+        let borrowing = Pi::with_lifetime(
+            Parametrized::try_new(Bool.into_ty(), Region::unary(Bool.into_ty())).unwrap(),
+            PiLifetime::borrows_from(0),
+        )
+        .unwrap();
+        assert!(unary.signature_matches(&unary));
+        assert!(!borrowing.signature_matches(&unary));
+        assert!(!unary.signature_matches(&borrowing));
+        // Signatures match across nested defining regions, but not across unrelated ones
+        let outer = Region::unary(Bool.into_ty());
+        let nested = Pi::try_new(
+            Bool.into_ty(),
+            Region::unary_with(Bool.into_ty(), outer.clone()).unwrap(),
+        )
+        .unwrap();
+        assert_ne!(nested, unary);
+        assert!(nested.signature_matches(&unary));
+        let other = Region::unary(Finite(2).into_ty());
+        let unrelated = Pi::try_new(
+            Bool.into_ty(),
+            Region::unary_with(Bool.into_ty(), other).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            outer.partial_cmp(unrelated.def_region().parent()),
+            None,
+            "Regions should be unrelated"
+        );
+        assert!(!nested.signature_matches(&unrelated));
+    }
 }
@@ -0,0 +1,367 @@
+/*!
+Partial evaluation of lambda functions on known arguments
+*/
+use super::lambda::Lambda;
+use crate::control::phi::Phi;
+use crate::control::ternary::{Ternary, TernaryKind};
+use crate::eval::{EvalCtx, MemoTable};
+use crate::region::Region;
+use crate::typing::Typed;
+use crate::value::{arr::TyArr, expr::Sexpr, tuple::Tuple, Error, ValId, Value, ValueEnum};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+
+/// A policy deciding whether to unfold the members of phi nodes encountered during specialization
+pub trait UnfoldPolicy {
+    /// Decide whether to unfold the `ix`th member of a phi node, which has been unfolded `unfolded` times so far
+    fn unfold(&mut self, phi: &Phi, ix: usize, unfolded: usize) -> bool;
+}
+
+/// A policy which never unfolds phi nodes
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct NoUnfold;
+
+impl UnfoldPolicy for NoUnfold {
+    #[inline]
+    fn unfold(&mut self, _phi: &Phi, _ix: usize, _unfolded: usize) -> bool {
+        false
+    }
+}
+
+/// A policy which unfolds each member of each phi node at most a given number of times
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct BoundedUnfold(pub usize);
+
+impl UnfoldPolicy for BoundedUnfold {
+    #[inline]
+    fn unfold(&mut self, _phi: &Phi, _ix: usize, unfolded: usize) -> bool {
+        unfolded < self.0
+    }
+}
+
+/**
+A specializer, which partially evaluates lambda functions on a subset of their arguments, folding constants in their
+results and unfolding recursive calls to phi nodes as permitted by its [`UnfoldPolicy`](UnfoldPolicy).
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Specializer<P = NoUnfold> {
+    /// The policy for unfolding phi nodes
+    policy: P,
+    /// The number of times each member of each phi node has been unfolded in the current specialization
+    unfolded: HashMap<(ValId, usize), usize, FxBuildHasher>,
+    /// The values already unfolded in the current specialization
+    cache: HashMap<ValId, ValId, FxBuildHasher>,
+    /// The memo table consulted when folding constants, if any
+    memo: Option<MemoTable>,
+}
+
+impl Specializer {
+    /// Create a new specializer which never unfolds phi nodes
+    #[inline]
+    pub fn new() -> Specializer {
+        Specializer::default()
+    }
+}
+
+impl<P: UnfoldPolicy> Specializer<P> {
+    /// Create a new specializer with a given policy for unfolding phi nodes
+    #[inline]
+    pub fn with_policy(policy: P) -> Specializer<P> {
+        Specializer {
+            policy,
+            unfolded: HashMap::default(),
+            cache: HashMap::default(),
+            memo: None,
+        }
+    }
+    /// Get the policy of this specializer
+    #[inline]
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+    /// Get the policy of this specializer, mutably
+    #[inline]
+    pub fn policy_mut(&mut self) -> &mut P {
+        &mut self.policy
+    }
+    /// Set the memo table consulted when folding constants, so that repeated specializations may reuse each other's work
+    #[inline]
+    pub fn set_memo(&mut self, memo: Option<MemoTable>) {
+        self.memo = memo
+    }
+    /**
+    Specialize a lambda function on a partial map from parameter indices to arguments, yielding a lambda function over
+    the remaining parameters, in order.
+
+    Return an error if an index is out of bounds or given twice, or an argument does not have its parameter's type.
+    */
+    pub fn specialize<I>(&mut self, lambda: &Lambda, known: I) -> Result<Lambda, Error>
+    where
+        I: IntoIterator<Item = (usize, ValId)>,
+    {
+        let def_region = lambda.def_region();
+        let param_tys = def_region.param_tys();
+        let mut args: Vec<Option<ValId>> = vec![None; def_region.len()];
+        for (ix, arg) in known {
+            let slot = args.get_mut(ix).ok_or(Error::InvalidParam)?;
+            if arg.ty() != param_tys[ix] {
+                return Err(Error::type_mismatch(param_tys[ix].clone(), arg.clone_ty())
+                    .with_value(arg)
+                    .at_arg(ix));
+            }
+            if slot.replace(arg).is_some() {
+                return Err(Error::InvalidRedef);
+            }
+        }
+        let remaining: TyArr = args
+            .iter()
+            .zip(param_tys.iter())
+            .filter(|(arg, _)| arg.is_none())
+            .map(|(_, ty)| ty.clone())
+            .collect();
+        let region = Region::with(remaining, def_region.parent().clone())?;
+        let mut params = region.params();
+        let args = args.into_iter().map(|arg| {
+            arg.unwrap_or_else(|| {
+                params
+                    .next()
+                    .expect("One parameter per unknown argument")
+                    .into_val()
+            })
+        });
+        let mut ctx = EvalCtx::new();
+        ctx.set_memo(self.memo.clone());
+        ctx.substitute_region(def_region, args, false)?;
+        let result = ctx.evaluate(lambda.result());
+        ctx.pop();
+        let result = self.unfold(&result?);
+        self.unfolded.clear();
+        self.cache.clear();
+        Lambda::try_new(result?, region)
+    }
+    /**
+    Unfold the phi nodes in a value as permitted by this specializer's policy, including within the branches of
+    ternary operations and the results of nested lambda functions
+    */
+    fn unfold(&mut self, value: &ValId) -> Result<ValId, Error> {
+        if let Some(result) = self.cache.get(value) {
+            return Ok(result.clone());
+        }
+        let result = match value.as_enum() {
+            ValueEnum::Sexpr(sexpr) if sexpr.len() > 1 => {
+                let args = sexpr
+                    .iter()
+                    .map(|arg| self.unfold(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let member = match (args[0].as_enum(), args[1].as_enum()) {
+                    (ValueEnum::Phi(phi), ValueEnum::Index(ix)) => {
+                        let key = (args[0].clone(), ix.ix() as usize);
+                        let unfolded = self.unfolded.get(&key).copied().unwrap_or(0);
+                        if self.policy.unfold(phi, key.1, unfolded) {
+                            Some((key.clone(), Self::instantiate(phi, key.1)?))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+                if let Some((key, member)) = member {
+                    *self.unfolded.entry(key).or_insert(0) += 1;
+                    let mut new_args = Vec::with_capacity(args.len() - 1);
+                    new_args.push(member);
+                    new_args.extend(args[2..].iter().cloned());
+                    let applied = Sexpr::try_new(new_args)?.into_val();
+                    self.unfold(&applied)?
+                } else if args.iter().eq(sexpr.iter()) {
+                    value.clone()
+                } else {
+                    Sexpr::try_new(args)?.into_val()
+                }
+            }
+            ValueEnum::Tuple(tuple) => {
+                let elems = tuple
+                    .iter()
+                    .map(|elem| self.unfold(elem))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if elems.iter().eq(tuple.iter()) {
+                    value.clone()
+                } else {
+                    Tuple::try_new(elems.into_iter().collect())?.into_val()
+                }
+            }
+            ValueEnum::Ternary(ternary) => {
+                let high = self.unfold(ternary.high())?;
+                let low = self.unfold(ternary.low())?;
+                if high == *ternary.high() && low == *ternary.low() {
+                    value.clone()
+                } else {
+                    match ternary.ternary_kind() {
+                        TernaryKind::Bool => Ternary::conditional(high, low)?,
+                        TernaryKind::Switch => Ternary::switch(high, low)?,
+                    }
+                    .into_val()
+                }
+            }
+            ValueEnum::Lambda(lambda) => {
+                let result = self.unfold(lambda.result())?;
+                if result == *lambda.result() {
+                    value.clone()
+                } else {
+                    Lambda::try_new(result, lambda.def_region().clone())?.into_val()
+                }
+            }
+            _ => value.clone(),
+        };
+        self.cache.insert(value.clone(), result.clone());
+        Ok(result)
+    }
+    /// Instantiate the `ix`th member of a phi node, binding the phi node's parameters to its members
+    fn instantiate(phi: &Phi, ix: usize) -> Result<ValId, Error> {
+        let mut ctx = EvalCtx::new();
+        ctx.substitute_region(phi.def_region(), phi.projections()?.into_iter(), false)?;
+        let member = ctx.evaluate(&phi.values()[ix]);
+        ctx.pop();
+        member
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+    use crate::primitive::logical::{unary_ty, Bool, Not};
+    use crate::typing::Type;
+    use crate::value::TypeId;
+    use crate::{tyarr, valarr};
+
+    #[test]
+    fn specializing_a_mux_folds_constants() {
+        let region = Region::with(tyarr![Bool.into_ty(); 3], Region::NULL).unwrap();
+        let select = region.param(0).unwrap().into_val();
+        let high = region.param(1).unwrap().into_val();
+        let low = region.param(2).unwrap().into_val();
+        let ternary = Ternary::conditional(high, low).unwrap().into_val();
+        let mux_res = Sexpr::try_new(vec![ternary, select]).unwrap().into_val();
+        let mux = Lambda::try_new(mux_res, region).unwrap();
+
+        let mut specializer = Specializer::new();
+        let high = specializer
+            .specialize(&mux, vec![(0, true.into_val())])
+            .unwrap();
+        assert_eq!(high.def_region().len(), 2);
+        assert_eq!(
+            *high.result(),
+            high.def_region().param(0).unwrap().into_val()
+        );
+        let mut interp = Interpreter::new();
+        assert_eq!(
+            interp.run(&high, &[false.into_val(), true.into_val()]),
+            Ok(false.into_val())
+        );
+
+        let low = specializer
+            .specialize(&mux, vec![(0, false.into_val()), (2, true.into_val())])
+            .unwrap();
+        assert_eq!(low.def_region().len(), 1);
+        assert_eq!(*low.result(), true.into_val());
+
+        assert_eq!(
+            specializer.specialize(&mux, vec![(3, true.into_val())]),
+            Err(Error::InvalidParam)
+        );
+        assert_eq!(
+            specializer.specialize(&mux, vec![(0, true.into_val()), (0, false.into_val())]),
+            Err(Error::InvalidRedef)
+        );
+    }
+
+    #[test]
+    fn bounded_unfolding_of_phi_nodes() {
+        // ping(b) = if b { pong(b) } else { #true }, pong(b) = ping(!b)
+        let fn_ty: TypeId = unary_ty().clone_as_ty();
+        let rec = Region::with(tyarr![fn_ty.clone(), fn_ty], Region::NULL).unwrap();
+        let ping_rec = rec.param(0).unwrap().into_val();
+        let pong_rec = rec.param(1).unwrap().into_val();
+
+        let ping_region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+        let b = ping_region.param(0).unwrap().into_val();
+        let recurse = Sexpr::try_new(vec![pong_rec, b.clone()])
+            .unwrap()
+            .into_val();
+        let ternary = Ternary::conditional(recurse, true.into_val())
+            .unwrap()
+            .into_val();
+        let ping_res = Sexpr::try_new(vec![ternary, b]).unwrap().into_val();
+        let ping = Lambda::try_new(ping_res, ping_region).unwrap().into_val();
+
+        let pong_region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+        let b = pong_region.param(0).unwrap().into_val();
+        let not_b = Sexpr::try_new(vec![Not.into_val(), b]).unwrap().into_val();
+        let pong_res = Sexpr::try_new(vec![ping_rec, not_b]).unwrap().into_val();
+        let pong = Lambda::try_new(pong_res, pong_region).unwrap().into_val();
+
+        let phi = Phi::try_new(valarr![ping, pong], rec).unwrap();
+        let ping = phi.project(0).unwrap();
+
+        // f(b) = ping(b)
+        let region = Region::unary(Bool.into_ty());
+        let b = region.param(0).unwrap().into_val();
+        let f_res = Sexpr::try_new(vec![ping, b]).unwrap().into_val();
+        let f = Lambda::try_new(f_res, region).unwrap();
+
+        let known = || vec![(0, true.into_val())];
+        let folded = Specializer::new().specialize(&f, known()).unwrap();
+        assert_ne!(*folded.result(), true.into_val());
+
+        // ping(#true) = pong(#true) = ping(#false) = #true, unfolding ping twice and pong once
+        let mut specializer = Specializer::with_policy(BoundedUnfold(1));
+        let unfolded = specializer.specialize(&f, known()).unwrap();
+        match unfolded.result().as_enum() {
+            ValueEnum::Sexpr(sexpr) => assert_eq!(sexpr[0], phi.clone().into_val()),
+            v => panic!("Expected a recursive call, got {}", v),
+        }
+        specializer.policy_mut().0 = 2;
+        let unfolded = specializer.specialize(&f, known()).unwrap();
+        assert_eq!(unfolded.def_region().len(), 0);
+        assert_eq!(*unfolded.result(), true.into_val());
+
+        // Recursive calls in ternary branches are unfolded too: ping(b) = if b { ping(!b) } else { #true }
+        specializer.policy_mut().0 = 1;
+        let unfolded = specializer
+            .specialize(&f, Vec::<(usize, ValId)>::new())
+            .unwrap();
+        let ternary = match unfolded.result().as_enum() {
+            ValueEnum::Sexpr(sexpr) => match sexpr[0].as_enum() {
+                ValueEnum::Ternary(ternary) => ternary.clone(),
+                v => panic!("Expected a ternary operation, got {}", v),
+            },
+            v => panic!("Expected a branch, got {}", v),
+        };
+        match ternary.high().as_enum() {
+            ValueEnum::Sexpr(sexpr) => {
+                assert_eq!(sexpr[0], phi.clone().into_val());
+                match sexpr[1].as_enum() {
+                    ValueEnum::Index(ix) => assert_eq!(ix.ix(), 0),
+                    v => panic!("Expected a call to ping, got {}", v),
+                }
+            }
+            v => panic!("Expected a recursive call, got {}", v),
+        }
+
+        // As are recursive calls in nested lambda functions: h(b) = |c| ping(b)
+        let region = Region::unary(Bool.into_ty());
+        let b = region.param(0).unwrap().into_val();
+        let inner = Region::with(tyarr![Bool.into_ty()], region.clone()).unwrap();
+        let inner_res = Sexpr::try_new(vec![phi.project(0).unwrap(), b])
+            .unwrap()
+            .into_val();
+        let inner = Lambda::try_new(inner_res, inner).unwrap().into_val();
+        let h = Lambda::try_new(inner, region).unwrap();
+        specializer.policy_mut().0 = 2;
+        let unfolded = specializer.specialize(&h, known()).unwrap();
+        match unfolded.result().as_enum() {
+            ValueEnum::Lambda(lambda) => assert_eq!(*lambda.result(), true.into_val()),
+            v => panic!("Expected a lambda function, got {}", v),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+/*!
+Export `rain` value graphs to the DOT language, for visualization with Graphviz
+*/
+use super::{ValIdFilter, VisitedFilter};
+use crate::lifetime::LifetimeGraph;
+use crate::region::{Region, Regional};
+use crate::tokens::{KEYWORD_PHI, KEYWORD_PROD};
+use crate::util::HasAddr;
+use crate::value::{ValId, Value, ValueEnum};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+use std::fmt::{self, Write};
+
+/**
+An exporter rendering a value and its transitive dependencies as a DOT graph.
+
+Each region is rendered as a cluster, nested within the cluster of its parent, and containing the values which live in
+it. Parameters are rendered as filled ellipses, and all other values as boxes. Edges point from a value to its
+dependencies: owned dependencies are drawn solid, and borrowed dependencies dashed. The results of lambda functions, pi
+types and phi nodes are drawn bold. If a lifetime graph is provided, its temporal edges are drawn dotted, from the
+value which must be scheduled first.
+*/
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DotExporter<'a> {
+    /// The lifetime graph to draw temporal edges from, if any
+    temporal: Option<&'a LifetimeGraph>,
+}
+
+/// A region cluster in a DOT graph
+#[derive(Debug, Clone)]
+struct Cluster {
+    /// The index of this cluster
+    ix: usize,
+    /// The values in this cluster
+    values: Vec<usize>,
+    /// The child regions of this cluster
+    children: Vec<Region>,
+}
+
+impl<'a> DotExporter<'a> {
+    /// Create a new DOT exporter
+    #[inline]
+    pub fn new() -> DotExporter<'a> {
+        DotExporter { temporal: None }
+    }
+    /// Create a new DOT exporter drawing the temporal edges of a lifetime graph
+    #[inline]
+    pub fn with_temporal(graph: &'a LifetimeGraph) -> DotExporter<'a> {
+        DotExporter {
+            temporal: Some(graph),
+        }
+    }
+    /// Render a value and its transitive dependencies as a DOT graph
+    pub fn export(&self, root: &ValId) -> String {
+        let mut out = String::new();
+        self.write(root, &mut out)
+            .expect("Writing to a string never fails");
+        out
+    }
+    /// Write a value and its transitive dependencies as a DOT graph
+    pub fn write<W: Write>(&self, root: &ValId, out: &mut W) -> fmt::Result {
+        let values = Self::collect(root);
+        let indices: HashMap<usize, usize, FxBuildHasher> = values
+            .iter()
+            .enumerate()
+            .map(|(ix, value)| (value.as_addr().raw_addr(), ix))
+            .collect();
+        let mut clusters: HashMap<Region, Cluster, FxBuildHasher> = HashMap::default();
+        Self::cluster(&mut clusters, &Region::NULL);
+        for (ix, value) in values.iter().enumerate() {
+            Self::cluster(&mut clusters, &value.clone_region())
+                .values
+                .push(ix);
+        }
+        writeln!(out, "digraph rain {{")?;
+        self.write_cluster(&Region::NULL, &clusters, &values, 1, out)?;
+        for (ix, value) in values.iter().enumerate() {
+            for result in results(value) {
+                let result_ix = indices[&result.as_addr().raw_addr()];
+                writeln!(out, "    n{} -> n{} [style=bold];", ix, result_ix)?;
+            }
+            for dep in 0..value.no_deps() {
+                let dep_ix = indices[&value.get_dep(dep).as_addr().raw_addr()];
+                let style = if value.dep_owned(dep) {
+                    "solid"
+                } else {
+                    "dashed"
+                };
+                writeln!(out, "    n{} -> n{} [style={}];", ix, dep_ix, style)?;
+            }
+            if let Some(data) = self.temporal.and_then(|graph| graph.valid_data(value)) {
+                for source in data.temporal() {
+                    if let Some(source) = indices.get(&source.raw_addr()) {
+                        writeln!(
+                            out,
+                            "    n{} -> n{} [style=dotted, color=blue, constraint=false];",
+                            source, ix
+                        )?;
+                    }
+                }
+            }
+        }
+        writeln!(out, "}}")
+    }
+    /// Collect a value, its dependencies and the results of the functions it depends on, depth-first
+    fn collect(root: &ValId) -> Vec<&ValId> {
+        let mut visited = VisitedFilter::new();
+        let mut frontier: Vec<&ValId> = visited.filter(root).into_iter().collect();
+        let mut values = Vec::new();
+        while let Some(top) = frontier.pop() {
+            let next: Vec<_> = results(top)
+                .into_iter()
+                .chain(top.deps().iter())
+                .filter_map(|value| visited.filter(value))
+                .collect();
+            frontier.extend(next.into_iter().rev());
+            values.push(top);
+        }
+        values
+    }
+    /// Get the cluster of a region, inserting it and its ancestors if necessary
+    fn cluster<'c>(
+        clusters: &'c mut HashMap<Region, Cluster, FxBuildHasher>,
+        region: &Region,
+    ) -> &'c mut Cluster {
+        if !clusters.contains_key(region) {
+            if !region.is_null() {
+                Self::cluster(clusters, region.parent())
+                    .children
+                    .push(region.clone());
+            }
+            let ix = clusters.len();
+            clusters.insert(
+                region.clone(),
+                Cluster {
+                    ix,
+                    values: Vec::new(),
+                    children: Vec::new(),
+                },
+            );
+        }
+        clusters.get_mut(region).expect("Cluster was just inserted")
+    }
+    /// Write the cluster of a region, along with its values and child clusters
+    fn write_cluster<W: Write>(
+        &self,
+        region: &Region,
+        clusters: &HashMap<Region, Cluster, FxBuildHasher>,
+        values: &[&ValId],
+        depth: usize,
+        out: &mut W,
+    ) -> fmt::Result {
+        let cluster = &clusters[region];
+        let indent = "    ".repeat(depth);
+        for &ix in cluster.values.iter() {
+            let value = values[ix];
+            let shape = match value.as_enum() {
+                ValueEnum::Parameter(_) => "shape=ellipse, style=filled, fillcolor=lightgrey",
+                _ => "shape=box",
+            };
+            writeln!(
+                out,
+                "{}n{} [label=\"{}\", {}];",
+                indent,
+                ix,
+                escape(&label(value)),
+                shape
+            )?;
+        }
+        for child in cluster.children.iter() {
+            let params: Vec<_> = child.param_tys().iter().map(|ty| ty.to_string()).collect();
+            writeln!(out, "{}subgraph cluster_{} {{", indent, clusters[child].ix)?;
+            writeln!(
+                out,
+                "{}    label=\"{}\";",
+                indent,
+                escape(&format!("|{}|", params.join(", ")))
+            )?;
+            self.write_cluster(child, clusters, values, depth + 1, out)?;
+            writeln!(out, "{}}}", indent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the results of a value's bodies, if it is a lambda function, pi type or phi node
+fn results(value: &ValId) -> Vec<&ValId> {
+    match value.as_enum() {
+        ValueEnum::Lambda(l) => vec![l.result()],
+        ValueEnum::Pi(p) => vec![p.result().as_val()],
+        ValueEnum::Phi(p) => p.values().iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Get the label of a value in a DOT graph
+fn label(value: &ValId) -> String {
+    match value.as_enum() {
+        ValueEnum::Parameter(p) => format!("#param {}", p.ix()),
+        ValueEnum::Sexpr(_) => "()".into(),
+        ValueEnum::Tuple(_) => "[]".into(),
+        ValueEnum::Product(_) => KEYWORD_PROD.into(),
+        ValueEnum::Pi(_) => "#pi".into(),
+        ValueEnum::Lambda(_) => "#lambda".into(),
+        ValueEnum::Ternary(_) => "#gamma".into(),
+        ValueEnum::Phi(_) => KEYWORD_PHI.into(),
+        _ => value.to_string(),
+    }
+}
+
+/// Escape a string for use within a quoted DOT identifier
+fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ternary::Ternary;
+    use crate::data::reference::Borrow;
+    use crate::function::lambda::Lambda;
+    use crate::lifetime::LifetimeCtx;
+    use crate::primitive::logical::Bool;
+    use crate::tyarr;
+    use crate::typing::Type;
+    use crate::value::expr::Sexpr;
+
+    #[test]
+    fn mux_is_exported_with_a_cluster_per_region() {
+        let region = Region::with(tyarr![Bool.into_ty(); 3], Region::NULL).unwrap();
+        let select = region.param(0).unwrap().into_val();
+        let high = region.param(1).unwrap().into_val();
+        let low = region.param(2).unwrap().into_val();
+        let ternary = Ternary::conditional(high, low).unwrap().into_val();
+        let mux_res = Sexpr::try_new(vec![ternary, select]).unwrap().into_val();
+        let mux = Lambda::try_new(mux_res, region).unwrap().into_val();
+        let dot = DotExporter::new().export(&mux);
+        assert!(dot.starts_with("digraph rain {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(dot.matches("subgraph cluster_").count(), 1);
+        assert_eq!(dot.matches("#param").count(), 3);
+        assert!(dot.contains("n0 [label=\"#lambda\", shape=box];"));
+        assert!(dot.contains("n0 -> n1 [style=bold];"));
+        assert!(dot.contains("n0 -> n2 [style=solid];"));
+    }
+
+    #[test]
+    fn borrowed_and_temporal_edges_are_distinguished() {
+        let region = Region::unary(Bool.into_ty());
+        let b = region.param(0).unwrap().into_val();
+        let borrow = Borrow::shared(b.clone()).unwrap().into_val();
+        let dot = DotExporter::new().export(&borrow);
+        assert!(dot.contains("n0 -> n1 [style=dashed];"));
+
+        let mut ctx = LifetimeCtx::new(region);
+        ctx.graph_mut().push_temporal(&borrow, &b);
+        let dot = DotExporter::with_temporal(ctx.graph()).export(&borrow);
+        assert!(dot.contains("n0 -> n1 [style=dotted, color=blue, constraint=false];"));
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        assert_eq!(escape("\"a\\b\"\n"), "\\\"a\\\\b\\\"\\n");
+    }
+}
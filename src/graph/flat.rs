@@ -0,0 +1,863 @@
+/*!
+Value graphs flattened into a list of nodes, the common model of the binary format and `serde` support
+
+Each region and value a graph depends upon appears exactly once, after everything it depends on, so that shared
+sub-DAGs are preserved by index rather than duplicated. Rebuilding a graph goes through the normal checked
+constructors, so that malformed input yields an [`Error`](Error) rather than an invalid value.
+*/
+use crate::control::{
+    effect::{Effect, World},
+    phi::Phi,
+    ternary::{Ternary, TernaryKind},
+};
+use crate::data::array::{Array, ArrayOp, ArrayOpKind, ArrayTy};
+use crate::data::memory::{AllocTy, MemOp, MemOpKind};
+use crate::data::reference::{Borrow, Dereference, RefKind, RefTy};
+use crate::function::external::{Extern, Linkage};
+use crate::function::{lambda::Lambda, pi::Pi};
+use crate::lifetime::{Group, Lifetime, LifetimeData, LifetimeParams, PiLifetime};
+use crate::primitive::{
+    bits::{BinOp, BitsKind, BitsTy, Neg},
+    finite::Finite,
+    logical::{Bool, Logical},
+};
+use crate::proof::paths::{induction::PathInd, Id, IdFamily, Refl};
+use crate::region::{Parameter, Parametrized, Region, Regional};
+use crate::typing::primitive::{Fin, Prop, Set};
+use crate::typing::Typed;
+use crate::value::{
+    arr::{TyArr, ValArr},
+    expr::Sexpr,
+    thunk::Thunk,
+    tuple::{Product, Tuple},
+    Error, KindId, NormalValue, TypeId, ValId, Value, ValueEnum, VarId,
+};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+use std::convert::{TryFrom, TryInto};
+
+/// A graph of regions and values, flattened into a list of nodes in topological order
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValueGraph {
+    /// The nodes of this graph, each of which may only refer to the nodes before it
+    pub nodes: Vec<GraphNode>,
+    /// The indices of the root nodes of this graph
+    pub roots: Vec<usize>,
+}
+
+/// A flattened lifetime, referring to the nodes of a [`ValueGraph`](ValueGraph)
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LifetimeNode {
+    /// The region of this lifetime, if any
+    pub region: Option<usize>,
+    /// The values this lifetime borrows from
+    pub lender: Vec<usize>,
+    /// The values this lifetime transiently borrows from
+    pub transient: Vec<usize>,
+    /// The values borrowed from by each parameter of this lifetime
+    pub params: Vec<Vec<usize>>,
+}
+
+/// A node of a [`ValueGraph`](ValueGraph), referring to previous nodes by index
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GraphNode {
+    /// A region, with a parent region and parameter types
+    Region {
+        /// The parent of this region, or `None` for the null region
+        parent: Option<usize>,
+        /// The types of this region's parameters
+        params: Vec<usize>,
+    },
+    /// An S-expression
+    Sexpr(Vec<usize>),
+    /// A parameter to a region
+    Parameter {
+        /// The region this is a parameter of
+        region: usize,
+        /// The index of this parameter
+        ix: usize,
+    },
+    /// A tuple
+    Tuple {
+        /// The elements of this tuple
+        elems: Vec<usize>,
+        /// Whether this tuple is an anchor, in which case it must be empty
+        anchor: bool,
+    },
+    /// A product type
+    Product {
+        /// The element types of this product
+        elems: Vec<usize>,
+        /// Whether this product is an anchor type
+        anchor: bool,
+        /// Whether this product is a flare type
+        flare: bool,
+    },
+    /// The kind of mere propositions
+    Prop,
+    /// The kind of finite types
+    Fin,
+    /// An n-set
+    Set(usize),
+    /// The kind of bits types
+    BitsKind,
+    /// A bits type
+    BitsTy(u32),
+    /// A bitset value
+    Bits {
+        /// The length of this bitset
+        len: u32,
+        /// The data of this bitset
+        data: u128,
+    },
+    /// The type of booleans
+    BoolTy,
+    /// A boolean
+    Bool(bool),
+    /// A finite type
+    Finite(u128),
+    /// An index into a finite type
+    Index {
+        /// The size of the finite type indexed
+        ty: u128,
+        /// The index
+        ix: u128,
+    },
+    /// A pi type
+    Pi {
+        /// The region this pi type is defined in
+        region: usize,
+        /// The result type
+        result: usize,
+        /// The parameters lending to the result
+        lenders: Vec<usize>,
+        /// The parameters transiently lending to the result
+        transients: Vec<usize>,
+    },
+    /// A lambda function
+    Lambda {
+        /// The region this lambda function is defined in
+        region: usize,
+        /// The result
+        result: usize,
+    },
+    /// A ternary operation
+    Ternary {
+        /// The kind of this ternary operation
+        kind: TernaryKind,
+        /// The high branch
+        high: usize,
+        /// The low branch
+        low: usize,
+    },
+    /// A phi node
+    Phi {
+        /// The region this phi node is defined in
+        region: usize,
+        /// The values bound
+        values: Vec<usize>,
+    },
+    /// A logical operation
+    Logical {
+        /// The arity of this operation
+        arity: u8,
+        /// The truth table of this operation
+        data: u128,
+    },
+    /// An identity type
+    Id {
+        /// The left-hand side
+        left: usize,
+        /// The right-hand side
+        right: usize,
+    },
+    /// An instance of the reflexivity axiom
+    Refl(usize),
+    /// A family of identity types over a kind
+    IdFamily(usize),
+    /// An instance of the path induction axiom
+    PathInd {
+        /// The base types
+        base_tys: Vec<usize>,
+        /// The target kind
+        target: usize,
+    },
+    /// A bitvector binary operation
+    BinOp(BinOp),
+    /// Bitvector negation
+    Neg,
+    /// A reference type
+    RefTy {
+        /// The referent type
+        referent: usize,
+        /// The lifetime of this reference type
+        lifetime: LifetimeNode,
+        /// The kind of this reference type
+        kind: RefKind,
+    },
+    /// A borrow or reborrow
+    Borrow {
+        /// The value borrowed
+        source: usize,
+        /// The kind of this borrow
+        kind: RefKind,
+        /// Whether this is a reborrow
+        reborrow: bool,
+    },
+    /// A dereference
+    Dereference(usize),
+    /// An allocation type
+    AllocTy(usize),
+    /// A memory operation
+    MemOp {
+        /// The kind of this operation
+        kind: MemOpKind,
+        /// The type operated on
+        ty: usize,
+    },
+    /// The type of world tokens
+    World,
+    /// An effectful primitive
+    Effect {
+        /// The name of this effect
+        name: String,
+        /// The parameter types of this effect, excluding the world token
+        param_tys: Vec<usize>,
+        /// The result type of this effect, excluding the world token
+        result: usize,
+    },
+    /// An array type
+    ArrayTy {
+        /// The element type
+        elem: usize,
+        /// The length
+        len: u128,
+    },
+    /// An array literal
+    Array {
+        /// The elements of this array
+        elems: Vec<usize>,
+        /// The element type
+        elem_ty: usize,
+    },
+    /// An array operation
+    ArrayOp {
+        /// The kind of this operation
+        kind: ArrayOpKind,
+        /// The array type operated on
+        array_ty: usize,
+        /// The auxiliary type of this operation
+        aux_ty: usize,
+    },
+    /// An external function declaration
+    Extern {
+        /// The symbol name
+        name: String,
+        /// The function type
+        ty: usize,
+        /// The linkage of this declaration
+        linkage: Linkage,
+    },
+    /// A thunk
+    Thunk(Vec<usize>),
+}
+
+impl ValueGraph {
+    /// Flatten a list of root values, along with everything they depend on
+    pub fn from_values<'a, I>(roots: I) -> ValueGraph
+    where
+        I: IntoIterator<Item = &'a ValId>,
+    {
+        let mut writer = GraphWriter::default();
+        let roots = writer.values(roots);
+        ValueGraph {
+            nodes: writer.nodes,
+            roots,
+        }
+    }
+    /// Flatten a region, along with everything it depends on. The null region has no roots.
+    pub fn from_region(region: &Region) -> ValueGraph {
+        let mut writer = GraphWriter::default();
+        let roots = writer.region(region).into_iter().collect();
+        ValueGraph {
+            nodes: writer.nodes,
+            roots,
+        }
+    }
+    /// Rebuild the root values of this graph
+    pub fn values(&self) -> Result<Vec<ValId>, Error> {
+        let builder = GraphBuilder::build(&self.nodes)?;
+        self.roots.iter().map(|root| builder.val(*root)).collect()
+    }
+    /// Rebuild the single root value of this graph
+    pub fn value(&self) -> Result<ValId, Error> {
+        match self.roots.as_slice() {
+            [root] => GraphBuilder::build(&self.nodes)?.val(*root),
+            _ => Err(Error::InvalidGraph),
+        }
+    }
+    /// Rebuild the region rooting this graph, or the null region if it has no roots
+    pub fn region(&self) -> Result<Region, Error> {
+        match self.roots.as_slice() {
+            [] => Ok(Region::NULL),
+            [root] => GraphBuilder::build(&self.nodes)?.region(Some(*root)),
+            _ => Err(Error::InvalidGraph),
+        }
+    }
+}
+
+/// A writer for value graphs, assigning each region and value it encounters an index exactly once
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GraphWriter {
+    /// The nodes written so far
+    nodes: Vec<GraphNode>,
+    /// The indices of the values written so far
+    values: HashMap<ValId, usize, FxBuildHasher>,
+    /// The indices of the regions written so far
+    regions: HashMap<Region, usize, FxBuildHasher>,
+}
+
+impl GraphWriter {
+    /// Get the nodes written so far
+    #[inline]
+    pub(crate) fn nodes(&self) -> &[GraphNode] {
+        &self.nodes
+    }
+    /// Add a node to the graph, returning its index
+    #[inline]
+    fn push(&mut self, node: GraphNode) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+    /// Write a list of values, returning their indices
+    fn values<'a, I>(&mut self, values: I) -> Vec<usize>
+    where
+        I: IntoIterator<Item = &'a ValId>,
+    {
+        values.into_iter().map(|value| self.value(value)).collect()
+    }
+    /// Write a group of values, returning their indices
+    fn group(&mut self, group: Option<&Group>) -> Vec<usize> {
+        match group {
+            Some(group) => group
+                .values()
+                .iter()
+                .map(|value| self.value(value.as_valid()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+    /// Write a region, returning its index, or `None` for the null region
+    fn region(&mut self, region: &Region) -> Option<usize> {
+        if region.is_null() {
+            return None;
+        }
+        if let Some(ix) = self.regions.get(region) {
+            return Some(*ix);
+        }
+        let parent = self.region(region.parent());
+        let params = self.values(region.param_tys().iter().map(|ty| ty.as_val()));
+        let ix = self.push(GraphNode::Region { parent, params });
+        self.regions.insert(region.clone(), ix);
+        Some(ix)
+    }
+    /// Write the region a value is defined in, which is never the null region
+    fn def_region(&mut self, region: &Region) -> usize {
+        self.region(region)
+            .expect("Values are never defined in the null region")
+    }
+    /// Write a lifetime
+    fn lifetime(&mut self, lifetime: &Lifetime) -> LifetimeNode {
+        let region = self.region(&lifetime.clone_region());
+        let lender = self.group(lifetime.lender());
+        let transient = self.group(lifetime.transient());
+        let params = lifetime
+            .params()
+            .map(|params| {
+                params
+                    .0
+                    .iter()
+                    .map(|group| self.group(Some(group)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        LifetimeNode {
+            region,
+            lender,
+            transient,
+            params,
+        }
+    }
+    /// Write a value, returning its index
+    pub(crate) fn value(&mut self, value: &ValId) -> usize {
+        if let Some(ix) = self.values.get(value) {
+            return *ix;
+        }
+        let node = match value.as_enum() {
+            ValueEnum::Sexpr(s) => GraphNode::Sexpr(self.values(s.iter())),
+            ValueEnum::Parameter(p) => GraphNode::Parameter {
+                region: self.def_region(p.get_region()),
+                ix: p.ix(),
+            },
+            // Anchors can only be built by `Tuple::const_anchor`, so an anchor is always empty and reads back
+            ValueEnum::Tuple(t) => GraphNode::Tuple {
+                elems: self.values(t.iter()),
+                anchor: t.is_anchor(),
+            },
+            ValueEnum::Product(p) => GraphNode::Product {
+                elems: self.values(p.iter().map(|ty| ty.as_val())),
+                anchor: p.is_anchor(),
+                flare: p.is_flare(),
+            },
+            ValueEnum::Prop(_) => GraphNode::Prop,
+            ValueEnum::Fin(_) => GraphNode::Fin,
+            ValueEnum::Set(s) => GraphNode::Set(s.level()),
+            ValueEnum::BitsKind(_) => GraphNode::BitsKind,
+            ValueEnum::BitsTy(b) => GraphNode::BitsTy(b.0),
+            ValueEnum::Bits(b) => GraphNode::Bits {
+                len: b.len(),
+                data: b.data(),
+            },
+            ValueEnum::BoolTy(_) => GraphNode::BoolTy,
+            ValueEnum::Bool(b) => GraphNode::Bool(*b),
+            ValueEnum::Finite(f) => GraphNode::Finite(f.0),
+            ValueEnum::Index(i) => GraphNode::Index {
+                ty: i.get_ty().0,
+                ix: i.ix(),
+            },
+            ValueEnum::Pi(p) => GraphNode::Pi {
+                region: self.def_region(p.def_region()),
+                result: self.value(p.result().as_val()),
+                lenders: p.lifetime_component().lenders().to_vec(),
+                transients: p.lifetime_component().transients().to_vec(),
+            },
+            ValueEnum::Lambda(l) => GraphNode::Lambda {
+                region: self.def_region(l.def_region()),
+                result: self.value(l.result()),
+            },
+            ValueEnum::Ternary(t) => GraphNode::Ternary {
+                kind: t.ternary_kind(),
+                high: self.value(t.high()),
+                low: self.value(t.low()),
+            },
+            ValueEnum::Phi(p) => GraphNode::Phi {
+                region: self.def_region(p.def_region()),
+                values: self.values(p.values().iter()),
+            },
+            ValueEnum::Logical(l) => GraphNode::Logical {
+                arity: l.arity(),
+                data: l.data(),
+            },
+            ValueEnum::Id(i) => GraphNode::Id {
+                left: self.value(i.left()),
+                right: self.value(i.right()),
+            },
+            ValueEnum::Refl(r) => GraphNode::Refl(self.value(r.value())),
+            ValueEnum::IdFamily(f) => match f.ty().as_enum() {
+                ValueEnum::Pi(pi) => GraphNode::IdFamily(self.value(pi.param_tys()[0].as_val())),
+                ty => panic!("Invalid identity family type {}", ty),
+            },
+            ValueEnum::PathInd(p) => GraphNode::PathInd {
+                base_tys: self.values(p.base_tys().iter().map(|ty| ty.as_val())),
+                target: self.value(p.target().as_val()),
+            },
+            ValueEnum::BinOp(b) => GraphNode::BinOp(b.clone()),
+            ValueEnum::Neg(_) => GraphNode::Neg,
+            ValueEnum::RefTy(r) => GraphNode::RefTy {
+                referent: self.value(r.referent().as_val()),
+                lifetime: self.lifetime(r.ref_lifetime()),
+                kind: r.kind(),
+            },
+            ValueEnum::Borrow(b) => GraphNode::Borrow {
+                source: self.value(b.source()),
+                kind: b.kind(),
+                reborrow: b.is_reborrow(),
+            },
+            ValueEnum::Dereference(d) => GraphNode::Dereference(self.value(d.reference())),
+            ValueEnum::AllocTy(a) => GraphNode::AllocTy(self.value(a.alloc_ty().as_val())),
+            ValueEnum::MemOp(m) => GraphNode::MemOp {
+                kind: m.kind(),
+                ty: self.value(m.op_ty().as_val()),
+            },
+            ValueEnum::World(_) => GraphNode::World,
+            ValueEnum::Effect(e) => {
+                let ty = e.get_ty();
+                let param_tys = self.values(ty.param_tys()[1..].iter().map(|ty| ty.as_val()));
+                let result = match ty.result().as_enum() {
+                    ValueEnum::Product(p) => self.value(p[1].as_val()),
+                    ty => panic!("Invalid effect result type {}", ty),
+                };
+                GraphNode::Effect {
+                    name: e.name().to_owned(),
+                    param_tys,
+                    result,
+                }
+            }
+            ValueEnum::ArrayTy(a) => GraphNode::ArrayTy {
+                elem: self.value(a.elem().as_val()),
+                len: a.len(),
+            },
+            ValueEnum::Array(a) => GraphNode::Array {
+                elems: self.values(a.iter()),
+                elem_ty: self.value(a.get_ty().elem().as_val()),
+            },
+            ValueEnum::ArrayOp(a) => GraphNode::ArrayOp {
+                kind: a.kind(),
+                array_ty: self.value(a.array_ty().as_val()),
+                aux_ty: self.value(a.aux_ty().as_val()),
+            },
+            ValueEnum::Extern(e) => GraphNode::Extern {
+                name: e.name().to_owned(),
+                ty: self.value(e.get_ty().as_val()),
+                linkage: e.linkage(),
+            },
+            ValueEnum::Thunk(t) => GraphNode::Thunk(self.values(t.iter())),
+        };
+        let ix = self.push(node);
+        self.values.insert(value.clone(), ix);
+        ix
+    }
+}
+
+/// A region or value rebuilt from a node of a value graph
+#[derive(Debug, Clone)]
+enum Built {
+    /// A region
+    Region(Region),
+    /// A value
+    Value(ValId),
+}
+
+/// A builder for value graphs, rebuilding each node through the normal checked constructors
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GraphBuilder {
+    /// The nodes built so far
+    built: Vec<Built>,
+}
+
+impl GraphBuilder {
+    /// Build every node of a value graph, in order
+    pub(crate) fn build(nodes: &[GraphNode]) -> Result<GraphBuilder, Error> {
+        let mut builder = GraphBuilder {
+            built: Vec::with_capacity(nodes.len()),
+        };
+        for node in nodes {
+            let built = builder.node(node)?;
+            builder.built.push(built);
+        }
+        Ok(builder)
+    }
+    /// Get a value built previously
+    pub(crate) fn val(&self, ix: usize) -> Result<ValId, Error> {
+        match self.built.get(ix) {
+            Some(Built::Value(value)) => Ok(value.clone()),
+            _ => Err(Error::InvalidGraph),
+        }
+    }
+    /// Get a list of values built previously
+    fn vals(&self, ixes: &[usize]) -> Result<ValArr, Error> {
+        ixes.iter().map(|ix| self.val(*ix)).collect()
+    }
+    /// Get a type built previously
+    fn ty(&self, ix: usize) -> Result<TypeId, Error> {
+        TypeId::try_from(self.val(ix)?).map_err(|_| Error::NotATypeError)
+    }
+    /// Get a list of types built previously
+    fn tys(&self, ixes: &[usize]) -> Result<TyArr, Error> {
+        ixes.iter().map(|ix| self.ty(*ix)).collect()
+    }
+    /// Get a kind built previously
+    fn kind(&self, ix: usize) -> Result<KindId, Error> {
+        KindId::try_from(self.val(ix)?).map_err(|_| Error::NotAKindError)
+    }
+    /// Get a value of a given variant built previously
+    fn var<V>(&self, ix: usize) -> Result<VarId<V>, Error>
+    where
+        for<'b> &'b NormalValue: TryInto<&'b V>,
+    {
+        VarId::try_from(self.val(ix)?).map_err(|_| Error::TypeMismatch)
+    }
+    /// Get a region built previously, or the null region
+    fn region(&self, ix: Option<usize>) -> Result<Region, Error> {
+        match ix.map(|ix| self.built.get(ix)) {
+            None => Ok(Region::NULL),
+            Some(Some(Built::Region(region))) => Ok(region.clone()),
+            Some(_) => Err(Error::InvalidGraph),
+        }
+    }
+    /// Get a group of values built previously, if any
+    fn group(&self, ixes: &[usize]) -> Result<Option<Group>, Error> {
+        let singletons = ixes
+            .iter()
+            .map(|ix| Option::<Group>::from(self.val(*ix)?).ok_or(Error::InvalidGraph))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Group::merge(singletons.iter()))
+    }
+    /// Build a lifetime
+    fn lifetime(&self, lifetime: &LifetimeNode) -> Result<Lifetime, Error> {
+        let region = self.region(lifetime.region)?;
+        let lender = self.group(&lifetime.lender)?;
+        let transient = self.group(&lifetime.transient)?;
+        let params = lifetime
+            .params
+            .iter()
+            .map(|param| self.group(param)?.ok_or(Error::InvalidGraph))
+            .collect::<Result<_, _>>()?;
+        Ok(LifetimeData::try_new(region, lender, transient, LifetimeParams(params))?.into())
+    }
+    /// Build a node
+    fn node(&self, node: &GraphNode) -> Result<Built, Error> {
+        let value = match node {
+            GraphNode::Region { parent, params } => {
+                let parent = self.region(*parent)?;
+                let region = Region::with(self.tys(params)?, parent)?;
+                return Ok(Built::Region(region));
+            }
+            GraphNode::Sexpr(args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.val(*arg))
+                    .collect::<Result<_, _>>()?;
+                Sexpr::try_new(args)?.into_val()
+            }
+            GraphNode::Parameter { region, ix } => {
+                Parameter::try_new(self.region(Some(*region))?, *ix)?.into_val()
+            }
+            GraphNode::Tuple { elems, anchor } => {
+                let elems = self.vals(elems)?;
+                if !anchor {
+                    Tuple::try_new(elems)?.into_val()
+                } else if elems.is_empty() {
+                    Tuple::const_anchor().into_val()
+                } else {
+                    return Err(Error::InvalidGraph);
+                }
+            }
+            GraphNode::Product {
+                elems,
+                anchor,
+                flare,
+            } => Product::try_new_forced(self.tys(elems)?, *anchor, *flare)?.into_val(),
+            GraphNode::Prop => Prop.into_val(),
+            GraphNode::Fin => Fin.into_val(),
+            GraphNode::Set(level) => Set::new(*level).into_val(),
+            GraphNode::BitsKind => BitsKind.into_val(),
+            GraphNode::BitsTy(len) => BitsTy(*len).into_val(),
+            GraphNode::Bits { len, data } => BitsTy(*len).data(*data)?.into_val(),
+            GraphNode::BoolTy => Bool.into_val(),
+            GraphNode::Bool(b) => (*b).into_val(),
+            GraphNode::Finite(n) => Finite(*n).into_val(),
+            GraphNode::Index { ty, ix } => Finite(*ty)
+                .ix(*ix)
+                .map_err(|_| Error::InvalidGraph)?
+                .into_val(),
+            GraphNode::Pi {
+                region,
+                result,
+                lenders,
+                transients,
+            } => Pi::with_lifetime(
+                Parametrized::try_new(self.ty(*result)?, self.region(Some(*region))?)?,
+                PiLifetime::new(
+                    lenders.iter().copied().collect(),
+                    transients.iter().copied().collect(),
+                ),
+            )?
+            .into_val(),
+            GraphNode::Lambda { region, result } => {
+                Lambda::try_new(self.val(*result)?, self.region(Some(*region))?)?.into_val()
+            }
+            GraphNode::Ternary { kind, high, low } => {
+                let high = self.val(*high)?;
+                let low = self.val(*low)?;
+                match kind {
+                    TernaryKind::Bool => Ternary::conditional(high, low)?.into_val(),
+                    TernaryKind::Switch => Ternary::switch(high, low)?.into_val(),
+                }
+            }
+            GraphNode::Phi { region, values } => {
+                Phi::try_new(self.vals(values)?, self.region(Some(*region))?)?.into_val()
+            }
+            GraphNode::Logical { arity, data } => Logical::try_new(*arity, *data)
+                .map_err(|_| Error::InvalidGraph)?
+                .into_val(),
+            GraphNode::Id { left, right } => {
+                Id::try_new(self.val(*left)?, self.val(*right)?)?.into_val()
+            }
+            GraphNode::Refl(value) => Refl::refl(self.val(*value)?).into_val(),
+            GraphNode::IdFamily(kind) => IdFamily::universal(self.kind(*kind)?).into_val(),
+            GraphNode::PathInd { base_tys, target } => {
+                PathInd::try_new(self.tys(base_tys)?, self.kind(*target)?)?.into_val()
+            }
+            GraphNode::BinOp(op) => op.clone().into_val(),
+            GraphNode::Neg => Neg.into_val(),
+            GraphNode::RefTy {
+                referent,
+                lifetime,
+                kind,
+            } => RefTy::try_new(self.ty(*referent)?, self.lifetime(lifetime)?, *kind)?.into_val(),
+            GraphNode::Borrow {
+                source,
+                kind,
+                reborrow,
+            } => {
+                let source = self.val(*source)?;
+                if *reborrow {
+                    Borrow::reborrow(source, *kind)?.into_val()
+                } else {
+                    Borrow::borrow(source, *kind)?.into_val()
+                }
+            }
+            GraphNode::Dereference(reference) => {
+                Dereference::try_new(self.val(*reference)?)?.into_val()
+            }
+            GraphNode::AllocTy(ty) => AllocTy::try_new(self.ty(*ty)?)?.into_val(),
+            GraphNode::MemOp { kind, ty } => MemOp::try_new(*kind, self.ty(*ty)?)?.into_val(),
+            GraphNode::World => World.into_val(),
+            GraphNode::Effect {
+                name,
+                param_tys,
+                result,
+            } => Effect::try_new(name, &self.tys(param_tys)?, self.ty(*result)?)?.into_val(),
+            GraphNode::ArrayTy { elem, len } => ArrayTy::new(self.ty(*elem)?, *len).into_val(),
+            GraphNode::Array { elems, elem_ty } => {
+                Array::try_new(self.vals(elems)?, self.ty(*elem_ty)?)?.into_val()
+            }
+            GraphNode::ArrayOp {
+                kind,
+                array_ty,
+                aux_ty,
+            } => {
+                let array_ty = self.var::<ArrayTy>(*array_ty)?;
+                let aux_ty = self.ty(*aux_ty)?;
+                match kind {
+                    ArrayOpKind::Get => ArrayOp::get(array_ty)?,
+                    ArrayOpKind::Set => ArrayOp::set(array_ty)?,
+                    ArrayOpKind::Map => ArrayOp::map(array_ty, aux_ty)?,
+                    ArrayOpKind::Fold => ArrayOp::fold(array_ty, aux_ty)?,
+                    ArrayOpKind::Repeat => ArrayOp::repeat(array_ty)?,
+                }
+                .into_val()
+            }
+            GraphNode::Extern { name, ty, linkage } => {
+                Extern::try_new(name, self.var::<Pi>(*ty)?, *linkage)?.into_val()
+            }
+            GraphNode::Thunk(args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.val(*arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Thunk::try_defer(&args, &mut None)?
+                    .ok_or(Error::InvalidGraph)?
+                    .into_val()
+            }
+        };
+        Ok(Built::Value(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typing::Type;
+    use crate::valarr;
+
+    #[test]
+    fn shared_subgraphs_are_stored_once() {
+        let pair = Tuple::try_new(valarr![true.into_val(), false.into_val()])
+            .unwrap()
+            .into_val();
+        let once = ValueGraph::from_values(&[pair.clone()]);
+        let twice = ValueGraph::from_values(&[pair.clone(), pair.clone()]);
+        assert_eq!(once.nodes, twice.nodes);
+        assert_eq!(twice.roots, vec![once.roots[0]; 2]);
+        let with_ty = ValueGraph::from_values(&[pair.ty().clone_val(), pair.clone()]);
+        let bools = with_ty
+            .nodes
+            .iter()
+            .filter(|node| **node == GraphNode::BoolTy)
+            .count();
+        assert_eq!(bools, 1);
+        assert_eq!(with_ty.values(), Ok(vec![pair.ty().clone_val(), pair]));
+    }
+
+    #[test]
+    fn anchors_round_trip() {
+        let anchor = Tuple::const_anchor().into_val();
+        let pair = Tuple::try_new(valarr![anchor.clone(), true.into_val()])
+            .unwrap()
+            .into_val();
+        let graph = ValueGraph::from_values(&[anchor.clone(), pair.clone()]);
+        let anchors = graph
+            .nodes
+            .iter()
+            .filter(|node| match node {
+                GraphNode::Tuple { anchor, .. } => *anchor,
+                _ => false,
+            })
+            .count();
+        assert_eq!(anchors, 1);
+        assert_eq!(graph.values(), Ok(vec![anchor, pair]));
+    }
+
+    #[test]
+    fn malformed_graphs_are_rejected() {
+        let dangling = ValueGraph {
+            nodes: vec![GraphNode::Refl(1)],
+            roots: vec![0],
+        };
+        assert_eq!(dangling.value(), Err(Error::InvalidGraph));
+        let not_a_type = ValueGraph {
+            nodes: vec![
+                GraphNode::Bool(true),
+                GraphNode::Region {
+                    parent: None,
+                    params: vec![0],
+                },
+            ],
+            roots: vec![1],
+        };
+        assert_eq!(not_a_type.region(), Err(Error::NotATypeError));
+        let mismatch = ValueGraph {
+            nodes: vec![
+                GraphNode::Bool(true),
+                GraphNode::Finite(2),
+                GraphNode::Array {
+                    elems: vec![0],
+                    elem_ty: 1,
+                },
+            ],
+            roots: vec![2],
+        };
+        assert!(mismatch.value().is_err());
+        // Lifetimes may only borrow from values within their region
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let escaping = ValueGraph {
+            nodes: vec![
+                GraphNode::BoolTy,
+                GraphNode::Region {
+                    parent: None,
+                    params: vec![0],
+                },
+                GraphNode::Parameter { region: 1, ix: 0 },
+                GraphNode::RefTy {
+                    referent: 0,
+                    lifetime: LifetimeNode {
+                        region: None,
+                        lender: vec![2],
+                        transient: vec![],
+                        params: vec![],
+                    },
+                    kind: RefKind::Shared,
+                },
+            ],
+            roots: vec![2, 3],
+        };
+        assert_eq!(escaping.values(), Err(Error::IncomparableRegions));
+        assert_eq!(ValueGraph::from_values(&[x.clone()]).value(), Ok(x));
+    }
+}
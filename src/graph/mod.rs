@@ -5,6 +5,8 @@ use crate::value::{Deps, ValId, Value};
 use fxhash::FxHashSet;
 
 pub mod dfs;
+pub mod dot;
+pub mod flat;
 
 /// Filter already-visited addresses
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
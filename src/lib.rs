@@ -15,12 +15,15 @@ Contributions, ideas and collaboration proposals are welcome: please make an iss
 #![recursion_limit = "256"]
 #![warn(clippy::all)]
 
+pub mod binary;
 pub mod control;
 pub mod data;
+pub mod debug;
 pub mod eval;
 pub mod function;
 pub mod graph;
 pub mod lifetime;
+pub mod lower;
 pub mod primitive;
 pub mod proof;
 pub mod region;
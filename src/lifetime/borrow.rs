@@ -0,0 +1,296 @@
+/*!
+Borrow checking and borrow-compatible scheduling for the values of a `rain` region
+*/
+use super::*;
+use crate::value::{Error, Value, ValueEnum};
+use hashbrown::HashSet;
+
+impl LifetimeCtx {
+    /**
+    Borrow-check the values reachable from a set of results in this context's region, inserting the temporal edges
+    necessary to guarantee that any topological sort including them is borrow-compatible.
+
+    A value borrowing from a lender in this region must be computed, and all its users in this region must be
+    computed, before any value which consumes that lender. A transient borrow only requires the borrowing value itself to be
+    computed before the lender is consumed. A value may only consume a lender it also borrows from, directly or through
+    its dependencies, if the lender's type is not affine, as consuming it then leaves the lender intact. Results of this
+    region may not borrow from non-parameter values of this region.
+
+    On failure, the first value found violating a borrowing constraint is returned along with the constraint violated.
+    */
+    pub fn check_borrows(&mut self, results: &[&ValId]) -> Result<(), UsageError> {
+        let order = self.region_order(results);
+        let mut users: HashMap<ValAddr, Vec<&ValId>, FxBuildHasher> = HashMap::default();
+        let mut consumers: HashMap<ValAddr, Vec<&ValId>, FxBuildHasher> = HashMap::default();
+        for &value in order.iter() {
+            let norm = value.as_norm();
+            for ix in 0..norm.no_deps() {
+                let dep = norm.get_dep(ix);
+                if !self.in_region(dep) {
+                    continue;
+                }
+                users.entry(dep.as_addr()).or_default().push(value);
+                if norm.dep_owned(ix) {
+                    consumers.entry(dep.as_addr()).or_default().push(value);
+                }
+            }
+        }
+        for result in results {
+            if let Some(lender) = result.lifetime().lender() {
+                for lender in lender.values() {
+                    let lender = lender.clone_val();
+                    let is_param = match lender.as_enum() {
+                        ValueEnum::Parameter(param) => param.get_region() == self.region(),
+                        _ => false,
+                    };
+                    if self.in_region(&lender) && !is_param {
+                        return Err(UsageError {
+                            value: (*result).clone(),
+                            error: Error::BorrowedMismatch,
+                        });
+                    }
+                }
+            }
+        }
+        for &value in order.iter() {
+            let lifetime = value.lifetime();
+            let value_users = users
+                .get(&value.as_addr())
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            if let Some(lender) = lifetime.lender() {
+                for lender in lender.values() {
+                    for &consumer in consumers
+                        .get(&lender.as_addr())
+                        .map(Vec::as_slice)
+                        .unwrap_or(&[])
+                    {
+                        if consumer == value || value_users.contains(&consumer) {
+                            if lender.ty().is_affine() {
+                                return Err(UsageError {
+                                    value: lender.clone_val(),
+                                    error: Error::BorrowUsed,
+                                });
+                            }
+                            continue;
+                        }
+                        self.graph_mut().push_temporal(value, consumer);
+                        for &user in value_users {
+                            self.graph_mut().push_temporal(user, consumer);
+                        }
+                    }
+                }
+            }
+            if let Some(transient) = lifetime.transient() {
+                for lender in transient.values() {
+                    for &consumer in consumers
+                        .get(&lender.as_addr())
+                        .map(Vec::as_slice)
+                        .unwrap_or(&[])
+                    {
+                        if consumer == value {
+                            if lender.ty().is_affine() {
+                                return Err(UsageError {
+                                    value: lender.clone_val(),
+                                    error: Error::BorrowUsed,
+                                });
+                            }
+                            continue;
+                        }
+                        self.graph_mut().push_temporal(value, consumer);
+                    }
+                }
+            }
+        }
+        self.graph_mut().cleanup();
+        self.schedule(results).map(|_| ())
+    }
+    /**
+    Compute a schedule of the values reachable from a set of results in this context's region, dependencies first, which
+    respects the temporal edges in this context's graph.
+
+    Returns an error if no such schedule exists, i.e. if some value must be consumed before a borrow of it ends.
+    */
+    pub fn schedule(&self, results: &[&ValId]) -> Result<Vec<ValId>, UsageError> {
+        let order = self.region_order(results);
+        let indices: HashMap<usize, usize, FxBuildHasher> = order
+            .iter()
+            .enumerate()
+            .map(|(ix, value)| (value.as_addr().raw_addr(), ix))
+            .collect();
+        let mut successors: Vec<Vec<usize>> = vec![Vec::new(); order.len()];
+        let mut predecessors: Vec<usize> = vec![0; order.len()];
+        for (ix, value) in order.iter().enumerate() {
+            let mut preds: HashSet<usize, FxBuildHasher> = HashSet::default();
+            preds.extend(
+                value
+                    .deps()
+                    .iter()
+                    .filter_map(|dep| indices.get(&dep.as_addr().raw_addr()).copied()),
+            );
+            if let Some(data) = self.graph().valid_data(value) {
+                preds.extend(
+                    data.temporal()
+                        .iter()
+                        .filter_map(|source| indices.get(&source.raw_addr()).copied()),
+                );
+            }
+            predecessors[ix] = preds.len();
+            for pred in preds {
+                successors[pred].push(ix);
+            }
+        }
+        let mut ready: Vec<usize> = (0..order.len())
+            .filter(|ix| predecessors[*ix] == 0)
+            .collect();
+        let mut schedule = Vec::with_capacity(order.len());
+        while let Some(top) = ready.pop() {
+            schedule.push(order[top].clone());
+            for &succ in successors[top].iter() {
+                predecessors[succ] -= 1;
+                if predecessors[succ] == 0 {
+                    ready.push(succ);
+                }
+            }
+        }
+        if schedule.len() == order.len() {
+            Ok(schedule)
+        } else {
+            let stuck = predecessors
+                .iter()
+                .rposition(|preds| *preds != 0)
+                .expect("Some value is not scheduled");
+            Err(UsageError {
+                value: order[stuck].clone(),
+                error: Error::BorrowUsed,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::reference::{Borrow, RefTy};
+    use crate::function::{external::Extern, pi::Pi};
+    use crate::primitive::logical::Bool;
+    use crate::typing::Type;
+    use crate::value::expr::Sexpr;
+    use crate::value::tuple::{Product, Tuple};
+    use crate::{tyarr, valarr};
+
+    #[test]
+    fn borrow_lifetime_construction() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let borrow = Lifetime::from(LifetimeData::borrow(&x));
+        assert!(!borrow.is_trivial());
+        assert!(!borrow.is_transient());
+        assert!(borrow.is_concrete());
+        assert_eq!(borrow.region(), region);
+        let lenders = borrow.lender().unwrap().values();
+        assert_eq!(lenders.len(), 1);
+        assert_eq!(lenders[0].as_addr(), x.as_addr());
+        let transient = Lifetime::from(LifetimeData::transient_borrow(&x));
+        assert!(transient.is_transient());
+        assert!(!transient.is_concrete());
+        assert_eq!(transient.lender(), None);
+        assert_eq!(transient.concrete(), Lifetime::from(region));
+    }
+
+    #[test]
+    fn schedule_respects_dependencies() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let xy = Tuple::try_new(valarr![x.clone(), y.clone()])
+            .unwrap()
+            .into_val();
+        let yxy = Tuple::try_new(valarr![y.clone(), xy.clone()])
+            .unwrap()
+            .into_val();
+        let mut ctx = LifetimeCtx::new(region);
+        assert_eq!(ctx.check_borrows(&[&yxy]), Ok(()));
+        let schedule = ctx.schedule(&[&yxy]).unwrap();
+        assert_eq!(schedule.len(), 4);
+        let position = |v: &ValId| schedule.iter().position(|s| s == v).unwrap();
+        assert!(position(&x) < position(&xy));
+        assert!(position(&y) < position(&xy));
+        assert!(position(&xy) < position(&yxy));
+    }
+
+    #[test]
+    fn borrowed_values_are_scheduled_before_consumers() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let borrow = Borrow::shared(x.clone()).unwrap().into_val();
+        let user = Tuple::try_new(valarr![borrow.clone()]).unwrap().into_val();
+        let consumer = Tuple::try_new(valarr![x.clone()]).unwrap().into_val();
+        let mut ctx = LifetimeCtx::new(region);
+        assert_eq!(ctx.check_borrows(&[&user, &consumer]), Ok(()));
+        let temporal = ctx.graph().valid_data(&consumer).unwrap().temporal();
+        assert!(temporal.contains(&NodeId::valid(&borrow)));
+        assert!(temporal.contains(&NodeId::valid(&user)));
+        let schedule = ctx.schedule(&[&user, &consumer]).unwrap();
+        assert_eq!(schedule.len(), 4);
+        let position = |v: &ValId| schedule.iter().position(|s| s == v).unwrap();
+        assert!(position(&x) < position(&borrow));
+        assert!(position(&borrow) < position(&user));
+        assert!(position(&user) < position(&consumer));
+    }
+
+    #[test]
+    fn consuming_a_borrowed_value_is_rejected() {
+        let anchor = Product::anchor_ty().into_ty();
+        let anchor_ref = RefTy::shared(anchor.clone(), Lifetime::STATIC)
+            .unwrap()
+            .into_ty();
+        let consume_region =
+            Region::with(tyarr![anchor.clone(), anchor_ref], Region::NULL).unwrap();
+        let consume_ty = Pi::try_new(Bool.into_ty(), consume_region)
+            .unwrap()
+            .into_var();
+        let consume = Extern::c_fn("consume", consume_ty).unwrap().into_val();
+        let region = Region::unary(anchor);
+        let a = region.param(0).unwrap().into_val();
+        let borrow = Borrow::shared(a.clone()).unwrap().into_val();
+        let call = Sexpr::try_new(vec![consume, a.clone(), borrow])
+            .unwrap()
+            .into_val();
+        let mut ctx = LifetimeCtx::new(region);
+        assert_eq!(
+            ctx.check_borrows(&[&call]),
+            Err(UsageError {
+                value: a,
+                error: Error::BorrowUsed
+            })
+        );
+    }
+
+    #[test]
+    fn consuming_a_borrowed_copyable_value_is_accepted() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let borrow = Borrow::shared(x.clone()).unwrap().into_val();
+        let both = Tuple::try_new(valarr![x, borrow]).unwrap().into_val();
+        let mut ctx = LifetimeCtx::new(region);
+        assert_eq!(ctx.check_borrows(&[&both]), Ok(()));
+    }
+
+    #[test]
+    fn results_may_only_borrow_from_parameters() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let xy = Tuple::try_new(valarr![x, y]).unwrap().into_val();
+        let borrow = Borrow::shared(xy).unwrap().into_val();
+        let mut ctx = LifetimeCtx::new(region);
+        assert_eq!(
+            ctx.check_borrows(&[&borrow]),
+            Err(UsageError {
+                value: borrow,
+                error: Error::BorrowedMismatch
+            })
+        );
+    }
+}
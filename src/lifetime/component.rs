@@ -0,0 +1,162 @@
+/*!
+Lifetime components of pi types
+*/
+use super::*;
+use crate::value::ValueEnum;
+
+/// The size of a small vector of parameter indices
+pub const SMALL_PARAM_INDICES: usize = 2;
+
+/// A vector of parameter indices
+pub type ParamIndices = SmallVec<[usize; SMALL_PARAM_INDICES]>;
+
+/// The lifetime component of a pi type, describing which of its parameters the result of a function borrows from.
+/// The default lifetime component is trivial, i.e. the result does not borrow from any parameter
+///
+/// TODO: pi and lambda regions cannot yet declare lifetime parameters of their own, so a function cannot abstract
+/// over the lifetime of a borrow passed to it, only forward borrows of its parameters
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct PiLifetime {
+    /// The parameters the result of this function borrows from, sorted and deduplicated
+    lenders: ParamIndices,
+    /// The parameters the result of this function transiently borrows from, sorted and deduplicated
+    transients: ParamIndices,
+}
+
+impl PiLifetime {
+    /// Create a new lifetime component from the parameters the result borrows from and transiently borrows from
+    pub fn new(mut lenders: ParamIndices, mut transients: ParamIndices) -> PiLifetime {
+        lenders.sort_unstable();
+        lenders.dedup();
+        transients.sort_unstable();
+        transients.dedup();
+        PiLifetime {
+            lenders,
+            transients,
+        }
+    }
+    /// Create the lifetime component "result borrows from argument `ix`"
+    pub fn borrows_from(ix: usize) -> PiLifetime {
+        PiLifetime {
+            lenders: smallvec::smallvec![ix],
+            transients: ParamIndices::new(),
+        }
+    }
+    /// Create the lifetime component of a result with a given lifetime defined in a given region
+    ///
+    /// Only borrows from the parameters of the defining region are recorded: borrows from values outside the
+    /// defining region are already accounted for by the lifetime of the function itself.
+    pub fn from_result(result: &Lifetime, def_region: &Region) -> PiLifetime {
+        let param_ixes = |group: Option<&Group>| -> ParamIndices {
+            group
+                .map(Group::values)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|value| match value.as_enum() {
+                    ValueEnum::Parameter(p) if p.get_region() == def_region => Some(p.ix()),
+                    _ => None,
+                })
+                .collect()
+        };
+        Self::new(param_ixes(result.lender()), param_ixes(result.transient()))
+    }
+    /// Get the parameters the result borrows from
+    #[inline]
+    pub fn lenders(&self) -> &[usize] {
+        &self.lenders
+    }
+    /// Get the parameters the result transiently borrows from
+    #[inline]
+    pub fn transients(&self) -> &[usize] {
+        &self.transients
+    }
+    /// Check whether the result borrows, possibly transiently, from a given parameter
+    #[inline]
+    pub fn borrows(&self, ix: usize) -> bool {
+        self.lenders.binary_search(&ix).is_ok() || self.transients.binary_search(&ix).is_ok()
+    }
+    /// Check whether this lifetime component is trivial, i.e. the result does not borrow from any parameter
+    #[inline]
+    pub fn is_trivial(&self) -> bool {
+        self.lenders.is_empty() && self.transients.is_empty()
+    }
+    /// Get the largest parameter index borrowed from, if any
+    #[inline]
+    pub fn max_ix(&self) -> Option<usize> {
+        self.lenders
+            .last()
+            .copied()
+            .max(self.transients.last().copied())
+    }
+    /// Get the lifetime component of this pi type after `n` arguments have been applied
+    ///
+    /// Borrows from applied arguments are dropped, as they are accounted for in the lifetime of the partial application.
+    pub fn partially_applied(&self, n: usize) -> PiLifetime {
+        let shift = |ixes: &ParamIndices| -> ParamIndices {
+            ixes.iter()
+                .filter(|ix| **ix >= n)
+                .map(|ix| ix - n)
+                .collect()
+        };
+        PiLifetime {
+            lenders: shift(&self.lenders),
+            transients: shift(&self.transients),
+        }
+    }
+    /// Instantiate this lifetime component with a set of arguments, yielding the lender and transient groups borrowed
+    /// from by the result.
+    ///
+    /// Borrowing from an argument which is itself borrowed borrows from that argument's lender instead.
+    /// Parameters for which no argument is given are ignored.
+    pub fn instantiate(&self, args: &[ValId]) -> (Option<Group>, Option<Group>) {
+        let groups = |ixes: &ParamIndices| -> Option<Group> {
+            let groups: SmallVec<[Group; SMALL_PARAM_INDICES]> = ixes
+                .iter()
+                .filter_map(|ix| args.get(*ix))
+                .filter_map(|arg| match arg.lifetime().lender() {
+                    Some(lender) => Some(lender.clone()),
+                    None => arg.clone().into(),
+                })
+                .collect();
+            Group::merge(groups.iter())
+        };
+        (groups(&self.lenders), groups(&self.transients))
+    }
+}
+
+impl LifetimeCtx {
+    /// Generate the lifetime component of a pi type having a given value, in this context's region, as result
+    #[inline]
+    pub fn pi_component(&self, result: &ValId) -> PiLifetime {
+        PiLifetime::from_result(&result.clone_lifetime(), self.region())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::logical::Bool;
+    use crate::value::Value;
+
+    #[test]
+    fn pi_lifetime_from_result() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let borrow_y = Lifetime::from(LifetimeData::borrow(&y));
+        let component = PiLifetime::from_result(&borrow_y, &region);
+        assert_eq!(component, PiLifetime::borrows_from(1));
+        assert_eq!(component.max_ix(), Some(1));
+        assert!(component.borrows(1));
+        assert!(!component.borrows(0));
+        assert_eq!(component.partially_applied(1), PiLifetime::borrows_from(0));
+        assert!(component.partially_applied(2).is_trivial());
+        let (lender, transient) = component.instantiate(&[y.clone(), x.clone()]);
+        assert_eq!(lender, x.clone().into());
+        assert_eq!(transient, None);
+        let region_lt = Lifetime::from(region.clone());
+        assert!(PiLifetime::from_result(&region_lt, &region).is_trivial());
+        let ctx = LifetimeCtx::new(region);
+        assert!(ctx.pi_component(&x).is_trivial());
+    }
+}
@@ -2,13 +2,14 @@
 A `rain` lifetime context.
 */
 use super::*;
+use crate::util::AddrLookup;
 
 /**
 A `rain` lifetime context graph
 
 Handles:
 - Checking affine values are only used once
-- Checking relevant values are only used once
+- Checking relevant values are used at least once
 - Enumerating out-of-region dependencies of a `rain` value
 - Generating a lifetime-component for a pi type having a given `rain` value as result
 - Checking that a borrow-compatible topological sort of the `rain`-graph is possible
@@ -25,8 +26,8 @@ pub struct LifetimeCtx {
     /// TODO: potential unsafe optimization: the owned variant can be a raw poiner
     /// (avoiding double atomic reference count updates) as long as owned externals
     /// are guaranteed to get an entry in the lifetime graph (to hold their owners).
-    /// This is currently, however, wrong, since there is no affinity check in the
-    /// lifetime graph yet.
+    /// This is currently, however, wrong, since the results of the affinity check
+    /// are not yet recorded in the lifetime graph.
     deps: Vec<Union2<Arc<NormalValue>, Arc<NormalValue>>>,
 }
 
@@ -45,6 +46,11 @@ impl LifetimeCtx {
     pub fn graph(&self) -> &LifetimeGraph {
         &self.graph
     }
+    /// Mutably access the graph of this lifetime context
+    #[inline]
+    pub fn graph_mut(&mut self) -> &mut LifetimeGraph {
+        &mut self.graph
+    }
     /// Get the region of this lifetime context
     #[inline]
     pub fn region(&self) -> &Region {
@@ -103,6 +109,25 @@ impl LifetimeGraph {
             .lookup_or_insert(grp, || (grp.clone(), NodeData::default()));
         data
     }
+    /// Get the data associated with a given `ValId`, if any
+    #[inline]
+    pub fn valid_data(&self, val: &ValId) -> Option<&NodeData> {
+        self.values.lookup(val).map(|(_, data)| data)
+    }
+    /// Add a temporal edge to this graph, requiring `before` to be scheduled before `after`
+    #[inline]
+    pub fn push_temporal(&mut self, before: &ValId, after: &ValId) {
+        self.valid_entry(after).push_temporal(NodeId::valid(before))
+    }
+    /// Cleanup the data of every node in this graph
+    pub fn cleanup(&mut self) {
+        for data in self.values.values_mut() {
+            data.cleanup()
+        }
+        for data in self.groups.values_mut() {
+            data.cleanup()
+        }
+    }
     /// Mutably get the data associated with a given `NodeId` if it already exists
     pub fn node_data_mut(&mut self, id: NodeId) -> Option<&mut NodeData> {
         match id.disc() {
@@ -170,6 +195,11 @@ impl NodeData {
     pub fn push_temporal(&mut self, source: NodeId) {
         self.temporal.push(source)
     }
+    /// Get the temporal edges leading to this node
+    #[inline]
+    pub fn temporal(&self) -> &[NodeId] {
+        &self.temporal
+    }
     /// Cleanup this temporal node's data, sorting and deduplicating it's temporal dependencies
     pub fn cleanup(&mut self) {
         self.temporal.sort_unstable();
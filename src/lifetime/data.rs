@@ -39,6 +39,28 @@ impl LifetimeData {
             lt_params,
         }
     }
+    /// Construct a new lifetime, checking that its lender, transient component and lifetime parameters are all
+    /// contained in its region
+    pub fn try_new(
+        region: Region,
+        lender: Option<Group>,
+        transient: Option<Group>,
+        lt_params: LifetimeParams,
+    ) -> Result<LifetimeData, Error> {
+        let groups = lender
+            .iter()
+            .chain(transient.iter())
+            .chain(lt_params.iter());
+        for value in groups.flat_map(Group::values) {
+            match value.region().partial_cmp(&region.region()) {
+                Some(Ordering::Less) | Some(Ordering::Equal) => {}
+                _ => return Err(Error::IncomparableRegions),
+            }
+        }
+        Ok(LifetimeData::new_unchecked(
+            region, lender, transient, lt_params,
+        ))
+    }
     /// Construct a new trivial lifetime from a region
     #[inline]
     pub fn from_region(region: Region) -> LifetimeData {
@@ -49,6 +71,36 @@ impl LifetimeData {
             lt_params: LifetimeParams::default(),
         }
     }
+    /// Construct the lifetime of a borrow of a given value
+    ///
+    /// Borrowing a value which is itself borrowed yields a borrow from that value's lender, i.e. a reborrow.
+    pub fn borrow(value: &ValId) -> LifetimeData {
+        let lifetime = value.lifetime();
+        let lender = match lifetime.lender() {
+            Some(lender) => Some(lender.clone()),
+            None => value.clone().into(),
+        };
+        LifetimeData {
+            region: value.clone_region(),
+            lender,
+            transient: None,
+            lt_params: lifetime.params().cloned().unwrap_or_default(),
+        }
+    }
+    /// Construct the lifetime of a transient borrow of a given value, which ends as soon as the borrowing value is computed
+    pub fn transient_borrow(value: &ValId) -> LifetimeData {
+        let lifetime = value.lifetime();
+        let transient = match lifetime.lender() {
+            Some(lender) => Some(lender.clone()),
+            None => value.clone().into(),
+        };
+        LifetimeData {
+            region: value.clone_region(),
+            lender: None,
+            transient,
+            lt_params: LifetimeParams::default(),
+        }
+    }
     /// Check if lifetime data is trivial, i.e. consists only of region data
     #[inline]
     pub fn is_trivial(&self) -> bool {
@@ -23,6 +23,55 @@ impl Group {
     pub fn addr(&self) -> GroupAddr {
         unsafe { std::mem::transmute_copy(self) }
     }
+    /// Get the value making up this group, if it is a singleton
+    #[inline]
+    pub fn value(&self) -> Option<ValRef> {
+        self.0
+            .with_a(|value| &**value as *const NormalValue)
+            .map(|ptr| unsafe { ValRef::from_raw(ptr) })
+    }
+    /// Get the subgroups making up this group, if it is a multigroup. Otherwise, return an empty slice
+    #[inline]
+    pub fn subgroups(&self) -> &[Group] {
+        self.0
+            .with_b(|mg| {
+                let slice: &[Group] = &mg.slice;
+                (slice.as_ptr(), slice.len())
+            })
+            .map(|(ptr, len)| unsafe { std::slice::from_raw_parts(ptr, len) })
+            .unwrap_or(&[])
+    }
+    /// Merge a set of groups into a single group containing all their values, or `None` if the set is empty
+    pub fn merge<'a, I>(groups: I) -> Option<Group>
+    where
+        I: IntoIterator<Item = &'a Group>,
+    {
+        let mut values: Vec<ValRef> = groups.into_iter().flat_map(Group::values).collect();
+        values.sort_unstable_by_key(|value| value.as_addr());
+        values.dedup_by_key(|value| value.as_addr());
+        match values.len() {
+            0 => None,
+            1 => values[0].clone_val().into(),
+            _ => Some(
+                MultiGroup::new(values.into_iter().map(|value| {
+                    Option::<Group>::from(value.clone_val()).expect("Values always form a group")
+                }))
+                .into(),
+            ),
+        }
+    }
+    /// Get the values making up this group
+    pub fn values(&self) -> Vec<ValRef> {
+        let mut values = Vec::new();
+        let mut frontier = vec![self];
+        while let Some(top) = frontier.pop() {
+            if let Some(value) = top.value() {
+                values.push(value)
+            }
+            frontier.extend(top.subgroups().iter().rev());
+        }
+        values
+    }
 }
 
 /// The address of a non-empty group of values
@@ -124,6 +173,21 @@ pub type GSArc = Arc<SliceWithHeader<(), Group>>;
 #[repr(transparent)]
 pub struct MultiGroup(Thin<GSArc>);
 
+impl MultiGroup {
+    /// Create a new multigroup from an iterator of groups, deduplicating it via the `MULTIGROUP_CACHE`
+    ///
+    /// # Correctness
+    /// It is a logic error to create a multigroup from groups which are not sorted by address and deduplicated,
+    /// as then equal multigroups may not be pointer-equal.
+    pub fn new<I>(groups: I) -> MultiGroup
+    where
+        I: Iterator<Item = Group> + ExactSizeIterator,
+    {
+        let arc: GSArc = SliceWithHeader::new((), groups);
+        MultiGroup(Thin::from(MULTIGROUP_CACHE.cache(arc)))
+    }
+}
+
 impl Deref for MultiGroup {
     type Target = [Group];
     #[inline]
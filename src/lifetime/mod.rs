@@ -2,9 +2,11 @@
 The `rain` lifetime system
 */
 
+use crate::eval::{EvalCtx, Substitute};
 use crate::region::{data::RegionData, Region, RegionBorrow, Regional};
+use crate::typing::{Type, Typed};
 use crate::util::{AddrLookupMut, HasAddr};
-use crate::value::{NormalValue, ValAddr, ValId, ValRef, VALUE_CACHE};
+use crate::value::{Error, NormalValue, ValAddr, ValId, ValRef, VALUE_CACHE};
 use dashcache::{DashCache, GlobalCache};
 use elysees::UnionAlign;
 use elysees::{Arc, ArcBorrow};
@@ -28,6 +30,11 @@ mod params;
 pub use params::*;
 mod ctx;
 pub use ctx::*;
+mod usage;
+pub use usage::*;
+mod borrow;
+mod component;
+pub use component::*;
 
 /// A `rain` lifetime
 #[derive(Debug, Clone, Eq, Default)]
@@ -114,6 +121,63 @@ impl Lifetime {
             _ => self.clone(),
         }
     }
+    /**
+    Compute the lifetime of a value lying in (at least) a given base region with a given set of dependencies,
+    along with whether each dependency is owned.
+
+    The lenders and transient components of the dependencies' lifetimes are merged into a single lender and transient
+    component, respectively, and their lifetime parameters are merged pointwise. Returns an error if the regions of
+    the dependencies are incomparable, their lifetime parameters are mismatched, or an affine dependency is
+    both owned and borrowed from.
+    */
+    pub fn from_deps<'a, I>(base: &Region, deps: I) -> Result<Lifetime, Error>
+    where
+        I: IntoIterator<Item = (&'a ValId, bool)>,
+    {
+        let mut region = base.borrow_region();
+        let mut lenders = SmallVec::<[Group; 2]>::new();
+        let mut transients = SmallVec::<[Group; 2]>::new();
+        let mut lt_params = LifetimeParams::default();
+        let mut owned = SmallVec::<[ValAddr; 4]>::new();
+        for (dep, is_owned) in deps {
+            region = region.get_gcr(dep.region())?;
+            let lifetime = dep.lifetime();
+            if let Some(lender) = lifetime.lender() {
+                lenders.push(lender.clone())
+            }
+            if let Some(transient) = lifetime.transient() {
+                transients.push(transient.clone())
+            }
+            if let Some(params) = lifetime.params() {
+                lt_params = lt_params.merge(params)?;
+            }
+            if is_owned && dep.ty().is_affine() {
+                owned.push(dep.as_addr())
+            }
+        }
+        let lender = Group::merge(lenders.iter());
+        let transient = Group::merge(transients.iter());
+        for group in lender.iter().chain(transient.iter()) {
+            if group
+                .values()
+                .iter()
+                .any(|value| owned.contains(&value.as_addr()))
+            {
+                return Err(Error::BorrowUsed);
+            }
+        }
+        Ok(LifetimeData::new_unchecked(region.clone_region(), lender, transient, lt_params).into())
+    }
+    /// Extend this lifetime with borrows from a given lender and transient group, which must lie within its region
+    pub fn with_borrows(&self, lender: Option<&Group>, transient: Option<&Group>) -> Lifetime {
+        if lender.is_none() && transient.is_none() {
+            return self.clone();
+        }
+        let lender = Group::merge(self.lender().into_iter().chain(lender));
+        let transient = Group::merge(self.transient().into_iter().chain(transient));
+        let lt_params = self.params().cloned().unwrap_or_default();
+        LifetimeData::new_unchecked(self.clone_region(), lender, transient, lt_params).into()
+    }
     /// Get the lifetime parameters of this lifetime
     #[inline]
     pub fn params(&self) -> Option<&LifetimeParams> {
@@ -133,6 +197,60 @@ impl Live for Lifetime {
     }
 }
 
+/// Substitute the values of a group, borrowing from the lender of each substituted value which is itself borrowed
+fn substitute_group(
+    group: Option<&Group>,
+    ctx: &mut EvalCtx,
+    values: &mut Vec<ValId>,
+) -> Result<Option<Group>, Error> {
+    let group = if let Some(group) = group {
+        group
+    } else {
+        return Ok(None);
+    };
+    let mut lenders = SmallVec::<[Group; 2]>::new();
+    for value in group.values() {
+        let value: ValId = value.clone_val().substitute(ctx)?;
+        match value.lifetime().lender() {
+            Some(lender) => lenders.push(lender.clone()),
+            None => lenders.extend(Option::<Group>::from(value.clone())),
+        }
+        values.push(value);
+    }
+    Ok(Group::merge(lenders.iter()))
+}
+
+impl Substitute for Lifetime {
+    /**
+    Substitute the values borrowed from by this lifetime.
+
+    Each value borrowed from is replaced by its substitution, or by the lender of its substitution if the latter is
+    itself borrowed. The region of the result is the greatest common region of the substituted values, with a
+    trivial lifetime substituting to the static lifetime.
+    */
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<Lifetime, Error> {
+        let data = if let Some(data) = self.lt_data() {
+            data
+        } else {
+            return Ok(Lifetime::STATIC);
+        };
+        let mut values = Vec::new();
+        let lender = substitute_group(data.lender(), ctx, &mut values)?;
+        let transient = substitute_group(data.transient(), ctx, &mut values)?;
+        let mut lt_params = LifetimeParams::default();
+        for param in data.params().iter() {
+            let param = substitute_group(Some(param), ctx, &mut values)?
+                .expect("Substituting a nonempty group yields a nonempty group");
+            lt_params.0.push(param);
+        }
+        let mut region = RegionBorrow::NULL;
+        for value in values.iter() {
+            region = region.get_gcr(value.region())?;
+        }
+        Ok(LifetimeData::new_unchecked(region.clone_region(), lender, transient, lt_params).into())
+    }
+}
+
 impl<'a> LifetimeBorrow<'a> {
     /// The static `rain` lifetime
     pub const STATIC: LifetimeBorrow<'static> = LifetimeBorrow(None);
@@ -266,7 +384,7 @@ impl<T: Live> Regional for T {
 mod tests {
     use super::*;
     use crate::primitive::logical::Bool;
-    use crate::typing::Type;
+    use crate::value::Value;
 
     #[test]
     fn lifetime_layout() {
@@ -298,4 +416,49 @@ mod tests {
         let direct_region_lt = Lifetime::from(LifetimeData::from(region));
         assert_eq!(direct_region_lt, region_lt);
     }
+
+    #[test]
+    fn group_merging() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let gx = Option::<Group>::from(x.clone()).unwrap();
+        let gy = Option::<Group>::from(y.clone()).unwrap();
+        let xy = Group::merge(vec![&gx, &gy]).unwrap();
+        let yx = Group::merge(vec![&gy, &gx, &gy]).unwrap();
+        assert_eq!(xy, yx);
+        let mut values: Vec<_> = xy.values().iter().map(|value| value.as_addr()).collect();
+        values.sort();
+        let mut expected = vec![x.as_addr(), y.as_addr()];
+        expected.sort();
+        assert_eq!(values, expected);
+        assert_eq!(Group::merge(vec![&gx, &gx]), Some(gx.clone()));
+        assert_eq!(Group::merge(vec![&xy, &gx]), Some(xy.clone()));
+        assert_eq!(Group::merge(Vec::<&Group>::new()), None);
+        let single = LifetimeParams(smallvec::smallvec![gx.clone()]);
+        let double = LifetimeParams(smallvec::smallvec![gx, gy]);
+        assert_eq!(single.merge(&double), Err(Error::BorrowingMismatch));
+        assert_eq!(single.merge(&LifetimeParams::default()), Ok(single.clone()));
+    }
+
+    #[test]
+    fn dependency_lifetimes() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let t = true.into_val();
+        assert_eq!(
+            Lifetime::from_deps(&Region::NULL, vec![(&x, true), (&t, true)]),
+            Ok(Lifetime::from(region.clone()))
+        );
+        assert_eq!(
+            Lifetime::from_deps(&Region::NULL, vec![(&t, true)]),
+            Ok(Lifetime::STATIC)
+        );
+        let other = Region::unary(Bool.into_ty());
+        let z = other.param(0).unwrap().into_val();
+        assert_eq!(
+            Lifetime::from_deps(&Region::NULL, vec![(&x, true), (&z, true)]),
+            Err(Error::incomparable_regions(region, other))
+        );
+    }
 }
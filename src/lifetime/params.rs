@@ -3,7 +3,13 @@ use super::*;
 /// The size of a small vector of lifetime parameters
 pub const SMALL_LIFETIME_PARAMS: usize = 2;
 
-/// A vector of lifetime parameters
+/**
+A vector of lifetime parameters
+
+Lifetime parameters are carried through lifetime merges and substitution, but are not currently generated by
+function definitions: which parameters the result of a function borrows from is instead recorded by the
+[`PiLifetime`](PiLifetime) component of its pi type, which lambdas inherit via [`Pi::ty`](crate::function::pi::Pi::ty).
+*/
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub struct LifetimeParams(pub SmallVec<[Group; SMALL_LIFETIME_PARAMS]>);
 
@@ -14,3 +20,28 @@ impl Deref for LifetimeParams {
         &self.0[..]
     }
 }
+
+impl LifetimeParams {
+    /// Merge two sets of lifetime parameters, borrowing from the lenders of both for each parameter
+    ///
+    /// Returns an error if both are nonempty and have a different number of parameters.
+    pub fn merge(&self, other: &LifetimeParams) -> Result<LifetimeParams, Error> {
+        if self.is_empty() {
+            return Ok(other.clone());
+        }
+        if other.is_empty() {
+            return Ok(self.clone());
+        }
+        if self.len() != other.len() {
+            return Err(Error::BorrowingMismatch);
+        }
+        Ok(LifetimeParams(
+            self.iter()
+                .zip(other.iter())
+                .map(|(left, right)| {
+                    Group::merge(vec![left, right]).expect("Merging nonempty groups is nonempty")
+                })
+                .collect(),
+        ))
+    }
+}
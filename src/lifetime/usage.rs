@@ -0,0 +1,273 @@
+/*!
+Affinity and relevance checking for the values of a `rain` region
+*/
+use super::*;
+use crate::typing::{Type, Typed};
+use crate::value::{Error, Value};
+use hashbrown::HashSet;
+use indexmap::IndexMap;
+
+/// A violation of a usage constraint (affinity, relevance or borrowing), along with the value which violated it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UsageError {
+    /// The value whose usage constraint was violated
+    pub value: ValId,
+    /// The constraint which was violated
+    pub error: Error,
+}
+
+impl From<UsageError> for Error {
+    #[inline]
+    fn from(err: UsageError) -> Error {
+        err.error
+    }
+}
+
+/// The size of a small branch path
+const SMALL_BRANCH_PATH: usize = 4;
+
+/// A path of branches taken to reach a value, given as pairs of branching values and dependency indices,
+/// outermost branch first
+pub type BranchPath = SmallVec<[(ValAddr, usize); SMALL_BRANCH_PATH]>;
+
+/// Get the longest common prefix of two branch paths, i.e. the branch path under which both are evaluated
+pub fn join_paths(left: &BranchPath, right: &BranchPath) -> BranchPath {
+    left.iter()
+        .zip(right.iter())
+        .take_while(|(l, r)| l == r)
+        .map(|(l, _)| *l)
+        .collect()
+}
+
+/// Check whether two branch paths are mutually exclusive, i.e. at most one of them is ever taken
+pub fn exclusive_paths(left: &BranchPath, right: &BranchPath) -> bool {
+    left.iter()
+        .zip(right.iter())
+        .find(|(l, r)| l != r)
+        .map(|((l_node, l_ix), (r_node, r_ix))| l_node == r_node && l_ix != r_ix)
+        .unwrap_or(false)
+}
+
+impl LifetimeCtx {
+    /// Check whether a value lies in the region of this lifetime context
+    #[inline]
+    pub fn in_region(&self, value: &ValId) -> bool {
+        value.region() == *self.region()
+    }
+    /// Topologically sort the values in this context's region reachable from a set of results, users first
+    pub fn region_order<'a>(&self, results: &[&'a ValId]) -> Vec<&'a ValId> {
+        let mut visited: HashSet<ValAddr, FxBuildHasher> = HashSet::default();
+        let mut order = Vec::new();
+        let mut stack: Vec<(&'a ValId, usize)> = results
+            .iter()
+            .filter(|result| self.in_region(result))
+            .map(|result| (*result, 0))
+            .collect();
+        while let Some((top, ix)) = stack.pop() {
+            if ix == 0 && !visited.insert(top.as_addr()) {
+                continue;
+            }
+            let norm = top.as_norm();
+            if ix < norm.no_deps() {
+                stack.push((top, ix + 1));
+                let dep = norm.get_dep(ix);
+                if self.in_region(dep) && !visited.contains(&dep.as_addr()) {
+                    stack.push((dep, 0));
+                }
+            } else {
+                order.push(top);
+            }
+        }
+        order.reverse();
+        order
+    }
+    /**
+    Check that the values reachable from a set of results in this context's region satisfy their usage constraints
+
+    Affine values may be used at most once along any branch path, i.e. an affine value may be used once by each branch of a
+    branching value (such as a `Ternary`), but not both within a branch and outside of it. Relevant values, including the
+    relevant parameters of this context's region, must be used at least once. Results are considered to be used once each,
+    whereas borrowed dependencies are not considered uses.
+
+    On failure, the first value found violating its usage constraints is returned along with the constraint violated.
+    */
+    pub fn check_usage<'a>(&self, results: &[&'a ValId]) -> Result<(), UsageError> {
+        let order = self.region_order(results);
+        let mut paths: HashMap<ValAddr, BranchPath, FxBuildHasher> = HashMap::default();
+        let mut uses: IndexMap<ValAddr, (&'a ValId, SmallVec<[BranchPath; 2]>), FxBuildHasher> =
+            IndexMap::default();
+        for result in results {
+            uses.entry(result.as_addr())
+                .or_insert_with(|| (*result, SmallVec::new()))
+                .1
+                .push(BranchPath::new());
+            if self.in_region(result) {
+                paths.insert(result.as_addr(), BranchPath::new());
+            }
+        }
+        for &value in order.iter() {
+            let path = paths
+                .get(&value.as_addr())
+                .cloned()
+                .expect("Users are always visited before their dependencies");
+            let norm = value.as_norm();
+            for ix in 0..norm.no_deps() {
+                let dep = norm.get_dep(ix);
+                let mut dep_path = path.clone();
+                if norm.is_branching() {
+                    dep_path.push((value.as_addr(), ix));
+                }
+                if self.in_region(dep) {
+                    let joined = match paths.get(&dep.as_addr()) {
+                        Some(old) => join_paths(old, &dep_path),
+                        None => dep_path.clone(),
+                    };
+                    paths.insert(dep.as_addr(), joined);
+                }
+                if norm.dep_owned(ix) {
+                    uses.entry(dep.as_addr())
+                        .or_insert_with(|| (dep, SmallVec::new()))
+                        .1
+                        .push(dep_path);
+                }
+            }
+        }
+        for (value, value_uses) in uses.values() {
+            if !value.ty().is_affine() {
+                continue;
+            }
+            for (i, left) in value_uses.iter().enumerate() {
+                for right in &value_uses[i + 1..] {
+                    if exclusive_paths(left, right) {
+                        continue;
+                    }
+                    let error = if left == right {
+                        Error::AffineUsed
+                    } else {
+                        Error::AffineBranched
+                    };
+                    return Err(UsageError {
+                        value: (*value).clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        for value in order.iter() {
+            if value.ty().is_relevant() && !uses.contains_key(&value.as_addr()) {
+                return Err(UsageError {
+                    value: (*value).clone(),
+                    error: Error::RelevantUnused,
+                });
+            }
+        }
+        for param in self.region().params() {
+            let param = param.into_val();
+            if param.ty().is_relevant() && !uses.contains_key(&param.as_addr()) {
+                return Err(UsageError {
+                    value: param,
+                    error: Error::RelevantUnused,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ternary::Ternary;
+    use crate::valarr;
+    use crate::value::{
+        arr::TyArr,
+        tuple::{Product, Tuple},
+    };
+
+    fn anchor_region() -> Region {
+        Region::unary(Product::anchor_ty().into_ty())
+    }
+
+    #[test]
+    fn single_affine_use_is_valid() {
+        let region = anchor_region();
+        let x = region.param(0).unwrap().into_val();
+        let tuple = Tuple::try_new(valarr![x.clone(), true.into_val()])
+            .unwrap()
+            .into_val();
+        let ctx = LifetimeCtx::new(region);
+        assert_eq!(ctx.check_usage(&[&tuple]), Ok(()));
+        assert_eq!(ctx.check_usage(&[&x]), Ok(()));
+    }
+
+    #[test]
+    fn double_affine_use_is_reported() {
+        let region = anchor_region();
+        let x = region.param(0).unwrap().into_val();
+        let tuple = Tuple::try_new(valarr![x.clone(), x.clone()])
+            .unwrap()
+            .into_val();
+        let ctx = LifetimeCtx::new(region);
+        assert_eq!(
+            ctx.check_usage(&[&tuple]),
+            Err(UsageError {
+                value: x.clone(),
+                error: Error::AffineUsed
+            })
+        );
+        assert_eq!(
+            ctx.check_usage(&[&x, &x]),
+            Err(UsageError {
+                value: x,
+                error: Error::AffineUsed
+            })
+        );
+    }
+
+    #[test]
+    fn affine_use_per_branch_is_valid() {
+        let region = anchor_region();
+        let x = region.param(0).unwrap().into_val();
+        let high = Tuple::try_new(valarr![x.clone()]).unwrap().into_val();
+        let low = Tuple::try_new(valarr![x.clone(), true.into_val()])
+            .unwrap()
+            .into_val();
+        let ternary = Ternary::conditional(high, low).unwrap().into_val();
+        let ctx = LifetimeCtx::new(region);
+        assert_eq!(ctx.check_usage(&[&ternary]), Ok(()));
+        let both = Tuple::try_new(valarr![ternary, x.clone()])
+            .unwrap()
+            .into_val();
+        assert_eq!(
+            ctx.check_usage(&[&both]),
+            Err(UsageError {
+                value: x,
+                error: Error::AffineBranched
+            })
+        );
+    }
+
+    #[test]
+    fn unused_relevant_parameter_is_reported() {
+        let flare = Product::try_new_forced(TyArr::EMPTY, false, true)
+            .unwrap()
+            .into_ty();
+        assert!(flare.is_relevant());
+        assert!(!flare.is_affine());
+        let region = Region::unary(flare);
+        let x = region.param(0).unwrap().into_val();
+        let t = true.into_val();
+        let ctx = LifetimeCtx::new(region);
+        assert_eq!(
+            ctx.check_usage(&[&t]),
+            Err(UsageError {
+                value: x.clone(),
+                error: Error::RelevantUnused
+            })
+        );
+        let tuple = Tuple::try_new(valarr![x.clone(), x.clone()])
+            .unwrap()
+            .into_val();
+        assert_eq!(ctx.check_usage(&[&tuple]), Ok(()));
+    }
+}
@@ -0,0 +1,741 @@
+/*!
+Lowering of `rain-ast` syntax trees into `rain` IR values.
+
+Names are resolved through a scoped symbol table: every scope, function and phi node opens a new level of the table,
+which is closed again once its body has been lowered, so that definitions never leak out of the scope they were made
+in. Errors are reported along with the AST node at which they occurred and, if the builder was given the source text
+the AST was parsed from, its location in that text.
+*/
+use crate::control::phi::Phi;
+use crate::function::{lambda::Lambda, pi::Pi};
+use crate::primitive::{finite::Finite, logical::Bool};
+use crate::region::Region;
+use crate::typing::Typed;
+use crate::value::{
+    arr::{TyArr, ValArr},
+    expr::Sexpr,
+    tuple::{Product, Tuple},
+    Error, TypeId, ValId, Value, ValueEnum,
+};
+use fxhash::FxBuildHasher;
+use hayami::{SymbolMap, SymbolTable};
+use rain_ast::ast::{Expr, Ident, Let, Parametrized, Pattern, Scope, Statement};
+use std::hash::BuildHasher;
+use std::ops::Deref;
+
+/// A location in the source text an AST was parsed from, given as a range of byte offsets
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Location {
+    /// The offset of the start of this location
+    pub start: usize,
+    /// The offset of the end of this location
+    pub end: usize,
+}
+
+/// An error lowering a `rain` AST, along with the AST node at which it occurred
+#[derive(Debug, Clone, PartialEq)]
+pub struct LowerError<'a> {
+    /// The expression being lowered when this error occurred
+    pub at: &'a Expr<'a>,
+    /// The location of this error in the source text, if known
+    ///
+    /// This is the span of the source text covered by the innermost expression enclosing `at` which has a known
+    /// location, which is `at` itself unless it consists only of literals.
+    pub location: Option<Location>,
+    /// The kind of this error
+    pub kind: LowerErrorKind<'a>,
+}
+
+impl<'a> LowerError<'a> {
+    /// Create a new lowering error at a given expression, with an unknown location
+    #[inline]
+    pub fn new(at: &'a Expr<'a>, kind: LowerErrorKind<'a>) -> LowerError<'a> {
+        LowerError {
+            at,
+            location: None,
+            kind,
+        }
+    }
+}
+
+/// The kind of an error lowering a `rain` AST
+#[derive(Debug, Clone, PartialEq)]
+pub enum LowerErrorKind<'a> {
+    /// A reference to an identifier which is not in scope
+    UndefinedIdent(&'a str),
+    /// A tuple pattern with a different number of elements than the value it destructures
+    TupleSizeMismatch {
+        /// The number of elements of the value being destructured
+        tuple: usize,
+        /// The number of elements of the pattern
+        pattern: usize,
+    },
+    /// A tuple pattern destructuring a value which is not a tuple
+    NotATuple,
+    /// A phi node member without a type annotation, which is needed to set up recursion
+    MissingType,
+    /// An index literal without a type, which cannot be inferred
+    UntypedIndex,
+    /// An index greater than or equal to the size of its type
+    IndexOutOfRange {
+        /// The index given
+        ix: u128,
+        /// The size of the type of the index
+        size: u128,
+    },
+    /// A syntactic construct which cannot be lowered into IR
+    Unsupported,
+    /// The AST describes an invalid value
+    Value(Error),
+}
+
+/// A builder lowering `rain` ASTs into IR values
+#[derive(Debug, Clone)]
+pub struct Builder<'s, S: BuildHasher = FxBuildHasher> {
+    /// The values bound to each name in scope
+    symbols: SymbolTable<String, ValId, S>,
+    /// The regions currently being built, innermost last
+    regions: Vec<Region>,
+    /// The source text lowered ASTs borrow their identifiers from, if known
+    source: Option<&'s str>,
+}
+
+impl<'s> Default for Builder<'s> {
+    #[inline]
+    fn default() -> Builder<'s> {
+        Builder::new()
+    }
+}
+
+impl<'s> Builder<'s> {
+    /// Create a new builder with no names in scope
+    pub fn new() -> Builder<'s> {
+        Builder {
+            symbols: SymbolTable::default(),
+            regions: Vec::new(),
+            source: None,
+        }
+    }
+    /**
+    Create a new builder with no names in scope, lowering ASTs parsed from a given source text.
+
+    Since ASTs borrow their identifiers from the text they were parsed from, errors in ASTs parsed from `source` are
+    reported with their location in it.
+    */
+    pub fn with_source(source: &'s str) -> Builder<'s> {
+        Builder {
+            source: Some(source),
+            ..Builder::new()
+        }
+    }
+}
+
+impl<'s, S: BuildHasher> Builder<'s, S> {
+    /// Bind a name to a value in the current scope, returning the value previously bound to it in this scope, if any
+    #[inline]
+    pub fn define(&mut self, name: &str, value: ValId) -> Option<ValId> {
+        self.symbols.insert(name.to_owned(), value)
+    }
+    /// Look up the value bound to a name, if any
+    #[inline]
+    pub fn lookup(&self, name: &str) -> Option<&ValId> {
+        self.symbols.get(name)
+    }
+    /// Get the region values are currently being lowered in
+    #[inline]
+    fn region(&self) -> Region {
+        self.regions.last().cloned().unwrap_or(Region::NULL)
+    }
+    /// Lower an expression into a value
+    pub fn lower<'a>(&mut self, expr: &'a Expr<'a>) -> Result<ValId, LowerError<'a>> {
+        self.lower_node(expr).map_err(|mut err| {
+            if err.location.is_none() {
+                err.location = self.location(expr)
+            }
+            err
+        })
+    }
+    /// Lower an expression into a type
+    pub fn lower_ty<'a>(&mut self, expr: &'a Expr<'a>) -> Result<TypeId, LowerError<'a>> {
+        self.lower(expr)?.try_into_ty().map_err(|_| {
+            let err = LowerError::new(expr, LowerErrorKind::Value(Error::NotATypeError));
+            self.locate(err)
+        })
+    }
+    /// Lower an expression into a value, without locating errors at it
+    fn lower_node<'a>(&mut self, expr: &'a Expr<'a>) -> Result<ValId, LowerError<'a>> {
+        let err = |kind| LowerError::new(expr, kind);
+        let val_err = |err: Error| LowerError::new(expr, LowerErrorKind::Value(err));
+        let value = match expr {
+            Expr::Ident(ident) => self.ident(expr, ident)?,
+            Expr::Sexpr(sexpr) => {
+                let args = self.lower_all(&sexpr.0)?;
+                Sexpr::try_new(args).map_err(val_err)?.into_val()
+            }
+            Expr::Tuple(tuple) => {
+                let elems: ValArr = self.lower_all(&tuple.0)?.into_iter().collect();
+                Tuple::try_new(elems).map_err(val_err)?.into_val()
+            }
+            Expr::Product(product) => {
+                let elems = product
+                    .0
+                    .iter()
+                    .map(|elem| self.lower_ty(elem))
+                    .collect::<Result<TyArr, _>>()?;
+                Product::try_new(elems).map_err(val_err)?.into_val()
+            }
+            Expr::Bool(b) => (*b).into_val(),
+            Expr::BoolTy(_) => Bool.into_val(),
+            Expr::Finite(finite) => Finite(finite.0).into_val(),
+            Expr::Index(index) => {
+                let size = index.ty.ok_or_else(|| err(LowerErrorKind::UntypedIndex))?;
+                Finite(size)
+                    .ix(index.ix)
+                    .map_err(|_| err(LowerErrorKind::IndexOutOfRange { ix: index.ix, size }))?
+                    .into_val()
+            }
+            Expr::TypeOf(type_of) => self.lower(&type_of.0)?.clone_ty().into_val(),
+            Expr::Member(member) => {
+                let mut value = self.lower(&member.base)?;
+                for ix in member.path.iter() {
+                    value = Self::project(expr, &value, *ix)?;
+                }
+                value
+            }
+            Expr::Scope(scope) => {
+                self.symbols.push();
+                let result = self.scope(scope);
+                self.symbols.pop();
+                result?
+            }
+            Expr::Lambda(lambda) => {
+                let (result, region) = self.parametrized(expr, lambda)?;
+                Lambda::try_new(result, region).map_err(val_err)?.into_val()
+            }
+            Expr::Pi(pi) => {
+                let (result, region) = self.parametrized(expr, pi)?;
+                let result = result.try_into_ty().map_err(|_| {
+                    let err =
+                        LowerError::new(&pi.result, LowerErrorKind::Value(Error::NotATypeError));
+                    self.locate(err)
+                })?;
+                Pi::try_new(result, region).map_err(val_err)?.into_val()
+            }
+            Expr::Phi(phi) => self.phi(expr, &phi.0)?,
+            _ => return Err(err(LowerErrorKind::Unsupported)),
+        };
+        Ok(value)
+    }
+    /// Lower a list of expressions
+    fn lower_all<'a>(&mut self, exprs: &'a [Expr<'a>]) -> Result<Vec<ValId>, LowerError<'a>> {
+        exprs.iter().map(|expr| self.lower(expr)).collect()
+    }
+    /// Resolve an identifier
+    fn ident<'a>(&self, expr: &'a Expr<'a>, ident: &'a Ident<'a>) -> Result<ValId, LowerError<'a>> {
+        let name: &'a str = ident.deref();
+        self.symbols
+            .get(name)
+            .cloned()
+            .ok_or_else(|| LowerError::new(expr, LowerErrorKind::UndefinedIdent(name)))
+    }
+    /// Lower the statements of a scope, followed by its result. The caller is responsible for opening a new scope.
+    fn scope<'a>(&mut self, scope: &'a Scope<'a>) -> Result<ValId, LowerError<'a>> {
+        for statement in scope.definitions.iter() {
+            match statement {
+                Statement::Let(binding) => self.binding(binding)?,
+            }
+        }
+        match &scope.value {
+            Some(value) => self.lower(value),
+            None => Ok(().into()),
+        }
+    }
+    /// Lower a `let` statement, binding the names in its pattern
+    fn binding<'a>(&mut self, binding: &'a Let<'a>) -> Result<(), LowerError<'a>> {
+        let value = self.lower(&binding.value)?;
+        self.pattern(&binding.value, &binding.pattern, value)
+            .map_err(|err| self.locate(err))
+    }
+    /// Bind the names in a pattern to the components of a value, lowered from a given expression
+    fn pattern<'a>(
+        &mut self,
+        expr: &'a Expr<'a>,
+        pattern: &'a Pattern<'a>,
+        value: ValId,
+    ) -> Result<(), LowerError<'a>> {
+        match pattern {
+            Pattern::Simple(simple) => {
+                if let Some(ty) = &simple.ty {
+                    let ty = self.lower_ty(ty)?;
+                    if value.ty() != ty {
+                        return Err(LowerError::new(
+                            expr,
+                            LowerErrorKind::Value(Error::TypeMismatch),
+                        ));
+                    }
+                }
+                if let Some(var) = &simple.var {
+                    self.symbols.insert(var.deref().to_owned(), value);
+                }
+                Ok(())
+            }
+            Pattern::Detuple(detuple) => {
+                let len = match value.ty().as_enum() {
+                    ValueEnum::Product(product) => product.len(),
+                    _ => return Err(LowerError::new(expr, LowerErrorKind::NotATuple)),
+                };
+                if len != detuple.0.len() {
+                    return Err(LowerError::new(
+                        expr,
+                        LowerErrorKind::TupleSizeMismatch {
+                            tuple: len,
+                            pattern: detuple.0.len(),
+                        },
+                    ));
+                }
+                for (ix, pattern) in detuple.0.iter().enumerate() {
+                    let elem = Self::project(expr, &value, ix as u128)?;
+                    self.pattern(expr, pattern, elem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+    /// Project the `ix`th element out of a value of product type, lowered from a given expression
+    fn project<'a>(expr: &'a Expr<'a>, value: &ValId, ix: u128) -> Result<ValId, LowerError<'a>> {
+        let size = match value.ty().as_enum() {
+            ValueEnum::Product(product) => product.len() as u128,
+            _ => return Err(LowerError::new(expr, LowerErrorKind::NotATuple)),
+        };
+        let ix = Finite(size)
+            .ix(ix)
+            .map_err(|_| LowerError::new(expr, LowerErrorKind::IndexOutOfRange { ix, size }))?;
+        Sexpr::try_new(vec![value.clone(), ix.into_val()])
+            .map(Sexpr::into_val)
+            .map_err(|err| LowerError::new(expr, LowerErrorKind::Value(err)))
+    }
+    /**
+    Lower the body of a lambda function or pi type in a new region, binding its parameters.
+
+    Parameter types are lowered in the enclosing scope, so that a parameter is never in scope in its own type.
+    */
+    fn parametrized<'a>(
+        &mut self,
+        expr: &'a Expr<'a>,
+        parametrized: &'a Parametrized<'a>,
+    ) -> Result<(ValId, Region), LowerError<'a>> {
+        let tys = parametrized
+            .args
+            .iter()
+            .map(|(_, ty)| self.lower_ty(ty))
+            .collect::<Result<TyArr, _>>()?;
+        let region = Region::with(tys, self.region())
+            .map_err(|err| LowerError::new(expr, LowerErrorKind::Value(err)))?;
+        self.symbols.push();
+        for ((name, _), param) in parametrized.args.iter().zip(region.params()) {
+            if let Some(name) = name {
+                self.symbols
+                    .insert(name.deref().to_owned(), param.into_val());
+            }
+        }
+        self.regions.push(region.clone());
+        let result = self.lower(&parametrized.result);
+        self.regions.pop();
+        self.symbols.pop();
+        Ok((result?, region))
+    }
+    /**
+    Lower a phi node from its member definitions.
+
+    Every member must be a simple, typed binding: the members are bound to the parameters of the defining region while
+    their definitions are lowered, and to projections of the phi node afterwards, in the enclosing scope.
+    */
+    fn phi<'a>(
+        &mut self,
+        expr: &'a Expr<'a>,
+        members: &'a [Let<'a>],
+    ) -> Result<ValId, LowerError<'a>> {
+        let mut names = Vec::with_capacity(members.len());
+        let mut tys = Vec::with_capacity(members.len());
+        for member in members {
+            match &member.pattern {
+                Pattern::Simple(simple) => {
+                    let ty = simple.ty.as_ref().ok_or_else(|| {
+                        self.locate(LowerError::new(&member.value, LowerErrorKind::MissingType))
+                    })?;
+                    tys.push(self.lower_ty(ty)?);
+                    names.push(simple.var.as_ref());
+                }
+                Pattern::Detuple(_) => {
+                    let err = LowerError::new(&member.value, LowerErrorKind::Unsupported);
+                    return Err(self.locate(err));
+                }
+            }
+        }
+        let region = Region::with(tys.into_iter().collect(), self.region())
+            .map_err(|err| LowerError::new(expr, LowerErrorKind::Value(err)))?;
+        self.symbols.push();
+        for (name, param) in names.iter().zip(region.params()) {
+            if let Some(name) = name {
+                self.symbols
+                    .insert(name.deref().to_owned(), param.into_val());
+            }
+        }
+        self.regions.push(region.clone());
+        let values = members
+            .iter()
+            .map(|member| self.lower(&member.value))
+            .collect::<Result<ValArr, _>>();
+        self.regions.pop();
+        self.symbols.pop();
+        let phi = Phi::try_new(values?, region)
+            .map_err(|err| LowerError::new(expr, LowerErrorKind::Value(err)))?;
+        let projections = phi
+            .projections()
+            .map_err(|err| LowerError::new(expr, LowerErrorKind::Value(err)))?;
+        for (name, projection) in names.into_iter().zip(projections) {
+            if let Some(name) = name {
+                self.symbols.insert(name.deref().to_owned(), projection);
+            }
+        }
+        Ok(phi.into_val())
+    }
+    /// Locate an error at the expression it occurred at, if it does not already have a location
+    fn locate<'a>(&self, mut err: LowerError<'a>) -> LowerError<'a> {
+        if err.location.is_none() {
+            err.location = self.location(err.at)
+        }
+        err
+    }
+    /**
+    Get the location of an expression in the source text, if known.
+
+    Only identifiers borrow from the source text, so this is the span from the first to the last identifier occurring
+    in the expression, or `None` if the expression consists only of literals.
+    */
+    pub fn location(&self, expr: &Expr) -> Option<Location> {
+        let source = self.source?;
+        let base = source.as_ptr() as usize;
+        let mut location: Option<Location> = None;
+        visit_idents(expr, &mut |ident| {
+            let start = match (ident.as_ptr() as usize).checked_sub(base) {
+                Some(start) if start + ident.len() <= source.len() => start,
+                // Identifiers which do not borrow from the source text have no location
+                _ => return,
+            };
+            let end = start + ident.len();
+            location = Some(match location {
+                Some(location) => Location {
+                    start: location.start.min(start),
+                    end: location.end.max(end),
+                },
+                None => Location { start, end },
+            });
+        });
+        location
+    }
+}
+
+/// Call a function on every identifier occurring in an expression, in order
+fn visit_idents<'a>(expr: &'a Expr<'a>, visit: &mut impl FnMut(&'a str)) {
+    match expr {
+        Expr::Ident(ident) => visit(ident.deref()),
+        Expr::Sexpr(sexpr) => sexpr.0.iter().for_each(|expr| visit_idents(expr, visit)),
+        Expr::Tuple(tuple) => tuple.0.iter().for_each(|expr| visit_idents(expr, visit)),
+        Expr::Product(product) => product.0.iter().for_each(|expr| visit_idents(expr, visit)),
+        Expr::TypeOf(type_of) => visit_idents(&type_of.0, visit),
+        Expr::Member(member) => visit_idents(&member.base, visit),
+        Expr::Scope(scope) => {
+            for statement in scope.definitions.iter() {
+                match statement {
+                    Statement::Let(binding) => visit_let_idents(binding, visit),
+                }
+            }
+            if let Some(value) = &scope.value {
+                visit_idents(value, visit)
+            }
+        }
+        Expr::Lambda(parametrized) | Expr::Pi(parametrized) => {
+            for (name, ty) in parametrized.args.iter() {
+                if let Some(name) = name {
+                    visit(name.deref())
+                }
+                visit_idents(ty, visit)
+            }
+            visit_idents(&parametrized.result, visit)
+        }
+        Expr::Phi(phi) => phi
+            .0
+            .iter()
+            .for_each(|binding| visit_let_idents(binding, visit)),
+        _ => {}
+    }
+}
+
+/// Call a function on every identifier occurring in a `let` statement, in order
+fn visit_let_idents<'a>(binding: &'a Let<'a>, visit: &mut impl FnMut(&'a str)) {
+    visit_pattern_idents(&binding.pattern, visit);
+    visit_idents(&binding.value, visit)
+}
+
+/// Call a function on every identifier occurring in a pattern, in order
+fn visit_pattern_idents<'a>(pattern: &'a Pattern<'a>, visit: &mut impl FnMut(&'a str)) {
+    match pattern {
+        Pattern::Simple(simple) => {
+            if let Some(var) = &simple.var {
+                visit(var.deref())
+            }
+            if let Some(ty) = &simple.ty {
+                visit_idents(ty, visit)
+            }
+        }
+        Pattern::Detuple(detuple) => detuple
+            .0
+            .iter()
+            .for_each(|pattern| visit_pattern_idents(pattern, visit)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::logical::Not;
+    use crate::tyarr;
+    use rain_ast::ast::{
+        Detuple, Index, Phi as PhiExpr, Product as ProductExpr, Sexpr as SexprExpr, Simple,
+        Tuple as TupleExpr,
+    };
+    use std::convert::TryFrom;
+
+    fn ident(name: &str) -> Expr {
+        Expr::Ident(Ident::try_from(name).unwrap())
+    }
+
+    fn bool_ty<'a>() -> Expr<'a> {
+        Expr::BoolTy(Default::default())
+    }
+
+    fn simple<'a>(name: &'a str, ty: Option<Expr<'a>>) -> Pattern<'a> {
+        Pattern::Simple(Simple {
+            var: Some(Ident::try_from(name).unwrap()),
+            ty,
+        })
+    }
+
+    fn scope<'a>(lets: Vec<(Pattern<'a>, Expr<'a>)>, value: Expr<'a>) -> Expr<'a> {
+        Expr::Scope(Scope {
+            definitions: lets
+                .into_iter()
+                .map(|(pattern, value)| Statement::Let(Let { pattern, value }))
+                .collect(),
+            value: Some(Box::new(value)),
+        })
+    }
+
+    fn unary<'a>(name: &'a str, ty: Expr<'a>, result: Expr<'a>) -> Parametrized<'a> {
+        Parametrized {
+            args: vec![(Some(Ident::try_from(name).unwrap()), ty)],
+            result: Box::new(result),
+        }
+    }
+
+    #[test]
+    fn identity_lowers() {
+        let id = Expr::Lambda(unary("x", bool_ty(), ident("x")));
+        let mut builder = Builder::new();
+        let lowered = builder.lower(&id).unwrap();
+        let region = Region::with(tyarr![Bool.into_ty()], Region::NULL).unwrap();
+        let x = region.param(0).unwrap().into_val();
+        let expected = Lambda::try_new(x, region).unwrap().into_val();
+        assert_eq!(lowered, expected);
+    }
+
+    #[test]
+    fn pi_lowers() {
+        let pi = Expr::Pi(unary("x", bool_ty(), bool_ty()));
+        let lowered = Builder::new().lower(&pi).unwrap();
+        let region = Region::with(tyarr![Bool.into_ty()], Region::NULL).unwrap();
+        let expected = Pi::try_new(Bool.into_ty(), region).unwrap().into_val();
+        assert_eq!(lowered, expected);
+        // The result of a pi type must be a type
+        let not_pi = Expr::Pi(unary("x", bool_ty(), Expr::Bool(true)));
+        let err = Builder::new().lower(&not_pi).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::Value(Error::NotATypeError));
+    }
+
+    #[test]
+    fn scoped_names_resolve() {
+        let scoped = scope(
+            vec![(simple("y", Some(bool_ty())), Expr::Bool(true))],
+            Expr::Tuple(TupleExpr(vec![
+                ident("y"),
+                Expr::Sexpr(SexprExpr(vec![ident("not"), ident("y")])),
+            ])),
+        );
+        let mut builder = Builder::new();
+        builder.define("not", Not.into_val());
+        let lowered = builder.lower(&scoped).unwrap();
+        let expected = Tuple::try_new(
+            vec![true.into_val(), false.into_val()]
+                .into_iter()
+                .collect(),
+        )
+        .unwrap()
+        .into_val();
+        assert_eq!(lowered, expected);
+        // Names defined in a scope do not leak out of it
+        assert_eq!(builder.lookup("y"), None);
+        let undefined = ident("y");
+        assert_eq!(
+            builder.lower(&undefined),
+            Err(LowerError::new(
+                &undefined,
+                LowerErrorKind::UndefinedIdent("y")
+            ))
+        );
+    }
+
+    #[test]
+    fn detuple_patterns_bind_elements() {
+        let pair = Expr::Tuple(TupleExpr(vec![Expr::Bool(true), Expr::Bool(false)]));
+        let swapped = scope(
+            vec![(
+                Pattern::Detuple(Detuple(vec![simple("a", None), simple("b", None)])),
+                pair.clone(),
+            )],
+            Expr::Tuple(TupleExpr(vec![ident("b"), ident("a")])),
+        );
+        let lowered = Builder::new().lower(&swapped).unwrap();
+        let expected = Tuple::try_new(
+            vec![false.into_val(), true.into_val()]
+                .into_iter()
+                .collect(),
+        )
+        .unwrap()
+        .into_val();
+        assert_eq!(lowered, expected);
+
+        // Patterns must have as many elements as the tuple they destructure
+        let short = scope(
+            vec![(
+                Pattern::Detuple(Detuple(vec![simple("a", None)])),
+                pair.clone(),
+            )],
+            ident("a"),
+        );
+        let err = Builder::new().lower(&short).unwrap_err();
+        assert_eq!(
+            err.kind,
+            LowerErrorKind::TupleSizeMismatch {
+                tuple: 2,
+                pattern: 1
+            }
+        );
+        // Only tuples may be destructured
+        let not_tuple = scope(
+            vec![(
+                Pattern::Detuple(Detuple(vec![simple("a", None)])),
+                Expr::Bool(true),
+            )],
+            ident("a"),
+        );
+        let err = Builder::new().lower(&not_tuple).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::NotATuple);
+        // Type annotations are checked
+        let mistyped = scope(vec![(simple("a", Some(bool_ty())), pair)], ident("a"));
+        let err = Builder::new().lower(&mistyped).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::Value(Error::TypeMismatch));
+    }
+
+    #[test]
+    fn phi_binds_projections() {
+        // A function which calls itself forever
+        let f_ty = Expr::Pi(unary("x", bool_ty(), bool_ty()));
+        let body = Expr::Lambda(unary(
+            "x",
+            bool_ty(),
+            Expr::Sexpr(SexprExpr(vec![ident("f"), ident("x")])),
+        ));
+        let phi = Expr::Phi(PhiExpr(vec![Let {
+            pattern: simple("f", Some(f_ty.clone())),
+            value: body.clone(),
+        }]));
+        let mut builder = Builder::new();
+        let lowered = builder.lower(&phi).unwrap();
+        let lowered = match lowered.as_enum() {
+            ValueEnum::Phi(phi) => phi.clone(),
+            other => panic!("Expected a phi node, got {:?}", other),
+        };
+        assert_eq!(lowered.values().len(), 1);
+        assert_eq!(builder.lookup("f"), Some(&lowered.project(0).unwrap()));
+
+        // Members of a phi node must be typed
+        let untyped = Expr::Phi(PhiExpr(vec![Let {
+            pattern: simple("f", None),
+            value: body.clone(),
+        }]));
+        let err = Builder::new().lower(&untyped).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::MissingType);
+        assert_eq!(err.at, &body);
+        // and may not be destructured
+        let detupled = Expr::Phi(PhiExpr(vec![Let {
+            pattern: Pattern::Detuple(Detuple(vec![simple("f", Some(f_ty))])),
+            value: body.clone(),
+        }]));
+        let err = Builder::new().lower(&detupled).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn indices_are_checked() {
+        let ix = Expr::Index(Index { ix: 1, ty: Some(3) });
+        assert_eq!(
+            Builder::new().lower(&ix),
+            Ok(Finite(3).ix(1).unwrap().into_val())
+        );
+        let untyped = Expr::Index(Index { ix: 1, ty: None });
+        let err = Builder::new().lower(&untyped).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::UntypedIndex);
+        let out_of_range = Expr::Index(Index { ix: 3, ty: Some(3) });
+        let err = Builder::new().lower(&out_of_range).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::IndexOutOfRange { ix: 3, size: 3 });
+        // Products may only contain types
+        let product = Expr::Product(ProductExpr(vec![bool_ty(), Expr::Bool(true)]));
+        let err = Builder::new().lower(&product).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::Value(Error::NotATypeError));
+        assert_eq!(err.at, &Expr::Bool(true));
+    }
+
+    #[test]
+    fn errors_are_located() {
+        let source = "(f x) y";
+        let f = Ident::try_from(&source[1..2]).unwrap();
+        let x = Ident::try_from(&source[3..4]).unwrap();
+        let y = Ident::try_from(&source[6..7]).unwrap();
+        let sexpr = Expr::Sexpr(SexprExpr(vec![Expr::Ident(f), Expr::Ident(x)]));
+        let mut builder = Builder::with_source(source);
+        builder.define("f", Not.into_val());
+        let err = builder.lower(&sexpr).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::UndefinedIdent("x"));
+        assert_eq!(err.location, Some(Location { start: 3, end: 4 }));
+        assert_eq!(
+            builder.location(&sexpr),
+            Some(Location { start: 1, end: 4 })
+        );
+        // Literals are located at their innermost enclosing expression with a location
+        let tuple = Expr::Tuple(TupleExpr(vec![
+            Expr::Ident(y),
+            Expr::Index(Index { ix: 0, ty: None }),
+        ]));
+        builder.define("y", true.into_val());
+        let err = builder.lower(&tuple).unwrap_err();
+        assert_eq!(err.kind, LowerErrorKind::UntypedIndex);
+        assert_eq!(err.location, Some(Location { start: 6, end: 7 }));
+        // Identifiers borrowed from another text are not located in this one
+        let other = String::from("x");
+        let elsewhere = Expr::Ident(Ident::try_from(other.as_str()).unwrap());
+        assert_eq!(builder.location(&elsewhere), None);
+        // Without a source text, nothing is located
+        assert_eq!(Builder::new().location(&sexpr), None);
+    }
+}
@@ -1,18 +1,24 @@
 /*!
 A prettyprinter for `rain` programs
 */
+use crate::debug::DebugInfo;
 use crate::tokens::*;
 use crate::typing::Typed;
-use crate::value::{NormalValue, ValAddr, ValRef, Value};
+use crate::util::{AddrLookup, HasAddr};
+use crate::value::{NormalValue, ValAddr, ValId, ValRef, Value};
 use crate::{debug_from_display, quick_display};
 use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
 use hayami::{SymbolMap, SymbolTable};
 use ref_cast::RefCast;
 use smallvec::SmallVec;
+use std::cell::RefCell;
 use std::default::Default;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::BuildHasher;
 
+pub mod parser;
+
 /// The virtual register name format for `rain` values
 #[derive(Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct VirtualRegister(pub usize);
@@ -26,24 +32,92 @@ impl From<usize> for VirtualRegister {
 debug_from_display!(VirtualRegister);
 quick_display!(VirtualRegister, s, fmt => write!(fmt, "%{}", s.0));
 
+/// The layout of the values printed by a prettyprinter
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PrintStyle {
+    /// Bind subterms which are not inlined to registers using `let` statements
+    Let,
+    /// Print every subterm in place as a nested S-expression. Shared subterms are printed once per use.
+    Nested,
+}
+
+/// The configuration of a prettyprinter
+#[derive(Debug, Clone)]
+pub struct PrettyPrintConfig {
+    /// The maximum number of tabs to indent by
+    pub max_tabs: u16,
+    /// The maximum width of a line, beyond which lines are wrapped at spaces, if any
+    pub max_width: Option<usize>,
+    /// The maximum size, in values, of a subterm printed in place rather than bound to a register
+    pub inline_size: usize,
+    /// The layout of printed values
+    pub style: PrintStyle,
+    /// User-supplied names for values and parameters
+    names: HashMap<ValId, String, FxBuildHasher>,
+}
+
+impl PrettyPrintConfig {
+    /// Create the default prettyprinter configuration
+    #[inline]
+    pub fn new() -> PrettyPrintConfig {
+        PrettyPrintConfig {
+            max_tabs: DEFAULT_MAX_TABS,
+            max_width: None,
+            inline_size: DEFAULT_INLINE_SIZE,
+            style: PrintStyle::Let,
+            names: HashMap::default(),
+        }
+    }
+    /// Give a value, such as a parameter, a name to print instead of a register. Return its previous name, if any.
+    pub fn name(&mut self, value: ValId, name: &str) -> Option<String> {
+        self.names.insert(value, name.to_owned())
+    }
+    /// Get the name given to a value, if any
+    pub fn get_name(&self, value: &ValId) -> Option<&str> {
+        self.names.get(value).map(String::as_str)
+    }
+    /// Name every value given a source-level name by a debug information table. Return the number of values named.
+    pub fn use_debug_info(&mut self, info: &DebugInfo) -> usize {
+        let mut named = 0;
+        for (value, data) in info.values() {
+            if let Some(name) = &data.name {
+                self.name(value.clone(), name);
+                named += 1;
+            }
+        }
+        named
+    }
+}
+
+impl Default for PrettyPrintConfig {
+    #[inline]
+    fn default() -> PrettyPrintConfig {
+        Self::new()
+    }
+}
+
 /// A prettyprinter for `rain` values
 #[derive(Clone)]
 pub struct PrettyPrinter<I = VirtualRegister, S: BuildHasher = FxBuildHasher> {
     symbols: SymbolTable<ValAddr, I, S>,
+    names: HashMap<ValAddr, String, FxBuildHasher>,
+    uses: HashMap<String, usize, FxBuildHasher>,
     unique: usize,
     scope: Vec<bool>,
     open_scopes: usize,
-    max_tabs: u16,
+    config: PrettyPrintConfig,
 }
 
 impl<I: Debug, S: BuildHasher> Debug for PrettyPrinter<I, S> {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
         fmt.debug_struct("PrettyPrinter")
             .field("symbols", &self.symbols)
+            .field("names", &self.names)
+            .field("uses", &self.uses)
             .field("unique", &self.unique)
             .field("scope", &self.scope)
             .field("open_scopes", &self.open_scopes)
-            .field("max_tabs", &self.max_tabs)
+            .field("config", &self.config)
             .finish()
     }
 }
@@ -54,6 +128,12 @@ const PRETTYPRINTER_STACK_DEPTH: usize = 16;
 /// The default maximum number of tags for a prettyprinter
 pub const DEFAULT_MAX_TABS: u16 = 4;
 
+/// The default maximum size of a subterm printed in place, which inlines only values without dependencies
+pub const DEFAULT_INLINE_SIZE: usize = 1;
+
+/// The width of a tab, for the purposes of line wrapping
+const TAB_WIDTH: usize = 4;
+
 /// Display a value using an empty prettyprinter
 #[derive(Debug, Copy, Clone, RefCast, Eq, PartialEq, Hash)]
 #[repr(transparent)]
@@ -62,17 +142,36 @@ pub struct PrettyPrintable<T>(T);
 impl<I: Display + From<usize> + Sized> PrettyPrinter<I> {
     /// Create a new prettyprinter
     pub fn new() -> PrettyPrinter<I> {
+        Self::with_config(PrettyPrintConfig::default())
+    }
+    /// Create a new prettyprinter with a given configuration
+    pub fn with_config(config: PrettyPrintConfig) -> PrettyPrinter<I> {
         PrettyPrinter {
             symbols: SymbolTable::default(),
+            names: HashMap::default(),
+            uses: HashMap::default(),
             unique: 0,
             scope: Vec::new(),
             open_scopes: 0,
-            max_tabs: DEFAULT_MAX_TABS,
+            config,
+        }
+    }
+    /// Get the configuration of this prettyprinter
+    #[inline]
+    pub fn config(&self) -> &PrettyPrintConfig {
+        &self.config
+    }
+    /// Prettyprint a value to a string, wrapping lines to the configured maximum width, if any
+    pub fn render<V: PrettyPrint>(&mut self, value: &V) -> String {
+        let text = Rendered(RefCell::new(self), value).to_string();
+        match self.config.max_width {
+            Some(width) => wrap(&text, width),
+            None => text,
         }
     }
     /// Print the appropriate number of tabs for the given scope level, up to the maximum
     pub fn print_tabs(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
-        let to_print = self.open_scopes.min(self.max_tabs as usize);
+        let to_print = self.open_scopes.min(self.config.max_tabs as usize);
         for _ in 0..to_print {
             write!(fmt, "\t")?;
         }
@@ -84,13 +183,74 @@ impl<I: Display + From<usize> + Sized> PrettyPrinter<I> {
     }
     /// Try to prettyprint a `ValId`'s associated identifier. Return whether it was printed or not
     pub fn try_prettyprint(&self, fmt: &mut Formatter, value: ValRef) -> Result<bool, fmt::Error> {
-        if let Some(id) = self.symbols.get(&value.as_addr()) {
+        self.try_prettyprint_addr(fmt, value.as_addr())
+    }
+    /// Try to prettyprint the identifier associated with a value's address. Return whether it was printed or not
+    pub fn try_prettyprint_addr(
+        &self,
+        fmt: &mut Formatter,
+        addr: ValAddr,
+    ) -> Result<bool, fmt::Error> {
+        if let Some(name) = self.names.get(&addr) {
+            write!(fmt, "{}", name)?;
+            Ok(true)
+        } else if let Some(id) = self.symbols.get(&addr) {
             write!(fmt, "{}", id)?;
             Ok(true)
         } else {
             Ok(false)
         }
     }
+    /// Check whether a value without an identifier should be printed in place rather than bound to a register
+    fn inlined(&self, value: ValRef) -> bool {
+        if self.config.style == PrintStyle::Nested {
+            return true;
+        }
+        // Values with identifiers count towards the size of a subterm, but their dependencies do not. Note `value`
+        // itself never has an identifier.
+        let mut budget = self.config.inline_size;
+        let mut visit_stack = SmallVec::<[ValRef; PRETTYPRINTER_STACK_DEPTH]>::new();
+        visit_stack.push(value);
+        while let Some(top) = visit_stack.pop() {
+            if budget == 0 {
+                return false;
+            }
+            budget -= 1;
+            if !self.has_id(top) {
+                let norm = top.as_norm();
+                visit_stack.extend((0..norm.no_deps()).map(|ix| norm.get_dep(ix).borrow_val()));
+            }
+        }
+        true
+    }
+    /// Get the name to print for a value about to be registered, if one was given, disambiguating repeated names
+    fn user_name(&mut self, value: ValRef) -> Option<String> {
+        let (_, name) = self.config.names.lookup_addr(value.as_addr().raw_addr())?;
+        let uses = self.uses.entry(name.clone()).or_insert(0);
+        let unique_name = if *uses == 0 {
+            name.clone()
+        } else {
+            format!("{}.{}", name, uses)
+        };
+        *uses += 1;
+        Some(unique_name)
+    }
+    /// Print the name a value is about to be registered under
+    fn print_name(&self, fmt: &mut Formatter, name: Option<&str>) -> Result<(), fmt::Error> {
+        match name {
+            Some(name) => write!(fmt, "{}", name),
+            None => write!(fmt, "{}", I::from(self.unique)),
+        }
+    }
+    /// Register a new identifier for a value, along with the name given to it, if any
+    fn register(&mut self, value: ValRef, name: Option<String>) {
+        self.symbols.insert(value.as_addr(), self.unique.into());
+        if let Some(name) = name {
+            self.names.insert(value.as_addr(), name);
+        }
+        // Record the increase in the number of defined names
+        self.unique += 1;
+    }
     /// Prettyprint a `ValId` and its dependencies as `let` statements, avoiding recursion.
     /// Return the number of new definitions, if any.
     ///
@@ -102,15 +262,15 @@ impl<I: Display + From<usize> + Sized> PrettyPrinter<I> {
     ) -> Result<usize, fmt::Error> {
         let mut new_deps = 0;
         let mut visit_stack = SmallVec::<[(ValRef, usize); PRETTYPRINTER_STACK_DEPTH]>::new();
-        if self.has_id(value) {
+        if self.has_id(value) || self.inlined(value) {
             return Ok(0);
         }
         visit_stack.push((value, 0));
         while let Some((top, mut ix)) = visit_stack.pop() {
             while ix < top.no_deps() {
                 let dep = top.as_norm().get_dep(ix);
-                if self.has_id(dep.borrow_val()) || dep.no_deps() == 0 {
-                    // Note we avoid printing dependencies with no dependencies as `let` statements
+                if self.has_id(dep.borrow_val()) || self.inlined(dep.borrow_val()) {
+                    // Note we avoid printing small dependencies as `let` statements
                     ix += 1;
                 } else {
                     // Push the new dependency, and the old dependency
@@ -122,7 +282,7 @@ impl<I: Display + From<usize> + Sized> PrettyPrinter<I> {
             if ix == top.no_deps() {
                 ix += 1;
                 let ty = top.as_norm().ty();
-                if !ty.is_kind() {
+                if !ty.is_kind() && !self.has_id(ty.as_val()) && !self.inlined(ty.as_val()) {
                     // Print the dependencies of non-kind types
                     //TODO: be smarter about this, but later...
                     visit_stack.push((top, ix));
@@ -132,7 +292,7 @@ impl<I: Display + From<usize> + Sized> PrettyPrinter<I> {
             }
             if ix > top.no_deps() {
                 // Print the dependency, creating a new name
-                let name: I = self.unique.into();
+                let name = self.user_name(top);
                 let ty = top.ty();
                 // If the current scope is not open, open it. If not in a scope, ignore.
                 if let Some(top) = self.scope.last() {
@@ -151,21 +311,17 @@ impl<I: Display + From<usize> + Sized> PrettyPrinter<I> {
                 }
                 // Print the correct number of tabs (corresponding to the current scope level)
                 self.print_tabs(fmt)?;
+                write!(fmt, "{} ", KEYWORD_LET)?;
+                self.print_name(fmt, name.as_deref())?;
                 if !ty.is_universe() {
                     // Only print the type of non-universes, for now
-                    write!(
-                        fmt,
-                        "{} {}{} {} {} ",
-                        KEYWORD_LET, name, JUDGE_TYPE, ty, ASSIGN
-                    )?;
+                    write!(fmt, "{} {} {} ", JUDGE_TYPE, ty, ASSIGN)?;
                 } else {
-                    write!(fmt, "{} {} {} ", KEYWORD_LET, name, ASSIGN)?;
+                    write!(fmt, " {} ", ASSIGN)?;
                 }
                 top.prettyprint(self, fmt)?;
                 writeln!(fmt, "{}", STATEMENT_DELIM)?;
-                self.symbols.insert(top.as_addr(), name);
-                // Record the increase in the number of defined names
-                self.unique += 1;
+                self.register(top, name);
                 new_deps += 1;
                 // We're done with this iteration: pop again
                 continue;
@@ -179,10 +335,10 @@ impl<I: Display + From<usize> + Sized> PrettyPrinter<I> {
         fmt: &mut Formatter,
         value: ValRef,
     ) -> Result<(), fmt::Error> {
-        let name: I = self.unique.into();
-        write!(fmt, "{}: {}", name, value.ty())?;
-        self.symbols.insert(value.as_addr(), name);
-        self.unique += 1;
+        let name = self.user_name(value);
+        self.print_name(fmt, name.as_deref())?;
+        write!(fmt, ": {}", value.ty())?;
+        self.register(value, name);
         Ok(())
     }
     /// Prettyprint a value's dependencies as `let` statements, if not already printed.
@@ -240,6 +396,74 @@ impl Default for PrettyPrinter {
     }
 }
 
+/// A value prettyprinted using a given printer
+struct Rendered<'a, I, V>(RefCell<&'a mut PrettyPrinter<I>>, &'a V);
+
+impl<I: From<usize> + Display, V: PrettyPrint> Display for Rendered<'_, I, V> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), fmt::Error> {
+        let mut printer = self.0.borrow_mut();
+        self.1.prettyprint(&mut **printer, fmt)
+    }
+}
+
+/**
+Find where to break a line so that it fits within a given width, if it does not already.
+
+Lines are only broken at spaces outside of string literals and past their indentation. We break at the last such space
+which fits, or failing that at the first.
+*/
+fn break_point(line: &str, width: usize) -> Option<usize> {
+    let indent = line.len() - line.trim_start().len();
+    let mut column = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut last_fit = None;
+    let mut first_over = None;
+    for (ix, c) in line.char_indices() {
+        column += if c == '\t' { TAB_WIDTH } else { 1 };
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true
+        } else if c == ' ' && ix > indent {
+            if column <= width {
+                last_fit = Some(ix)
+            } else if first_over.is_none() {
+                first_over = Some(ix)
+            }
+        }
+    }
+    if column <= width {
+        None
+    } else {
+        last_fit.or(first_over)
+    }
+}
+
+/// Wrap the lines of prettyprinted text to a given width, indenting continuation lines by an additional tab
+fn wrap(text: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+    for (ix, line) in text.split('\n').enumerate() {
+        if ix > 0 {
+            wrapped.push('\n');
+        }
+        let tabs = line.len() - line.trim_start_matches('\t').len();
+        let mut rest = line.to_owned();
+        while let Some(point) = break_point(&rest, width) {
+            wrapped.push_str(&rest[..point]);
+            wrapped.push('\n');
+            rest = format!("{}{}", "\t".repeat(tabs + 1), rest[point..].trim_start());
+        }
+        wrapped.push_str(&rest);
+    }
+    wrapped
+}
+
 /// A value which can be prettyprinted
 pub trait PrettyPrint {
     /// Prettyprint a value using a given printer
@@ -288,3 +512,104 @@ macro_rules! prettyprint_by_display {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parser::parse;
+    use super::*;
+    use crate::debug::{DebugData, Span};
+    use crate::primitive::logical::Bool;
+    use crate::region::Region;
+    use crate::typing::Type;
+    use crate::value::tuple::Tuple;
+    use crate::{function::lambda::Lambda, tyarr, valarr};
+
+    /// Get the parameters of a binary function on booleans, along with the function, which shares a pair of them
+    fn shared_pair() -> (ValId, ValId, ValId) {
+        let region = Region::with(tyarr![Bool.into_ty(); 2], Region::NULL).unwrap();
+        let a = region.param(0).unwrap().into_val();
+        let b = region.param(1).unwrap().into_val();
+        let pair = Tuple::try_new(valarr![a.clone(), b.clone()])
+            .unwrap()
+            .into_val();
+        let result = Tuple::try_new(valarr![pair.clone(), pair])
+            .unwrap()
+            .into_val();
+        let function = Lambda::try_new(result, region).unwrap().into_val();
+        (a, b, function)
+    }
+
+    #[test]
+    fn default_config_matches_display() {
+        let (_, _, function) = shared_pair();
+        let text = PrettyPrinter::default().render(&function);
+        assert_eq!(text, format!("{}", function));
+        assert!(text.contains(KEYWORD_LET));
+    }
+
+    #[test]
+    fn small_subterms_are_inlined() {
+        let (_, _, function) = shared_pair();
+        for (style, inline_size, lets) in [
+            (PrintStyle::Let, 2, true),
+            (PrintStyle::Let, 3, false),
+            (PrintStyle::Nested, 0, false),
+        ]
+        .iter()
+        {
+            let mut config = PrettyPrintConfig::new();
+            config.style = *style;
+            config.inline_size = *inline_size;
+            let text = PrettyPrinter::<VirtualRegister>::with_config(config).render(&function);
+            assert_eq!(text.contains(KEYWORD_LET), *lets, "Printed:\n{}", text);
+            assert_eq!(parse(&text), Ok(function.clone()), "Parsing:\n{}", text);
+        }
+    }
+
+    #[test]
+    fn values_can_be_named() {
+        let (a, b, function) = shared_pair();
+        let mut config = PrettyPrintConfig::new();
+        assert_eq!(config.name(a.clone(), "x"), None);
+        assert_eq!(config.name(b.clone(), "x"), None);
+        assert_eq!(config.get_name(&a), Some("x"));
+        let text = PrettyPrinter::<VirtualRegister>::with_config(config).render(&function);
+        assert!(text.contains("x: "), "Printed:\n{}", text);
+        assert!(text.contains("x.1: "), "Printed:\n{}", text);
+        assert!(text.contains(&format!("{}x x.1{}", TUPLE_OPEN, TUPLE_CLOSE)));
+    }
+
+    #[test]
+    fn debug_info_names_values() {
+        let (a, b, function) = shared_pair();
+        let mut info = DebugInfo::new();
+        info.insert(a, DebugData::named("lhs"));
+        info.insert(b, DebugData::at(Span::new("pair.rn", 0, 1)));
+        let mut config = PrettyPrintConfig::new();
+        assert_eq!(config.use_debug_info(&info), 1);
+        let text = PrettyPrinter::<VirtualRegister>::with_config(config).render(&function);
+        assert!(text.contains("lhs: "), "Printed:\n{}", text);
+    }
+
+    #[test]
+    fn long_lines_are_wrapped() {
+        const WIDTH: usize = 16;
+        let (_, _, function) = shared_pair();
+        let mut config = PrettyPrintConfig::new();
+        config.max_width = Some(WIDTH);
+        let text = PrettyPrinter::<VirtualRegister>::with_config(config).render(&function);
+        for line in text.lines() {
+            let width: usize = line
+                .chars()
+                .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+                .sum();
+            assert!(
+                width <= WIDTH || !line.trim_start().contains(' '),
+                "Printed:\n{}",
+                text
+            );
+        }
+        assert_eq!(parse(&text), Ok(function));
+        assert_eq!(wrap("\t(a \"b c\" d)", 8), "\t(a\n\t\t\"b c\"\n\t\td)");
+    }
+}
@@ -0,0 +1,1462 @@
+/*!
+A parser for the textual format emitted by the [`PrettyPrinter`](super::PrettyPrinter), rebuilding the same `ValId`s
+which were printed.
+
+The input consists of any number of `let` statements followed by a value, just like the body of a function. Register
+names are global to the input, as the prettyprinter never reuses a name for a different value. Parameter types are
+parsed in a fresh namespace, since the prettyprinter prints them with a fresh printer, while the type annotations of
+`let` statements are skipped, since types are recomputed when their values are rebuilt.
+*/
+use crate::control::{
+    effect::{Effect, World},
+    phi::Phi,
+    ternary::Ternary,
+};
+use crate::data::array::{Array, ArrayOp, ArrayTy};
+use crate::data::memory::{AllocTy, MemOp, MemOpKind};
+use crate::data::reference::{Borrow, Dereference, RefKind, RefTy};
+use crate::function::external::{CallConv, Extern, Linkage, LinkageKind};
+use crate::function::{lambda::Lambda, pi::Pi};
+use crate::lifetime::{Group, Lifetime, ParamIndices, PiLifetime};
+use crate::primitive::{
+    bits::{BinOp, BitsKind, BitsTy, Neg},
+    finite::Finite,
+    logical::{self, And, Bool, Iff, Logical, Nand, Nor, Not, Or, Xor},
+};
+use crate::proof::paths::{induction::PathInd, Id, IdFamily, Refl};
+use crate::region::{Parametrized as RegionParametrized, Region, Regional};
+use crate::tokens::*;
+use crate::typing::{
+    primitive::{Fin, Prop, Set},
+    Typed,
+};
+use crate::value::{
+    arr::{TyArr, ValArr},
+    expr::Sexpr,
+    thunk::Thunk,
+    tuple::{Product, Tuple},
+    Error, KindId, NormalValue, TypeId, ValId, Value, ValueEnum, VarId,
+};
+use fxhash::FxBuildHasher;
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use std::convert::{TryFrom, TryInto};
+
+lazy_static! {
+    /// The symbols recognized by the parser. The longest symbol matching the input is always taken.
+    static ref SYMBOLS: Vec<&'static str> = vec![
+        SEXPR_OPEN,
+        SEXPR_CLOSE,
+        TUPLE_OPEN,
+        TUPLE_CLOSE,
+        UNIT_VALUE,
+        PARAM_OPEN,
+        PARAM_CLOSE,
+        KEYWORD_LET,
+        JUDGE_TYPE,
+        ASSIGN,
+        STATEMENT_DELIM,
+        KEYWORD_ANCHORED,
+        KEYWORD_ANCHOR,
+        KEYWORD_PROD,
+        KEYWORD_TRUE,
+        KEYWORD_FALSE,
+        KEYWORD_BOOL,
+        KEYWORD_FINITE,
+        KEYWORD_IX,
+        KEYWORD_LOGICAL,
+        KEYWORD_LOGICAL_ID,
+        KEYWORD_NOT,
+        KEYWORD_AND,
+        KEYWORD_OR,
+        KEYWORD_XOR,
+        KEYWORD_NOR,
+        KEYWORD_NAND,
+        KEYWORD_IFF,
+        "#lambda",
+        "#pi",
+        "#gamma",
+        "#ternary",
+        "#prop",
+        "#fin",
+        "#set",
+        "#bitskind",
+        "#bitsty",
+        "#unit",
+        "#world",
+        "#add",
+        "#sub",
+        "#mod",
+        "#mul",
+        "#neg",
+        "_linear",
+        KEYWORD_PHI,
+        "#id",
+        "#refl",
+        "#id_family",
+        "#path_ind",
+        "#ref",
+        "#ref_mut",
+        "#lender",
+        "#transient",
+        "#borrow",
+        "#borrow_mut",
+        "#reborrow",
+        "#reborrow_mut",
+        "#deref",
+        "#alloc",
+        "#malloc",
+        "#free",
+        "#load",
+        "#store",
+        "#array",
+        "#array_get",
+        "#array_set",
+        "#array_map",
+        "#array_fold",
+        "#array_repeat",
+        "#effect",
+        "#extern",
+        "#weak",
+        "#internal",
+        "#rain",
+        "#thunk",
+        "(",
+        ")",
+        "[",
+        "]",
+        "{",
+        "}",
+        ":",
+        ",",
+        ";",
+        "=>",
+    ];
+    /// The keywords of the primitive forms, which are printed like S-expressions
+    static ref FORMS: Vec<&'static str> = vec![
+        "#id",
+        "#refl",
+        "#id_family",
+        "#path_ind",
+        "#ref",
+        "#ref_mut",
+        "#borrow",
+        "#borrow_mut",
+        "#reborrow",
+        "#reborrow_mut",
+        "#deref",
+        "#alloc",
+        "#malloc",
+        "#free",
+        "#load",
+        "#store",
+        "#array_get",
+        "#array_set",
+        "#array_map",
+        "#array_fold",
+        "#array_repeat",
+        "#effect",
+        "#extern",
+    ];
+}
+
+/// An error parsing a prettyprinted `rain` value
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParseError {
+    /// The input ended unexpectedly
+    UnexpectedEof,
+    /// An unexpected token at a given byte offset
+    UnexpectedToken(usize),
+    /// An invalid literal at a given byte offset
+    InvalidLiteral(usize),
+    /// A reference to a register which was never defined
+    UndefinedRegister(usize),
+    /// A ternary operation at a given byte offset with invalid or missing branches
+    InvalidTernary(usize),
+    /// A primitive form at a given byte offset with invalid arguments
+    InvalidForm(usize),
+    /// The input describes an invalid value
+    Value(Error),
+}
+
+impl From<Error> for ParseError {
+    #[inline]
+    fn from(err: Error) -> ParseError {
+        ParseError::Value(err)
+    }
+}
+
+/**
+Parse the output of the prettyprinter back into the value printed.
+
+Since every value is rebuilt through its constructor, and hence re-hash-consed, parsing the prettyprinted form of a
+value which is still alive yields a pointer-identical `ValId`. This makes prettyprinted output suitable for storing
+golden IR files to be loaded in tests.
+*/
+pub fn parse(text: &str) -> Result<ValId, ParseError> {
+    let mut parser = Parser::new(text);
+    let scope = parser.scope()?;
+    parser.end()?;
+    Builder::default().scope(&scope)
+}
+
+/// A token of the textual format
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Token {
+    /// A symbol
+    Symbol(&'static str),
+    /// A register, e.g. `%3`
+    Register(usize),
+    /// An integer literal, in decimal, binary or hexadecimal
+    Int(u128),
+    /// A bits literal, e.g. `8'h1f`
+    Bits(u32, u128),
+    /// A string literal, given by the byte range of its contents
+    Str(usize, usize),
+}
+
+/// The kind of value binding the parameters of a region
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Binder {
+    /// A lambda function
+    Lambda,
+    /// A pi type
+    Pi,
+    /// A phi node
+    Phi,
+}
+
+/// A parsed expression, yet to be built into a value
+#[derive(Debug, Clone)]
+enum Expr {
+    /// A reference to a register
+    Register(usize),
+    /// A constant
+    Const(ValId),
+    /// An S-expression
+    Sexpr(Vec<Expr>),
+    /// A tuple
+    Tuple(Vec<Expr>),
+    /// A product type, which may be an anchor and may be linear
+    Product(Vec<Expr>, bool, bool),
+    /// A lambda function, pi type or phi node
+    Parametrized {
+        /// The kind of value binding the parameters
+        binder: Binder,
+        /// The parameters, along with their types
+        params: Vec<(usize, Expr)>,
+        /// The body
+        body: Box<Scope>,
+        /// The lifetime component of a pi type, which is trivial for other binders
+        lifetime: PiLifetime,
+    },
+    /// A ternary operation
+    Ternary {
+        /// The byte offset of this operation
+        offset: usize,
+        /// The type of the selector
+        ty: Box<Expr>,
+        /// The pattern and value of each branch
+        arms: Box<[(Expr, Expr); 2]>,
+    },
+    /// An array type
+    ArrayTy(Box<Expr>, u128),
+    /// An array, along with its element type if it is empty
+    Array(Option<Box<Expr>>, Vec<Expr>),
+    /// A thunk at a given byte offset
+    Thunk(usize, Vec<Expr>),
+    /// A primitive form
+    Form(Box<Form>),
+}
+
+/// A primitive form, e.g. `(#deref %3)`
+#[derive(Debug, Clone)]
+struct Form {
+    /// The byte offset of this form
+    offset: usize,
+    /// The keyword of this form
+    keyword: &'static str,
+    /// The name given to this form, if any
+    name: Option<String>,
+    /// The arguments of this form
+    args: Vec<Expr>,
+    /// The attribute keywords of this form
+    attrs: Vec<&'static str>,
+    /// The groups of values given to this form, along with their keywords
+    groups: Vec<(&'static str, Vec<Expr>)>,
+}
+
+/// A list of `let` statements followed by a value
+#[derive(Debug, Clone)]
+struct Scope {
+    /// The registers defined, in order
+    lets: Vec<(usize, Expr)>,
+    /// The value of this scope
+    result: Expr,
+}
+
+/// Get the constant denoted by a symbol, if any
+fn constant(symbol: &str) -> Option<ValId> {
+    let value = if symbol == KEYWORD_TRUE {
+        true.into_val()
+    } else if symbol == KEYWORD_FALSE {
+        false.into_val()
+    } else if symbol == KEYWORD_BOOL {
+        Bool.into_val()
+    } else if symbol == KEYWORD_LOGICAL_ID {
+        logical::Id.into_val()
+    } else if symbol == KEYWORD_NOT {
+        Not.into_val()
+    } else if symbol == KEYWORD_AND {
+        And.into_val()
+    } else if symbol == KEYWORD_OR {
+        Or.into_val()
+    } else if symbol == KEYWORD_XOR {
+        Xor.into_val()
+    } else if symbol == KEYWORD_NOR {
+        Nor.into_val()
+    } else if symbol == KEYWORD_NAND {
+        Nand.into_val()
+    } else if symbol == KEYWORD_IFF {
+        Iff.into_val()
+    } else if symbol == "#prop" {
+        Prop.into_val()
+    } else if symbol == "#fin" {
+        Fin.into_val()
+    } else if symbol == "#bitskind" {
+        BitsKind.into_val()
+    } else if symbol == "#unit" {
+        Product::unit_ty().into_val()
+    } else if symbol == "#world" {
+        World.into_val()
+    } else if symbol == "#add" {
+        BinOp::Add.into_val()
+    } else if symbol == "#sub" {
+        BinOp::Sub.into_val()
+    } else if symbol == "#mod" {
+        BinOp::Mod.into_val()
+    } else if symbol == "#mul" {
+        BinOp::Mul.into_val()
+    } else if symbol == "#neg" {
+        Neg.into_val()
+    } else {
+        return None;
+    };
+    Some(value)
+}
+
+/// Get the length of the alphanumeric prefix of a string
+fn alphanumeric_len(text: &str) -> usize {
+    text.find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or_else(|| text.len())
+}
+
+/// A parser for prettyprinted `rain` values
+#[derive(Debug, Clone)]
+struct Parser<'a> {
+    /// The input being parsed
+    text: &'a str,
+    /// The current byte offset into the input
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Create a new parser for a given input
+    fn new(text: &'a str) -> Parser<'a> {
+        Parser { text, pos: 0 }
+    }
+    /// Skip whitespace, returning the byte offset of the next token
+    fn offset(&mut self) -> usize {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.pos
+    }
+    /// Lex the next token, along with its length in bytes, without consuming it
+    fn lex(&mut self) -> Result<Option<(Token, usize)>, ParseError> {
+        let offset = self.offset();
+        let rest = &self.text[offset..];
+        let first = match rest.chars().next() {
+            Some(first) => first,
+            None => return Ok(None),
+        };
+        if first == '%' {
+            let digits = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - 1);
+            if digits > 0 {
+                let register = rest[1..=digits]
+                    .parse()
+                    .map_err(|_| ParseError::InvalidLiteral(offset))?;
+                return Ok(Some((Token::Register(register), digits + 1)));
+            }
+        }
+        if first == '"' {
+            return Self::string_len(rest)
+                .map(|len| Some((Token::Str(offset + 1, len - 2), len)))
+                .ok_or(ParseError::UnexpectedEof);
+        }
+        if first.is_ascii_digit() {
+            return Self::number(rest)
+                .map(Some)
+                .ok_or(ParseError::InvalidLiteral(offset));
+        }
+        SYMBOLS
+            .iter()
+            .filter(|symbol| !symbol.is_empty() && rest.starts_with(**symbol))
+            .max_by_key(|symbol| symbol.len())
+            .map(|symbol| Some((Token::Symbol(*symbol), symbol.len())))
+            .ok_or(ParseError::UnexpectedToken(offset))
+    }
+    /// Lex an integer or bits literal at the start of a string, along with its length in bytes
+    fn number(text: &str) -> Option<(Token, usize)> {
+        let radix = if text.starts_with("0x") {
+            16
+        } else if text.starts_with("0b") {
+            2
+        } else {
+            let len = text
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or_else(|| text.len());
+            let n: u128 = text[..len].parse().ok()?;
+            if !text[len..].starts_with("'h") {
+                return Some((Token::Int(n), len));
+            }
+            let start = len + 2;
+            let end = start + alphanumeric_len(&text[start..]);
+            let data = u128::from_str_radix(&text[start..end], 16).ok()?;
+            return Some((Token::Bits(u32::try_from(n).ok()?, data), end));
+        };
+        let end = 2 + alphanumeric_len(&text[2..]);
+        let n = u128::from_str_radix(&text[2..end], radix).ok()?;
+        Some((Token::Int(n), end))
+    }
+    /// Get the length in bytes of the quoted string literal at the start of a string, including its quotes
+    fn string_len(text: &str) -> Option<usize> {
+        let mut escaped = false;
+        for (ix, c) in text.char_indices().skip(1) {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => return Some(ix + 1),
+                _ => {}
+            }
+        }
+        None
+    }
+    /// Decode the contents of a string literal, as printed by `Debug`, given its byte range
+    fn string(&self, start: usize, len: usize) -> Result<String, ParseError> {
+        let invalid = ParseError::InvalidLiteral(start - 1);
+        let mut string = String::with_capacity(len);
+        let mut chars = self.text[start..start + len].chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                string.push(c);
+                continue;
+            }
+            let unescaped = match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+                Some('u') => {
+                    let rest = chars.as_str();
+                    let end = match rest.find('}') {
+                        Some(end) if rest.starts_with('{') => end,
+                        _ => return Err(invalid),
+                    };
+                    let c = u32::from_str_radix(&rest[1..end], 16)
+                        .ok()
+                        .and_then(std::char::from_u32);
+                    chars = rest[end + 1..].chars();
+                    match c {
+                        Some(c) => c,
+                        None => return Err(invalid),
+                    }
+                }
+                _ => return Err(invalid),
+            };
+            string.push(unescaped);
+        }
+        Ok(string)
+    }
+    /// Get the error for an unexpected token at the current position
+    fn unexpected(&mut self) -> ParseError {
+        let offset = self.offset();
+        if offset == self.text.len() {
+            ParseError::UnexpectedEof
+        } else {
+            ParseError::UnexpectedToken(offset)
+        }
+    }
+    /// Consume the next token
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let (token, len) = self.lex()?.ok_or(ParseError::UnexpectedEof)?;
+        self.pos += len;
+        Ok(token)
+    }
+    /// Consume the next token if it is a given symbol, returning whether it was
+    fn eat(&mut self, symbol: &str) -> Result<bool, ParseError> {
+        match self.lex()? {
+            Some((Token::Symbol(next), len)) if next == symbol => {
+                self.pos += len;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    /// Consume a given symbol, returning an error if it is not next
+    fn expect(&mut self, symbol: &str) -> Result<(), ParseError> {
+        if self.eat(symbol)? {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+    /// Check that the input has been consumed
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.lex()? {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+    /// Consume an integer literal
+    fn int(&mut self) -> Result<u128, ParseError> {
+        match self.lex()? {
+            Some((Token::Int(n), len)) => {
+                self.pos += len;
+                Ok(n)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+    /// Consume an integer literal, converting it to a given type
+    fn small<T: TryFrom<u128>>(&mut self) -> Result<T, ParseError> {
+        let offset = self.offset();
+        T::try_from(self.int()?).map_err(|_| ParseError::InvalidLiteral(offset))
+    }
+    /// Consume a parenthesized integer literal, converting it to a given type
+    fn arg<T: TryFrom<u128>>(&mut self) -> Result<T, ParseError> {
+        self.expect("(")?;
+        let arg = self.small()?;
+        self.expect(")")?;
+        Ok(arg)
+    }
+    /// Consume a register
+    fn register(&mut self) -> Result<usize, ParseError> {
+        match self.lex()? {
+            Some((Token::Register(register), len)) => {
+                self.pos += len;
+                Ok(register)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+    /// Parse a list of expressions terminated by a given symbol
+    fn list(&mut self, close: &str) -> Result<Vec<Expr>, ParseError> {
+        let mut exprs = Vec::new();
+        while !self.eat(close)? {
+            exprs.push(self.expr()?);
+        }
+        Ok(exprs)
+    }
+    /// Parse a bracketed list of elements, as printed for tuples and products
+    fn elems(&mut self) -> Result<Vec<Expr>, ParseError> {
+        if UNIT_VALUE == format!("{}{}", TUPLE_OPEN, TUPLE_CLOSE) && self.eat(UNIT_VALUE)? {
+            return Ok(Vec::new());
+        }
+        self.expect(TUPLE_OPEN)?;
+        self.list(TUPLE_CLOSE)
+    }
+    /// Parse an empty bracketed list of elements, as printed for anchors
+    fn no_elems(&mut self, offset: usize) -> Result<(), ParseError> {
+        if self.elems()?.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseError::InvalidLiteral(offset))
+        }
+    }
+    /// Skip a type annotation, up to the next assignment outside of any brackets
+    fn skip_annotation(&mut self) -> Result<(), ParseError> {
+        let mut depth = 0usize;
+        for (ix, c) in self.text[self.pos..].char_indices() {
+            if depth == 0 && self.text[self.pos + ix..].starts_with(ASSIGN) {
+                self.pos += ix;
+                return Ok(());
+            }
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        Err(ParseError::UnexpectedEof)
+    }
+    /// Parse a list of `let` statements followed by a value
+    fn scope(&mut self) -> Result<Scope, ParseError> {
+        let mut lets = Vec::new();
+        while self.eat(KEYWORD_LET)? {
+            let register = self.register()?;
+            if self.eat(JUDGE_TYPE)? {
+                self.skip_annotation()?;
+            }
+            self.expect(ASSIGN)?;
+            let value = self.expr()?;
+            self.expect(STATEMENT_DELIM)?;
+            lets.push((register, value));
+        }
+        let result = self.expr()?;
+        Ok(Scope { lets, result })
+    }
+    /// Parse the body of a lambda function or pi type, which is either a value or a braced scope
+    fn body(&mut self) -> Result<Scope, ParseError> {
+        if self.eat("{")? {
+            let scope = self.scope()?;
+            self.expect("}")?;
+            Ok(scope)
+        } else {
+            let result = self.expr()?;
+            Ok(Scope {
+                lets: Vec::new(),
+                result,
+            })
+        }
+    }
+    /// Parse the lifetime component of a pi type, i.e. the indices of the parameters its result borrows from
+    fn pi_lifetime(&mut self) -> Result<PiLifetime, ParseError> {
+        let mut lenders = ParamIndices::new();
+        let mut transients = ParamIndices::new();
+        loop {
+            let group = if self.eat("#lender")? {
+                &mut lenders
+            } else if self.eat("#transient")? {
+                &mut transients
+            } else {
+                return Ok(PiLifetime::new(lenders, transients));
+            };
+            self.expect("[")?;
+            while !self.eat("]")? {
+                group.push(self.small()?);
+            }
+        }
+    }
+    /// Parse the parameters and body of a lambda function, pi type or phi node, after the opening of the parameter list
+    fn parametrized(&mut self, binder: Binder, lifetime: PiLifetime) -> Result<Expr, ParseError> {
+        let mut params = Vec::new();
+        while !self.eat(PARAM_CLOSE)? {
+            let register = self.register()?;
+            self.expect(":")?;
+            params.push((register, self.expr()?));
+        }
+        let body = Box::new(self.body()?);
+        Ok(Expr::Parametrized {
+            binder,
+            params,
+            body,
+            lifetime,
+        })
+    }
+    /// Parse a branch of a ternary operation
+    fn arm(&mut self) -> Result<(Expr, Expr), ParseError> {
+        let pattern = self.expr()?;
+        self.expect("=>")?;
+        Ok((pattern, self.expr()?))
+    }
+    /// Parse a ternary operation, after its keyword
+    fn ternary(&mut self, offset: usize) -> Result<Expr, ParseError> {
+        self.expect(PARAM_OPEN)?;
+        let ty = Box::new(self.expr()?);
+        self.expect(PARAM_CLOSE)?;
+        self.expect("{")?;
+        let first = self.arm()?;
+        self.expect(",")?;
+        let second = self.arm()?;
+        self.expect("}")?;
+        let arms = Box::new([first, second]);
+        Ok(Expr::Ternary { offset, ty, arms })
+    }
+    /// Parse a bracketed list of elements or an array type, after the opening bracket
+    fn bracket(&mut self, offset: usize, open: &str) -> Result<Expr, ParseError> {
+        let close = if open == TUPLE_OPEN { TUPLE_CLOSE } else { "]" };
+        let mut elems = Vec::new();
+        while !self.eat(close)? {
+            elems.push(self.expr()?);
+            if elems.len() == 1 && self.eat(";")? {
+                let len = self.int()?;
+                self.expect("]")?;
+                return Ok(Expr::ArrayTy(Box::new(elems.remove(0)), len));
+            }
+        }
+        if open == TUPLE_OPEN {
+            Ok(Expr::Tuple(elems))
+        } else {
+            Err(ParseError::UnexpectedToken(offset))
+        }
+    }
+    /// Parse a primitive form, after its keyword
+    fn form(&mut self, offset: usize, keyword: &'static str) -> Result<Expr, ParseError> {
+        let name = match self.lex()? {
+            Some((Token::Str(start, len), token_len)) => {
+                self.pos += token_len;
+                Some(self.string(start, len)?)
+            }
+            _ => None,
+        };
+        let mut form = Form {
+            offset,
+            keyword,
+            name,
+            args: Vec::new(),
+            attrs: Vec::new(),
+            groups: Vec::new(),
+        };
+        while !self.eat(")")? {
+            let next = match self.lex()? {
+                Some((Token::Symbol(symbol), len)) => Some((symbol, len)),
+                _ => None,
+            };
+            match next {
+                Some((group, len)) if group == "#lender" || group == "#transient" => {
+                    self.pos += len;
+                    self.expect("[")?;
+                    form.groups.push((group, self.list("]")?));
+                }
+                Some((attr, len)) if ["#weak", "#internal", "#rain"].contains(&attr) => {
+                    self.pos += len;
+                    form.attrs.push(attr);
+                }
+                _ => form.args.push(self.expr()?),
+            }
+        }
+        Ok(Expr::Form(Box::new(form)))
+    }
+    /// Parse an expression
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let offset = self.offset();
+        let symbol = match self.next()? {
+            Token::Register(register) => return Ok(Expr::Register(register)),
+            Token::Bits(len, data) => return Ok(Expr::Const(BitsTy(len).data(data)?.into_val())),
+            Token::Int(_) | Token::Str(_, _) => return Err(ParseError::UnexpectedToken(offset)),
+            Token::Symbol(symbol) => symbol,
+        };
+        let expr = if let Some(value) = constant(symbol) {
+            Expr::Const(value)
+        } else if symbol == KEYWORD_FINITE {
+            Expr::Const(Finite(self.arg()?).into_val())
+        } else if symbol == KEYWORD_IX {
+            let n = self.arg()?;
+            self.expect("[")?;
+            let ix = self.int()?;
+            self.expect("]")?;
+            let ix = Finite(n)
+                .ix(ix)
+                .map_err(|_| ParseError::InvalidLiteral(offset))?;
+            Expr::Const(ix.into_val())
+        } else if symbol == KEYWORD_LOGICAL {
+            self.expect("(")?;
+            let arity = self.small()?;
+            self.expect(",")?;
+            let data = self.int()?;
+            self.expect(")")?;
+            let logical =
+                Logical::try_new(arity, data).map_err(|_| ParseError::InvalidLiteral(offset))?;
+            Expr::Const(logical.into_val())
+        } else if symbol == "#bitsty" {
+            Expr::Const(BitsTy(self.arg()?).into_val())
+        } else if symbol == "#set" {
+            Expr::Const(Set::new(self.arg()?).into_val())
+        } else if symbol == UNIT_VALUE {
+            Expr::Const(Tuple::unit().into_val())
+        } else if symbol == KEYWORD_ANCHORED {
+            self.no_elems(offset)?;
+            Expr::Const(Tuple::const_anchor().into_val())
+        } else if symbol == KEYWORD_ANCHOR || symbol == KEYWORD_PROD {
+            let anchor = symbol == KEYWORD_ANCHOR;
+            let flare = self.eat("_linear")?;
+            let elems = self.elems()?;
+            if anchor && flare && elems.is_empty() {
+                Expr::Const(Product::linear_anchor_ty().into_val())
+            } else {
+                Expr::Product(elems, anchor, flare)
+            }
+        } else if symbol == TUPLE_OPEN || symbol == "[" {
+            self.bracket(offset, symbol)?
+        } else if symbol == SEXPR_OPEN || symbol == "(" {
+            // Primitive forms are printed with parentheses, which may coincide with S-expression delimiters
+            let keyword = match self.lex()? {
+                Some((Token::Symbol(keyword), len)) if FORMS.contains(&keyword) => {
+                    self.pos += len;
+                    Some(keyword)
+                }
+                _ => None,
+            };
+            match keyword {
+                Some(keyword) => self.form(offset, keyword)?,
+                None if symbol == SEXPR_OPEN => Expr::Sexpr(self.list(SEXPR_CLOSE)?),
+                None => return Err(ParseError::UnexpectedToken(offset)),
+            }
+        } else if symbol == PARAM_OPEN {
+            self.parametrized(Binder::Lambda, PiLifetime::default())?
+        } else if symbol == "#lambda" {
+            self.expect(PARAM_OPEN)?;
+            self.parametrized(Binder::Lambda, PiLifetime::default())?
+        } else if symbol == "#pi" {
+            let lifetime = self.pi_lifetime()?;
+            self.expect(PARAM_OPEN)?;
+            self.parametrized(Binder::Pi, lifetime)?
+        } else if symbol == KEYWORD_PHI {
+            self.expect(PARAM_OPEN)?;
+            self.parametrized(Binder::Phi, PiLifetime::default())?
+        } else if symbol == "#array" {
+            let ty = if self.eat("(")? {
+                let ty = self.expr()?;
+                self.expect(")")?;
+                Some(Box::new(ty))
+            } else {
+                None
+            };
+            self.expect("[")?;
+            Expr::Array(ty, self.list("]")?)
+        } else if symbol == "#thunk" {
+            self.expect(SEXPR_OPEN)?;
+            Expr::Thunk(offset, self.list(SEXPR_CLOSE)?)
+        } else if symbol == "#gamma" || symbol == "#ternary" {
+            self.ternary(offset)?
+        } else {
+            return Err(ParseError::UnexpectedToken(offset));
+        };
+        Ok(expr)
+    }
+}
+
+/// A builder for parsed expressions, binding registers to values as it goes
+#[derive(Debug, Clone, Default)]
+struct Builder {
+    /// The values bound to each register
+    registers: HashMap<usize, ValId, FxBuildHasher>,
+    /// The regions currently being built, innermost last
+    regions: Vec<Region>,
+}
+
+impl Builder {
+    /// Build a scope, binding its registers
+    fn scope(&mut self, scope: &Scope) -> Result<ValId, ParseError> {
+        for (register, value) in scope.lets.iter() {
+            let value = self.build(value)?;
+            self.registers.insert(*register, value);
+        }
+        self.build(&scope.result)
+    }
+    /// Build an expression into a type
+    fn ty(&mut self, expr: &Expr) -> Result<TypeId, ParseError> {
+        self.build(expr)?
+            .try_into_ty()
+            .map_err(|_| ParseError::Value(Error::NotATypeError))
+    }
+    /// Build an expression into a kind
+    fn kind(&mut self, expr: &Expr) -> Result<KindId, ParseError> {
+        self.build(expr)?
+            .try_into_kind()
+            .map_err(|_| ParseError::Value(Error::NotAKindError))
+    }
+    /// Build an expression into a value of a given variant
+    fn var<V>(&mut self, expr: &Expr) -> Result<VarId<V>, ParseError>
+    where
+        for<'b> &'b NormalValue: TryInto<&'b V>,
+    {
+        VarId::try_from(self.build(expr)?).map_err(|_| ParseError::Value(Error::TypeMismatch))
+    }
+    /// Build a list of expressions
+    fn vals(&mut self, exprs: &[Expr]) -> Result<Vec<ValId>, ParseError> {
+        exprs.iter().map(|expr| self.build(expr)).collect()
+    }
+    /// Build an expression
+    fn build(&mut self, expr: &Expr) -> Result<ValId, ParseError> {
+        let value = match expr {
+            Expr::Register(register) => self
+                .registers
+                .get(register)
+                .cloned()
+                .ok_or(ParseError::UndefinedRegister(*register))?,
+            Expr::Const(value) => value.clone(),
+            Expr::Sexpr(args) => Sexpr::try_new(self.vals(args)?)?.into_val(),
+            Expr::Tuple(elems) => {
+                Tuple::try_new(self.vals(elems)?.into_iter().collect())?.into_val()
+            }
+            Expr::Product(elems, anchor, flare) => {
+                let elems = elems
+                    .iter()
+                    .map(|elem| self.ty(elem))
+                    .collect::<Result<TyArr, _>>()?;
+                Product::try_new_forced(elems, *anchor, *flare)?.into_val()
+            }
+            Expr::Parametrized {
+                binder,
+                params,
+                body,
+                lifetime,
+            } => self.parametrized(*binder, params, body, lifetime)?,
+            Expr::Ternary { offset, ty, arms } => self.ternary(*offset, ty, arms)?,
+            Expr::ArrayTy(elem, len) => ArrayTy::new(self.ty(elem)?, *len).into_val(),
+            Expr::Array(ty, elems) => {
+                let elems: ValArr = self.vals(elems)?.into_iter().collect();
+                let elem_ty = match (ty, elems.first()) {
+                    (Some(ty), _) => self.ty(ty)?,
+                    (None, Some(first)) => first.clone_ty(),
+                    (None, None) => return Err(ParseError::Value(Error::TypeMismatch)),
+                };
+                Array::try_new(elems, elem_ty)?.into_val()
+            }
+            Expr::Thunk(offset, args) => Thunk::try_defer(&self.vals(args)?, &mut None)?
+                .ok_or(ParseError::InvalidForm(*offset))?
+                .into_val(),
+            Expr::Form(form) => self.form(form)?,
+        };
+        Ok(value)
+    }
+    /**
+    Build a lambda function, pi type or phi node.
+
+    The printed form does not record the parent of a definition region, so we take the innermost region being built,
+    and then rebuild in the region of the result if it turns out to be further out, e.g. for a closed function
+    defined in the body of another.
+    */
+    fn parametrized(
+        &mut self,
+        binder: Binder,
+        params: &[(usize, Expr)],
+        body: &Scope,
+        lifetime: &PiLifetime,
+    ) -> Result<ValId, ParseError> {
+        let parent = self.regions.last().cloned().unwrap_or(Region::NULL);
+        let value = self.parametrized_in(binder, params, body, lifetime, parent.clone())?;
+        let region = value.clone_region();
+        if region == parent {
+            Ok(value)
+        } else {
+            self.parametrized_in(binder, params, body, lifetime, region)
+        }
+    }
+    /// Build a lambda function, pi type or phi node, the definition region of which has a given parent
+    fn parametrized_in(
+        &mut self,
+        binder: Binder,
+        params: &[(usize, Expr)],
+        body: &Scope,
+        lifetime: &PiLifetime,
+        parent: Region,
+    ) -> Result<ValId, ParseError> {
+        // Parameter types are printed with a fresh printer, and hence parsed with a fresh builder
+        let tys = params
+            .iter()
+            .map(|(_, ty)| Builder::default().ty(ty))
+            .collect::<Result<TyArr, _>>()?;
+        let region = Region::with(tys, parent)?;
+        for ((register, _), param) in params.iter().zip(region.params()) {
+            self.registers.insert(*register, param.into_val());
+        }
+        self.regions.push(region.clone());
+        let result = self.scope(body);
+        self.regions.pop();
+        let result = result?;
+        let value = match binder {
+            Binder::Lambda => Lambda::try_new(result, region)?.into_val(),
+            Binder::Pi => {
+                let result = result
+                    .try_into_ty()
+                    .map_err(|_| ParseError::Value(Error::NotATypeError))?;
+                let result = RegionParametrized::try_new(result, region)?;
+                Pi::with_lifetime(result, lifetime.clone())?.into_val()
+            }
+            Binder::Phi => {
+                // The members of a phi node are printed as a tuple
+                let values: ValArr = match result.as_enum() {
+                    ValueEnum::Tuple(tuple) => tuple.iter().cloned().collect(),
+                    _ => return Err(ParseError::Value(Error::TypeMismatch)),
+                };
+                Phi::try_new(values, region)?.into_val()
+            }
+        };
+        Ok(value)
+    }
+    /// Build a primitive form
+    fn form(&mut self, form: &Form) -> Result<ValId, ParseError> {
+        let invalid = ParseError::InvalidForm(form.offset);
+        let keyword = form.keyword;
+        let arity = match keyword {
+            "#id" => 3,
+            "#refl" | "#path_ind" | "#array_map" | "#array_fold" => 2,
+            _ => 1,
+        };
+        let named = keyword == "#effect" || keyword == "#extern";
+        let grouped = keyword == "#ref" || keyword == "#ref_mut";
+        if form.args.len() != arity
+            || form.name.is_some() != named
+            || (!form.groups.is_empty() && !grouped)
+            || (!form.attrs.is_empty() && keyword != "#extern")
+        {
+            return Err(invalid);
+        }
+        let args = &form.args[..];
+        let name = form.name.as_deref().unwrap_or("");
+        let value = match keyword {
+            "#id" => Id::try_new(self.build(&args[1])?, self.build(&args[2])?)?.into_val(),
+            "#refl" => Refl::refl(self.build(&args[1])?).into_val(),
+            "#id_family" => IdFamily::universal(self.kind(&args[0])?).into_val(),
+            "#path_ind" => {
+                let tys = match &args[0] {
+                    Expr::Tuple(tys) => tys
+                        .iter()
+                        .map(|ty| self.ty(ty))
+                        .collect::<Result<TyArr, _>>()?,
+                    Expr::Const(unit) if *unit == Tuple::unit().into_val() => TyArr::EMPTY,
+                    _ => return Err(invalid),
+                };
+                PathInd::try_new(tys, self.kind(&args[1])?)?.into_val()
+            }
+            "#ref" | "#ref_mut" => {
+                let referent = self.ty(&args[0])?;
+                let lender = self.group(form, "#lender")?;
+                let transient = self.group(form, "#transient")?;
+                let borrowed: Vec<ValId> = lender
+                    .iter()
+                    .chain(transient.iter())
+                    .flat_map(Group::values)
+                    .map(|value| value.clone_val())
+                    .collect();
+                let lifetime = Lifetime::from_deps(
+                    &Region::NULL,
+                    borrowed.iter().map(|value| (value, false)),
+                )?
+                .with_borrows(lender.as_ref(), transient.as_ref());
+                let kind = if keyword == "#ref" {
+                    RefKind::Shared
+                } else {
+                    RefKind::Unique
+                };
+                RefTy::try_new(referent, lifetime, kind)?.into_val()
+            }
+            "#borrow" => Borrow::borrow(self.build(&args[0])?, RefKind::Shared)?.into_val(),
+            "#borrow_mut" => Borrow::borrow(self.build(&args[0])?, RefKind::Unique)?.into_val(),
+            "#reborrow" => Borrow::reborrow(self.build(&args[0])?, RefKind::Shared)?.into_val(),
+            "#reborrow_mut" => Borrow::reborrow(self.build(&args[0])?, RefKind::Unique)?.into_val(),
+            "#deref" => Dereference::try_new(self.build(&args[0])?)?.into_val(),
+            "#alloc" => AllocTy::try_new(self.ty(&args[0])?)?.into_val(),
+            "#malloc" => MemOp::try_new(MemOpKind::Alloc, self.ty(&args[0])?)?.into_val(),
+            "#free" => MemOp::try_new(MemOpKind::Free, self.ty(&args[0])?)?.into_val(),
+            "#load" => MemOp::try_new(MemOpKind::Load, self.ty(&args[0])?)?.into_val(),
+            "#store" => MemOp::try_new(MemOpKind::Store, self.ty(&args[0])?)?.into_val(),
+            "#array_get" => ArrayOp::get(self.var(&args[0])?)?.into_val(),
+            "#array_set" => ArrayOp::set(self.var(&args[0])?)?.into_val(),
+            "#array_map" => ArrayOp::map(self.var(&args[0])?, self.ty(&args[1])?)?.into_val(),
+            "#array_fold" => ArrayOp::fold(self.var(&args[0])?, self.ty(&args[1])?)?.into_val(),
+            "#array_repeat" => ArrayOp::repeat(self.var(&args[0])?)?.into_val(),
+            "#effect" => {
+                // An effect is printed with its full type, from which its signature is recovered
+                let ty: VarId<Pi> = self.var(&args[0])?;
+                let result = match ty.result().as_enum() {
+                    ValueEnum::Product(product) if product.len() == 2 => product[1].clone(),
+                    _ => return Err(invalid),
+                };
+                let param_tys = ty.param_tys().get(1..).ok_or(invalid)?;
+                Effect::try_new(name, param_tys, result)?.into_val()
+            }
+            "#extern" => {
+                let mut linkage = Linkage::default();
+                for attr in form.attrs.iter() {
+                    match *attr {
+                        "#weak" => linkage.kind = LinkageKind::Weak,
+                        "#internal" => linkage.kind = LinkageKind::Internal,
+                        _ => linkage.conv = CallConv::Rain,
+                    }
+                }
+                Extern::try_new(name, self.var(&args[0])?, linkage)?.into_val()
+            }
+            _ => return Err(invalid),
+        };
+        Ok(value)
+    }
+    /// Build the group of values given to a form with a given keyword, if any
+    fn group(&mut self, form: &Form, keyword: &str) -> Result<Option<Group>, ParseError> {
+        let mut groups = Vec::new();
+        for (_, values) in form.groups.iter().filter(|(group, _)| *group == keyword) {
+            for value in values {
+                let group = Option::<Group>::from(self.build(value)?)
+                    .ok_or(ParseError::InvalidForm(form.offset))?;
+                groups.push(group);
+            }
+        }
+        Ok(Group::merge(groups.iter()))
+    }
+    /// Build a ternary operation from its selector type and branches
+    fn ternary(
+        &mut self,
+        offset: usize,
+        ty: &Expr,
+        arms: &[(Expr, Expr); 2],
+    ) -> Result<ValId, ParseError> {
+        let ty = self.build(ty)?;
+        let (high_pattern, low_pattern, switch) = if ty == Bool.into_val() {
+            (true.into_val(), false.into_val(), false)
+        } else if ty == Finite(2).into_val() {
+            let ix = |ix: u128| Finite(2).ix(ix).expect("Index in bounds").into_val();
+            (ix(1), ix(0), true)
+        } else {
+            return Err(ParseError::InvalidTernary(offset));
+        };
+        let mut high = None;
+        let mut low = None;
+        for (pattern, branch) in arms.iter() {
+            let pattern = self.build(pattern)?;
+            let slot = if pattern == high_pattern {
+                &mut high
+            } else if pattern == low_pattern {
+                &mut low
+            } else {
+                return Err(ParseError::InvalidTernary(offset));
+            };
+            if slot.replace(self.build(branch)?).is_some() {
+                return Err(ParseError::InvalidTernary(offset));
+            }
+        }
+        let (high, low) = match (high, low) {
+            (Some(high), Some(low)) => (high, low),
+            _ => return Err(ParseError::InvalidTernary(offset)),
+        };
+        let ternary = if switch {
+            Ternary::switch(high, low)?
+        } else {
+            Ternary::conditional(high, low)?
+        };
+        Ok(ternary.into_val())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::logical::{binary_ty, unary_ty};
+    use crate::typing::{Kind, Type};
+    use crate::{tyarr, valarr};
+
+    fn round_trip(value: &ValId) {
+        let text = format!("{}", value);
+        assert_eq!(parse(&text), Ok(value.clone()), "Parsing:\n{}", text);
+    }
+
+    #[test]
+    fn functions_round_trip() {
+        let region = Region::with(tyarr![Bool.into_ty(); 3], Region::NULL).unwrap();
+        let select = region.param(0).unwrap().into_val();
+        let high = region.param(1).unwrap().into_val();
+        let low = region.param(2).unwrap().into_val();
+        let ternary = Ternary::conditional(high, low).unwrap().into_val();
+        let mux_res = Sexpr::try_new(vec![ternary, select]).unwrap().into_val();
+        let mux = Lambda::try_new(mux_res, region).unwrap().into_val();
+        round_trip(&mux);
+        round_trip(&mux.ty().clone_val());
+
+        let bits = BitsTy(8).into_ty();
+        let region = Region::with(
+            tyarr![Finite(2).into_ty(), bits.clone(), bits],
+            Region::NULL,
+        )
+        .unwrap();
+        let select = region.param(0).unwrap().into_val();
+        let high = region.param(1).unwrap().into_val();
+        let low = region.param(2).unwrap().into_val();
+        let switch = Ternary::switch(high, low).unwrap().into_val();
+        let switch_res = Sexpr::try_new(vec![switch, select]).unwrap().into_val();
+        let switch = Lambda::try_new(switch_res, region).unwrap().into_val();
+        round_trip(&switch);
+
+        // A function returning a closure over its parameter, alongside a closed function
+        let outer = Region::unary(Bool.into_ty());
+        let x = outer.param(0).unwrap().into_val();
+        let inner = Region::unary_with(Bool.into_ty(), outer.clone()).unwrap();
+        let y = inner.param(0).unwrap().into_val();
+        let xor = Sexpr::try_new(vec![Xor.into_val(), x, y])
+            .unwrap()
+            .into_val();
+        let closure = Lambda::try_new(xor, inner).unwrap().into_val();
+        let id = Lambda::id(Bool.into_ty()).into_val();
+        let pair = Tuple::try_new(valarr![closure, id]).unwrap().into_val();
+        let curried = Lambda::try_new(pair, outer).unwrap().into_val();
+        round_trip(&curried);
+    }
+
+    #[test]
+    fn literals_round_trip() {
+        let byte = BitsTy(8).data(0x1f).unwrap().into_val();
+        let tuple = Tuple::try_new(valarr![true.into_val(), byte.clone()])
+            .unwrap()
+            .into_val();
+        let values = [
+            true.into_val(),
+            false.into_val(),
+            Bool.into_val(),
+            Finite(5).into_val(),
+            Finite(5).ix(3).unwrap().into_val(),
+            BitsKind.into_val(),
+            BitsTy(8).into_val(),
+            byte.clone(),
+            BinOp::Mul.into_val(),
+            Neg.into_val(),
+            And.into_val(),
+            Logical::try_new(2, 0b0010).unwrap().into_val(),
+            Logical::try_new(3, 0b1001_0110).unwrap().into_val(),
+            Logical::try_new(5, 0xdead_beef).unwrap().into_val(),
+            Prop.into_val(),
+            Fin.into_val(),
+            Set::new(2).into_val(),
+            Tuple::unit().into_val(),
+            Product::unit_ty().into_val(),
+            Tuple::const_anchor().into_val(),
+            Product::anchor_ty().into_val(),
+            Product::linear_anchor_ty().into_val(),
+            tuple.ty().clone_val(),
+            tuple,
+        ];
+        for value in values.iter() {
+            round_trip(value);
+        }
+        assert_eq!(parse("8'h1f"), Ok(byte));
+    }
+
+    #[test]
+    fn phi_nodes_round_trip() {
+        // ping(b) = if b { pong(b) } else { #true }, pong(b) = ping(!b)
+        let fn_ty = unary_ty().clone_as_ty();
+        let rec = Region::with(tyarr![fn_ty.clone(), fn_ty], Region::NULL).unwrap();
+        let ping_rec = rec.param(0).unwrap().into_val();
+        let pong_rec = rec.param(1).unwrap().into_val();
+        let ping_region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+        let b = ping_region.param(0).unwrap().into_val();
+        let recurse = Sexpr::try_new(vec![pong_rec, b.clone()])
+            .unwrap()
+            .into_val();
+        let ternary = Ternary::conditional(recurse, true.into_val())
+            .unwrap()
+            .into_val();
+        let ping_res = Sexpr::try_new(vec![ternary, b]).unwrap().into_val();
+        let ping = Lambda::try_new(ping_res, ping_region).unwrap().into_val();
+        let pong_region = Region::with(tyarr![Bool.into_ty()], rec.clone()).unwrap();
+        let b = pong_region.param(0).unwrap().into_val();
+        let not_b = Sexpr::try_new(vec![Not.into_val(), b]).unwrap().into_val();
+        let pong_res = Sexpr::try_new(vec![ping_rec, not_b]).unwrap().into_val();
+        let pong = Lambda::try_new(pong_res, pong_region).unwrap().into_val();
+        let phi = Phi::try_new(valarr![ping, pong], rec).unwrap();
+        round_trip(&phi.project(1).unwrap());
+        round_trip(&phi.into_val());
+    }
+
+    #[test]
+    fn data_round_trips() {
+        let array = Array::try_new(valarr![true.into_val(), false.into_val()], Bool.into_ty())
+            .unwrap()
+            .into_val();
+        let empty = Array::try_new(ValArr::EMPTY, BitsTy(8).into_ty())
+            .unwrap()
+            .into_val();
+        let arr_ty = ArrayTy::new(Bool.into_ty(), 2).into_var();
+        let map = ArrayOp::map(arr_ty.clone(), BitsTy(1).into_ty())
+            .unwrap()
+            .into_val();
+        let get = ArrayOp::get(arr_ty.clone()).unwrap().into_val();
+        let repeat = ArrayOp::repeat(arr_ty).unwrap().into_val();
+        let borrow = Borrow::shared(array.clone()).unwrap().into_val();
+        let deref = Dereference::try_new(borrow.clone()).unwrap().into_val();
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let borrow_x = Borrow::shared(x).unwrap();
+        let borrow_ty = borrow_x.get_ty().clone_as_ty();
+        let deref_x = Dereference::try_new(borrow_x.into_val())
+            .unwrap()
+            .into_val();
+        let deref_fn = Lambda::try_new(deref_x, region.clone()).unwrap().into_val();
+        let borrow_pi = Pi::try_new(borrow_ty, region).unwrap().into_val();
+        let values = [
+            array,
+            empty,
+            map,
+            get,
+            repeat,
+            borrow.ty().clone_val(),
+            borrow,
+            deref,
+            deref_fn,
+            borrow_pi,
+            AllocTy::try_new(Bool.into_ty()).unwrap().into_val(),
+            MemOp::load(Bool.into_ty()).unwrap().into_val(),
+            Product::try_new_forced(tyarr![Bool.into_ty()], true, true)
+                .unwrap()
+                .into_val(),
+        ];
+        for value in values.iter() {
+            round_trip(value);
+        }
+    }
+
+    #[test]
+    fn borrowing_functions_round_trip() {
+        let region = Region::unary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let borrow_x = Borrow::shared(x).unwrap().into_val();
+        let borrowing = Lambda::try_new(borrow_x, region).unwrap();
+        assert_eq!(borrowing.lifetime_component(), &PiLifetime::borrows_from(0));
+        let borrowing_ty = borrowing.get_ty().clone_as_ty();
+        // A function taking a borrowing function as a parameter
+        let apply_region =
+            Region::with(tyarr![borrowing_ty.clone(), Bool.into_ty()], Region::NULL).unwrap();
+        let f = apply_region.param(0).unwrap().into_val();
+        let y = apply_region.param(1).unwrap().into_val();
+        let fy = Sexpr::try_new(vec![f, y]).unwrap().into_val();
+        let apply = Lambda::try_new(fy, apply_region).unwrap().into_val();
+        let lifetime = PiLifetime::new(smallvec::smallvec![1], smallvec::smallvec![0, 1]);
+        let both = Pi::with_lifetime(
+            RegionParametrized::try_new(Bool.into_ty(), Region::binary(Bool.into_ty())).unwrap(),
+            lifetime,
+        )
+        .unwrap()
+        .into_val();
+        let text = format!("{}", both);
+        assert!(text.contains("#lender[1]"), "Printed:\n{}", text);
+        assert!(text.contains("#transient[0 1]"), "Printed:\n{}", text);
+        let values = [
+            borrowing.into_val(),
+            borrowing_ty.into_val(),
+            apply.ty().clone_val(),
+            apply,
+            both,
+        ];
+        for value in values.iter() {
+            round_trip(value);
+        }
+    }
+
+    #[test]
+    fn proofs_and_declarations_round_trip() {
+        let linkage = Linkage {
+            kind: LinkageKind::Weak,
+            conv: CallConv::Rain,
+        };
+        let args = [Lambda::id(Bool.into_ty()).into_val(), true.into_val()];
+        let values = [
+            Id::try_new(true.into_val(), false.into_val())
+                .unwrap()
+                .into_val(),
+            Refl::refl(true.into_val()).into_val(),
+            IdFamily::universal(Fin.into_kind()).into_val(),
+            PathInd::try_new(tyarr![Bool.into_ty(); 2], Prop.into_kind())
+                .unwrap()
+                .into_val(),
+            Effect::try_new("read \"line\"\n", &[Bool.into_ty()], Bool.into_ty())
+                .unwrap()
+                .into_val(),
+            Extern::c_fn("nand", binary_ty()).unwrap().into_val(),
+            Extern::try_new("nand", binary_ty(), linkage)
+                .unwrap()
+                .into_val(),
+            Thunk::try_defer(&args, &mut None)
+                .unwrap()
+                .unwrap()
+                .into_val(),
+        ];
+        for value in values.iter() {
+            round_trip(value);
+        }
+    }
+
+    #[test]
+    fn let_statements_bind_registers() {
+        let text = format!(
+            "{kw} %0 {assign} {and}{delim}\n{kw} %1 {assign} {open}%0 {t}{close}{delim}\n%1",
+            kw = KEYWORD_LET,
+            assign = ASSIGN,
+            and = KEYWORD_AND,
+            delim = STATEMENT_DELIM,
+            open = TUPLE_OPEN,
+            close = TUPLE_CLOSE,
+            t = KEYWORD_TRUE,
+        );
+        let tuple = Tuple::try_new(valarr![And.into_val(), true.into_val()])
+            .unwrap()
+            .into_val();
+        assert_eq!(parse(&text), Ok(tuple));
+    }
+
+    #[test]
+    fn malformed_text_is_rejected() {
+        assert_eq!(parse("%7"), Err(ParseError::UndefinedRegister(7)));
+        assert_eq!(parse(TUPLE_OPEN), Err(ParseError::UnexpectedEof));
+        let two = format!("{} {}", KEYWORD_TRUE, KEYWORD_FALSE);
+        assert_eq!(
+            parse(&two),
+            Err(ParseError::UnexpectedToken(KEYWORD_TRUE.len() + 1))
+        );
+        let arity = format!("{}(1, 0b100)", KEYWORD_LOGICAL);
+        assert_eq!(parse(&arity), Err(ParseError::InvalidLiteral(0)));
+        let branches = format!(
+            "#gamma{}{}{} {{ {t} => {t}, {t} => {t} }}",
+            PARAM_OPEN,
+            KEYWORD_BOOL,
+            PARAM_CLOSE,
+            t = KEYWORD_TRUE
+        );
+        assert_eq!(parse(&branches), Err(ParseError::InvalidTernary(0)));
+        let weak = format!("(#deref {} #weak)", KEYWORD_TRUE);
+        assert_eq!(parse(&weak), Err(ParseError::InvalidForm(0)));
+        assert_eq!(parse("(#effect \"read"), Err(ParseError::UnexpectedEof));
+    }
+
+    /// Generate a random boolean expression in a given set of parameters
+    #[cfg(feature = "rand")]
+    fn random_boolean<R: rand::Rng>(rng: &mut R, params: &[ValId], depth: usize) -> ValId {
+        use crate::primitive::logical::LOGICAL_OP_ARITY_MASKS;
+        if depth == 0 || rng.gen_bool(0.25) {
+            return if rng.gen_bool(0.8) {
+                params[rng.gen_range(0, params.len())].clone()
+            } else {
+                rng.gen::<bool>().into_val()
+            };
+        }
+        if rng.gen_bool(0.25) {
+            let high = random_boolean(rng, params, depth - 1);
+            let low = random_boolean(rng, params, depth - 1);
+            let select = random_boolean(rng, params, depth - 1);
+            if high == low {
+                // Constant ternary operations are normalized to constant functions
+                return high;
+            }
+            let ternary = Ternary::conditional(high, low).unwrap().into_val();
+            return Sexpr::try_new(vec![ternary, select]).unwrap().into_val();
+        }
+        let arity: u8 = rng.gen_range(1, 4);
+        let data = rng.gen::<u128>() & LOGICAL_OP_ARITY_MASKS[arity as usize];
+        let mut args = vec![Logical::try_new(arity, data).unwrap().into_val()];
+        for _ in 0..arity {
+            args.push(random_boolean(rng, params, depth - 1));
+        }
+        Sexpr::try_new(args).unwrap().into_val()
+    }
+
+    /// Randomly generated boolean functions round-trip through the prettyprinter and parser
+    #[cfg(feature = "rand")]
+    #[test]
+    fn random_boolean_functions_round_trip() {
+        use rand::{Rng, SeedableRng};
+        use rand_xoshiro::Xoroshiro128PlusPlus as TestRng;
+        const TEST_SEED: u64 = 0x3c6e_f372_fe94_f82b;
+        const FUNCTIONS_TO_TEST: usize = 100;
+        const MAX_DEPTH: usize = 4;
+        let mut rng = TestRng::seed_from_u64(TEST_SEED);
+        for _ in 0..FUNCTIONS_TO_TEST {
+            let region = Region::nary(Bool.into_ty(), rng.gen_range(1, 4));
+            let params: Vec<_> = region.params().map(|param| param.into_val()).collect();
+            let result = random_boolean(&mut rng, &params, MAX_DEPTH);
+            let function = Lambda::try_new(result, region).unwrap().into_val();
+            round_trip(&function);
+            round_trip(&function.ty().clone_val());
+        }
+    }
+}
@@ -199,6 +199,7 @@ impl Index<u32> for Bits {
 
 /// Bitvector operations
 #[derive(Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinOp {
     /// Bitvector addition
     Add,
@@ -245,7 +246,12 @@ impl BinOp {
 }
 
 debug_from_display!(BinOp);
-quick_pretty!(BinOp, "#BinOp");
+quick_pretty!(BinOp, b, fmt => write!(fmt, "{}", match b {
+    BinOp::Add => "#add",
+    BinOp::Sub => "#sub",
+    BinOp::Mod => "#mod",
+    BinOp::Mul => "#mul",
+}));
 trivial_substitute!(BinOp);
 enum_convert! {
     impl InjectionRef<ValueEnum> for BinOp {}
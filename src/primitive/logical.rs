@@ -615,7 +615,9 @@ impl Apply for Logical {
         // Evaluate
         for (i, arg) in args.iter().enumerate() {
             if arg.ty() != TypeId::from(Bool) {
-                return Err(Error::TypeMismatch);
+                return Err(Error::type_mismatch(Bool.into_ty(), arg.clone_ty())
+                    .with_value(arg.clone())
+                    .at_arg(i));
             }
             let ap = if cut_ix == i {
                 match arg.as_enum() {
@@ -28,6 +28,16 @@ impl PathInd {
             ty,
         })
     }
+    /// Get the base types over which path induction is being performed
+    #[inline]
+    pub fn base_tys(&self) -> &TyArr {
+        &self.base_tys
+    }
+    /// Get the target kind over which path induction is being performed
+    #[inline]
+    pub fn target(&self) -> &KindId {
+        &self.target
+    }
     /// Get the type of path induction for a given base type
     pub fn compute_ty(base_ty: TyArr, target: KindId) -> Result<Pi, Error> {
         let family_ty = Self::compute_family_ty(base_ty.clone(), target)?.into_var();
@@ -192,15 +202,27 @@ impl From<PathInd> for NormalValue {
 mod prettyprint_impl {
     use super::*;
     use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use crate::tokens::*;
     use std::fmt::{self, Display, Formatter};
 
     impl PrettyPrint for PathInd {
         fn prettyprint<I: From<usize> + Display>(
             &self,
-            _printer: &mut PrettyPrinter<I>,
+            printer: &mut PrettyPrinter<I>,
             fmt: &mut Formatter,
         ) -> Result<(), fmt::Error> {
-            write!(fmt, "(path induction prettyprinting unimplemented)")
+            write!(fmt, "(#path_ind {}", TUPLE_OPEN)?;
+            let mut first = true;
+            for ty in self.base_tys.iter() {
+                if !first {
+                    write!(fmt, " ")?;
+                }
+                first = false;
+                ty.prettyprint(printer, fmt)?;
+            }
+            write!(fmt, "{} ", TUPLE_CLOSE)?;
+            self.target.prettyprint(printer, fmt)?;
+            write!(fmt, ")")
         }
     }
 }
@@ -330,6 +330,11 @@ impl Refl {
         let region = value.clone_region();
         Refl { value, ty, region }
     }
+    /// Get the value this instance of the reflexivity axiom is over
+    #[inline]
+    pub fn value(&self) -> &ValId {
+        &self.value
+    }
 }
 
 impl Typed for Refl {
@@ -415,10 +420,12 @@ mod prettyprint_impl {
     impl PrettyPrint for IdFamily {
         fn prettyprint<I: From<usize> + Display>(
             &self,
-            _printer: &mut PrettyPrinter<I>,
+            printer: &mut PrettyPrinter<I>,
             fmt: &mut Formatter,
         ) -> Result<(), fmt::Error> {
-            write!(fmt, "#id")
+            write!(fmt, "(#id_family ")?;
+            self.ty.param_tys()[0].prettyprint(printer, fmt)?;
+            write!(fmt, ")")
         }
     }
 
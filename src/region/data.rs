@@ -46,8 +46,12 @@ impl RegionData {
         let mut universe = None;
         for param_ty in param_tys.iter() {
             match param_ty.region().partial_cmp(&parent.region()) {
-                None => return Err(Error::IncomparableRegions),
-                Some(Greater) => return Err(Error::IncomparableRegions),
+                None | Some(Greater) => {
+                    return Err(Error::incomparable_regions(
+                        param_ty.clone_region(),
+                        parent.clone(),
+                    ))
+                }
                 _ => {}
             }
             let param_universe = param_ty.universe();
@@ -104,7 +108,7 @@ impl RegionData {
             let universe = ty.clone_universe();
             Ok(Self::with_unchecked(once(ty).collect(), parent, universe))
         } else {
-            Err(Error::IncomparableRegions)
+            Err(Error::incomparable_regions(ty.clone_region(), parent))
         }
     }
     /// Get the minimal region for an n-ary operator over a given type. Never fails
@@ -125,7 +129,7 @@ impl RegionData {
                 universe,
             ))
         } else {
-            Err(Error::IncomparableRegions)
+            Err(Error::incomparable_regions(ty.clone_region(), parent))
         }
     }
     /// Get the minimal region for a binary operator over a given type. Never fails
@@ -494,20 +494,26 @@ impl<'a> RegionBorrow<'a> {
     }
     /// Get the greatest region containing this object and another, if any
     #[inline]
-    fn get_gcr(self, other: RegionBorrow<'a>) -> Result<RegionBorrow<'a>, Error> {
+    pub fn get_gcr(self, other: RegionBorrow<'a>) -> Result<RegionBorrow<'a>, Error> {
         match self.partial_cmp(&other) {
             Some(Ordering::Less) => Ok(other),
             Some(_) => Ok(self),
-            _ => Err(Error::IncomparableRegions),
+            _ => Err(Error::incomparable_regions(
+                self.clone_region(),
+                other.clone_region(),
+            )),
         }
     }
     /// Get the greatest region containing this object and another, if any
     #[inline]
-    fn get_lcr(self, other: RegionBorrow<'a>) -> Result<RegionBorrow<'a>, Error> {
+    pub fn get_lcr(self, other: RegionBorrow<'a>) -> Result<RegionBorrow<'a>, Error> {
         match self.partial_cmp(&other) {
             Some(Ordering::Greater) => Ok(other),
             Some(_) => Ok(self),
-            _ => Err(Error::IncomparableRegions),
+            _ => Err(Error::incomparable_regions(
+                self.clone_region(),
+                other.clone_region(),
+            )),
         }
     }
 }
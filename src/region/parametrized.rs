@@ -25,7 +25,12 @@ impl<V: Value + Clone> Parametrized<V> {
         use Ordering::*;
         let depth = region.depth();
         let deps: ValSet = match value.region().partial_cmp(&region) {
-            None => return Err(Error::IncomparableRegions),
+            None => {
+                return Err(Error::incomparable_regions(
+                    value.clone_region(),
+                    region.clone(),
+                ))
+            }
             Some(Greater) => return Err(Error::NestedResult),
             Some(Equal) => {
                 let mut results = Vec::new();
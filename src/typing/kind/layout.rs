@@ -3,3 +3,168 @@ Layouts of data in memory
 
 Heavily inspired by Rust's `alloc` interface, except with more support for the specification of uninitialized bytes
 */
+use crate::value::{ValId, ValueEnum};
+use std::convert::TryFrom;
+
+/// The layout of a value in memory, given as a size and an alignment in bytes
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Layout {
+    /// The size of this layout, in bytes
+    size: u64,
+    /// The alignment of this layout, in bytes. Always a power of two
+    align: u64,
+}
+
+impl Layout {
+    /// The layout of a zero-sized value
+    pub const ZERO: Layout = Layout { size: 0, align: 1 };
+    /// The layout of a pointer on the host
+    pub const POINTER: Layout = Layout {
+        size: std::mem::size_of::<usize>() as u64,
+        align: std::mem::align_of::<usize>() as u64,
+    };
+    /// The maximum alignment of a scalar value
+    pub const MAX_SCALAR_ALIGN: u64 = 16;
+    /// Create a new layout from a size and alignment. Return `None` if the alignment is not a power of two.
+    #[inline]
+    pub fn from_size_align(size: u64, align: u64) -> Option<Layout> {
+        if align.is_power_of_two() {
+            Some(Layout { size, align })
+        } else {
+            None
+        }
+    }
+    /// Get the layout of a scalar value with a given number of bits
+    ///
+    /// The size of a nonzero scalar is rounded up to a power of two number of bytes, which is also its alignment
+    /// (up to `MAX_SCALAR_ALIGN`).
+    pub fn bits(bits: u64) -> Layout {
+        if bits == 0 {
+            return Layout::ZERO;
+        }
+        let size = ((bits + 7) / 8).next_power_of_two();
+        Layout {
+            size,
+            align: size.min(Self::MAX_SCALAR_ALIGN),
+        }
+    }
+    /// Get the size of this layout, in bytes
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    /// Get the alignment of this layout, in bytes
+    #[inline]
+    pub fn align(&self) -> u64 {
+        self.align
+    }
+    /// Get the number of padding bytes needed after this layout to align the following value to `align`
+    #[inline]
+    pub fn padding_needed_for(&self, align: u64) -> u64 {
+        let rounded = (self.size + align - 1) & !(align - 1);
+        rounded - self.size
+    }
+    /// Pad the size of this layout to a multiple of its alignment
+    #[inline]
+    pub fn pad_to_align(&self) -> Layout {
+        Layout {
+            size: self.size + self.padding_needed_for(self.align),
+            align: self.align,
+        }
+    }
+    /// Extend this layout with another, C-style, returning the combined layout and the offset of the new member.
+    /// Return `None` on overflow.
+    pub fn extend(&self, next: Layout) -> Option<(Layout, u64)> {
+        let offset = self.size.checked_add(self.padding_needed_for(next.align))?;
+        let size = offset.checked_add(next.size)?;
+        let layout = Layout {
+            size,
+            align: self.align.max(next.align),
+        };
+        Some((layout, offset))
+    }
+    /// Get the layout of an array of `n` values of this layout. Return `None` on overflow.
+    pub fn array(&self, n: u64) -> Option<Layout> {
+        let size = self.pad_to_align().size.checked_mul(n)?;
+        Some(Layout {
+            size,
+            align: self.align,
+        })
+    }
+    /// Compute the layout of the values of a `rain` type, if it is representable
+    pub fn of(ty: &ValId) -> Option<Layout> {
+        match ty.as_enum() {
+            ValueEnum::BoolTy(_) => Some(Layout::bits(1)),
+            ValueEnum::Finite(f) => {
+                let bits = 128 - f.0.saturating_sub(1).leading_zeros();
+                Some(Layout::bits(bits as u64))
+            }
+            ValueEnum::BitsTy(b) => Some(Layout::bits(b.0 as u64)),
+            ValueEnum::Product(p) => {
+                let mut layout = Layout::ZERO;
+                for elem in p.iter() {
+                    layout = layout.extend(Layout::of(elem.as_val())?)?.0;
+                }
+                Some(layout.pad_to_align())
+            }
+            ValueEnum::ArrayTy(a) => {
+                Layout::of(a.elem().as_val())?.array(u64::try_from(a.len()).ok()?)
+            }
+            ValueEnum::World(_) => Some(Layout::ZERO),
+            ValueEnum::RefTy(_) | ValueEnum::AllocTy(_) => Some(Layout::POINTER),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::{bits::BitsTy, finite::Finite, logical::Bool};
+    use crate::tyarr;
+    use crate::typing::Type;
+    use crate::value::{tuple::Product, TypeId, Value};
+
+    #[test]
+    fn primitive_layouts() {
+        assert_eq!(Layout::of(&Bool.into_val()), Layout::from_size_align(1, 1));
+        assert_eq!(Layout::of(&Finite(1).into_val()), Some(Layout::ZERO));
+        assert_eq!(
+            Layout::of(&Finite(256).into_val()),
+            Layout::from_size_align(1, 1)
+        );
+        assert_eq!(
+            Layout::of(&Finite(257).into_val()),
+            Layout::from_size_align(2, 2)
+        );
+        assert_eq!(
+            Layout::of(&BitsTy(24).into_val()),
+            Layout::from_size_align(4, 4)
+        );
+        assert_eq!(
+            Layout::of(&BitsTy(128).into_val()),
+            Layout::from_size_align(16, 16)
+        );
+        assert_eq!(Layout::from_size_align(3, 3), None);
+    }
+
+    #[test]
+    fn product_layouts() {
+        let product = Product::try_new(tyarr![
+            Bool.into_ty(),
+            Finite(1 << 32).into_ty(),
+            Bool.into_ty()
+        ])
+        .unwrap()
+        .into_val();
+        assert_eq!(Layout::of(&product), Layout::from_size_align(12, 4));
+        assert_eq!(
+            Layout::of(&Product::anchor_ty().into_val()),
+            Some(Layout::ZERO)
+        );
+        let (layout, offset) = Layout::bits(8).extend(Layout::bits(64)).unwrap();
+        assert_eq!(offset, 8);
+        assert_eq!(layout, Layout::from_size_align(16, 8).unwrap());
+        assert_eq!(Layout::bits(24).array(3), Layout::from_size_align(12, 4));
+    }
+}
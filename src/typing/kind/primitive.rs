@@ -48,6 +48,11 @@ impl Set {
             succ: OnceCell::new(),
         }
     }
+    /// Get the level of this kind, i.e. the `n` of the n-sets
+    #[inline]
+    pub fn level(&self) -> usize {
+        self.n
+    }
 }
 // Constants:
 
@@ -285,6 +285,12 @@ impl<'a, P: TypePredicate> Type for NormalValue<P> {
             ValueEnum::Fin(u) => u.is_affine(),
             ValueEnum::Set(u) => u.is_affine(),
             ValueEnum::Product(p) => p.is_affine(),
+            ValueEnum::RefTy(r) => r.is_affine(),
+            ValueEnum::AllocTy(a) => a.is_affine(),
+            ValueEnum::World(w) => w.is_affine(),
+            ValueEnum::ArrayTy(a) => a.is_affine(),
+            // A type which fails to force, e.g. for lack of fuel, is conservatively assumed substructural
+            ValueEnum::Thunk(t) => t.force_ty().map_or(true, |ty| ty.is_affine()),
             ValueEnum::Parameter(p) => {
                 unimplemented!("Parameter affinity check for parameter {}", p)
             }
@@ -307,6 +313,11 @@ impl<'a, P: TypePredicate> Type for NormalValue<P> {
             ValueEnum::Fin(u) => u.is_relevant(),
             ValueEnum::Set(u) => u.is_relevant(),
             ValueEnum::Product(p) => p.is_relevant(),
+            ValueEnum::RefTy(r) => r.is_relevant(),
+            ValueEnum::AllocTy(a) => a.is_relevant(),
+            ValueEnum::World(w) => w.is_relevant(),
+            ValueEnum::ArrayTy(a) => a.is_relevant(),
+            ValueEnum::Thunk(t) => t.force_ty().map_or(true, |ty| ty.is_relevant()),
             ValueEnum::Parameter(p) => {
                 unimplemented!("Parameter relevance check for parameter {}", p)
             }
@@ -329,6 +340,11 @@ impl<'a, P: TypePredicate> Type for NormalValue<P> {
             ValueEnum::Fin(u) => u.is_linear(),
             ValueEnum::Set(u) => u.is_linear(),
             ValueEnum::Product(p) => p.is_linear(),
+            ValueEnum::RefTy(r) => r.is_linear(),
+            ValueEnum::AllocTy(a) => a.is_linear(),
+            ValueEnum::World(w) => w.is_linear(),
+            ValueEnum::ArrayTy(a) => a.is_linear(),
+            ValueEnum::Thunk(t) => t.force_ty().map_or(true, |ty| ty.is_linear()),
             ValueEnum::Parameter(p) => {
                 unimplemented!("Parameter linearity check for parameter {}", p)
             }
@@ -351,6 +367,11 @@ impl<'a, P: TypePredicate> Type for NormalValue<P> {
             ValueEnum::Fin(u) => u.is_substruct(),
             ValueEnum::Set(u) => u.is_substruct(),
             ValueEnum::Product(p) => p.is_substruct(),
+            ValueEnum::RefTy(r) => r.is_substruct(),
+            ValueEnum::AllocTy(a) => a.is_substruct(),
+            ValueEnum::World(w) => w.is_substruct(),
+            ValueEnum::ArrayTy(a) => a.is_substruct(),
+            ValueEnum::Thunk(t) => t.force_ty().map_or(true, |ty| ty.is_substruct()),
             ValueEnum::Parameter(p) => {
                 unimplemented!("Parameter substructurality check for parameter {}", p)
             }
@@ -373,6 +394,11 @@ impl<'a, P: TypePredicate> Type for NormalValue<P> {
             ValueEnum::Fin(u) => u.apply_ty(args),
             ValueEnum::Set(u) => u.apply_ty(args),
             ValueEnum::Product(p) => p.apply_ty(args),
+            ValueEnum::RefTy(r) => r.apply_ty(args),
+            ValueEnum::AllocTy(a) => a.apply_ty(args),
+            ValueEnum::World(w) => w.apply_ty(args),
+            ValueEnum::ArrayTy(a) => a.apply_ty(args),
+            ValueEnum::Thunk(t) => t.force_ty()?.apply_ty(args),
             ValueEnum::Parameter(p) => unimplemented!("Parameter application for parameter {}", p),
             ValueEnum::Sexpr(s) => unimplemented!("Partial evaluation application for sexpr {}", s),
             v => panic!(
@@ -391,6 +417,11 @@ impl<'a, P: TypePredicate> Type for NormalValue<P> {
             ValueEnum::Fin(u) => u.apply_ty_in(args, ctx),
             ValueEnum::Set(u) => u.apply_ty_in(args, ctx),
             ValueEnum::Product(p) => p.apply_ty_in(args, ctx),
+            ValueEnum::RefTy(r) => r.apply_ty_in(args, ctx),
+            ValueEnum::AllocTy(a) => a.apply_ty_in(args, ctx),
+            ValueEnum::World(w) => w.apply_ty_in(args, ctx),
+            ValueEnum::ArrayTy(a) => a.apply_ty_in(args, ctx),
+            ValueEnum::Thunk(t) => t.force_ty_in(ctx)?.apply_ty_in(args, ctx),
             ValueEnum::Parameter(p) => {
                 unimplemented!("Parameter contextual application for parameter {}", p)
             }
@@ -1,6 +1,28 @@
-/// An evaluation error
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum Error {
+/*!
+Errors arising during the construction and evaluation of `rain` values
+*/
+use super::{TypeId, ValId};
+use crate::pretty_display;
+use crate::region::Region;
+use std::fmt::{self, Debug, Formatter};
+
+/// Declare the kinds of evaluation error, along with a context-free `Error` constant for each
+macro_rules! error_kinds {
+    ($($(#[$attr:meta])* $kind:ident,)*) => {
+        /// The kind of an evaluation error, which is cheap to copy and match on
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+        pub enum ErrorKind {
+            $($(#[$attr])* $kind,)*
+        }
+
+        #[allow(non_upper_case_globals)]
+        impl Error {
+            $($(#[$attr])* pub const $kind: Error = Error::new(ErrorKind::$kind);)*
+        }
+    };
+}
+
+error_kinds! {
     /// Attempting to apply a non-function
     NotAFunction,
     /// Attempting to apply a non-function type
@@ -29,6 +51,8 @@ pub enum Error {
     EvalError,
     /// Tuple length mismatch
     TupleLengthMismatch,
+    /// Array length mismatch
+    ArrayLengthMismatch,
     /// Empty sexpr application
     EmptySexprApp,
     /// No inlining violation
@@ -44,6 +68,8 @@ pub enum Error {
     /// Too many arguments for a (non-curried!) function
     /// (or sometimes an object which *may* be a function)
     TooManyArgs,
+    /// Too few arguments for a function which must be fully applied
+    TooFewArgs,
     /// A pattern match failure
     MatchFailure,
     /// An incomplete match statement
@@ -66,4 +92,246 @@ pub enum Error {
     IncomparableSub,
     /// A symbol has been re-defined in an evaluation
     InvalidRedef,
+    /// Tried to dereference a reference to a non-representable type
+    NonReprDeref,
+    /// Tried to allocate or access memory for a type without a layout
+    NoLayout,
+    /// Tried to allocate or access memory for a relevant type, whose values freeing an allocation would discard
+    RelevantAlloc,
+    /// Tried to declare an external function with an open type
+    OpenDeclaration,
+    /// Tried to call an external function with no bound implementation
+    UnboundExtern,
+    /// Tried to interpret a value which is not closed
+    NotClosed,
+    /// An evaluation ran out of fuel
+    OutOfFuel,
+    /// An evaluation exceeded its maximum region depth
+    RegionTooDeep,
+    /// An interpretation exceeded its maximum call depth
+    CallTooDeep,
+    /// A serialized value graph is malformed
+    InvalidGraph,
+}
+
+/**
+An evaluation error
+
+Consists of an [`ErrorKind`](ErrorKind), along with optional context describing the values, types and regions involved.
+Errors without context are available as constants, e.g. `Error::TypeMismatch`.
+*/
+#[derive(Clone, Eq, PartialEq)]
+pub struct Error {
+    /// The kind of this error
+    kind: ErrorKind,
+    /// The context of this error, if any
+    context: Option<Box<ErrorContext>>,
+}
+
+/// The context of an evaluation error
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct ErrorContext {
+    /// The type which was expected, if any
+    pub expected: Option<TypeId>,
+    /// The type which was found, if any
+    pub actual: Option<TypeId>,
+    /// The offending value, if any
+    pub value: Option<ValId>,
+    /// The index of the offending argument, if any
+    pub arg: Option<usize>,
+    /// The regions which were found to be incomparable, if any
+    pub regions: Option<(Region, Region)>,
+}
+
+impl Error {
+    /// Create a new error of a given kind, without context
+    #[inline]
+    pub const fn new(kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            context: None,
+        }
+    }
+    /// Create a type mismatch between an expected and an actual type
+    #[inline]
+    pub fn type_mismatch(expected: TypeId, actual: TypeId) -> Error {
+        Error::TypeMismatch.with_types(expected, actual)
+    }
+    /// Create an error for a pair of incomparable regions
+    #[inline]
+    pub fn incomparable_regions(left: Region, right: Region) -> Error {
+        Error::IncomparableRegions.with_regions(left, right)
+    }
+    /// Get the kind of this error
+    #[inline]
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+    /// Get the context of this error, if any
+    #[inline]
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_deref()
+    }
+    /// Get a mutable reference to the context of this error, creating an empty context if there is none
+    #[inline]
+    pub fn context_mut(&mut self) -> &mut ErrorContext {
+        self.context.get_or_insert_with(Default::default)
+    }
+    /// Attach an expected and actual type to this error
+    #[inline]
+    pub fn with_types(mut self, expected: TypeId, actual: TypeId) -> Error {
+        let context = self.context_mut();
+        context.expected = Some(expected);
+        context.actual = Some(actual);
+        self
+    }
+    /// Attach the offending value to this error
+    #[inline]
+    pub fn with_value(mut self, value: ValId) -> Error {
+        self.context_mut().value = Some(value);
+        self
+    }
+    /// Attach the index of the offending argument to this error
+    #[inline]
+    pub fn at_arg(mut self, arg: usize) -> Error {
+        self.context_mut().arg = Some(arg);
+        self
+    }
+    /// Attach a pair of incomparable regions to this error
+    #[inline]
+    pub fn with_regions(mut self, left: Region, right: Region) -> Error {
+        self.context_mut().regions = Some((left, right));
+        self
+    }
+    /// Get the offending value of this error, if any
+    #[inline]
+    pub fn value(&self) -> Option<&ValId> {
+        self.context().and_then(|context| context.value.as_ref())
+    }
+}
+
+impl From<ErrorKind> for Error {
+    #[inline]
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind)
+    }
+}
+
+impl PartialEq<ErrorKind> for Error {
+    #[inline]
+    fn eq(&self, kind: &ErrorKind) -> bool {
+        self.kind == *kind
+    }
+}
+
+impl Debug for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        if let Some(context) = &self.context {
+            write!(fmt, "{:?}({:?})", self.kind, context)
+        } else {
+            write!(fmt, "{:?}", self.kind)
+        }
+    }
+}
+
+pretty_display!(Error, s, fmt => write!(fmt, "{:?}", s.kind));
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use crate::region::Regional;
+    use std::fmt::Display;
+
+    /// Prettyprint a region as its depth and parameter types
+    fn prettyprint_region<I: From<usize> + Display>(
+        region: &Region,
+        printer: &mut PrettyPrinter<I>,
+        fmt: &mut Formatter,
+    ) -> Result<(), fmt::Error> {
+        write!(fmt, "#region<{}>(", region.depth())?;
+        for (ix, ty) in region.param_tys().iter().enumerate() {
+            if ix != 0 {
+                write!(fmt, " ")?;
+            }
+            ty.prettyprint(printer, fmt)?;
+        }
+        write!(fmt, ")")
+    }
+
+    impl PrettyPrint for Error {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "{:?}", self.kind)?;
+            let context = if let Some(context) = &self.context {
+                context
+            } else {
+                return Ok(());
+            };
+            if let Some(arg) = context.arg {
+                write!(fmt, " at argument {}", arg)?;
+            }
+            if let Some(value) = &context.value {
+                write!(fmt, " in ")?;
+                value.prettyprint(printer, fmt)?;
+            }
+            if let Some(expected) = &context.expected {
+                write!(fmt, ": expected ")?;
+                expected.prettyprint(printer, fmt)?;
+                if let Some(actual) = &context.actual {
+                    write!(fmt, ", found ")?;
+                    actual.prettyprint(printer, fmt)?;
+                }
+            } else if let Some(actual) = &context.actual {
+                write!(fmt, ": found ")?;
+                actual.prettyprint(printer, fmt)?;
+            }
+            if let Some((left, right)) = &context.regions {
+                write!(fmt, ": ")?;
+                prettyprint_region(left, printer, fmt)?;
+                write!(fmt, " and ")?;
+                prettyprint_region(right, printer, fmt)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::logical::Bool;
+    use crate::primitive::UNIT_TY;
+    use crate::typing::Type;
+    use crate::value::Value;
+
+    #[test]
+    fn errors_carry_context() {
+        assert_eq!(Error::TypeMismatch.kind(), ErrorKind::TypeMismatch);
+        assert_eq!(Error::TypeMismatch.context(), None);
+        assert_eq!(Error::from(ErrorKind::OutOfFuel), Error::OutOfFuel);
+        assert_eq!(
+            std::mem::size_of::<Error>(),
+            2 * std::mem::size_of::<usize>()
+        );
+
+        let error = Error::type_mismatch(Bool.into_ty(), UNIT_TY.as_ty().clone())
+            .with_value(true.into_val())
+            .at_arg(1);
+        assert_eq!(error, ErrorKind::TypeMismatch);
+        assert_ne!(error, Error::TypeMismatch);
+        let context = error.context().unwrap();
+        assert_eq!(context.arg, Some(1));
+        assert_eq!(context.expected, Some(Bool.into_ty()));
+        assert_eq!(error.value(), Some(&true.into_val()));
+        #[cfg(feature = "prettyprinter")]
+        {
+            let rendered = format!("{}", error);
+            assert!(rendered.starts_with("TypeMismatch at argument 1"));
+            assert!(rendered.contains("expected"), "Rendered: {}", rendered);
+        }
+    }
 }
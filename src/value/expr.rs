@@ -1,12 +1,16 @@
 /*!
 `rain` expressions
 */
-use super::{arr::ValArr, Error, NormalValue, TypeId, TypeRef, ValId, Value, ValueData, ValueEnum};
+use super::{
+    arr::ValArr, thunk::Thunk, Error, NormalValue, TypeId, TypeRef, ValId, Value, ValueData,
+    ValueEnum,
+};
 use crate::enum_convert;
-use crate::eval::{Application, Apply, EvalCtx, Substitute};
-use crate::lifetime::{LifetimeBorrow, Live};
+use crate::eval::{Application, Apply, EvalCtx, Strategy, Substitute};
+use crate::function::pi::Pi;
+use crate::lifetime::{Lifetime, LifetimeBorrow, Live};
 use crate::primitive::UNIT_TY;
-use crate::region::{Region, Regional};
+use crate::region::Regional;
 use crate::typing::Typed;
 use crate::{debug_from_display, pretty_display, substitute_to_valid, valarr};
 use std::ops::Deref;
@@ -16,8 +20,8 @@ use std::ops::Deref;
 pub struct Sexpr {
     /// The arguments of this S-expression
     pub(super) args: ValArr,
-    /// The (cached) region of this S-expression
-    pub(super) region: Region,
+    /// The (cached) lifetime of this S-expression
+    pub(super) lifetime: Lifetime,
     /// The (cached) type of this S-expression
     pub(super) ty: TypeId,
 }
@@ -33,36 +37,102 @@ enum_convert! {
 
 impl Sexpr {
     /// Create a new S-expression fron unchecked components
-    pub(crate) fn new_unchecked(args: ValArr, region: Region, ty: TypeId) -> Sexpr {
-        Sexpr { args, region, ty }
+    pub(crate) fn new_unchecked(args: ValArr, lifetime: Lifetime, ty: TypeId) -> Sexpr {
+        Sexpr { args, lifetime, ty }
+    }
+    /// Compute the lifetime of an S-expression with a given type and argument list
+    ///
+    /// Arguments which the applied function's result borrows from, as given by its pi type's lifetime component, are
+    /// borrowed rather than consumed by the S-expression. References passed to reference parameters which the result
+    /// does not borrow from are only used during the application, and hence contribute only their region.
+    pub(crate) fn args_lifetime(ty: &TypeId, args: &[ValId]) -> Result<Lifetime, Error> {
+        let f_ty = args.first().map(|f| f.ty());
+        let pi = match f_ty.as_ref().map(|f_ty| f_ty.as_enum()) {
+            Some(ValueEnum::Pi(pi)) => Some(pi),
+            _ => None,
+        };
+        let component = pi.map(Pi::lifetime_component);
+        let owned = |ix: usize| ix == 0 || component.map(|c| !c.borrows(ix - 1)).unwrap_or(true);
+        let scoped = |ix: usize| {
+            ix != 0
+                && owned(ix)
+                && match pi.and_then(|pi| pi.param_tys().get(ix - 1)) {
+                    Some(param_ty) => match param_ty.as_enum() {
+                        ValueEnum::RefTy(_) => true,
+                        _ => false,
+                    },
+                    None => false,
+                }
+        };
+        let mut base = ty.clone_region();
+        for (_, arg) in args.iter().enumerate().filter(|(ix, _)| scoped(*ix)) {
+            let gcr = base.gcr(arg)?.clone_region();
+            base = gcr;
+        }
+        let lifetime = Lifetime::from_deps(
+            &base,
+            args.iter()
+                .enumerate()
+                .filter(|(ix, _)| !scoped(*ix))
+                .map(|(ix, arg)| (arg, owned(ix))),
+        )?;
+        match component {
+            Some(component) if args.len() > 1 => {
+                let (lender, transient) = component.instantiate(&args[1..]);
+                Ok(lifetime.with_borrows(lender.as_ref(), transient.as_ref()))
+            }
+            _ => Ok(lifetime),
+        }
     }
     /// Attempt to create an S-expression from an owned argument list, evaluating as necessary.
-    pub fn try_new(mut args: Vec<ValId>) -> Result<Sexpr, Error> {
+    #[inline]
+    pub fn try_new(args: Vec<ValId>) -> Result<Sexpr, Error> {
+        Self::try_new_in(args, &mut None)
+    }
+    /// Attempt to create an S-expression from an owned argument list, evaluating as necessary in a given context.
+    ///
+    /// Each application consumes a step of the context's budget, if any. Under the lazy normalization strategy,
+    /// applications which would beta-reduce are deferred into a [`Thunk`](Thunk) instead.
+    pub fn try_new_in(mut args: Vec<ValId>, ctx: &mut Option<EvalCtx>) -> Result<Sexpr, Error> {
         // Simple cases
         match args.len() {
             0 => return Ok(Self::unit()),
             1 => return Ok(Self::singleton(args.swap_remove(0))),
             _ => {}
         }
-        // Expand sexprs in the first argument
-        if let ValueEnum::Sexpr(s) = args[0].as_enum() {
-            if s.is_empty() {
+        let lazy = Strategy::of(ctx).is_lazy();
+        // Expand sexprs in the first argument, as well as thunks if evaluating lazily
+        let head = match args[0].as_enum() {
+            ValueEnum::Sexpr(s) if s.is_empty() => {
                 return Err(Error::EmptySexprApp); // Special error for unit application
             }
-            let mut new_args = Vec::with_capacity(args.len() + s.len());
-            new_args.extend(s.iter().cloned());
+            ValueEnum::Sexpr(s) => Some(&s[..]),
+            ValueEnum::Thunk(t) if lazy => Some(&t[..]),
+            _ => None,
+        };
+        if let Some(head) = head {
+            let mut new_args = Vec::with_capacity(args.len() + head.len());
+            new_args.extend(head.iter().cloned());
             new_args.extend(args.drain(1..));
             args = new_args;
         }
         // General case
-        let ty = match args[0].apply(&args[1..])? {
-            Application::Success(rest, valid) => return Self::applied_with(valid, rest),
+        if let Some(ctx) = ctx {
+            ctx.consume_fuel()?;
+        }
+        if lazy {
+            if let Some(thunk) = Thunk::try_defer(&args, ctx)? {
+                return Ok(Self::singleton(thunk.into_val()));
+            }
+        }
+        let ty = match args[0].apply_in(&args[1..], ctx)? {
+            Application::Success(rest, valid) => return Self::applied_with_in(valid, rest, ctx),
             Application::Symbolic(ty) => ty,
         };
-        let region = ty.gcrs(args.iter())?.clone_region();
+        let lifetime = Self::args_lifetime(&ty, &args)?;
         Ok(Sexpr {
             args: args.into(),
-            region,
+            lifetime,
             ty,
         })
     }
@@ -74,9 +144,24 @@ impl Sexpr {
         }
     }
     /// Attempt to create an S-expression by applying an argument to an argument list, evaluating as necessary.
-    pub fn applied_with(mut f: ValId, mut args: &[ValId]) -> Result<Sexpr, Error> {
+    #[inline]
+    pub fn applied_with(f: ValId, args: &[ValId]) -> Result<Sexpr, Error> {
+        Self::applied_with_in(f, args, &mut None)
+    }
+    /// Attempt to create an S-expression by applying an argument to an argument list, evaluating as necessary in a
+    /// given context.
+    ///
+    /// Each application consumes a step of the context's budget, if any.
+    pub fn applied_with_in(
+        mut f: ValId,
+        mut args: &[ValId],
+        ctx: &mut Option<EvalCtx>,
+    ) -> Result<Sexpr, Error> {
         while !args.is_empty() {
-            match f.apply(args)? {
+            if let Some(ctx) = ctx {
+                ctx.consume_fuel()?;
+            }
+            match f.apply_in(args, ctx)? {
                 Application::Success(rest, v) => {
                     args = rest;
                     f = v;
@@ -85,12 +170,12 @@ impl Sexpr {
                 Application::Symbolic(ty) => {
                     let mut a = Vec::with_capacity(1 + args.len());
                     a.push(f);
-                    a.clone_from_slice(args);
-                    let region = ty.gcrs(args.iter())?.clone_region();
+                    a.extend_from_slice(args);
+                    let lifetime = Self::args_lifetime(&ty, &a)?;
                     return Ok(Sexpr {
                         args: a.into(),
                         ty,
-                        region,
+                        lifetime,
                     });
                 }
             };
@@ -101,7 +186,7 @@ impl Sexpr {
     pub fn unit() -> Sexpr {
         Sexpr {
             args: ValArr::EMPTY,
-            region: Region::NULL,
+            lifetime: Lifetime::STATIC,
             ty: UNIT_TY.clone_as_ty(),
         }
     }
@@ -112,10 +197,10 @@ impl Sexpr {
             return s.clone();
         }
         let ty = value.clone_ty();
-        let region = value.clone_region();
+        let lifetime = value.clone_lifetime();
         Sexpr {
             args: valarr![value],
-            region,
+            lifetime,
             ty,
         }
     }
@@ -125,8 +210,7 @@ impl ValueData for Sexpr {}
 
 impl Live for Sexpr {
     fn lifetime(&self) -> LifetimeBorrow {
-        //FIXME: this
-        self.region.region().into()
+        self.lifetime.lifetime()
     }
 }
 
@@ -165,9 +249,14 @@ impl Value for Sexpr {
         &self[ix]
     }
     #[inline]
-    fn dep_owned(&self, _ix: usize) -> bool {
-        //TODO: generalize once pi nodes are fixed
-        true
+    fn dep_owned(&self, ix: usize) -> bool {
+        if ix == 0 {
+            return true;
+        }
+        match self.args[0].ty().as_enum() {
+            ValueEnum::Pi(pi) => !pi.lifetime_component().borrows(ix - 1),
+            _ => true,
+        }
     }
     #[inline]
     fn into_enum(self) -> ValueEnum {
@@ -196,8 +285,10 @@ impl Substitute for Sexpr {
             .cloned()
             .map(|val| val.substitute(ctx))
             .collect();
+        // Applications made during substitution draw on this context's budget and trace, if any
+        let mut app_ctx = ctx.app_ctx();
         //TODO: this
-        Sexpr::try_new(args?)
+        Sexpr::try_new_in(args?, &mut app_ctx)
     }
 }
 
@@ -274,4 +365,34 @@ mod tests {
         assert_eq!(NormalValue::from(st), NormalValue::from(true));
         assert_eq!(NormalValue::from(stv), NormalValue::from(true));
     }
+    /// Test applying a function to an affine value and a borrow of it is an error rather than a panic
+    #[test]
+    fn consuming_and_borrowing_an_argument_is_rejected() {
+        use crate::data::reference::Borrow;
+        use crate::primitive::logical::Bool;
+        use crate::region::Region;
+        use crate::tyarr;
+        use crate::typing::Type;
+        use crate::value::tuple::{Product, Tuple};
+        let region = Region::unary(Product::anchor_ty().into_ty());
+        let a = region.param(0).unwrap().into_val();
+        let borrow = Borrow::shared(a.clone()).unwrap().into_val();
+        let held = Tuple::try_new(valarr![borrow]).unwrap().into_val();
+        let consume_region =
+            Region::with(tyarr![a.clone_ty(), held.clone_ty()], region.clone()).unwrap();
+        let consume_ty = Pi::try_new(Bool.into_ty(), consume_region)
+            .unwrap()
+            .into_ty();
+        let consume = Region::with(tyarr![consume_ty], region)
+            .unwrap()
+            .param(0)
+            .unwrap()
+            .into_val();
+        let args = [a, held];
+        assert_eq!(consume.applied(&args), Err(Error::BorrowUsed));
+        assert_eq!(consume.applied_in(&args, &mut None), Err(Error::BorrowUsed));
+        let mut sexpr = vec![consume];
+        sexpr.extend_from_slice(&args);
+        assert_eq!(Sexpr::try_new(sexpr), Err(Error::BorrowUsed));
+    }
 }
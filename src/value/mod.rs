@@ -1,9 +1,16 @@
 /*!
 `rain` values
 */
-use crate::control::{phi::Phi, ternary::Ternary};
-use crate::eval::{Application, Apply, EvalCtx, Substitute};
-use crate::function::{lambda::Lambda, pi::Pi};
+use crate::control::{
+    effect::{Effect, World},
+    phi::Phi,
+    ternary::Ternary,
+};
+use crate::data::array::{Array, ArrayOp, ArrayTy};
+use crate::data::memory::{AllocTy, MemOp};
+use crate::data::reference::{Borrow, Dereference, RefTy};
+use crate::eval::{Application, Apply, EvalCtx, Substitute, TraceEvent};
+use crate::function::{external::Extern, lambda::Lambda, pi::Pi};
 use crate::lifetime::{LifetimeBorrow, Live};
 use crate::primitive::{
     bits::{BinOp, Bits, BitsKind, BitsTy, Neg},
@@ -32,14 +39,18 @@ pub mod arr;
 pub mod expr;
 pub mod predicate;
 pub mod sum;
+pub mod thunk;
 pub mod tuple;
 
 use arr::ValSet;
 use expr::Sexpr;
 use predicate::Is;
+use thunk::Thunk;
 use tuple::{Product, Tuple};
 
 mod error;
+#[cfg(feature = "serde")]
+mod serde_impl;
 mod valid_impl;
 pub use error::*;
 pub use valid_impl::*;
@@ -116,6 +127,30 @@ pub enum ValueEnum {
     BinOp(BinOp),
     /// An negation operation on bitvectors
     Neg(Neg),
+    /// A reference type
+    RefTy(RefTy),
+    /// A borrow or reborrow
+    Borrow(Borrow),
+    /// A dereference
+    Dereference(Dereference),
+    /// An allocation type
+    AllocTy(AllocTy),
+    /// A memory operation
+    MemOp(MemOp),
+    /// The type of world tokens
+    World(World),
+    /// An effectful primitive
+    Effect(Effect),
+    /// An array type
+    ArrayTy(ArrayTy),
+    /// An array literal
+    Array(Array),
+    /// An array operation
+    ArrayOp(ArrayOp),
+    /// An external function declaration
+    Extern(Extern),
+    /// An application whose beta reduction has been deferred
+    Thunk(Thunk),
 }
 
 // Common value type aliases:
@@ -224,7 +259,7 @@ pub trait Value: Sized + Typed + Apply + Substitute<ValId> + Regional + Live {
         Self: Clone,
     {
         let application = self.curried(args)?;
-        let (rest, success) = application.valid_to_success(self, args);
+        let (rest, success) = application.valid_to_success(self, args)?;
         debug_assert!(
             rest.is_empty(),
             "Incomplete currying: {:?} left, got {:?}",
@@ -240,7 +275,7 @@ pub trait Value: Sized + Typed + Apply + Substitute<ValId> + Regional + Live {
         Self: Clone,
     {
         let application = self.curried_in(args, ctx)?;
-        let (rest, success) = application.valid_to_success(self, args);
+        let (rest, success) = application.valid_to_success(self, args)?;
         debug_assert!(
             rest.is_empty(),
             "Incomplete currying: {:?} left, got {:?}",
@@ -689,6 +724,18 @@ macro_rules! forv {
             ValueEnum::PathInd($i) => $e,
             ValueEnum::BinOp($i) => $e,
             ValueEnum::Neg($i) => $e,
+            ValueEnum::RefTy($i) => $e,
+            ValueEnum::Borrow($i) => $e,
+            ValueEnum::Dereference($i) => $e,
+            ValueEnum::AllocTy($i) => $e,
+            ValueEnum::MemOp($i) => $e,
+            ValueEnum::World($i) => $e,
+            ValueEnum::Effect($i) => $e,
+            ValueEnum::ArrayTy($i) => $e,
+            ValueEnum::Array($i) => $e,
+            ValueEnum::ArrayOp($i) => $e,
+            ValueEnum::Extern($i) => $e,
+            ValueEnum::Thunk($i) => $e,
         }
     };
     (match ($v:expr) { $i:ident => $e:expr, }) => {
@@ -808,6 +855,18 @@ normal_valid!(PathInd);
 // normal_valid!(Sub);
 normal_valid!(BinOp);
 normal_valid!(Neg);
+normal_valid!(RefTy);
+normal_valid!(Borrow);
+normal_valid!(Dereference);
+normal_valid!(AllocTy);
+normal_valid!(MemOp);
+normal_valid!(World);
+normal_valid!(Effect);
+normal_valid!(ArrayTy);
+normal_valid!(Array);
+normal_valid!(ArrayOp);
+normal_valid!(Extern);
+normal_valid!(Thunk);
 
 /// Implement `From<T>` for TypeValue using the `From<T>` implementation of `NormalValue`, in effect
 /// asserting that a type's values are all `rain` types
@@ -827,6 +886,10 @@ impl_to_type!(Set);
 impl_to_type!(Bool);
 impl_to_type!(Finite);
 impl_to_type!(Pi);
+impl_to_type!(RefTy);
+impl_to_type!(AllocTy);
+impl_to_type!(World);
+impl_to_type!(ArrayTy);
 
 #[cfg(feature = "prettyprinter")]
 mod prettyprint_impl {
@@ -853,8 +916,8 @@ mod prettyprint_impl {
             printer: &mut PrettyPrinter<I>,
             fmt: &mut Formatter,
         ) -> Result<(), fmt::Error> {
-            if let Some(name) = printer.lookup(self) {
-                write!(fmt, "{}", name)
+            if printer.try_prettyprint_addr(fmt, self.into())? {
+                Ok(())
             } else {
                 self.deref().prettyprint(printer, fmt)
             }
@@ -0,0 +1,213 @@
+/*!
+`serde` support for `rain` values, regions and arrays
+
+Values are serialized as a [`ValueGraph`](ValueGraph): a flat list of nodes, each region or value depended upon
+appearing exactly once, after everything it depends on, so that shared sub-DAGs are preserved by ID rather than
+duplicated. Deserialization rebuilds every node through the normal checked constructors, so that malformed input
+yields an [`Error`](Error) rather than an invalid value.
+*/
+use super::*;
+use crate::graph::flat::ValueGraph;
+use crate::region::Region;
+use arr::{TyArr, ValArr};
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+/// Convert an error rebuilding a value graph into a deserialization error
+#[inline]
+fn de_error<E: de::Error>(err: Error) -> E {
+    E::custom(format_args!("invalid value graph: {:?}", err))
+}
+
+impl Serialize for ValId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ValueGraph::from_values(std::iter::once(self)).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ValId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ValId, D::Error> {
+        ValueGraph::deserialize(deserializer)?
+            .value()
+            .map_err(de_error)
+    }
+}
+
+impl Serialize for TypeId {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_val().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TypeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TypeId, D::Error> {
+        TypeId::try_from(ValId::deserialize(deserializer)?)
+            .map_err(|_| de_error(Error::NotATypeError))
+    }
+}
+
+impl Serialize for ValArr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ValueGraph::from_values(self.iter()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ValArr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ValArr, D::Error> {
+        let values = ValueGraph::deserialize(deserializer)?
+            .values()
+            .map_err(de_error)?;
+        Ok(values.into_iter().collect())
+    }
+}
+
+impl Serialize for TyArr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ValueGraph::from_values(self.iter().map(|ty| ty.as_val())).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TyArr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TyArr, D::Error> {
+        ValueGraph::deserialize(deserializer)?
+            .values()
+            .map_err(de_error)?
+            .into_iter()
+            .map(|value| TypeId::try_from(value).map_err(|_| de_error(Error::NotATypeError)))
+            .collect()
+    }
+}
+
+impl Serialize for Region {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ValueGraph::from_region(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Region {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Region, D::Error> {
+        ValueGraph::deserialize(deserializer)?
+            .region()
+            .map_err(de_error)
+    }
+}
+
+/// Implement `Serialize` and `Deserialize` for the variants of `ValueEnum`, via `ValId`
+macro_rules! serde_variants {
+    ($($variant:ident => $ty:ty),* $(,)?) => {$(
+        impl Serialize for $ty {
+            #[inline]
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.clone().into_val().serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$ty, D::Error> {
+                match ValId::deserialize(deserializer)?.as_enum() {
+                    ValueEnum::$variant(v) => Ok(v.clone()),
+                    _ => Err(de_error(Error::TypeMismatch)),
+                }
+            }
+        }
+    )*};
+}
+
+serde_variants! {
+    Sexpr => Sexpr,
+    Parameter => Parameter,
+    Tuple => Tuple,
+    Product => Product,
+    Prop => Prop,
+    Fin => Fin,
+    Set => Set,
+    BitsKind => BitsKind,
+    BitsTy => BitsTy,
+    Bits => Bits,
+    BoolTy => Bool,
+    Finite => Finite,
+    Index => Index,
+    Pi => Pi,
+    Lambda => Lambda,
+    Ternary => Ternary,
+    Phi => Phi,
+    Logical => Logical,
+    Id => Id,
+    Refl => Refl,
+    IdFamily => IdFamily,
+    PathInd => PathInd,
+    Neg => Neg,
+    RefTy => RefTy,
+    Borrow => Borrow,
+    Dereference => Dereference,
+    AllocTy => AllocTy,
+    MemOp => MemOp,
+    World => World,
+    Effect => Effect,
+    ArrayTy => ArrayTy,
+    Array => Array,
+    ArrayOp => ArrayOp,
+    Extern => Extern,
+    Thunk => Thunk,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::flat::GraphNode;
+    use crate::{tyarr, valarr};
+
+    fn mux() -> Lambda {
+        let region = Region::with(tyarr![Bool.into_ty(); 3], Region::NULL).unwrap();
+        let select = region.param(0).unwrap().into_val();
+        let high = region.param(1).unwrap().into_val();
+        let low = region.param(2).unwrap().into_val();
+        let ternary = Ternary::conditional(high, low).unwrap().into_val();
+        let mux_res = Sexpr::try_new(vec![ternary, select]).unwrap().into_val();
+        Lambda::try_new(mux_res, region).unwrap()
+    }
+
+    #[test]
+    fn values_round_trip_through_json_pointer_identically() {
+        let mux = mux();
+        let region = mux.def_region().clone();
+        let json = serde_json::to_string(&mux).unwrap();
+        let decoded: Lambda = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, mux);
+        let mux = mux.into_val();
+        let decoded: ValId = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.as_ptr(), mux.as_ptr());
+
+        let ty = mux.ty().clone_as_ty();
+        let decoded: TypeId = serde_json::from_str(&serde_json::to_string(&ty).unwrap()).unwrap();
+        assert_eq!(decoded, ty);
+
+        let decoded: Region =
+            serde_json::from_str(&serde_json::to_string(&region).unwrap()).unwrap();
+        assert_eq!(decoded, region);
+
+        let arr = valarr![
+            mux,
+            BinOp::Add.into_val(),
+            BitsTy(8).data(0x7f).unwrap().into_val()
+        ];
+        let decoded: ValArr = serde_json::from_str(&serde_json::to_string(&arr).unwrap()).unwrap();
+        assert_eq!(decoded, arr);
+    }
+
+    #[test]
+    fn malformed_graphs_are_rejected() {
+        let dangling = ValueGraph {
+            nodes: vec![GraphNode::Refl(1)],
+            roots: vec![0],
+        };
+        let json = serde_json::to_string(&dangling).unwrap();
+        assert!(serde_json::from_str::<ValId>(&json).is_err());
+        assert!(
+            serde_json::from_str::<Lambda>(&serde_json::to_string(&true.into_val()).unwrap())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,338 @@
+/*!
+Thunks: applications whose beta reduction has been deferred until forced
+*/
+use super::{
+    arr::ValArr, expr::Sexpr, Error, NormalValue, TypeId, TypeRef, ValId, Value, ValueData,
+    ValueEnum,
+};
+use crate::eval::{Application, Apply, EvalCtx, Strategy, Substitute};
+use crate::lifetime::{Lifetime, LifetimeBorrow, Live};
+use crate::typing::{Type, Typed};
+use crate::{debug_from_display, enum_convert, pretty_display};
+use elysees::Arc;
+use once_cell::sync::OnceCell;
+use std::convert::TryInto;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+/**
+An application whose beta reduction has been deferred, to be performed at most once when forced.
+
+Thunks are constructed in place of reducible S-expressions under the [lazy](Strategy::Lazy) normalization strategy.
+A thunk is a normal form distinct from the result of forcing it, and is hash-consed on its arguments alone: equal
+thunks share the memoized result of forcing them, and never compare equal to that result.
+
+Applications producing types are never deferred, since types are compared by identity: a type is always in normal
+form, and so equal to the type it would be forced to.
+*/
+#[derive(Clone)]
+pub struct Thunk {
+    /// The arguments of this thunk, the first of which is the function applied
+    args: ValArr,
+    /// The (cached) lifetime of this thunk
+    lifetime: Lifetime,
+    /// The (cached) type of this thunk
+    ty: TypeId,
+    /// The result of forcing this thunk, if it has been forced
+    forced: Arc<OnceCell<ValId>>,
+}
+
+impl Thunk {
+    /**
+    Attempt to defer the application of a function to an argument list, returning `None` if the application does not
+    beta-reduce, e.g. since the function is not a lambda or is only partially applied.
+
+    The function is the first element of the argument list. Applications producing types are not deferred either, and
+    should be reduced immediately.
+    */
+    pub fn try_defer(args: &[ValId], ctx: &mut Option<EvalCtx>) -> Result<Option<Thunk>, Error> {
+        let lambda = match args.first().map(|f| f.as_enum()) {
+            Some(ValueEnum::Lambda(lambda)) => lambda,
+            _ => return Ok(None),
+        };
+        if lambda.def_region().len() >= args.len() {
+            return Ok(None);
+        }
+        let ty = lambda.get_ty().apply_ty_in(&args[1..], ctx)?;
+        if ty.is_kind() {
+            return Ok(None);
+        }
+        let lifetime = Sexpr::args_lifetime(&ty, args)?;
+        Ok(Some(Thunk {
+            args: args.iter().cloned().collect(),
+            lifetime,
+            ty,
+            forced: Arc::new(OnceCell::new()),
+        }))
+    }
+    /// Check whether this thunk has already been forced
+    #[inline]
+    pub fn is_forced(&self) -> bool {
+        self.forced.get().is_some()
+    }
+    /// Force this thunk, reducing its application and memoizing the result
+    #[inline]
+    pub fn force(&self) -> Result<ValId, Error> {
+        self.force_in(&mut None)
+    }
+    /**
+    Force this thunk in a given evaluation context, reducing its application and memoizing the result.
+
+    The application is reduced to weak head normal form: thunks in its result, other than the result itself, are left
+    unforced. Errors, such as running out of fuel, are not memoized, and forcing may be retried.
+    */
+    pub fn force_in(&self, ctx: &mut Option<EvalCtx>) -> Result<ValId, Error> {
+        if let Some(forced) = self.forced.get() {
+            return Ok(forced.clone());
+        }
+        if let Some(ctx) = ctx {
+            ctx.consume_fuel()?;
+        }
+        let mut forced = match self.args[0].apply_in(&self.args[1..], ctx)? {
+            Application::Success(rest, value) => {
+                Sexpr::applied_with_in(value, rest, ctx)?.into_val()
+            }
+            Application::Symbolic(_) => {
+                Sexpr::new_unchecked(self.args.clone(), self.lifetime.clone(), self.ty.clone())
+                    .into_val()
+            }
+        };
+        while let ValueEnum::Thunk(thunk) = forced.as_enum() {
+            let next = thunk.force_in(ctx)?;
+            forced = next;
+        }
+        Ok(self.forced.get_or_init(|| forced).clone())
+    }
+    /// Force this thunk as a type, returning an error if it does not reduce to one
+    #[inline]
+    pub fn force_ty(&self) -> Result<TypeId, Error> {
+        self.force_ty_in(&mut None)
+    }
+    /// Force this thunk as a type in a given evaluation context, returning an error if it does not reduce to one
+    #[inline]
+    pub fn force_ty_in(&self, ctx: &mut Option<EvalCtx>) -> Result<TypeId, Error> {
+        self.force_in(ctx)?
+            .try_into()
+            .map_err(|_| Error::NotATypeError)
+    }
+}
+
+impl PartialEq for Thunk {
+    #[inline]
+    fn eq(&self, other: &Thunk) -> bool {
+        self.args == other.args
+    }
+}
+
+impl Eq for Thunk {}
+
+impl Hash for Thunk {
+    #[inline]
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.args.hash(hasher)
+    }
+}
+
+impl Deref for Thunk {
+    type Target = ValArr;
+    #[inline]
+    fn deref(&self) -> &ValArr {
+        &self.args
+    }
+}
+
+impl ValueData for Thunk {}
+
+impl Live for Thunk {
+    #[inline]
+    fn lifetime(&self) -> LifetimeBorrow {
+        self.lifetime.lifetime()
+    }
+}
+
+impl Typed for Thunk {
+    #[inline]
+    fn ty(&self) -> TypeRef {
+        self.ty.borrow_ty()
+    }
+    #[inline]
+    fn is_ty(&self) -> bool {
+        self.ty().is_kind()
+    }
+    #[inline]
+    fn is_kind(&self) -> bool {
+        self.ty().ty().is_kind()
+    }
+}
+
+impl Value for Thunk {
+    #[inline]
+    fn no_deps(&self) -> usize {
+        self.len()
+    }
+    #[inline]
+    fn get_dep(&self, ix: usize) -> &ValId {
+        &self[ix]
+    }
+    #[inline]
+    fn dep_owned(&self, ix: usize) -> bool {
+        if ix == 0 {
+            return true;
+        }
+        match self.args[0].ty().as_enum() {
+            ValueEnum::Pi(pi) => !pi.lifetime_component().borrows(ix - 1),
+            _ => true,
+        }
+    }
+    #[inline]
+    fn into_enum(self) -> ValueEnum {
+        ValueEnum::Thunk(self)
+    }
+    #[inline]
+    fn into_norm(self) -> NormalValue {
+        self.into()
+    }
+}
+
+impl Apply for Thunk {
+    fn apply_in<'a>(
+        &self,
+        args: &'a [ValId],
+        ctx: &mut Option<EvalCtx>,
+    ) -> Result<Application<'a>, Error> {
+        if args.is_empty() || Strategy::of(ctx).is_lazy() {
+            self.ty.apply_ty_in(args, ctx).map(Application::Symbolic)
+        } else {
+            self.force_in(ctx)
+                .map(|forced| Application::Success(args, forced))
+        }
+    }
+}
+
+impl Substitute<ValId> for Thunk {
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<ValId, Error> {
+        let args: Result<_, _> = self
+            .args
+            .iter()
+            .cloned()
+            .map(|val| val.substitute(ctx))
+            .collect();
+        // The substituted application is deferred again only if the context is lazy
+        let mut app_ctx = ctx.app_ctx();
+        Sexpr::try_new_in(args?, &mut app_ctx).map(Sexpr::into_val)
+    }
+}
+
+impl Substitute<ValueEnum> for Thunk {
+    #[inline]
+    fn substitute(&self, ctx: &mut EvalCtx) -> Result<ValueEnum, Error> {
+        let substituted: ValId = self.substitute(ctx)?;
+        Ok(substituted.as_enum().clone())
+    }
+}
+
+impl From<Thunk> for NormalValue {
+    fn from(thunk: Thunk) -> NormalValue {
+        NormalValue::assert_normal(ValueEnum::Thunk(thunk))
+    }
+}
+
+debug_from_display!(Thunk);
+pretty_display!(Thunk, "#thunk(...)");
+enum_convert! {
+    impl InjectionRef<ValueEnum> for Thunk {}
+    impl TryFrom<NormalValue> for Thunk { as ValueEnum, }
+    impl TryFromRef<NormalValue> for Thunk { as ValueEnum, }
+}
+
+#[cfg(feature = "prettyprinter")]
+mod prettyprint_impl {
+    use super::*;
+    use crate::prettyprinter::{PrettyPrint, PrettyPrinter};
+    use crate::tokens::*;
+    use std::fmt::{self, Display, Formatter};
+
+    impl PrettyPrint for Thunk {
+        fn prettyprint<I: From<usize> + Display>(
+            &self,
+            printer: &mut PrettyPrinter<I>,
+            fmt: &mut Formatter,
+        ) -> Result<(), fmt::Error> {
+            write!(fmt, "#thunk{}", SEXPR_OPEN)?;
+            let mut first = true;
+            for value in self.iter() {
+                if !first {
+                    write!(fmt, " ")?;
+                }
+                first = false;
+                value.prettyprint(printer, fmt)?;
+            }
+            write!(fmt, "{}", SEXPR_CLOSE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+    use crate::function::lambda::Lambda;
+    use crate::primitive::logical::{And, Bool};
+    use crate::region::Region;
+
+    #[test]
+    fn lazy_applications_are_deferred_and_forced_once() {
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let and = Sexpr::try_new(vec![And.into_val(), x, y])
+            .unwrap()
+            .into_val();
+        let and = Lambda::try_new(and, region).unwrap().into_val();
+        let args = vec![and.clone(), true.into_val(), false.into_val()];
+
+        let eager = Sexpr::try_new_in(
+            args.clone(),
+            &mut Some(EvalCtx::with_strategy(Strategy::Eager)),
+        )
+        .unwrap()
+        .into_val();
+        assert_eq!(eager, false.into_val());
+
+        let mut ctx = Some(EvalCtx::with_strategy(Strategy::Lazy));
+        let lazy = Sexpr::try_new_in(args.clone(), &mut ctx)
+            .unwrap()
+            .into_val();
+        assert_ne!(lazy, eager);
+        assert_eq!(lazy.ty(), Bool.into_ty());
+        let thunk = match lazy.as_enum() {
+            ValueEnum::Thunk(thunk) => thunk,
+            v => panic!("Expected a thunk, got {}", v),
+        };
+        assert_eq!(thunk.len(), 3);
+        assert!(!thunk.is_forced());
+        assert_eq!(thunk.force(), Ok(false.into_val()));
+        assert!(thunk.is_forced());
+
+        // Equal thunks are hash-consed, sharing the result of forcing them
+        let again = Sexpr::try_new_in(args, &mut ctx).unwrap().into_val();
+        assert_eq!(again, lazy);
+        match again.as_enum() {
+            ValueEnum::Thunk(thunk) => assert!(thunk.is_forced()),
+            v => panic!("Expected a thunk, got {}", v),
+        }
+        assert_eq!(Interpreter::new().call(&lazy, &[]), Ok(false.into_val()));
+    }
+
+    #[test]
+    fn lazy_type_applications_are_reduced() {
+        let region = Region::unary(Bool.into_ty());
+        let ty = Lambda::try_new(Bool.into_val(), region).unwrap().into_val();
+        let args = vec![ty, true.into_val()];
+        assert_eq!(Thunk::try_defer(&args, &mut None), Ok(None));
+        let mut ctx = Some(EvalCtx::with_strategy(Strategy::Lazy));
+        let lazy = Sexpr::try_new_in(args, &mut ctx).unwrap().into_val();
+        assert_eq!(lazy, Bool.into_val());
+        assert!(lazy.is_ty());
+    }
+}
@@ -8,7 +8,7 @@ use super::{
 use crate::eval::{Application, Apply, EvalCtx, Substitute};
 use crate::lifetime::{Lifetime, LifetimeBorrow, Live};
 use crate::primitive::{Unit, UNIT, UNIT_TY};
-use crate::region::{Region, Regional};
+use crate::region::Region;
 use crate::typing::{primitive::Prop, Kind, Type, Typed};
 use crate::{debug_from_display, enum_convert, pretty_display, substitute_to_valid};
 use std::convert::TryInto;
@@ -29,12 +29,11 @@ impl Tuple {
     /// Try to create a new product from a vector of values. Return an error if they have incompatible lifetimes.
     #[inline]
     pub fn try_new(elems: ValArr) -> Result<Tuple, Error> {
-        let region = Region::NULL.gcrs(elems.iter())?.clone_region();
+        let lifetime = Lifetime::from_deps(&Region::NULL, elems.iter().map(|elem| (elem, true)))?;
         let ty = Product::try_new(elems.iter().map(|elem| elem.clone_ty()).collect())?.into();
-        //FIXME: not region, lifetime!
         Ok(Tuple {
             elems,
-            lifetime: region.into(),
+            lifetime,
             ty,
         })
     }
@@ -156,14 +155,13 @@ impl Apply for Tuple {
 
 impl Substitute for Tuple {
     fn substitute(&self, ctx: &mut EvalCtx) -> Result<Tuple, Error> {
-        //FIXME!!!
-        let lifetime = self.lifetime.clone();
-        let elems = self
+        let elems: ValArr = self
             .elems
             .iter()
             .cloned()
             .map(|val| val.substitute(ctx))
             .collect::<Result<_, _>>()?;
+        let lifetime = Lifetime::from_deps(&Region::NULL, elems.iter().map(|elem| (elem, true)))?;
         Ok(Tuple {
             elems,
             lifetime,
@@ -249,7 +247,10 @@ impl Product {
         force_affine: bool,
         force_relevant: bool,
     ) -> Result<Product, Error> {
-        let region = Region::NULL.gcrs(elems.iter())?.clone_region();
+        let lifetime = Lifetime::from_deps(
+            &Region::NULL,
+            elems.iter().map(|elem| (elem.as_val(), true)),
+        )?;
         let affine = force_affine || elems.iter().any(|t| t.is_affine());
         let relevant = force_relevant || elems.iter().any(|t| t.is_relevant());
         let flags = ProductFlags::new(affine, force_affine, relevant, force_relevant);
@@ -259,10 +260,9 @@ impl Product {
             .max()
             .map(Kind::into_kind)
             .unwrap_or_else(|| Prop.into_kind());
-        //FIXME: compute lifetime here!!!
         Ok(Product {
             elems,
-            lifetime: region.into(),
+            lifetime,
             ty,
             flags,
         })
@@ -292,6 +292,18 @@ impl Product {
             flags: ProductFlags(FLAG_AFFIN | FLAG_ANCHR),
         }
     }
+    /// Create the product corresponding to the "linear anchor" type, i.e. the unit type made linear
+    ///
+    /// Values of this type must be used exactly once, making them suitable as state tokens.
+    #[inline]
+    pub fn linear_anchor_ty() -> Product {
+        Product {
+            elems: TyArr::EMPTY,
+            lifetime: Lifetime::STATIC,
+            ty: Prop.into_kind(),
+            flags: ProductFlags(FLAG_AFFIN | FLAG_ANCHR | FLAG_RLVNT | FLAG_FLARE),
+        }
+    }
     /// Get the type-tuple corresponding to this product type
     ///
     /// TODO: consider caching this (or the tuple type) in an atomic, as it may need to be computed many times
@@ -335,10 +347,13 @@ impl Substitute for Product {
         let affine = self.is_anchor() || elems.iter().any(|t| t.is_affine());
         let relevant = self.is_flare() || elems.iter().any(|t| t.is_affine());
         let flags = ProductFlags::new(affine, self.is_anchor(), relevant, self.is_flare());
-        //FIXME: compute lifetimes properly!!!
+        let lifetime = Lifetime::from_deps(
+            &Region::NULL,
+            elems.iter().map(|elem| (elem.as_val(), true)),
+        )?;
         Ok(Product {
             elems,
-            lifetime: self.lifetime.clone(),
+            lifetime,
             ty: self.ty.substitute(ctx)?.try_into().expect("Impossible"),
             flags,
         })
@@ -499,12 +514,13 @@ mod prettyprint_impl {
             }
             write!(
                 fmt,
-                "{}{}",
+                "{}{}{}",
                 if self.is_anchor() {
                     KEYWORD_ANCHOR
                 } else {
                     KEYWORD_PROD
                 },
+                if self.is_flare() { "_linear" } else { "" },
                 TUPLE_OPEN
             )?;
             let mut first = true;
@@ -610,4 +626,51 @@ mod tests {
         assert!(anchor_product.is_affine());
         assert!(!anchor_product.is_relevant());
     }
+
+    /// Test the linear anchor type is both affine and relevant, and distinct from the anchor type
+    #[test]
+    fn linear_anchor_type_construction() {
+        let linear_ty: TypeId = Product::linear_anchor_ty().into();
+        assert!(linear_ty.is_affine());
+        assert!(linear_ty.is_relevant());
+        assert!(linear_ty.is_linear());
+        assert_ne!(linear_ty, TypeId::from(Product::anchor_ty()));
+    }
+
+    /// Test tuples take the lifetimes of their elements, and that incompatible lifetimes are rejected
+    #[test]
+    fn tuple_lifetimes() {
+        use crate::primitive::logical::Bool;
+        let region = Region::binary(Bool.into_ty());
+        let x = region.param(0).unwrap().into_val();
+        let y = region.param(1).unwrap().into_val();
+        let xy = Tuple::try_new(vec![x.clone(), y, true.into_val()].into()).unwrap();
+        assert_eq!(xy.clone_lifetime(), Lifetime::from(region.clone()));
+        let constant = Tuple::try_new(vec![true.into_val(), false.into_val()].into()).unwrap();
+        assert_eq!(constant.clone_lifetime(), Lifetime::STATIC);
+        let other = Region::unary(Bool.into_ty());
+        let z = other.param(0).unwrap().into_val();
+        assert_eq!(
+            Tuple::try_new(vec![x, z].into()),
+            Err(Error::incomparable_regions(region, other))
+        );
+    }
+
+    /// Test a tuple holding a borrow borrows from its lender, and may not also consume an affine lender
+    #[test]
+    fn tuple_borrow_lifetimes() {
+        use crate::data::reference::Borrow;
+        let region = Region::unary(Product::linear_anchor_ty().into());
+        let a = region.param(0).unwrap().into_val();
+        let borrow = Borrow::shared(a.clone()).unwrap().into_val();
+        let held = Tuple::try_new(vec![borrow.clone(), true.into_val()].into()).unwrap();
+        let lifetime = held.clone_lifetime();
+        let lender = lifetime.lender().unwrap().values();
+        assert_eq!(lender.len(), 1);
+        assert_eq!(lender[0].as_addr(), a.as_addr());
+        assert_eq!(
+            Tuple::try_new(vec![a, borrow].into()),
+            Err(Error::BorrowUsed)
+        );
+    }
 }
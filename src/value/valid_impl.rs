@@ -111,6 +111,8 @@ impl ValId {
             return Ok(value);
         }
         let result: ValId = self.deref().substitute(ctx)?;
+        ctx.memoize(self, &result);
+        ctx.preserve_debug_info(self, &result);
         ctx.substitute_unchecked(self.clone(), result.clone())?;
         Ok(result)
     }
@@ -418,7 +420,11 @@ impl<P> Apply for ValId<P> {
         args: &'a [ValId],
         ctx: &mut Option<EvalCtx>,
     ) -> Result<Application<'a>, Error> {
-        self.as_norm().apply_in(args, ctx)
+        let application = self.as_norm().apply_in(args, ctx)?;
+        if let Some(ctx) = ctx {
+            ctx.record(|| TraceEvent::application(self.as_val(), args, &application));
+        }
+        Ok(application)
     }
 }
 
@@ -429,7 +435,11 @@ impl<P> Apply for ValRef<'_, P> {
         args: &'a [ValId],
         ctx: &mut Option<EvalCtx>,
     ) -> Result<Application<'a>, Error> {
-        self.as_norm().apply_in(args, ctx)
+        let application = self.as_norm().apply_in(args, ctx)?;
+        if let Some(ctx) = ctx {
+            ctx.record(|| TraceEvent::application(self.as_valid(), args, &application));
+        }
+        Ok(application)
     }
 }
 